@@ -0,0 +1,167 @@
+//! Benchmarks of the storage layer
+//!
+//! Every size is seeded as a fresh database in a scratch directory, which the
+//! database task then reloads. Operations go through the same functions the
+//! routes use, so they include waiting for the database task and writing the
+//! file. Run with `cargo bench`, or `BENCH_STORAGE=sqlite cargo bench` for
+//! the operations on the SQLite backend, whose databases are seeded by moving
+//! the seeded file in as the first start with `--storage sqlite` does.
+
+use std::{fs::File, hint::black_box};
+
+use bxyz_invite::{
+    event_db::{self, format, Attendee, Event, EventDB, Storage},
+    ids::Id,
+    templates::AttendeePatch,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::runtime::Runtime;
+
+/// Attendees in the whole database
+const SIZES: [u64; 3] = [10, 1_000, 50_000];
+const ATTENDEES_PER_EVENT: u64 = 50;
+/// Attendee IDs are offset so they don't collide with event IDs
+const ATTENDEE_ID_BASE: u64 = 1 << 32;
+
+/// A database with `attendees` attendees spread over events of up to
+/// `ATTENDEES_PER_EVENT`
+fn seed(attendees: u64) -> EventDB {
+    let mut db = EventDB::default();
+    for ev in 0..attendees.div_ceil(ATTENDEES_PER_EVENT) {
+        let first = ev * ATTENDEES_PER_EVENT;
+        let last = (first + ATTENDEES_PER_EVENT).min(attendees);
+        db.insert_event(Event {
+            name: Some(format!("Event {ev}")),
+            attendees: (first..last)
+                .map(|at| Attendee {
+                    name: format!("Guest {at}"),
+                    custom_html: "<p>You're invited! Bring snacks.</p>"
+                        .repeat(4),
+                    ..Attendee::new(Id::from(ATTENDEE_ID_BASE + at))
+                })
+                .collect(),
+            ..Event::new(Id::from(ev))
+        });
+    }
+    db
+}
+
+/// The backend to benchmark, from `BENCH_STORAGE`
+fn backend() -> Storage {
+    match std::env::var("BENCH_STORAGE").as_deref() {
+        Ok("sqlite") => Storage::Sqlite,
+        Ok("cbor") | Err(_) => Storage::Cbor,
+        Ok(other) => panic!("Unknown BENCH_STORAGE {other}"),
+    }
+}
+
+fn backend_name(storage: Storage) -> &'static str {
+    match storage {
+        Storage::Cbor => "cbor",
+        Storage::Sqlite => "sqlite",
+    }
+}
+
+/// Replace the live database with a seeded one
+fn install(rt: &Runtime, attendees: u64) {
+    let file = File::create("events.db").expect("Database file is writable");
+    format::write(file, &seed(attendees)).expect("Database is writable");
+    // without a database of its own, SQLite moves the seeded one in
+    for path in ["events.sqlite", "events.sqlite-wal", "events.sqlite-shm"] {
+        let _ = std::fs::remove_file(path);
+    }
+    rt.block_on(event_db::reload_db())
+        .expect("Seeded database loads");
+}
+
+/// An attendee in the middle of the database
+fn some_attendee(attendees: u64) -> (Id, Id) {
+    let at = attendees / 2;
+    (
+        Id::from(at / ATTENDEES_PER_EVENT),
+        Id::from(ATTENDEE_ID_BASE + at),
+    )
+}
+
+fn file_format(c: &mut Criterion) {
+    let mut group = c.benchmark_group("file");
+    group.sample_size(20);
+    for size in SIZES {
+        let db = seed(size);
+        group.bench_with_input(
+            BenchmarkId::new("write", size),
+            &db,
+            |b, db| {
+                b.iter(|| {
+                    let file = File::create("bench.db").unwrap();
+                    format::write(file, black_box(db)).unwrap()
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("read", size), &(), |b, _| {
+            b.iter(|| format::read(File::open("bench.db").unwrap()).unwrap())
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file("bench.db");
+}
+
+fn operations(c: &mut Criterion) {
+    let rt = Runtime::new().expect("Runtime starts");
+    let name = format!("operations/{}", backend_name(backend()));
+    let mut group = c.benchmark_group(name);
+    group.sample_size(20);
+    for size in SIZES {
+        let (ev_id, at_id) = some_attendee(size);
+
+        // each iteration adds an event, so start over from the seed
+        install(&rt, size);
+        group.bench_function(BenchmarkId::new("create", size), |b| {
+            b.to_async(&rt)
+                .iter(|| async { event_db::create_event().await.unwrap() })
+        });
+
+        install(&rt, size);
+        group.bench_function(BenchmarkId::new("find", size), |b| {
+            b.to_async(&rt).iter(|| async {
+                event_db::find_event_by_attendee(black_box(at_id))
+                    .await
+                    .unwrap()
+            })
+        });
+        group.bench_function(BenchmarkId::new("update", size), |b| {
+            b.to_async(&rt).iter(|| async {
+                let patch = AttendeePatch {
+                    notes: Some("Allergic to peanuts".to_string()),
+                    ..Default::default()
+                };
+                event_db::update_attendee(ev_id, at_id, patch)
+                    .await
+                    .unwrap()
+            })
+        });
+        // nothing has expired, so this is the cost of checking
+        group.bench_function(BenchmarkId::new("purge", size), |b| {
+            b.to_async(&rt)
+                .iter(|| async { event_db::purge_old_events().await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+/// Work in a scratch directory so the benchmarks don't touch `events.db`
+fn scratch_dir() {
+    let dir = std::env::temp_dir().join("bxyz-invite-bench");
+    std::fs::create_dir_all(&dir).expect("Scratch directory is creatable");
+    std::env::set_current_dir(&dir).expect("Scratch directory is usable");
+}
+
+fn benches(c: &mut Criterion) {
+    scratch_dir();
+    event_db::set_storage(backend());
+    file_format(c);
+    operations(c);
+}
+
+criterion_group!(storage, benches);
+criterion_main!(storage);
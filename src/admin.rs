@@ -0,0 +1,309 @@
+//! Operator-only routes
+//!
+//! Admin routes are disabled unless a token is given with `--admin-token` or
+//! passkeys or OpenID Connect admins are enabled. Requests must then carry
+//! the token as `Authorization: Bearer <token>`. Browsers sign in with a
+//! passkey or through the provider instead, see `passkeys` and `oidc`, since
+//! a token in the address would leak through the history and logs.
+
+use std::collections::BTreeMap;
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::{ApiError, ErrorCode},
+    event_db::{self, EventOrder, EventSummary},
+    ids, metrics, oidc, passkeys, settings, templates,
+};
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 200;
+
+/// Compare without exiting early so the time taken does not leak how much of
+/// the token was correct
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Middleware rejecting requests without the admin token or a passkey session
+pub async fn require_admin(
+    State(expected): State<Option<String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if expected.is_none() && !passkeys::enabled() && !oidc::admins_enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if passkeys::has_session(req.headers()) {
+        return next.run(req).await;
+    }
+
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let browser = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+    match (provided, &expected) {
+        (Some(token), Some(expected))
+            if constant_time_eq(token.as_bytes(), expected.as_bytes()) =>
+        {
+            next.run(req).await
+        }
+        _ if browser && passkeys::enabled() => passkeys::login_redirect(),
+        _ if browser && oidc::admins_enabled() => {
+            Redirect::to(&oidc::login_link(req.uri().path())).into_response()
+        }
+        _ => ApiError::new(
+            ErrorCode::Unauthorized,
+            "Missing or invalid admin token",
+        )
+        .into_response(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PageQuery {
+    page: Option<usize>,
+    per_page: Option<usize>,
+    #[serde(default)]
+    order: EventOrder,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventPage {
+    page: usize,
+    per_page: usize,
+    total: usize,
+    events: Vec<EventSummary>,
+}
+
+/// `GET /invite/admin/api/events?page=&per_page=&order=`
+///
+/// Pages are numbered from 1
+pub async fn list_events(Query(query): Query<PageQuery>) -> Response {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+
+    let offset = (page - 1).saturating_mul(per_page);
+    match event_db::list_event_summaries(query.order, offset, per_page).await {
+        Ok((total, events)) => Json(EventPage {
+            page,
+            per_page,
+            total,
+            events,
+        })
+        .into_response(),
+        Err(e) => {
+            ApiError::new(ErrorCode::DatabaseUnavailable, e).into_response()
+        }
+    }
+}
+
+/// `POST /invite/admin/api/events/{ev_id}/pin`
+///
+/// Exempt an event from purging
+pub async fn pin_event(Path(id): Path<String>) -> Response {
+    set_pinned(&id, true).await
+}
+
+/// `DELETE /invite/admin/api/events/{ev_id}/pin`
+///
+/// Let an event expire again, counting its lifetime from now
+pub async fn unpin_event(Path(id): Path<String>) -> Response {
+    set_pinned(&id, false).await
+}
+
+async fn set_pinned(id: &str, pinned: bool) -> Response {
+    let Ok(ev_id) = ids::decode_id(id) else {
+        return ApiError::new(ErrorCode::NotFound, "Event does not exist")
+            .into_response();
+    };
+    match event_db::set_pinned(ev_id, pinned).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// `POST /invite/admin/api/events/{ev_id}/freeze`
+///
+/// Make an event a read-only record
+pub async fn freeze_event(Path(id): Path<String>) -> Response {
+    set_frozen(&id, true).await
+}
+
+/// `DELETE /invite/admin/api/events/{ev_id}/freeze`
+///
+/// Let the organizer and guests change an event again
+pub async fn unfreeze_event(Path(id): Path<String>) -> Response {
+    set_frozen(&id, false).await
+}
+
+async fn set_frozen(id: &str, frozen: bool) -> Response {
+    let Ok(ev_id) = ids::decode_id(id) else {
+        return ApiError::new(ErrorCode::NotFound, "Event does not exist")
+            .into_response();
+    };
+    match event_db::set_frozen(ev_id, frozen).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// `GET /invite/admin/metrics` in the Prometheus text format
+pub async fn prometheus() -> Response {
+    match metrics::snapshot().await {
+        Ok(snapshot) => (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            metrics::render_prometheus(&snapshot),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// `GET /invite/admin`
+pub async fn dashboard() -> Response {
+    let snapshot = match metrics::snapshot().await {
+        Ok(s) => s,
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+    };
+
+    let busiest = snapshot.events_per_week.iter().copied().max().unwrap_or(0);
+    let weeks = snapshot
+        .events_per_week
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| templates::WeekRow {
+            label: match i {
+                0 => "This week".to_string(),
+                1 => "Last week".to_string(),
+                n => format!("{n} weeks ago"),
+            },
+            count,
+            percent: (count * 100).checked_div(busiest).unwrap_or(0),
+        })
+        .collect();
+
+    let Ok(page) = templates::AdminDashboard {
+        events: snapshot.events,
+        attendees: snapshot.attendees,
+        accepted: snapshot.accepted,
+        rsvp_rate: format!("{:.0}%", snapshot.rsvp_rate() * 100.0),
+        db_size: match snapshot.db_max_bytes {
+            Some(max) => format!(
+                "{} of {} ({:.0}%)",
+                human_bytes(snapshot.db_bytes),
+                human_bytes(max),
+                snapshot.db_bytes as f64 / max as f64 * 100.0
+            ),
+            None => human_bytes(snapshot.db_bytes),
+        },
+        notification_failures: snapshot.notification_failures,
+        last_purge: templates::relative_time(snapshot.last_purge, "Never"),
+        next_purge: templates::relative_time(
+            snapshot.next_purge,
+            "Not scheduled",
+        ),
+        purges_succeeded: snapshot.purges_succeeded,
+        purges_failed: snapshot.purges_failed,
+        events_purged: snapshot.events_purged,
+        slot_registered: snapshot.slot_registered,
+        slot_registrations: snapshot.slot_registrations,
+        weeks,
+        banner: settings::current().banner.unwrap_or_default(),
+        banner_link: "/invite/admin/banner",
+        max_banner_chars: settings::MAX_BANNER_CHARS,
+        settings: settings::KEYS
+            .iter()
+            .map(|&(key, description)| templates::SettingRow {
+                key,
+                description,
+                value: settings::read(|s| s.get(key)),
+            })
+            .collect(),
+        settings_link: "/invite/admin/settings",
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(page).into_response()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BannerForm {
+    #[serde(default)]
+    banner: String,
+}
+
+/// `POST /invite/admin/banner`
+///
+/// Show a notice atop every page, or remove it with an empty `banner`
+pub async fn set_banner(Form(form): Form<BannerForm>) -> Response {
+    match settings::set_banner(&form.banner).await {
+        Ok(()) => Redirect::to("/invite/admin").into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `POST /invite/admin/settings` with settings as form fields, see
+/// `settings::Settings::set`
+pub async fn set_settings(
+    Form(values): Form<Vec<(String, String)>>,
+) -> Response {
+    match settings::set(&values).await {
+        Ok(()) => Redirect::to("/invite/admin").into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `GET /invite/admin/api/settings`
+pub async fn get_settings() -> Json<settings::Settings> {
+    Json(settings::current())
+}
+
+/// `PATCH /invite/admin/api/settings` with settings as text by name, e.g.,
+/// `{"event_lifetime": "30d"}`, answering with all settings
+pub async fn patch_settings(
+    Json(values): Json<BTreeMap<String, String>>,
+) -> Result<Json<settings::Settings>, ApiError> {
+    let values: Vec<_> = values.into_iter().collect();
+    settings::set(&values).await?;
+    Ok(Json(settings::current()))
+}
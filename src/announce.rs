@@ -0,0 +1,55 @@
+//! Announcing the bound address without a Slot server
+//!
+//! With HTTP port 0 the module binds wherever the OS puts it. When there is no
+//! Slot server to register with, `--announce-file` writes the address to a JSON
+//! file so local scripts and other services can find it. The file is removed
+//! on shutdown.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+struct Announcement<'a> {
+    module: &'a str,
+    address: String,
+    port: u16,
+    pid: u32,
+}
+
+/// Write the announcement file for a module bound to `addr`
+pub async fn write(path: &Path, module: &str, addr: SocketAddr) {
+    let announcement = Announcement {
+        module,
+        address: addr.ip().to_string(),
+        port: addr.port(),
+        pid: std::process::id(),
+    };
+    let json = match serde_json::to_vec_pretty(&announcement) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to serialize announcement: \"{e}\"");
+            return;
+        }
+    };
+    match tokio::fs::write(path, json).await {
+        Ok(_) => log::info!("Announced {addr} in {}", path.display()),
+        Err(e) => log::error!(
+            "Failed to write announcement file {}: \"{e}\"",
+            path.display()
+        ),
+    }
+}
+
+/// Remove the announcement file so nothing tries to reach a dead port
+pub async fn withdraw(path: PathBuf) {
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        log::warn!(
+            "Failed to remove announcement file {}: \"{e}\"",
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! JSON API for scripts
+//!
+//! Routes under `/invite/api/v1` do what the manage page and invitations do,
+//! answering with JSON bodies and status codes instead of pages and
+//! redirects. As with the pages, knowing an event's ID is what lets a caller
+//! manage it, and knowing an attendee's ID lets them answer the invitation.
+//! Listing every event is for the operator alone, so it takes the admin token
+//! and answers as `GET /invite/admin/api/events` does.
+
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::{ApiError, ErrorCode},
+    clock::{self, unix_secs},
+    event_db::{self, Attendee, CustomField, Decline, Event, Rsvp, RsvpCounts},
+    i18n::Language,
+    ids::{self, Id},
+    settings,
+    templates::{EventPatch, NewAttendee},
+};
+
+fn decode(id: &str, what: &str) -> Result<Id, ApiError> {
+    ids::decode_id(id).map_err(|_| {
+        ApiError::new(ErrorCode::NotFound, format!("{what} does not exist"))
+    })
+}
+
+/// An event as its organizer sees it
+#[derive(Serialize, Debug)]
+pub struct EventResource {
+    id: Id,
+    name: Option<String>,
+    revision: u64,
+    /// Seconds since the Unix epoch
+    created: u64,
+    frozen: bool,
+    /// Whether guests can no longer change their response
+    responses_closed: bool,
+    date: Option<NaiveDate>,
+    start_time: Option<NaiveTime>,
+    end_time: Option<NaiveTime>,
+    timezone: Option<String>,
+    location: Option<String>,
+    rsvp_counts: RsvpCounts,
+    /// Guests who accepted and whoever they bring along
+    headcount: usize,
+    manage_link: String,
+    /// Extra columns of the guest list, each with `name` and `kind`
+    custom_fields: Vec<CustomField>,
+    /// In the guest list's order
+    attendees: Vec<AttendeeResource>,
+}
+
+impl From<Event> for EventResource {
+    fn from(mut event: Event) -> Self {
+        event.attendees.sort_by_key(|at| at.order);
+        Self {
+            id: event.id,
+            revision: event.revision,
+            created: unix_secs(event.created),
+            frozen: event.frozen,
+            responses_closed: event.responses_closed(clock::now()),
+            date: event.date,
+            start_time: event.start_time,
+            end_time: event.end_time,
+            rsvp_counts: event.rsvp_counts(),
+            headcount: event.headcount(),
+            manage_link: event.manage_link(),
+            attendees: event
+                .attendees
+                .iter()
+                .filter(|at| !at.anonymized)
+                .map(|at| AttendeeResource::of(&event, at))
+                .collect(),
+            custom_fields: event.custom_fields,
+            name: event.name,
+            timezone: event.timezone,
+            location: event.location,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct AttendeeResource {
+    id: Id,
+    name: String,
+    email: Option<String>,
+    rsvp: Rsvp,
+    plus_ones: u8,
+    time_slot: Option<u32>,
+    /// The organizer's own notes
+    notes: String,
+    /// What the guest wrote when accepting
+    comment: Option<String>,
+    language: Language,
+    invite_link: String,
+    /// Seconds since the Unix epoch
+    checked_in: Option<u64>,
+    /// Values of the event's custom fields by name, null where unset
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl AttendeeResource {
+    fn of(event: &Event, attendee: &Attendee) -> Self {
+        Self {
+            id: attendee.id,
+            name: attendee.name.clone(),
+            email: attendee.email.clone(),
+            rsvp: attendee.rsvp,
+            plus_ones: attendee.plus_ones,
+            time_slot: attendee.time_slot,
+            notes: attendee.notes.clone(),
+            comment: attendee.comment.clone(),
+            language: attendee.language,
+            invite_link: format!(
+                "{}/invite/attend/{}",
+                settings::public_url(),
+                attendee.id
+            ),
+            checked_in: attendee.checked_in.map(unix_secs),
+            fields: event.field_values(attendee),
+        }
+    }
+}
+
+/// Answer with a created resource and where it can be found
+fn created(location: String, body: impl Serialize) -> Response {
+    (
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(body),
+    )
+        .into_response()
+}
+
+async fn event_resource(ev_id: Id) -> Result<EventResource, ApiError> {
+    Ok(event_db::find_event_by_id(ev_id).await?.into())
+}
+
+/// `POST /invite/api/v1/events`, optionally with the fields of a patch to
+/// fill in right away
+pub async fn create_event(
+    patch: Option<Json<EventPatch>>,
+) -> Result<Response, ApiError> {
+    let ev_id = event_db::create_event().await?.id;
+    if let Some(Json(patch)) = patch {
+        event_db::patch_event(ev_id, patch).await?;
+    }
+    Ok(created(
+        format!("/invite/api/v1/events/{ev_id}"),
+        event_resource(ev_id).await?,
+    ))
+}
+
+/// `GET /invite/api/v1/events/{ev_id}`
+pub async fn get_event(
+    Path(id): Path<String>,
+) -> Result<Json<EventResource>, ApiError> {
+    let ev_id = decode(&id, "Event")?;
+    Ok(Json(event_resource(ev_id).await?))
+}
+
+/// `PATCH /invite/api/v1/events/{ev_id}`, answering with the changed event
+pub async fn patch_event(
+    Path(id): Path<String>,
+    Json(patch): Json<EventPatch>,
+) -> Result<Json<EventResource>, ApiError> {
+    let ev_id = decode(&id, "Event")?;
+    event_db::patch_event(ev_id, patch).await?;
+    Ok(Json(event_resource(ev_id).await?))
+}
+
+/// `POST /invite/api/v1/events/{ev_id}/attendees`
+pub async fn add_attendee(
+    Path(id): Path<String>,
+    new: Option<Json<NewAttendee>>,
+) -> Result<Response, ApiError> {
+    let ev_id = decode(&id, "Event")?;
+    let new = new.map(|Json(new)| new).unwrap_or_default();
+    let at_id = event_db::add_attendee(ev_id, new).await?;
+    let (event, attendee) = event_db::find_event_by_attendee(at_id).await?;
+    Ok(created(
+        format!("/invite/api/v1/events/{ev_id}/attendees/{at_id}"),
+        AttendeeResource::of(&event, &attendee),
+    ))
+}
+
+/// The attendee and their event, if it's the one given
+async fn find_attendee(
+    id: &str,
+    at_id: &str,
+) -> Result<(Event, Attendee), ApiError> {
+    let ev_id = decode(id, "Event")?;
+    let at_id = decode(at_id, "Attendee")?;
+    match event_db::find_event_by_attendee(at_id).await? {
+        (event, attendee) if event.id == ev_id => Ok((event, attendee)),
+        _ => Err(ApiError::new(
+            ErrorCode::NotFound,
+            "The event has no such attendee",
+        )),
+    }
+}
+
+/// `GET /invite/api/v1/events/{ev_id}/attendees/{at_id}`
+pub async fn get_attendee(
+    Path((id, at_id)): Path<(String, String)>,
+) -> Result<Json<AttendeeResource>, ApiError> {
+    let (event, attendee) = find_attendee(&id, &at_id).await?;
+    Ok(Json(AttendeeResource::of(&event, &attendee)))
+}
+
+/// `DELETE /invite/api/v1/events/{ev_id}/attendees/{at_id}`
+pub async fn remove_attendee(
+    Path((id, at_id)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let (_, attendee) = find_attendee(&id, &at_id).await?;
+    event_db::remove_attendee(attendee.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SetRsvp {
+    rsvp: Rsvp,
+    plus_ones: Option<u8>,
+    /// For events in appointment mode
+    time_slot: Option<u32>,
+    /// Why a guest isn't coming, for "no"
+    reason: Option<String>,
+    note: Option<String>,
+}
+
+/// What a guest's answer came to. Leaves out what only the organizer sees,
+/// since guests answer with their own ID.
+#[derive(Serialize, Debug)]
+pub struct RsvpResource {
+    attendee_id: Id,
+    rsvp: Rsvp,
+    plus_ones: u8,
+    time_slot: Option<u32>,
+    /// Seconds since the Unix epoch
+    answered: Option<u64>,
+}
+
+/// `PUT /invite/api/v1/attendees/{at_id}/rsvp`, answering an invitation as
+/// accepting, answering maybe, and withdrawing do
+pub async fn set_rsvp(
+    Path(at_id): Path<String>,
+    Json(answer): Json<SetRsvp>,
+) -> Result<Json<RsvpResource>, ApiError> {
+    let at_id = decode(&at_id, "Attendee")?;
+    if answer.rsvp == Rsvp::Pending {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "rsvp must be \"yes\", \"maybe\", or \"no\"",
+        ));
+    }
+    let decline = Decline {
+        reason: answer.reason,
+        note: answer.note,
+    };
+    event_db::set_rsvp(
+        at_id,
+        answer.rsvp,
+        answer.plus_ones,
+        answer.time_slot,
+        decline,
+    )
+    .await?;
+    let (_, attendee) = event_db::find_event_by_attendee(at_id).await?;
+    Ok(Json(RsvpResource {
+        attendee_id: attendee.id,
+        rsvp: attendee.rsvp,
+        plus_ones: attendee.plus_ones,
+        time_slot: attendee.time_slot,
+        answered: attendee
+            .rsvp_history
+            .last()
+            .map(|change| unix_secs(change.at)),
+    }))
+}
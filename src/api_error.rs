@@ -0,0 +1,190 @@
+//! Machine-readable errors for API callers
+//!
+//! Handlers which answer scripts rather than browsers report failures as an
+//! `ApiError`, serialized as
+//!
+//! ```json
+//! {"code": "not_found", "message": "...", "details": null, "request_id": "..."}
+//! ```
+//!
+//! `code` is stable and meant to be branched on. `message` is for people and
+//! may change. `details` carries extra data for some codes, e.g., the current
+//! state of the event for `conflict`. `request_id` matches the `x-request-id`
+//! response header, for finding the request in the logs.
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    event_db::{CreateEventError, FindEventError, RsvpError, UpdateEventError},
+    templates::ManagePageJson,
+};
+
+tokio::task_local! {
+    /// ID of the request currently being handled
+    static REQUEST_ID: Option<String>;
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The event or attendee doesn't exist
+    NotFound,
+    /// The request was malformed or named something invalid
+    InvalidRequest,
+    /// The event changed since the caller last saw it
+    Conflict,
+    /// The event is frozen and can't be changed
+    Frozen,
+    /// Responses to the event are closed, or it has no room left
+    Closed,
+    /// The server isn't taking new events
+    Full,
+    /// Missing or wrong credentials
+    Unauthorized,
+    /// Too many requests, try again later
+    RateLimited,
+    /// The database couldn't be read or written
+    DatabaseUnavailable,
+    /// Anything else which went wrong on the server
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::Frozen => StatusCode::FORBIDDEN,
+            ErrorCode::Closed => StatusCode::CONFLICT,
+            ErrorCode::Full => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::DatabaseUnavailable | ErrorCode::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    /// Filled in when the response is built
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            request_id: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(mut self) -> Response {
+        if self.request_id.is_none() {
+            self.request_id = REQUEST_ID.try_with(Clone::clone).ok().flatten();
+        }
+        (self.code.status(), Json(self)).into_response()
+    }
+}
+
+impl From<FindEventError> for ApiError {
+    fn from(value: FindEventError) -> Self {
+        match value {
+            FindEventError::Database(e) => {
+                ApiError::new(ErrorCode::DatabaseUnavailable, e)
+            }
+            FindEventError::NotFound(e) => {
+                ApiError::new(ErrorCode::NotFound, e)
+            }
+        }
+    }
+}
+
+impl From<CreateEventError> for ApiError {
+    fn from(value: CreateEventError) -> Self {
+        match value {
+            CreateEventError::Database(e) => {
+                ApiError::new(ErrorCode::DatabaseUnavailable, e)
+            }
+            CreateEventError::Full => ApiError::new(
+                ErrorCode::Full,
+                "The server isn't taking new events right now",
+            ),
+            CreateEventError::NotFound(e) => {
+                ApiError::new(ErrorCode::NotFound, e)
+            }
+        }
+    }
+}
+
+impl From<RsvpError> for ApiError {
+    fn from(value: RsvpError) -> Self {
+        match value {
+            RsvpError::Find(e) => e.into(),
+            RsvpError::Closed => ApiError::new(
+                ErrorCode::Closed,
+                "Responses to the event are closed",
+            ),
+            RsvpError::Full => {
+                ApiError::new(ErrorCode::Closed, "The event has no room left")
+            }
+            RsvpError::SlotRequired => ApiError::new(
+                ErrorCode::InvalidRequest,
+                "The event takes appointments, so time_slot is required",
+            ),
+            RsvpError::SlotFull => ApiError::new(
+                ErrorCode::Closed,
+                "The time slot picked has no room left",
+            ),
+        }
+    }
+}
+
+impl From<UpdateEventError> for ApiError {
+    fn from(value: UpdateEventError) -> Self {
+        match value {
+            UpdateEventError::Find(e) => e.into(),
+            UpdateEventError::Conflict(event) => ApiError::new(
+                ErrorCode::Conflict,
+                "The event changed since it was loaded",
+            )
+            .with_details(ManagePageJson::from(event.as_ref())),
+            UpdateEventError::Frozen => ApiError::new(
+                ErrorCode::Frozen,
+                "The event is archived and can't be changed",
+            ),
+        }
+    }
+}
+
+/// Middleware making the request's ID available to errors built while handling
+/// it. Must run inside the layer which sets `x-request-id`.
+pub async fn track_request_id(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    REQUEST_ID.scope(request_id, next.run(req)).await
+}
@@ -0,0 +1,48 @@
+//! Cold storage for purged events
+//!
+//! In archive purge mode, expired events are written to their own zstd
+//! compressed CBOR file in `archive/` before being removed from the live
+//! database. `database-debug unarchive <ID>` brings one back.
+
+use std::path::{Path, PathBuf};
+
+use crate::{event_db::Event, ids::Id};
+
+pub const ARCHIVE_DIR: &str = "archive";
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// Location of the archive file for an event
+pub fn archive_path(dir: &Path, id: Id) -> PathBuf {
+    dir.join(format!("{id}.cbor.zst"))
+}
+
+/// Write an event to the archive directory, creating it if needed
+pub async fn write(event: &Event) -> Result<PathBuf, String> {
+    let dir = Path::new(ARCHIVE_DIR);
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Could not create archive directory: {e}"))?;
+
+    let data = serde_cbor::to_vec(event)
+        .map_err(|e| format!("Could not serialize event: {e}"))?;
+    let compressed = zstd::encode_all(data.as_slice(), COMPRESSION_LEVEL)
+        .map_err(|e| format!("Could not compress event: {e}"))?;
+
+    let path = archive_path(dir, event.id);
+    tokio::fs::write(&path, compressed)
+        .await
+        .map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+/// Read an archived event back
+pub fn read(path: &Path) -> Result<Event, String> {
+    let compressed = std::fs::read(path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    let data = zstd::decode_all(compressed.as_slice())
+        .map_err(|e| format!("Could not decompress archive: {e}"))?;
+    let mut event: Event = serde_cbor::from_slice(&data)
+        .map_err(|e| format!("Could not parse archived event: {e}"))?;
+    event.upgrade();
+    Ok(event)
+}
@@ -0,0 +1,257 @@
+//! Triggers and actions for no-code automation platforms
+//!
+//! Platforms like Zapier and IFTTT poll for new items and act through simple
+//! calls, so these routes are shaped for them rather than for the manage
+//! page: every call is authenticated with the event's API key alone, made on
+//! the manage page, sent as `X-Api-Key: <key>` or `Authorization: Bearer
+//! <key>`. Triggers list items newest first, each with an `id` which never
+//! changes, so platforms can tell which items they've seen. Actions answer
+//! with the item they created or changed, in the same shape as the triggers.
+
+use std::{cmp::Reverse, time::SystemTime};
+
+use axum::{
+    extract::Query,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::{ApiError, ErrorCode},
+    event_db::{self, Attendee, Decline, Event, FindEventError, Rsvp},
+    ids::{self, Id},
+    settings,
+    templates::NewAttendee,
+};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+
+/// The event the request's API key belongs to
+async fn authenticate(headers: &HeaderMap) -> Result<Event, ApiError> {
+    let key = headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(axum::http::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        })
+        .filter(|key| !key.is_empty());
+    let unauthorized =
+        || ApiError::new(ErrorCode::Unauthorized, "Missing or invalid API key");
+    let key = key.ok_or_else(unauthorized)?;
+    event_db::find_event_by_api_key(key)
+        .await
+        .map_err(|e| match e {
+            FindEventError::NotFound(_) => unauthorized(),
+            e => e.into(),
+        })
+}
+
+fn rfc_3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventItem {
+    id: Id,
+    name: String,
+}
+
+/// `GET /invite/automation/me`, for platforms to test the key with
+pub async fn me(headers: HeaderMap) -> Result<Json<EventItem>, ApiError> {
+    let event = authenticate(&headers).await?;
+    Ok(Json(EventItem {
+        id: event.id,
+        name: event.display_name(),
+    }))
+}
+
+#[derive(Serialize, Debug)]
+pub struct AttendeeItem {
+    id: Id,
+    name: String,
+    email: Option<String>,
+    rsvp: Rsvp,
+    plus_ones: u8,
+    invite_link: String,
+}
+
+impl From<&Attendee> for AttendeeItem {
+    fn from(attendee: &Attendee) -> Self {
+        Self {
+            id: attendee.id,
+            name: attendee.name.clone(),
+            email: attendee.email.clone(),
+            rsvp: attendee.rsvp,
+            plus_ones: attendee.plus_ones,
+            invite_link: format!(
+                "{}/invite/attend/{}",
+                settings::public_url(),
+                attendee.id
+            ),
+        }
+    }
+}
+
+/// An answer a guest gave
+#[derive(Serialize, Debug)]
+pub struct RsvpItem {
+    /// The guest's ID and when they answered
+    id: String,
+    /// Pass the largest seen as `since` to get only newer answers
+    cursor: u64,
+    attendee_id: Id,
+    name: String,
+    email: Option<String>,
+    rsvp: Rsvp,
+    /// How many people the guest brings along now, which may have changed
+    /// since this answer
+    plus_ones: u8,
+    /// When the guest answered, in RFC 3339
+    at: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RsvpQuery {
+    /// Only answers after this cursor
+    since: Option<u64>,
+    limit: Option<usize>,
+}
+
+/// `GET /invite/automation/rsvps?since=&limit=`, a polling trigger for new
+/// answers, newest first
+pub async fn recent_rsvps(
+    headers: HeaderMap,
+    Query(query): Query<RsvpQuery>,
+) -> Result<Json<Vec<RsvpItem>>, ApiError> {
+    let event = authenticate(&headers).await?;
+    let since = query.since.unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut items: Vec<RsvpItem> = event
+        .attendees
+        .iter()
+        .filter(|at| !at.anonymized)
+        .flat_map(|at| {
+            at.rsvp_history.iter().map(move |change| {
+                let cursor = millis(change.at);
+                RsvpItem {
+                    id: format!("{}-{cursor}", at.id),
+                    cursor,
+                    attendee_id: at.id,
+                    name: at.name.clone(),
+                    email: at.email.clone(),
+                    rsvp: change.rsvp,
+                    plus_ones: at.plus_ones,
+                    at: rfc_3339(change.at),
+                }
+            })
+        })
+        .filter(|item| item.cursor > since)
+        .collect();
+    items.sort_by_key(|item| Reverse(item.cursor));
+    items.truncate(limit);
+    Ok(Json(items))
+}
+
+/// `GET /invite/automation/attendees`, every guest in the guest list's order
+pub async fn list_attendees(
+    headers: HeaderMap,
+) -> Result<Json<Vec<AttendeeItem>>, ApiError> {
+    let event = authenticate(&headers).await?;
+    Ok(Json(
+        event
+            .attendees
+            .iter()
+            .filter(|at| !at.anonymized)
+            .map(AttendeeItem::from)
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewAttendeeAction {
+    name: String,
+    email: Option<String>,
+}
+
+/// `POST /invite/automation/attendees`, an action adding a guest
+pub async fn create_attendee(
+    headers: HeaderMap,
+    Json(action): Json<NewAttendeeAction>,
+) -> Result<(StatusCode, Json<AttendeeItem>), ApiError> {
+    let event = authenticate(&headers).await?;
+    let new = NewAttendee {
+        name: Some(action.name),
+        email: action.email,
+        template: None,
+    };
+    let at_id = event_db::add_attendee(event.id, new).await?;
+    let (_, attendee) = event_db::find_event_by_attendee(at_id).await?;
+    Ok((StatusCode::CREATED, Json(AttendeeItem::from(&attendee))))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SetRsvpAction {
+    /// Either this or `email` names the guest
+    attendee_id: Option<String>,
+    email: Option<String>,
+    rsvp: Rsvp,
+    plus_ones: Option<u8>,
+    /// For events in appointment mode
+    time_slot: Option<u32>,
+}
+
+/// `POST /invite/automation/rsvp`, an action answering for a guest
+pub async fn set_rsvp(
+    headers: HeaderMap,
+    Json(action): Json<SetRsvpAction>,
+) -> Result<Json<AttendeeItem>, ApiError> {
+    let event = authenticate(&headers).await?;
+    if action.rsvp == Rsvp::Pending {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "rsvp must be \"yes\", \"maybe\", or \"no\"",
+        ));
+    }
+    let attendee_id = action
+        .attendee_id
+        .as_deref()
+        .map(|id| ids::decode_id(id).ok());
+    let email = action.email.as_deref().map(str::trim);
+    let attendee = event
+        .attendees
+        .iter()
+        .filter(|at| !at.anonymized)
+        .find(|at| match (attendee_id, email) {
+            (Some(id), _) => id == Some(at.id),
+            (None, Some(email)) => at
+                .email
+                .as_deref()
+                .is_some_and(|e| e.trim().eq_ignore_ascii_case(email)),
+            (None, None) => false,
+        })
+        .ok_or_else(|| {
+            ApiError::new(ErrorCode::NotFound, "The event has no such guest")
+        })?;
+    event_db::set_rsvp(
+        attendee.id,
+        action.rsvp,
+        action.plus_ones,
+        action.time_slot,
+        Decline::default(),
+    )
+    .await?;
+    let (_, attendee) = event_db::find_event_by_attendee(attendee.id).await?;
+    Ok(Json(AttendeeItem::from(&attendee)))
+}
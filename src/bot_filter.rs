@@ -0,0 +1,91 @@
+//! Heuristics for telling guests apart from bots on the RSVP endpoints
+//!
+//! Mail scanners and link previewers follow links and sometimes submit forms
+//! in invitations. Built-in RSVP forms, see `rsvp_form`, carry a honeypot
+//! field which people never see and the time the form was rendered. A
+//! submission is flagged if the honeypot is filled in, if it arrives
+//! implausibly fast, or if the user agent looks automated. Flagged requests
+//! don't change any state.
+
+use axum::http::{header, HeaderMap};
+
+use crate::{
+    clock::{self, unix_secs},
+    rsvp_form::RsvpForm,
+};
+
+/// Fastest a person can plausibly read an invitation and click a button
+const MIN_FORM_SECS: u64 = 2;
+
+/// Substrings of user agents belonging to crawlers, scanners, and scripts
+const BOT_USER_AGENTS: &[&str] = &[
+    "bot",
+    "crawler",
+    "spider",
+    "preview",
+    "scanner",
+    "facebookexternalhit",
+    "curl",
+    "wget",
+    "python-requests",
+    "python-urllib",
+    "go-http-client",
+    "headlesschrome",
+    "barracuda",
+    "proofpoint",
+    "mimecast",
+    "ms-office",
+    "microsoft office",
+];
+
+/// Why a request was judged to be automated
+#[derive(Clone, Copy, Debug)]
+pub enum Verdict {
+    Human,
+    Honeypot,
+    TooFast,
+    BotUserAgent,
+}
+
+impl Verdict {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Verdict::Human => "none",
+            Verdict::Honeypot => "filled in the hidden honeypot field",
+            Verdict::TooFast => "submitted faster than a person could",
+            Verdict::BotUserAgent => "user agent looks automated",
+        }
+    }
+}
+
+fn check_user_agent(headers: &HeaderMap) -> Verdict {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    if user_agent.is_empty()
+        || BOT_USER_AGENTS.iter().any(|bot| user_agent.contains(bot))
+    {
+        return Verdict::BotUserAgent;
+    }
+    Verdict::Human
+}
+
+/// Judge a plain page fetch, which has nothing but headers to go on
+pub fn check_link(headers: &HeaderMap) -> Verdict {
+    check_user_agent(headers)
+}
+
+/// Judge a form submission
+pub fn check_form(headers: &HeaderMap, form: &RsvpForm) -> Verdict {
+    if !form.honeypot.is_empty() {
+        return Verdict::Honeypot;
+    }
+    let now = unix_secs(clock::now());
+    match form.t {
+        Some(t) if now.saturating_sub(t) >= MIN_FORM_SECS => {}
+        _ => return Verdict::TooFast,
+    }
+    check_user_agent(headers)
+}
@@ -0,0 +1,179 @@
+//! Publishing events to a CalDAV calendar
+//!
+//! With `--caldav-url` pointing at a calendar collection, e.g., a Nextcloud
+//! calendar, every event with a date is kept there as an entry of its own,
+//! named after the event's ID. Entries describe how many guests are coming
+//! and are updated when the event or its responses change. Events which lose
+//! their date or are deleted are removed from the calendar.
+//!
+//! What was published is only remembered while the server runs, so every
+//! event is published again after a restart, and events deleted while it was
+//! down stay in the calendar. Failed requests are tried again with the next
+//! change, or after a while without one.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+
+use reqwest::{header, Method, RequestBuilder};
+
+use crate::{
+    event_db::{self, Event, EventOrder},
+    ical,
+    ids::Id,
+};
+
+/// How long changes may keep coming before the calendar is updated
+const SETTLE: Duration = Duration::from_secs(5);
+/// How long to wait before trying failed requests again without a change
+const RETRY_AFTER: Duration = Duration::from_mins(15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+static CALENDAR: OnceLock<Calendar> = OnceLock::new();
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+
+/// The calendar collection events are published to
+#[derive(Clone, Debug)]
+pub struct Calendar {
+    /// Address of the collection, e.g.,
+    /// "https://cloud.example.org/remote.php/dav/calendars/me/family/"
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+pub fn initialize(calendar: Calendar) {
+    if CALENDAR.set(calendar).is_err() {
+        log::warn!("CalDAV calendar was already set");
+    }
+}
+
+/// What an event's entry was published with. The entry is published again
+/// once it differs.
+#[derive(PartialEq, Eq, Debug)]
+struct Published {
+    revision: u64,
+    headcount: usize,
+    /// Guests who haven't answered, said yes, maybe, and no
+    answers: [usize; 4],
+}
+
+impl Published {
+    fn of(event: &Event) -> Self {
+        let counts = event.rsvp_counts();
+        Self {
+            revision: event.revision,
+            headcount: event.headcount(),
+            answers: [counts.pending, counts.yes, counts.maybe, counts.no],
+        }
+    }
+}
+
+/// Keep the calendar in step with the database. Returns right away without
+/// a calendar.
+pub async fn sync_task() {
+    let Some(calendar) = CALENDAR.get() else {
+        return;
+    };
+    let mut published: HashMap<Id, Published> = HashMap::new();
+    let mut changes = event_db::watch_changes();
+    changes.mark_changed();
+    loop {
+        match tokio::time::timeout(RETRY_AFTER, changes.changed()).await {
+            Ok(Ok(())) => tokio::time::sleep(SETTLE).await,
+            // the database thread is gone
+            Ok(Err(_)) => return,
+            Err(_) => {}
+        }
+        changes.mark_unchanged();
+        let events = match event_db::list_events(EventOrder::OldestFirst).await
+        {
+            Ok(events) => events,
+            Err(e) => {
+                log::warn!("Could not read events to publish: {e}");
+                continue;
+            }
+        };
+        sync(calendar, &events, &mut published).await;
+    }
+}
+
+async fn sync(
+    calendar: &Calendar,
+    events: &[Event],
+    published: &mut HashMap<Id, Published>,
+) {
+    let mut dated = HashSet::with_capacity(events.len());
+    for event in events {
+        let Some(entry) = ical::organizer_calendar(event) else {
+            continue;
+        };
+        dated.insert(event.id);
+        let current = Published::of(event);
+        if published.get(&event.id) == Some(&current) {
+            continue;
+        }
+        let request = calendar
+            .request(Method::PUT, event.id)
+            .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+            .body(entry);
+        match send(request, false).await {
+            Ok(()) => {
+                published.insert(event.id, current);
+            }
+            Err(e) => log::warn!("Could not publish event {}: {e}", event.id),
+        }
+    }
+    let gone: Vec<Id> = published
+        .keys()
+        .filter(|id| !dated.contains(id))
+        .copied()
+        .collect();
+    for id in gone {
+        match send(calendar.request(Method::DELETE, id), true).await {
+            Ok(()) => {
+                published.remove(&id);
+            }
+            Err(e) => log::warn!("Could not unpublish event {id}: {e}"),
+        }
+    }
+}
+
+impl Calendar {
+    /// A request on the entry of the given event
+    fn request(&self, method: Method, ev_id: Id) -> RequestBuilder {
+        let url = format!(
+            "{}/{}.ics",
+            self.url.trim_end_matches('/'),
+            ev_id.encode()
+        );
+        let request = CLIENT.request(method, url);
+        match &self.username {
+            Some(username) => {
+                request.basic_auth(username, self.password.as_deref())
+            }
+            None => request,
+        }
+    }
+}
+
+/// Send a request, which succeeds if the server says so. With `gone_ok`, it
+/// also succeeds if the entry doesn't exist, e.g., when deleting it.
+async fn send(request: RequestBuilder, gone_ok: bool) -> Result<(), String> {
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if status.is_success()
+        || gone_ok && status == reqwest::StatusCode::NOT_FOUND
+    {
+        Ok(())
+    } else {
+        Err(format!("The server answered {status}"))
+    }
+}
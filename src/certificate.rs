@@ -0,0 +1,137 @@
+//! Printable RSVP confirmations
+//!
+//! Guests who accepted can download a one-page PDF confirming it, e.g., to
+//! show at a venue's gate. Besides the event and the guest's name, it carries
+//! a QR code of the guest's invitation, which shows whether they're still
+//! coming when scanned. The PDF only uses the fonts built into every PDF
+//! reader, so text is limited to Windows-1252, which covers every language
+//! guests can be given.
+
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, Rect};
+use qrcode::{Color, QrCode};
+
+use crate::{
+    clock,
+    event_db::{Attendee, Event},
+    settings, templates,
+};
+
+// A4 portrait
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 25.0;
+/// Width of the QR code in mm
+const QR_SIDE: f32 = 60.0;
+/// Longest line of the event's and guest's name before wrapping, in
+/// characters. The built-in fonts come without metrics, so this is a guess
+/// which fits Helvetica at the headline size.
+const HEADLINE_CHARS: usize = 38;
+/// Likewise for lines at the body size
+const BODY_CHARS: usize = 76;
+
+/// The guest's confirmation as a PDF. Fails if the PDF couldn't be written.
+pub fn render(
+    event: &Event,
+    attendee: &Attendee,
+    id: &str,
+) -> Result<Vec<u8>, String> {
+    let language = attendee.language;
+    let event_name = event.name.as_deref().unwrap_or("Untitled Event");
+    let t = language.phrases(event_name);
+
+    let (doc, page, layer) = PdfDocument::new(
+        format!("{} | {event_name}", t.certificate_title),
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "Confirmation",
+    );
+    let layer = doc.get_page(page).get_layer(layer);
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| e.to_string())?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| e.to_string())?;
+
+    let mut y = PAGE_HEIGHT - MARGIN;
+    let mut line = |text: &str, size: f32, font: &IndirectFontRef| {
+        // a line is about 0.5 mm per point of font size
+        y -= size * 0.5;
+        // an empty line only adds space
+        if !text.is_empty() {
+            layer.use_text(text, size, Mm(MARGIN), Mm(y), font);
+        }
+    };
+    line(t.certificate_title, 14.0, &regular);
+    for part in wrap(event_name, HEADLINE_CHARS) {
+        line(&part, 24.0, &bold);
+    }
+    line("", 12.0, &regular);
+    for part in wrap(&attendee.name, HEADLINE_CHARS) {
+        line(&part, 24.0, &bold);
+    }
+    line(t.certificate_accepted, 14.0, &regular);
+    line("", 12.0, &regular);
+    if let Some(when) = event.describe_when(language) {
+        line(&format!("{} {when}", t.when), 12.0, &regular);
+    } else if let Some(start) = event.starts_at() {
+        let starts =
+            format!("{} {}", t.certificate_starts, language.date_time(start));
+        line(&starts, 12.0, &regular);
+    }
+    if let Some(location) = &event.location {
+        for part in wrap(&format!("{} {location}", t.location), BODY_CHARS) {
+            line(&part, 12.0, &regular);
+        }
+    }
+    let time_slot = templates::booked_slot(event, attendee);
+    if !time_slot.is_empty() {
+        line(&format!("{} {time_slot}", t.your_slot), 12.0, &regular);
+    }
+
+    let code =
+        QrCode::new(format!("{}/invite/attend/{id}", settings::public_url()))
+            .map_err(|e| e.to_string())?;
+    let modules = code.width();
+    let module = QR_SIDE / modules as f32;
+    let top = y - 10.0;
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != Color::Dark {
+            continue;
+        }
+        let x = MARGIN + (i % modules) as f32 * module;
+        let y = top - (i / modules + 1) as f32 * module;
+        layer.add_rect(Rect::new(Mm(x), Mm(y), Mm(x + module), Mm(y + module)));
+    }
+
+    let mut y = top - QR_SIDE - 10.0;
+    let issued = format!(
+        "{} {}",
+        t.certificate_issued,
+        language.date_time(clock::now())
+    );
+    for text in [t.certificate_scan, &issued] {
+        y -= 6.0;
+        layer.use_text(text, 10.0, Mm(MARGIN), Mm(y), &regular);
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+/// Break text into lines of at most `width` characters between words. Words
+/// longer than that get a line of their own.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line)
+                if line.chars().count() + 1 + word.chars().count() <= width =>
+            {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
@@ -2,8 +2,20 @@
 //!
 //! Adding attributes to this structure will add CLI options
 
+use chrono::NaiveTime;
 use clap::Parser;
-use std::net::IpAddr;
+use ipnet::IpNet;
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+use crate::{
+    clock,
+    event_db::{PurgeMode, Storage},
+    ids::IdFormat,
+    integrity::OnCorruption,
+    mail,
+    network::{self, ForwardedHeader},
+    settings, shortlink,
+};
 
 const DEFAULT_LOG_LEVEL: &str = "INFO";
 const DEFAULT_BIND: &str = "127.0.0.1";
@@ -27,4 +39,273 @@ pub struct Args {
     /// The port of the Slot server on localhost e.g., "7568"
     #[arg(short = 's', long = "slot-addr")]
     pub slot_port: Option<u16>,
+
+    /// Without a Slot server, write the bound address to this JSON file so
+    /// other local services can find the module
+    #[arg(long = "announce-file", conflicts_with = "slot_port")]
+    pub announce_file: Option<PathBuf>,
+
+    /// Sentry-compatible DSN to report panics and server errors to e.g.,
+    /// "https://key@sentry.example.com/1"
+    #[arg(long = "sentry-dsn")]
+    pub sentry_dsn: Option<String>,
+
+    /// Format of newly generated event and attendee IDs
+    #[arg(long = "id-format", value_enum, default_value_t = IdFormat::Random)]
+    pub id_format: IdFormat,
+
+    /// Bearer token for the admin routes. Admin routes are disabled if unset
+    #[arg(long = "admin-token", env = "INVITE_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Origin operators open the admin pages at, e.g.,
+    /// "https://blacepos.xyz". Enables signing in to them with a passkey.
+    #[arg(long = "admin-passkey-origin", env = "INVITE_ADMIN_PASSKEY_ORIGIN")]
+    pub admin_passkey_origin: Option<String>,
+
+    /// Print a one-time link for registering an admin passkey, e.g., the
+    /// first one
+    #[arg(long = "register-admin-passkey", requires = "admin_passkey_origin")]
+    pub register_admin_passkey: bool,
+
+    /// OpenID Connect provider to sign organizers and operators in with,
+    /// e.g., "https://auth.example.org/realms/home"
+    #[arg(
+        long = "oidc-issuer",
+        env = "INVITE_OIDC_ISSUER",
+        requires_all = ["oidc_client_id", "oidc_client_secret"]
+    )]
+    pub oidc_issuer: Option<String>,
+
+    /// Client ID this module is registered with at the provider
+    #[arg(long = "oidc-client-id", env = "INVITE_OIDC_CLIENT_ID")]
+    pub oidc_client_id: Option<String>,
+
+    /// Client secret this module is registered with at the provider
+    #[arg(long = "oidc-client-secret", env = "INVITE_OIDC_CLIENT_SECRET")]
+    pub oidc_client_secret: Option<String>,
+
+    /// Email addresses or subjects of the provider's users who may open the
+    /// admin pages. Repeat or separate with commas.
+    #[arg(
+        long = "oidc-admin",
+        value_delimiter = ',',
+        requires = "oidc_issuer"
+    )]
+    pub oidc_admins: Vec<String>,
+
+    /// Networks which may reach the admin routes, e.g., "10.0.0.0/8".
+    /// Repeat or separate with commas. Any client may if unset.
+    #[arg(
+        long = "admin-allow",
+        value_delimiter = ',',
+        value_parser = network::parse_network
+    )]
+    pub admin_allow: Vec<IpNet>,
+
+    /// Proxy header to take the client's address from. Only believed when
+    /// sent by a --trusted-proxy.
+    #[arg(
+        long = "forwarded-header",
+        value_enum,
+        default_value_t = ForwardedHeader::None
+    )]
+    pub forwarded_header: ForwardedHeader,
+
+    /// Proxies whose forwarded header is believed, e.g., the Slot server.
+    /// Repeat or separate with commas.
+    #[arg(
+        long = "trusted-proxy",
+        value_delimiter = ',',
+        value_parser = network::parse_network,
+        default_values = ["127.0.0.1", "::1"]
+    )]
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Bearer token external systems send when posting RSVPs they collected.
+    /// The inbound endpoint is disabled if unset
+    #[arg(long = "inbound-token", env = "INVITE_INBOUND_TOKEN")]
+    pub inbound_token: Option<String>,
+
+    /// URL which receives every organizer notification as a JSON POST
+    #[arg(long = "notify-webhook")]
+    pub notify_webhook: Option<String>,
+
+    /// Register every guest's invitation link with this URL shortener and
+    /// show the short link on the manage page
+    #[arg(long = "shortener", value_enum)]
+    pub shortener: Option<shortlink::Service>,
+
+    /// Where the shortener's API is, for self-hosted ones
+    #[arg(long = "shortener-url", requires = "shortener")]
+    pub shortener_url: Option<String>,
+
+    /// API key, token, or signature for the shortener
+    #[arg(
+        long = "shortener-key",
+        env = "INVITE_SHORTENER_KEY",
+        requires = "shortener"
+    )]
+    pub shortener_key: Option<String>,
+
+    /// CalDAV calendar collection to publish events with a date to, e.g.,
+    /// "https://cloud.example.org/remote.php/dav/calendars/me/family/"
+    #[arg(long = "caldav-url")]
+    pub caldav_url: Option<String>,
+
+    /// User name for the CalDAV calendar
+    #[arg(long = "caldav-user", requires = "caldav_url")]
+    pub caldav_user: Option<String>,
+
+    /// Password or app password for the CalDAV calendar
+    #[arg(
+        long = "caldav-password",
+        env = "INVITE_CALDAV_PASSWORD",
+        requires = "caldav_url"
+    )]
+    pub caldav_password: Option<String>,
+
+    /// SMTP server to email organizers about responses through. Organizers
+    /// are only emailed if they give their address on the manage page.
+    #[arg(long = "smtp-host", requires = "smtp_from")]
+    pub smtp_host: Option<String>,
+
+    /// Port of the SMTP server. Defaults to the usual port for the security.
+    #[arg(long = "smtp-port", requires = "smtp_host")]
+    pub smtp_port: Option<u16>,
+
+    /// How the connection to the SMTP server is secured
+    #[arg(
+        long = "smtp-security",
+        value_enum,
+        default_value_t = mail::Security::Starttls
+    )]
+    pub smtp_security: mail::Security,
+
+    /// User name for the SMTP server
+    #[arg(long = "smtp-user", requires = "smtp_host")]
+    pub smtp_user: Option<String>,
+
+    /// Password for the SMTP server
+    #[arg(
+        long = "smtp-password",
+        env = "INVITE_SMTP_PASSWORD",
+        requires = "smtp_host"
+    )]
+    pub smtp_password: Option<String>,
+
+    /// Sender of emails, e.g., "Invites <invite@example.org>"
+    #[arg(long = "smtp-from", requires = "smtp_host")]
+    pub smtp_from: Option<String>,
+
+    /// What to do when the database fails the startup integrity check
+    #[arg(
+        long = "on-corruption",
+        value_enum,
+        default_value_t = OnCorruption::Refuse
+    )]
+    pub on_corruption: OnCorruption,
+
+    /// Largest the database should grow, in MiB. Warnings are logged from 90%
+    /// of the limit on.
+    #[arg(long = "max-db-size")]
+    pub max_db_mib: Option<u64>,
+
+    /// Turn away new events once the database reaches --max-db-size
+    #[arg(long = "refuse-when-full", requires = "max_db_mib")]
+    pub refuse_when_full: bool,
+
+    /// How the database is kept on disk. Switching to sqlite moves the
+    /// events of an existing events.db in on the next start.
+    #[arg(long = "storage", value_enum, default_value_t = Storage::Cbor)]
+    pub storage: Storage,
+
+    /// Compress the database file with zstd. Files are read either way.
+    #[arg(long = "compress-db")]
+    pub compress_db: bool,
+
+    /// Share the database file with other instances, e.g., for blue-green
+    /// deploys. Each operation locks the file and picks up changes made by
+    /// the others, so saves are no longer batched.
+    #[arg(long = "shared-storage")]
+    pub shared_storage: bool,
+
+    /// How long events are kept after creation or their last extension, e.g.,
+    /// "90d". Units are w, d, h, m, and s.
+    #[arg(
+        long = "event-lifetime",
+        env = "INVITE_EVENT_LIFETIME",
+        default_value = "90d",
+        value_parser = clock::parse_period
+    )]
+    pub event_lifetime: Duration,
+
+    /// Time between purges of expired events, e.g., "1d"
+    #[arg(
+        long = "purge-period",
+        env = "INVITE_PURGE_PERIOD",
+        default_value = "1d",
+        value_parser = clock::parse_period
+    )]
+    pub purge_period: Duration,
+
+    /// Local time of day to purge expired events at, e.g., "04:00", instead
+    /// of every --purge-period
+    #[arg(
+        long = "purge-at",
+        env = "INVITE_PURGE_AT",
+        conflicts_with = "purge_period",
+        value_parser = clock::parse_time_of_day
+    )]
+    pub purge_at: Option<NaiveTime>,
+
+    /// Time before a failed purge is tried again, e.g., "1m"
+    #[arg(
+        long = "purge-retry-period",
+        env = "INVITE_PURGE_RETRY_PERIOD",
+        default_value = "1m",
+        value_parser = clock::parse_period
+    )]
+    pub purge_retry_period: Duration,
+
+    /// Let organizers pin their own events so they're never purged. Without
+    /// this, only the admin API can pin events.
+    #[arg(long = "organizer-pinning")]
+    pub organizer_pinning: bool,
+
+    /// What to do with expired events
+    #[arg(long = "purge-mode", value_enum, default_value_t = PurgeMode::Delete)]
+    pub purge_mode: PurgeMode,
+
+    /// Hours before a guest who still hasn't opened their invitation can be
+    /// nudged again
+    #[arg(long = "nudge-cooldown", default_value_t = 72)]
+    pub nudge_cooldown_hours: u64,
+
+    /// Put the event's schema.org JSON-LD on invitations too, not just the
+    /// thanks page
+    #[arg(long = "json-ld-on-invitations")]
+    pub json_ld_on_invitations: bool,
+
+    /// Show guests the weather forecast from Open-Meteo for events whose
+    /// organizer set a forecast location
+    #[arg(long = "weather-forecasts")]
+    pub weather_forecasts: bool,
+
+    /// Change a setting, e.g., "event_lifetime=30d", and keep it across
+    /// restarts. Repeat for more. An empty value returns the setting to the
+    /// option it overrides.
+    #[arg(long = "setting", value_parser = settings::parse_assignment)]
+    pub settings: Vec<(String, String)>,
+
+    /// Show this notice atop every page, e.g., "Down for maintenance Sunday
+    /// 02:00-04:00 UTC", until it's changed on the admin dashboard. An empty
+    /// text removes the banner.
+    #[arg(long = "banner")]
+    pub banner: Option<String>,
+
+    /// Directory of Tera templates replacing the built-in thanks, withdraw, and
+    /// manage pages. Changes are picked up without a restart.
+    #[arg(long = "live-templates")]
+    pub live_templates: Option<PathBuf>,
 }
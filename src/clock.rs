@@ -0,0 +1,356 @@
+//! Source of the current time
+//!
+//! Everything that compares against the current time (expiry, timestamps on
+//! new records) reads it through `now` rather than `SystemTime::now`, and
+//! everything that waits for time to pass (purges, digests, settling RSVPs)
+//! waits through `sleep` rather than `tokio::time::sleep`. The clock can be
+//! swapped out with `set_clock` to drive time by hand: with a `ManualClock`,
+//! advancing the clock wakes whatever was waiting for that time.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, LazyLock, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime};
+use tokio::sync::watch;
+
+/// Longest `sleep_until` goes without looking at the wall clock
+const WALL_CLOCK_CHECK: Duration = Duration::from_mins(1);
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Wait until `duration` has passed on this clock
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// The real wall clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock which only moves when told to
+pub struct ManualClock {
+    time: watch::Sender<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            time: watch::Sender::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.time.send_modify(|time| *time += by);
+    }
+
+    pub fn set(&self, to: SystemTime) {
+        self.time.send_replace(to);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.time.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let mut time = self.time.subscribe();
+        let until = *time.borrow() + duration;
+        Box::pin(async move {
+            // only fails once the clock is gone, and then nothing will wake us
+            let _ = time.wait_for(|now| *now >= until).await;
+        })
+    }
+}
+
+static CLOCK: LazyLock<RwLock<Arc<dyn Clock>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(SystemClock)));
+
+/// Replace the clock used by the rest of the module
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *CLOCK.write().expect("Clock lock is not poisoned") = clock;
+}
+
+/// The current time according to the installed clock
+pub fn now() -> SystemTime {
+    CLOCK.read().expect("Clock lock is not poisoned").now()
+}
+
+/// Wait until `duration` has passed on the installed clock
+pub async fn sleep(duration: Duration) {
+    let sleep = CLOCK
+        .read()
+        .expect("Clock lock is not poisoned")
+        .sleep(duration);
+    sleep.await
+}
+
+/// Seconds since the Unix epoch, or 0 for times before it
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+const UNITS: &[(&str, u64)] = &[
+    ("w", 7 * 24 * 60 * 60),
+    ("d", 24 * 60 * 60),
+    ("h", 60 * 60),
+    ("m", 60),
+    ("s", 1),
+];
+
+/// Parse a duration like "90d", "12h", or "1d12h". Units are w(eeks),
+/// d(ays), h(ours), m(inutes), and s(econds).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    let mut secs: u64 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(format!(
+                "\"{s}\" is not a duration, expected e.g., \"90d\" or \"12h\""
+            ));
+        }
+        let (number, after) = rest.split_at(digits);
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_len);
+        if unit.is_empty() {
+            return Err(format!("\"{s}\" is missing a unit, e.g., \"{s}d\""));
+        }
+        let Some((_, unit_secs)) = UNITS.iter().find(|(name, _)| *name == unit)
+        else {
+            return Err(format!(
+                "unknown unit \"{unit}\" in \"{s}\", expected one of w, d, h, \
+                 m, s"
+            ));
+        };
+        secs = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(*unit_secs))
+            .and_then(|n| n.checked_add(secs))
+            .ok_or_else(|| format!("\"{s}\" is too long"))?;
+        rest = after;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Longest lifetime or period `parse_period` accepts, about a century
+pub const MAX_PERIOD: Duration = Duration::from_days(100 * 365);
+
+/// Parse a lifetime or a period between tasks as `parse_duration` does,
+/// rejecting zero, which would purge everything or repeat a task without
+/// pause, and anything longer than `MAX_PERIOD`
+pub fn parse_period(s: &str) -> Result<Duration, String> {
+    let duration = parse_duration(s)?;
+    let s = s.trim();
+    if duration.is_zero() {
+        return Err(format!("\"{s}\" is too short, it must be longer than 0"));
+    }
+    if duration > MAX_PERIOD {
+        return Err(format!("\"{s}\" is too long, it may be 100 years at most"));
+    }
+    Ok(duration)
+}
+
+/// `time + duration`, held to ten thousand years after the Unix epoch
+/// rather than overflowing, for durations which may come from operators
+pub fn add_clamped(time: SystemTime, duration: Duration) -> SystemTime {
+    time.checked_add(duration)
+        .filter(|sum| *sum <= far_future())
+        .unwrap_or_else(|| time.max(far_future()))
+}
+
+/// A time no event lasts until, which every platform can represent
+fn far_future() -> SystemTime {
+    UNIX_EPOCH + Duration::from_days(10_000 * 365)
+}
+
+/// Describe a duration in its largest whole unit for people, e.g., "90 days"
+pub fn describe_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (name, unit_secs) =
+        [("day", 24 * 60 * 60), ("hour", 60 * 60), ("minute", 60)]
+            .into_iter()
+            .find(|(_, unit_secs)| secs >= *unit_secs)
+            .unwrap_or(("second", 1));
+    let n = secs / unit_secs;
+    if n == 1 {
+        format!("1 {name}")
+    } else {
+        format!("{n} {name}s")
+    }
+}
+
+/// Parse a local time of day like "04:00"
+pub fn parse_time_of_day(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|_| {
+        format!("\"{s}\" is not a time of day, expected e.g., \"04:00\"")
+    })
+}
+
+/// The first time after `after` at which the local clock reads `at`. Days on
+/// which `at` doesn't exist, because the clocks skip over it, are skipped.
+pub fn next_local_time(at: NaiveTime, after: SystemTime) -> SystemTime {
+    let after = DateTime::<Local>::from(after);
+    let mut date = after.date_naive();
+    loop {
+        let candidate = date.and_time(at).and_local_timezone(Local).earliest();
+        if let Some(candidate) = candidate.filter(|c| *c > after) {
+            return candidate.into();
+        }
+        date = date.succ_opt().expect("Dates don't run out");
+    }
+}
+
+/// Format of the date and time inputs of forms
+const LOCAL_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// Parse a local date and time as a form's date and time input sends it, e.g.,
+/// "2027-03-04T18:00"
+pub fn parse_local_input(s: &str) -> Result<SystemTime, String> {
+    NaiveDateTime::parse_from_str(s.trim(), LOCAL_INPUT_FORMAT)
+        .map_err(|_| format!("\"{s}\" is not a date and time"))?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(SystemTime::from)
+        .ok_or_else(|| format!("\"{s}\" doesn't exist in local time"))
+}
+
+/// Format a point in time for a form's date and time input
+pub fn local_input(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format(LOCAL_INPUT_FORMAT)
+        .to_string()
+}
+
+/// Describe a point in time in local time for people
+pub fn describe_local_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M %:z")
+        .to_string()
+}
+
+/// Describe the local time of day of a point in time, e.g., "14:05"
+pub fn describe_local_clock(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%H:%M").to_string()
+}
+
+/// Describe the local date of a point in time for people, e.g., "March 4,
+/// 2027"
+pub fn describe_local_date(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%B %-d, %Y")
+        .to_string()
+}
+
+/// Wait until the clock reaches `at`. A single long sleep measures time on
+/// a clock which may stop while the system is suspended and ignores changes
+/// to the wall clock, so this wakes up regularly to look at the clock again.
+pub async fn sleep_until(at: SystemTime) {
+    while let Ok(left) = at.duration_since(now()) {
+        if left.is_zero() {
+            return;
+        }
+        sleep(left.min(WALL_CLOCK_CHECK)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        let day = 24 * 60 * 60;
+        assert_eq!(parse_duration("90d"), Ok(Duration::from_secs(90 * day)));
+        assert_eq!(
+            parse_duration("1d12h"),
+            Ok(Duration::from_secs(day + 12 * 60 * 60))
+        );
+        assert_eq!(parse_duration(" 2w "), Ok(Duration::from_secs(14 * day)));
+        assert_eq!(parse_duration("0s"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        for bad in ["", "  ", "d", "12", "1d12", "3y", "1.5h", "-1d"] {
+            assert!(parse_duration(bad).is_err(), "{bad:?} was accepted");
+        }
+        assert_eq!(
+            parse_duration("99999999999999999999w"),
+            Err("\"99999999999999999999w\" is too long".to_string())
+        );
+    }
+
+    #[test]
+    fn describes_durations() {
+        assert_eq!(describe_duration(Duration::from_days(90)), "90 days");
+        assert_eq!(describe_duration(Duration::from_hours(1)), "1 hour");
+        assert_eq!(describe_duration(Duration::from_mins(90)), "1 hour");
+        assert_eq!(describe_duration(Duration::from_secs(30)), "30 seconds");
+        assert_eq!(describe_duration(Duration::ZERO), "0 seconds");
+    }
+
+    #[test]
+    fn bounds_periods() {
+        assert_eq!(parse_period("1d"), Ok(Duration::from_days(1)));
+        assert_eq!(parse_period("100w"), Ok(Duration::from_days(700)));
+        assert!(parse_period("0s").is_err());
+        assert!(parse_period("0d0h").is_err());
+        assert!(parse_period("99999999999w").is_err());
+        assert!(parse_period("bad").is_err());
+    }
+
+    #[test]
+    fn clamps_far_times() {
+        let start = UNIX_EPOCH + Duration::from_days(20_000);
+        let day = Duration::from_days(1);
+        assert_eq!(add_clamped(start, day), start + day);
+        let huge = Duration::from_secs(u64::MAX);
+        assert_eq!(add_clamped(start, huge), far_future());
+        assert_eq!(add_clamped(far_future(), day), far_future());
+    }
+
+    #[tokio::test]
+    async fn manual_clock_wakes_sleepers() {
+        let start = UNIX_EPOCH + Duration::from_days(20_000);
+        let clock = ManualClock::new(start);
+        let sleeper = tokio::spawn(clock.sleep(Duration::from_mins(10)));
+
+        clock.advance(Duration::from_mins(5));
+        assert_eq!(clock.now(), start + Duration::from_mins(5));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        clock.set(start + Duration::from_mins(10));
+        tokio::time::timeout(Duration::from_secs(5), sleeper)
+            .await
+            .expect("Sleeper woke up")
+            .unwrap();
+    }
+}
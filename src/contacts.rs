@@ -0,0 +1,384 @@
+//! Contact books organizers reuse across events
+//!
+//! Hosts who invite the same people again and again keep them in a contact
+//! book instead of typing them into every guest list. Like events, a book has
+//! no owner account: whoever has its link can see and change it. An event
+//! linked to a book offers its contacts on the manage page, and can save its
+//! guests back into the book.
+//!
+//! Books are kept in `contacts.json` beside the database rather than in it,
+//! so they outlive the events they were used for and aren't purged with them.
+
+use std::time::SystemTime;
+
+use askama::Template;
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock,
+    event_db::Attendee,
+    ids::{self, Id},
+    settings,
+};
+
+const CONTACTS_PATH: &str = "contacts.json";
+/// Most contacts a book can hold
+pub const MAX_CONTACTS: usize = 500;
+/// Longest name, email address, or notes of a contact
+const MAX_CONTACT_TEXT_CHARS: usize = 500;
+/// How many times a colliding book ID is re-rolled before giving up
+const MAX_ID_ATTEMPTS: usize = 8;
+
+/// Held while the contact file is read and written back
+static STORE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ContactBook {
+    pub id: Id,
+    pub created: SystemTime,
+    /// Number the next contact gets. Numbers aren't reused, so a form for a
+    /// removed contact can't change another.
+    next_number: u32,
+    pub contacts: Vec<Contact>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Contact {
+    pub number: u32,
+    pub name: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// The organizer's own notes, copied to the guest when they're invited
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl Contact {
+    /// Whether the attendee is this contact, by email address if the contact
+    /// has one and by name otherwise
+    pub fn matches(&self, attendee: &Attendee) -> bool {
+        match (&self.email, &attendee.email) {
+            (Some(email), Some(other)) => email.eq_ignore_ascii_case(other),
+            (Some(_), None) => false,
+            (None, _) => self.name.trim() == attendee.name.trim(),
+        }
+    }
+}
+
+/// A contact to add, before it has a number
+#[derive(Deserialize, Debug)]
+pub struct NewContact {
+    pub name: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl NewContact {
+    fn into_contact(self, number: u32) -> Result<Contact, ContactsError> {
+        let name = self.name.trim().to_string();
+        let email = self.email.trim().to_string();
+        if name.is_empty() {
+            return Err(ContactsError::Invalid("A contact needs a name"));
+        }
+        if [&name, &email, &self.notes]
+            .iter()
+            .any(|text| text.chars().count() > MAX_CONTACT_TEXT_CHARS)
+        {
+            return Err(ContactsError::Invalid(
+                "Names, email addresses, and notes can be at most 500 \
+                 characters",
+            ));
+        }
+        Ok(Contact {
+            number,
+            name,
+            email: Some(email).filter(|e| !e.is_empty()),
+            notes: self.notes,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ContactsError {
+    NotFound,
+    Full,
+    Invalid(&'static str),
+    /// The contact file couldn't be written
+    Storage,
+}
+
+impl IntoResponse for ContactsError {
+    fn into_response(self) -> Response {
+        match self {
+            ContactsError::NotFound => {
+                (StatusCode::NOT_FOUND, "Contact book does not exist")
+            }
+            ContactsError::Full => (
+                StatusCode::BAD_REQUEST,
+                "The contact book can hold at most 500 contacts",
+            ),
+            ContactsError::Invalid(message) => {
+                (StatusCode::BAD_REQUEST, message)
+            }
+            ContactsError::Storage => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not save the contact book",
+            ),
+        }
+        .into_response()
+    }
+}
+
+async fn load() -> Vec<ContactBook> {
+    match tokio::fs::read(CONTACTS_PATH).await {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            log::error!("Could not parse {CONTACTS_PATH}: {e}");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save(books: &[ContactBook]) -> Result<(), ContactsError> {
+    let data = serde_json::to_vec_pretty(books)
+        .expect("Contact books are serializable");
+    tokio::fs::write(CONTACTS_PATH, data).await.map_err(|e| {
+        log::error!("Could not write {CONTACTS_PATH}: {e}");
+        ContactsError::Storage
+    })
+}
+
+/// Start an empty contact book
+pub async fn create_book() -> Result<Id, ContactsError> {
+    let _guard = STORE.lock().await;
+    let mut books = load().await;
+    let mut id = None;
+    for _ in 0..MAX_ID_ATTEMPTS {
+        let candidate = ids::generate_id().await;
+        if books.iter().all(|book| book.id != candidate) {
+            id = Some(candidate);
+            break;
+        }
+    }
+    let Some(id) = id else {
+        log::error!("Could not generate a unique contact book ID");
+        return Err(ContactsError::Storage);
+    };
+    books.push(ContactBook {
+        id,
+        created: clock::now(),
+        next_number: 0,
+        contacts: Vec::new(),
+    });
+    save(&books).await?;
+    Ok(id)
+}
+
+pub async fn find_book(id: Id) -> Result<ContactBook, ContactsError> {
+    let _guard = STORE.lock().await;
+    load()
+        .await
+        .into_iter()
+        .find(|book| book.id == id)
+        .ok_or(ContactsError::NotFound)
+}
+
+/// Change a book and write it back if the change succeeds
+async fn change_book<T>(
+    id: Id,
+    change: impl FnOnce(&mut ContactBook) -> Result<T, ContactsError>,
+) -> Result<T, ContactsError> {
+    let _guard = STORE.lock().await;
+    let mut books = load().await;
+    let book = books
+        .iter_mut()
+        .find(|book| book.id == id)
+        .ok_or(ContactsError::NotFound)?;
+    let result = change(book)?;
+    save(&books).await?;
+    Ok(result)
+}
+
+impl ContactBook {
+    fn add(&mut self, new: NewContact) -> Result<(), ContactsError> {
+        if self.contacts.len() >= MAX_CONTACTS {
+            return Err(ContactsError::Full);
+        }
+        let contact = new.into_contact(self.next_number)?;
+        self.next_number += 1;
+        self.contacts.push(contact);
+        Ok(())
+    }
+}
+
+/// Add the event's named guests who aren't in the book yet. Returns how many
+/// were added.
+pub async fn save_guests(
+    id: Id,
+    attendees: &[Attendee],
+) -> Result<usize, ContactsError> {
+    change_book(id, |book| {
+        let mut added = 0;
+        for attendee in attendees {
+            let unnamed =
+                attendee.name.trim().is_empty() || attendee.name == "Unnamed";
+            if attendee.anonymized
+                || unnamed
+                || book.contacts.iter().any(|c| c.matches(attendee))
+            {
+                continue;
+            }
+            book.add(NewContact {
+                name: attendee.name.clone(),
+                email: attendee.email.clone().unwrap_or_default(),
+                notes: String::new(),
+            })?;
+            added += 1;
+        }
+        Ok(added)
+    })
+    .await
+}
+
+/// The book ID in a contact book link, or the ID itself
+pub fn parse_book_link(link: &str) -> Option<Id> {
+    let link = link.trim().trim_end_matches('/');
+    let id = link.rsplit('/').next().unwrap_or(link);
+    ids::decode_id(id).ok()
+}
+
+pub fn book_url(id: Id) -> String {
+    format!("{}/invite/contacts/{id}", settings::public_url())
+}
+
+#[derive(Serialize, Debug)]
+struct ContactRow {
+    number: u32,
+    name: String,
+    email: String,
+    notes: String,
+    edit_link: String,
+    remove_link: String,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "contacts.html")]
+struct ContactsPage {
+    book_url: String,
+    contacts: Vec<ContactRow>,
+    add_link: String,
+    max_contacts: usize,
+}
+
+fn decode_book(id: &str) -> Result<Id, ContactsError> {
+    ids::decode_id(id).map_err(|_| ContactsError::NotFound)
+}
+
+fn redirect_to_book(id: &str) -> Response {
+    Redirect::to(&format!("/invite/contacts/{id}")).into_response()
+}
+
+/// `GET /invite/contacts/{book_id}`
+pub async fn view_book(Path(id): Path<String>) -> Response {
+    let book = match decode_book(&id) {
+        Ok(book_id) => find_book(book_id).await,
+        Err(e) => Err(e),
+    };
+    let book = match book {
+        Ok(book) => book,
+        Err(e) => return e.into_response(),
+    };
+    let contacts = book
+        .contacts
+        .into_iter()
+        .map(|contact| ContactRow {
+            number: contact.number,
+            name: contact.name,
+            email: contact.email.unwrap_or_default(),
+            notes: contact.notes,
+            edit_link: format!("/invite/edit-contact/{id}/{}", contact.number),
+            remove_link: format!(
+                "/invite/remove-contact/{id}/{}",
+                contact.number
+            ),
+        })
+        .collect();
+    let page = ContactsPage {
+        book_url: book_url(book.id),
+        contacts,
+        add_link: format!("/invite/add-contact/{id}"),
+        max_contacts: MAX_CONTACTS,
+    };
+    match page.render() {
+        Ok(page) => Html(page).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response(),
+    }
+}
+
+/// `POST /invite/add-contact/{book_id}`
+pub async fn add_contact(
+    Path(id): Path<String>,
+    Form(new): Form<NewContact>,
+) -> Response {
+    let book_id = match decode_book(&id) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    match change_book(book_id, |book| book.add(new)).await {
+        Ok(()) => redirect_to_book(&id),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `POST /invite/edit-contact/{book_id}/{number}`
+pub async fn edit_contact(
+    Path((id, number)): Path<(String, u32)>,
+    Form(edited): Form<NewContact>,
+) -> Response {
+    let book_id = match decode_book(&id) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let result = change_book(book_id, |book| {
+        let contact = book
+            .contacts
+            .iter_mut()
+            .find(|c| c.number == number)
+            .ok_or(ContactsError::NotFound)?;
+        *contact = edited.into_contact(number)?;
+        Ok(())
+    })
+    .await;
+    match result {
+        Ok(()) => redirect_to_book(&id),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `POST /invite/remove-contact/{book_id}/{number}`
+pub async fn remove_contact(
+    Path((id, number)): Path<(String, u32)>,
+) -> Response {
+    let book_id = match decode_book(&id) {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let result = change_book(book_id, |book| {
+        book.contacts.retain(|c| c.number != number);
+        Ok(())
+    })
+    .await;
+    match result {
+        Ok(()) => redirect_to_book(&id),
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,72 @@
+//! Static files served under `/invite/content`
+//!
+//! Files are read from the content directory on disk. With the `embed` feature
+//! the directory is also compiled into the binary, and anything missing on disk
+//! is served from the embedded copy, so a deployment can be a single executable
+//! while files on disk still override the built-in ones.
+
+use std::path::{Component, Path};
+
+use axum::routing::{get_service, MethodRouter};
+use tower_http::services::ServeDir;
+
+#[cfg(feature = "embed")]
+use axum::{
+    handler::HandlerWithoutStateExt,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+
+#[cfg(feature = "embed")]
+#[derive(rust_embed::RustEmbed)]
+#[folder = "content/"]
+struct Embedded;
+
+/// Service for every file under the content directory
+#[cfg(not(feature = "embed"))]
+pub fn service(content_dir: &Path) -> MethodRouter {
+    get_service(ServeDir::new(content_dir))
+}
+
+/// Service for every file under the content directory, falling back to the
+/// embedded copy
+#[cfg(feature = "embed")]
+pub fn service(content_dir: &Path) -> MethodRouter {
+    get_service(
+        ServeDir::new(content_dir).fallback(serve_embedded.into_service()),
+    )
+}
+
+#[cfg(feature = "embed")]
+async fn serve_embedded(uri: Uri) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    match Embedded::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Read one file relative to the content directory. Paths which could escape
+/// the directory are rejected.
+pub async fn read(content_dir: &Path, path: &str) -> Option<Vec<u8>> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    match tokio::fs::read(content_dir.join(relative)).await {
+        Ok(data) => return Some(data),
+        Err(e) => log::debug!("{path} not found on disk: \"{e}\""),
+    }
+    #[cfg(feature = "embed")]
+    if let Some(file) = Embedded::get(path) {
+        return Some(file.data.into_owned());
+    }
+    None
+}
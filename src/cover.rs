@@ -0,0 +1,147 @@
+//! Cover images organizers upload for their events
+//!
+//! Uploads are decoded to check they really are images, scaled down to fit
+//! within `MAX_SIDE` pixels, and encoded again, which also drops metadata such
+//! as where a photo was taken. They're kept under `uploads/` in the content
+//! directory with a random name, so a replaced cover is never shown from a
+//! stale cache.
+
+use std::{
+    fmt,
+    io::{Cursor, ErrorKind},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat,
+    ImageReader, Limits,
+};
+
+use crate::ids;
+
+/// Largest upload accepted
+pub const MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+/// Covers are scaled down to fit within this many pixels each way
+const MAX_SIDE: u32 = 1200;
+/// Larger images are refused before decoding, since decoding them would take
+/// too much memory
+const MAX_SOURCE_SIDE: u32 = 10_000;
+const JPEG_QUALITY: u8 = 85;
+const DEFAULT_UPLOADS_DIR: &str = "content/uploads";
+
+static UPLOADS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum CoverError {
+    /// Not a PNG, JPEG, GIF, or WebP image, or too large to decode
+    NotAnImage,
+    Storage(String),
+}
+
+impl fmt::Display for CoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnImage => write!(
+                f,
+                "The file isn't a PNG, JPEG, GIF, or WebP image of at most \
+                 {MAX_SOURCE_SIDE} pixels each way"
+            ),
+            Self::Storage(e) => write!(f, "Could not store the image: {e}"),
+        }
+    }
+}
+
+/// Keep uploads in `dir`, which has to be served as `uploads/` of the content
+/// directory
+pub fn set_uploads_dir(dir: PathBuf) {
+    if UPLOADS_DIR.set(dir).is_err() {
+        log::warn!("Uploads directory was already set");
+    }
+}
+
+fn uploads_dir() -> PathBuf {
+    UPLOADS_DIR
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_UPLOADS_DIR))
+}
+
+/// Where a stored cover is served
+pub fn url(name: &str) -> String {
+    format!("/invite/content/uploads/{name}")
+}
+
+/// Check, scale, and store an uploaded image. Returns the name it's stored
+/// under.
+pub async fn store(data: Vec<u8>) -> Result<String, CoverError> {
+    let (encoded, extension) =
+        tokio::task::spawn_blocking(move || process(&data))
+            .await
+            .map_err(|e| CoverError::Storage(e.to_string()))??;
+    let name = format!("{}.{extension}", ids::generate_id().await.encode());
+    let dir = uploads_dir();
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| CoverError::Storage(e.to_string()))?;
+    tokio::fs::write(dir.join(&name), encoded)
+        .await
+        .map_err(|e| CoverError::Storage(e.to_string()))?;
+    Ok(name)
+}
+
+/// Delete a stored cover
+pub async fn remove(name: &str) {
+    match tokio::fs::remove_file(uploads_dir().join(name)).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => log::error!("Could not delete cover image {name}: {e}"),
+    }
+}
+
+/// Decode, scale, and encode the image. Images with transparency are kept as
+/// PNG, others become JPEG.
+fn process(data: &[u8]) -> Result<(Vec<u8>, &'static str), CoverError> {
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| CoverError::NotAnImage)?;
+    if !matches!(
+        reader.format(),
+        Some(
+            ImageFormat::Png
+                | ImageFormat::Jpeg
+                | ImageFormat::Gif
+                | ImageFormat::WebP
+        )
+    ) {
+        return Err(CoverError::NotAnImage);
+    }
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_SIDE);
+    limits.max_image_height = Some(MAX_SOURCE_SIDE);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|_| CoverError::NotAnImage)?;
+
+    let image = if image.width() > MAX_SIDE || image.height() > MAX_SIDE {
+        image.resize(MAX_SIDE, MAX_SIDE, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let mut encoded = Vec::new();
+    let extension = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)
+            .map_err(|e| CoverError::Storage(e.to_string()))?;
+        "png"
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(
+                &mut encoded,
+                JPEG_QUALITY,
+            ))
+            .map_err(|e| CoverError::Storage(e.to_string()))?;
+        "jpg"
+    };
+    Ok((encoded, extension))
+}
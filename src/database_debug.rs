@@ -1,11 +1,11 @@
-#![feature(duration_constructors, duration_constructors_lite)]
-pub mod cli;
-pub mod event_db;
-pub mod templates;
+use std::path::Path;
 
-use clap::Parser;
-
-use crate::event_db::EventDB;
+use bxyz_invite::{
+    archive, clock,
+    event_db::{format, EventDB},
+    ids,
+};
+use clap::{Parser, Subcommand};
 
 const DEFAULT_DB_PATH: &str = "events.db";
 
@@ -14,22 +14,92 @@ const DEFAULT_DB_PATH: &str = "events.db";
 pub struct Args {
     #[arg(short='f', long="file", default_value=DEFAULT_DB_PATH)]
     pub db_file: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the whole database (the default)
+    Dump,
+    /// Move an archived event back into the database
+    Unarchive {
+        /// The event's base62 ID
+        id: String,
 
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
+        #[arg(short='a', long="archive-dir", default_value=archive::ARCHIVE_DIR)]
+        archive_dir: String,
+    },
+}
 
-    let Ok(data) = tokio::fs::read(args.db_file).await else {
+fn read_db(path: &str) -> EventDB {
+    let Ok(file) = std::fs::File::open(path) else {
         eprintln!("Failed to read database file");
         std::process::exit(1);
     };
 
-    let Ok(db) = serde_cbor::from_slice::<EventDB>(&data) else {
-        eprintln!("Failed to parse database file");
+    match format::read(file) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to load database file: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn unarchive(db_file: &str, id: &str, archive_dir: &str) {
+    let id = match ids::decode_id(id) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("Invalid event ID: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut db = read_db(db_file);
+    if db.events.iter().any(|ev| ev.id == id) {
+        eprintln!("Event {id} is already in the database");
         std::process::exit(1);
+    }
+
+    let path = archive::archive_path(Path::new(archive_dir), id);
+    let mut event = match archive::read(&path) {
+        Ok(ev) => ev,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
     };
+    // restart its lifetime so the next purge doesn't archive it again
+    event.extended = Some(clock::now());
+    event.purge_warning_sent = None;
+    db.insert_event(event);
+
+    let written = std::fs::File::create(db_file)
+        .and_then(|file| format::write(file, &db));
+    if written.is_err() {
+        eprintln!("Failed to write database file");
+        std::process::exit(1);
+    }
+    if std::fs::remove_file(&path).is_err() {
+        eprintln!("Restored event, but could not remove {}", path.display());
+        std::process::exit(1);
+    }
+    println!("Restored event {id}");
+}
 
-    println!("{db:?}");
-}
\ No newline at end of file
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    match args.command.unwrap_or(Command::Dump) {
+        Command::Dump => {
+            let db = read_db(&args.db_file);
+            println!("{db:?}");
+        }
+        Command::Unarchive { id, archive_dir } => {
+            unarchive(&args.db_file, &id, &archive_dir);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Optional error reporting
+//!
+//! Captures panics and responses with a 5xx status and ships them to a
+//! Sentry-compatible endpoint. Reporting is disabled unless a DSN is given on
+//! the command line, in which case every helper in this module is a no-op.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+
+/// Upper bound on how much of an error response body is attached to a report
+const MAX_REPORTED_BODY: usize = 4096;
+const CLIENT_NAME: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+static REPORTER: OnceLock<Reporter> = OnceLock::new();
+
+tokio::task_local! {
+    /// Information about the request currently being handled, so a panic hook
+    /// running on the same task can attach it to its report
+    static REQUEST: RequestContext;
+}
+
+#[derive(Clone, Debug)]
+struct RequestContext {
+    request_id: String,
+    route: String,
+}
+
+/// The parts of a Sentry DSN needed to submit events
+///
+/// A DSN looks like `https://<public_key>@<host>/<project_id>`
+#[derive(Clone, Debug)]
+pub struct Dsn {
+    store_url: String,
+    public_key: String,
+}
+
+impl FromStr for Dsn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .ok_or("DSN is missing a scheme".to_string())?;
+        let (public_key, rest) = rest
+            .split_once('@')
+            .ok_or("DSN is missing a public key".to_string())?;
+        // the public key may be followed by a deprecated secret key
+        let public_key = public_key.split(':').next().unwrap_or_default();
+        let (host, project_id) = rest
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .ok_or("DSN is missing a project ID".to_string())?;
+
+        if public_key.is_empty() || host.is_empty() || project_id.is_empty() {
+            return Err("DSN is incomplete".to_string());
+        }
+
+        Ok(Dsn {
+            store_url: format!("{scheme}://{host}/api/{project_id}/store/"),
+            public_key: public_key.to_string(),
+        })
+    }
+}
+
+struct Reporter {
+    dsn: Dsn,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct SentryEvent {
+    event_id: String,
+    timestamp: f64,
+    platform: &'static str,
+    level: &'static str,
+    logger: &'static str,
+    release: &'static str,
+    message: SentryMessage,
+    tags: HashMap<&'static str, String>,
+    extra: HashMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+struct SentryMessage {
+    formatted: String,
+}
+
+/// Enable error reporting to the given DSN and install a panic hook which
+/// reports panics before deferring to the previous hook
+pub fn initialize(dsn: &str) {
+    let dsn = match Dsn::from_str(dsn) {
+        Ok(d) => d,
+        Err(e) => {
+            log::error!("Invalid error reporting DSN: \"{e}\". Disabled.");
+            return;
+        }
+    };
+
+    let reporter = Reporter {
+        dsn,
+        client: reqwest::Client::new(),
+    };
+    if REPORTER.set(reporter).is_err() {
+        log::warn!("Error reporting was already initialized");
+        return;
+    }
+
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or("<non-string panic payload>".to_string());
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+
+        let mut extra = HashMap::new();
+        extra.insert("location", location);
+        report("fatal", format!("panic: {payload}"), extra);
+
+        previous_hook(info);
+    }));
+
+    log::info!("Error reporting enabled");
+}
+
+/// Middleware which records the request ID and route for the duration of the
+/// request and reports any response with a server error status
+pub async fn capture_errors(req: Request, next: Next) -> Response {
+    if REPORTER.get().is_none() {
+        return next.run(req).await;
+    }
+
+    let context = RequestContext {
+        request_id: req
+            .headers()
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string(),
+        route: req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|p| p.as_str().to_string())
+            .unwrap_or(req.uri().path().to_string()),
+    };
+    let method = req.method().to_string();
+
+    let response = REQUEST.scope(context, next.run(req)).await;
+    if !response.status().is_server_error() {
+        return response;
+    }
+
+    // the body is read so the error text can be included in the report, then
+    // put back together for the client
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let text = String::from_utf8_lossy(&bytes);
+    let text = match text.char_indices().nth(MAX_REPORTED_BODY) {
+        Some((idx, _)) => &text[..idx],
+        None => &text,
+    };
+
+    let mut extra = HashMap::new();
+    extra.insert("method", method);
+    extra.insert("status", parts.status.as_u16().to_string());
+    extra.insert("body", text.to_string());
+    report("error", format!("{} response", parts.status), extra);
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Send an event to the configured endpoint in the background. Any request
+/// context recorded by the middleware is attached as tags.
+fn report(
+    level: &'static str,
+    message: String,
+    extra: HashMap<&'static str, String>,
+) {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+    // panics outside of the runtime (e.g. during startup) have nowhere to
+    // send from
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    let mut tags = HashMap::new();
+    let _ = REQUEST.try_with(|ctx| {
+        tags.insert("request_id", ctx.request_id.clone());
+        tags.insert("route", ctx.route.clone());
+    });
+
+    let event = SentryEvent {
+        event_id: format!("{:032x}", rand::random::<u128>()),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default(),
+        platform: "other",
+        level,
+        logger: env!("CARGO_PKG_NAME"),
+        release: CLIENT_NAME,
+        message: SentryMessage { formatted: message },
+        tags,
+        extra,
+    };
+
+    let request = reporter
+        .client
+        .post(&reporter.dsn.store_url)
+        .header(
+            "X-Sentry-Auth",
+            format!(
+                "Sentry sentry_version=7, sentry_client={CLIENT_NAME}, \
+                 sentry_key={}",
+                reporter.dsn.public_key
+            ),
+        )
+        .json(&event);
+
+    runtime.spawn(async move {
+        match request.send().await {
+            Ok(resp) if !resp.status().is_success() => {
+                log::warn!(
+                    "Error reporting endpoint responded with {}",
+                    resp.status()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Failed to send error report: \"{e}\"");
+            }
+        }
+    });
+}
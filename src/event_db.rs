@@ -1,23 +1,325 @@
 use std::{
-    sync::LazyLock,
+    collections::BTreeMap,
+    fs::File,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
     time::{Duration, SystemTime},
 };
 
-use rand::{rngs::StdRng, Rng, SeedableRng};
-use serde::{Deserialize, Serialize};
-use tokio::sync::{Mutex, MutexGuard};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::templates::ManagePageJson;
+use crate::{
+    archive,
+    clock::{self, unix_secs},
+    cover,
+    i18n::Language,
+    ids::{self, Id, IdSource},
+    integrity, legacy, mail, metrics,
+    notify::{self, Notification, NotificationKind, Priority},
+    organizer,
+    sanitize::sanitize,
+    settings, shortlink,
+    templates::{AttendeePatch, EventPatch, ManagePageJson, NewAttendee},
+};
+
+pub mod format;
+mod lock;
+mod oplog;
+mod sqlite;
+mod store;
+mod writer;
+
+use format::ReadError;
+pub use store::{replace_file, set_storage, Storage};
+use store::{Lookup, Scan, CBOR_PATH, SQLITE_PATH};
+pub use writer::{flush_db, reload_db, start, watch_changes};
+use writer::{open_db, save_db};
+
+/// Where a storage test keeps its files, cleared of any an earlier run left
+#[cfg(test)]
+fn temp_path(name: &str) -> String {
+    let name = format!("bxyz-invite-{}-{name}", std::process::id());
+    let path = std::env::temp_dir().join(name);
+    let path = path.to_string_lossy().into_owned();
+    for leftover in [path.clone(), format!("{path}.log")] {
+        let _ = std::fs::remove_file(leftover);
+    }
+    path
+}
+
+/// Written and removed again by the storage check
+const PROBE_PATH: &str = "events.db.probe";
+/// How long before its purge an organizer is warned. An event is never purged
+/// sooner than this after its warning was sent.
+const PURGE_WARNING_PERIOD: Duration = Duration::from_days(7);
+/// How many ignored bot requests are kept per event
+const MAX_BOT_FLAGS: usize = 20;
+/// How many invitations which failed to render are kept per event
+const MAX_RENDER_ERRORS: usize = 20;
+/// How many entries of its access log are kept per event
+const MAX_ACCESS_ENTRIES: usize = 200;
+/// How many view timestamps are kept per attendee
+const MAX_RECENT_VIEWS: usize = 100;
+/// Resolution of stored view timestamps
+const VIEW_GRANULARITY: Duration = Duration::from_hours(1);
+/// Shortest time between two nudges to the same guest, unless configured
+const DEFAULT_NUDGE_COOLDOWN: Duration = Duration::from_days(3);
+/// Reasons offered to guests who withdraw, unless the organizer sets their own
+const DEFAULT_DECLINE_REASONS: &[&str] = &["Out of town", "Schedule conflict"];
+/// RSVP changes undone within this long are dropped as if they never
+/// happened, and withdrawal notifications wait this long to see whether the
+/// guest changes their mind
+const FLAP_WINDOW: Duration = Duration::from_mins(2);
+/// Longest note a guest can leave the organizer
+const MAX_NOTE_CHARS: usize = 1000;
+/// Longest comment a guest can add to their acceptance
+pub const MAX_COMMENT_CHARS: usize = 280;
+/// Most people a guest can bring along
+pub const MAX_PLUS_ONES: u8 = 9;
+/// Longest announcement an organizer can post
+const MAX_ANNOUNCEMENT_CHARS: usize = 1000;
+/// Most items an event's agenda can have
+pub const MAX_AGENDA_ITEMS: usize = 50;
+/// Longest title of an agenda item
+const MAX_AGENDA_TITLE_CHARS: usize = 200;
+/// Longest details of an agenda item
+const MAX_AGENDA_DETAILS_CHARS: usize = 1000;
+/// Longest location of an event
+const MAX_LOCATION_CHARS: usize = 300;
+/// Most expenses an event can track
+pub const MAX_EXPENSES: usize = 200;
+/// Longest description of an expense or name of who paid
+const MAX_EXPENSE_TEXT_CHARS: usize = 200;
+/// Longest note on a carpool offer or request
+const MAX_CARPOOL_NOTE_CHARS: usize = 200;
+/// Most seats a driver can offer
+pub const MAX_CARPOOL_SEATS: u8 = 8;
+/// Most time slots an event can have
+pub const MAX_TIME_SLOTS: usize = 200;
+/// Longest name kept for guests joining through the open RSVP link
+const MAX_OPEN_NAME_CHARS: usize = 100;
+/// Most invitation sends an event can have scheduled at once
+pub const MAX_SCHEDULED_SENDS: usize = 50;
+/// Most custom fields an event can have
+pub const MAX_CUSTOM_FIELDS: usize = 20;
+/// Longest name of a custom field
+const MAX_FIELD_NAME_CHARS: usize = 50;
+/// Longest value of a custom field
+const MAX_FIELD_VALUE_CHARS: usize = 500;
+/// Changes to the same detail this close together are logged as one, so
+/// autosaves while typing don't each become an update, and guests are
+/// notified once this long has passed without another change
+const DETAIL_SETTLE_WINDOW: Duration = Duration::from_mins(10);
+/// How often events are checked for a due digest
+const DIGEST_CHECK_PERIOD: Duration = Duration::from_hours(1);
+/// How often the organizer's views of manage pages are written down
+const VIEW_FLUSH_PERIOD: Duration = Duration::from_mins(5);
+/// How many times a colliding ID is re-rolled before giving up
+const MAX_ID_ATTEMPTS: usize = 8;
+/// Share of the size limit at which the database counts as nearly full
+const SIZE_WARNING_FRACTION: f64 = 0.9;
+
+static PURGE_MODE: OnceLock<PurgeMode> = OnceLock::new();
+static RETENTION: OnceLock<Retention> = OnceLock::new();
+static ORGANIZER_PINNING: OnceLock<bool> = OnceLock::new();
+static NUDGE_COOLDOWN: OnceLock<Duration> = OnceLock::new();
+static SIZE_LIMIT: OnceLock<SizeLimit> = OnceLock::new();
+static SHARED_STORAGE: OnceLock<bool> = OnceLock::new();
+/// Whether the last save came close to the size limit, so the warning is only
+/// logged when the limit is first approached
+static NEAR_SIZE_LIMIT: AtomicBool = AtomicBool::new(false);
+/// Views of manage pages not written down yet, counted by event and the time
+/// they're logged at
+static MANAGE_VIEWS: Mutex<BTreeMap<(Id, SystemTime), u32>> =
+    Mutex::new(BTreeMap::new());
+
+/// How large the database may grow. With the CBOR file, every change rewrites
+/// the whole file, so a very large database makes every request slow.
+#[derive(Clone, Copy, Debug)]
+pub struct SizeLimit {
+    pub max_bytes: u64,
+    /// Turn away new events once the limit is reached. Otherwise, the limit
+    /// only causes warnings.
+    pub refuse_new_events: bool,
+}
+
+pub fn set_size_limit(limit: SizeLimit) {
+    if SIZE_LIMIT.set(limit).is_err() {
+        log::warn!("Database size limit was already set");
+    }
+}
+
+pub fn size_limit() -> Option<SizeLimit> {
+    SIZE_LIMIT.get().copied()
+}
+
+/// Warn when a save brings the database close to its size limit
+fn check_size(bytes: u64) {
+    let Some(limit) = size_limit() else {
+        return;
+    };
+    let near = bytes as f64 >= limit.max_bytes as f64 * SIZE_WARNING_FRACTION;
+    if near && !NEAR_SIZE_LIMIT.swap(true, Ordering::Relaxed) {
+        log::warn!(
+            "Database is {bytes} bytes, {:.0}% of its {} byte limit",
+            bytes as f64 / limit.max_bytes as f64 * 100.0,
+            limit.max_bytes
+        );
+    } else if !near && NEAR_SIZE_LIMIT.swap(false, Ordering::Relaxed) {
+        log::info!("Database is back below its size warning threshold");
+    }
+}
+
+/// What happens to expired events
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PurgeMode {
+    /// Remove them for good
+    #[default]
+    Delete,
+    /// Move them to compressed files in the archive directory
+    Archive,
+}
+
+/// How long events are kept and how often expired ones are purged
+#[derive(Clone, Copy, Debug)]
+pub struct Retention {
+    /// Time from creation, or the last extension, until an event expires
+    pub event_lifetime: Duration,
+    /// Time between purges
+    pub purge_period: Duration,
+    /// Local time of day to purge at, instead of every `purge_period`
+    pub purge_at: Option<NaiveTime>,
+    /// Time before a failed purge is tried again
+    pub purge_retry_period: Duration,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            event_lifetime: Duration::from_days(30 * 3),
+            purge_period: Duration::from_days(1),
+            purge_at: None,
+            purge_retry_period: Duration::from_mins(1),
+        }
+    }
+}
+
+pub fn set_retention(retention: Retention) {
+    if RETENTION.set(retention).is_err() {
+        log::warn!("Retention was already set");
+    }
+}
+
+/// The retention as started, with the lifetime set in the settings if any
+pub fn retention() -> Retention {
+    let mut retention = RETENTION.get().copied().unwrap_or_default();
+    if let Some(secs) = settings::read(|s| s.event_lifetime_secs) {
+        retention.event_lifetime = Duration::from_secs(secs);
+    }
+    retention
+}
+
+impl Retention {
+    /// When the purge after one at `now` is due
+    fn next_purge(&self, now: SystemTime) -> SystemTime {
+        match self.purge_at {
+            Some(at) => clock::next_local_time(at, now),
+            None => clock::add_clamped(now, self.purge_period),
+        }
+    }
+}
+
+/// Let organizers pin their own events. Otherwise only the operator can.
+pub fn set_organizer_pinning(allowed: bool) {
+    if ORGANIZER_PINNING.set(allowed).is_err() {
+        log::warn!("Organizer pinning was already set");
+    }
+}
+
+pub fn organizer_pinning() -> bool {
+    settings::read(|s| s.organizer_pinning)
+        .unwrap_or_else(|| ORGANIZER_PINNING.get().copied().unwrap_or_default())
+}
+
+pub fn set_purge_mode(mode: PurgeMode) {
+    if PURGE_MODE.set(mode).is_err() {
+        log::warn!("Purge mode was already set");
+    }
+}
+
+/// Coordinate with other instances using the same database file
+pub fn set_shared_storage(shared: bool) {
+    if SHARED_STORAGE.set(shared).is_err() {
+        log::warn!("Shared storage was already set");
+    }
+}
 
-const EVENT_LIFETIME: Duration = Duration::from_days(30 * 3);
-const DB_PATH: &str = "events.db";
-const PURGE_PERIOD: Duration = Duration::from_days(1);
-const PURGE_RETRY_PERIOD: Duration = Duration::from_mins(1);
+fn shared_storage() -> bool {
+    SHARED_STORAGE.get().copied().unwrap_or_default()
+}
+
+pub fn set_nudge_cooldown(cooldown: Duration) {
+    if NUDGE_COOLDOWN.set(cooldown).is_err() {
+        log::warn!("Nudge cooldown was already set");
+    }
+}
+
+fn nudge_cooldown() -> Duration {
+    match settings::read(|s| s.nudge_cooldown_secs) {
+        Some(secs) => Duration::from_secs(secs),
+        None => NUDGE_COOLDOWN
+            .get()
+            .copied()
+            .unwrap_or(DEFAULT_NUDGE_COOLDOWN),
+    }
+}
+
+/// How often an organizer receives a summary of RSVP activity
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    #[default]
+    Off,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Off => "off",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
 
-static DB_GUARD: Mutex<()> = Mutex::const_new(());
-static RNG: LazyLock<Mutex<StdRng>> =
-    LazyLock::new(|| Mutex::new(StdRng::from_os_rng()));
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(DigestFrequency::Off),
+            "daily" => Some(DigestFrequency::Daily),
+            "weekly" => Some(DigestFrequency::Weekly),
+            _ => None,
+        }
+    }
+
+    fn period(&self) -> Option<Duration> {
+        match self {
+            DigestFrequency::Off => None,
+            DigestFrequency::Daily => Some(Duration::from_days(1)),
+            DigestFrequency::Weekly => Some(Duration::from_days(7)),
+        }
+    }
+}
 
+/// Events are kept sorted by creation time, oldest first
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct EventDB {
     pub events: Vec<Event>,
@@ -25,239 +327,4574 @@ pub struct EventDB {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Event {
-    pub id: u64,
+    pub id: Id,
     // Option since it starts unset
     pub name: Option<String>,
     pub attendees: Vec<Attendee>,
     pub created: SystemTime,
+    /// When the organizer last extended the event. Its lifetime counts from
+    /// here instead of from `created`.
+    #[serde(default)]
+    pub extended: Option<SystemTime>,
+    /// When the organizer was warned about the upcoming purge
+    #[serde(default)]
+    pub purge_warning_sent: Option<SystemTime>,
+    /// Organizer's own endpoint for notifications about this event
+    #[serde(default)]
+    pub notify_webhook: Option<String>,
+    /// The most recent ignored bot requests, oldest first
+    #[serde(default)]
+    pub bot_flags: Vec<BotFlag>,
+    /// Invitations which failed to render lately, one per guest, oldest
+    /// first. A guest's is cleared when their invitation is changed.
+    #[serde(default)]
+    pub render_errors: Vec<RenderError>,
+    /// What guests and the organizer did lately, oldest first
+    #[serde(default)]
+    pub access_log: Vec<AccessEntry>,
+    #[serde(default)]
+    pub digest: DigestFrequency,
+    /// End of the period covered by the last digest
+    #[serde(default)]
+    pub last_digest: Option<SystemTime>,
+    /// Organizer's Tera template replacing the default thanks page
+    #[serde(default)]
+    pub thanks_html: Option<String>,
+    /// Organizer's Tera template replacing the default withdraw page
+    #[serde(default)]
+    pub withdraw_html: Option<String>,
+    /// Reasons offered to guests who withdraw. None for the defaults.
+    #[serde(default)]
+    pub decline_reasons: Option<Vec<String>>,
+    /// Incremented by every change the organizer makes, so concurrent edits
+    /// can be detected
+    #[serde(default)]
+    pub revision: u64,
+    /// Kept however old it gets, until unpinned
+    #[serde(default)]
+    pub pinned: bool,
+    /// Guests can't change their response from then on
+    #[serde(default)]
+    pub rsvp_deadline: Option<SystemTime>,
+    /// Set by the organizer to stop guests changing their response, whatever
+    /// the deadline
+    #[serde(default)]
+    pub responses_locked: bool,
+    /// Kept as a read-only record: viewable until purged, but neither the
+    /// organizer nor guests can change it
+    #[serde(default)]
+    pub frozen: bool,
+    /// News for the guests, oldest first
+    #[serde(default)]
+    pub announcements: Vec<Announcement>,
+    /// Changes to details guests rely on, oldest first
+    #[serde(default)]
+    pub detail_changes: Vec<DetailChange>,
+    /// Name the cover image is stored under in the uploads directory
+    #[serde(default)]
+    pub cover_image: Option<String>,
+    /// Whether accepted guests can offer and request rides
+    #[serde(default)]
+    pub carpool_open: bool,
+    /// Rides offered and requested, oldest first
+    #[serde(default)]
+    pub carpool: Vec<CarpoolEntry>,
+    /// Times guests sign up for, earliest first. With any, the event is in
+    /// appointment mode: accepting means picking one with room left.
+    #[serde(default)]
+    pub time_slots: Vec<TimeSlot>,
+    /// Invitations the organizer set to go out later, soonest first
+    #[serde(default)]
+    pub scheduled_sends: Vec<ScheduledSend>,
+    /// The link anyone can RSVP through, if the organizer made one
+    #[serde(default)]
+    pub open_rsvp: Option<OpenRsvp>,
+    /// Names the event's kiosk display, which shows arrivals at the entrance
+    /// without letting anyone change anything. None while there's none.
+    #[serde(default)]
+    pub kiosk: Option<Id>,
+    /// Attendees merged into others, whose links now lead to the survivor
+    #[serde(default)]
+    pub merged: Vec<MergedAttendee>,
+    /// What happens when, earliest first
+    #[serde(default)]
+    pub agenda: Vec<AgendaItem>,
+    /// Extra columns of the guest list, in the order they're shown
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+    /// Where the weather forecast is for
+    #[serde(default)]
+    pub weather_location: Option<Coordinates>,
+    /// What was spent on the event, oldest first
+    #[serde(default)]
+    pub expenses: Vec<Expense>,
+    /// Whether guests who accepted see the expenses and their share
+    #[serde(default)]
+    pub expenses_shared: bool,
+    /// Day the event takes place on, in its time zone
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+    /// When the event starts on its day. None for an all-day event.
+    #[serde(default)]
+    pub start_time: Option<NaiveTime>,
+    /// When the event ends. Earlier than the start for events running past
+    /// midnight.
+    #[serde(default)]
+    pub end_time: Option<NaiveTime>,
+    /// IANA name of the time zone the date and times are in, e.g.,
+    /// "Europe/Berlin". None for the server's.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Where the event takes place, as guests should read it
+    #[serde(default)]
+    pub location: Option<String>,
+    /// Where the organizer is emailed about responses, see `mail`
+    #[serde(default)]
+    pub organizer_email: Option<String>,
+    /// The organizer's contact book guests can be added from, see `contacts`
+    #[serde(default)]
+    pub contact_book: Option<Id>,
+    /// Key automation platforms use to act on the event, see `automation`.
+    /// None while the organizer hasn't made one.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Secret the organizer's pages require, see `organizer`. None for
+    /// events made before there were keys, until `assign_organizer_keys`
+    /// gives them one.
+    #[serde(default)]
+    pub organizer_key: Option<String>,
+}
+
+impl Event {
+    /// A new untitled event with no attendees
+    pub fn new(id: Id) -> Self {
+        Self {
+            id,
+            name: None,
+            attendees: Vec::new(),
+            created: clock::now(),
+            extended: None,
+            purge_warning_sent: None,
+            notify_webhook: None,
+            bot_flags: Vec::new(),
+            render_errors: Vec::new(),
+            access_log: Vec::new(),
+            digest: settings::read(|s| s.default_digest).unwrap_or_default(),
+            last_digest: None,
+            thanks_html: None,
+            withdraw_html: None,
+            decline_reasons: None,
+            revision: 0,
+            pinned: false,
+            rsvp_deadline: None,
+            responses_locked: false,
+            frozen: false,
+            announcements: Vec::new(),
+            detail_changes: Vec::new(),
+            cover_image: None,
+            carpool_open: false,
+            carpool: Vec::new(),
+            time_slots: Vec::new(),
+            scheduled_sends: Vec::new(),
+            open_rsvp: None,
+            kiosk: None,
+            merged: Vec::new(),
+            agenda: Vec::new(),
+            custom_fields: Vec::new(),
+            weather_location: None,
+            expenses: Vec::new(),
+            expenses_shared: false,
+            date: None,
+            start_time: None,
+            end_time: None,
+            timezone: None,
+            location: None,
+            organizer_email: None,
+            contact_book: None,
+            api_key: None,
+            organizer_key: None,
+        }
+    }
+
+    /// Full link to the event's manage page, with the organizer key
+    pub fn manage_link(&self) -> String {
+        let mut link =
+            format!("{}/invite/manage/{}", settings::public_url(), self.id);
+        if let Some(key) = &self.organizer_key {
+            link.push_str(&format!("?key={key}"));
+        }
+        link
+    }
+
+    /// What was spent in total, in cents
+    pub fn expenses_total(&self) -> u64 {
+        self.expenses.iter().map(|expense| expense.cents).sum()
+    }
+
+    /// Each accepted guest's share of the expenses in cents, rounded up so
+    /// the shares cover the total. None while nobody has accepted.
+    pub fn expense_share(&self) -> Option<u64> {
+        let heads =
+            self.attendees.iter().filter(|at| at.has_accepted()).count();
+        (heads > 0).then(|| self.expenses_total().div_ceil(heads as u64))
+    }
+
+    /// How many people are coming: the guests who accepted and whoever they
+    /// bring along
+    pub fn headcount(&self) -> usize {
+        self.attendees
+            .iter()
+            .filter(|at| at.has_accepted())
+            .map(|at| 1 + usize::from(at.plus_ones))
+            .sum()
+    }
+
+    /// How many guests gave each answer
+    pub fn rsvp_counts(&self) -> RsvpCounts {
+        let mut counts = RsvpCounts::default();
+        for attendee in &self.attendees {
+            match attendee.rsvp {
+                Rsvp::Pending => counts.pending += 1,
+                Rsvp::Yes => counts.yes += 1,
+                Rsvp::Maybe => counts.maybe += 1,
+                Rsvp::No => counts.no += 1,
+            }
+        }
+        counts
+    }
+
+    /// Bring an event stored by an older version up to date
+    pub fn upgrade(&mut self) {
+        self.upgrade_rsvps();
+        self.upgrade_numbers();
+    }
+
+    /// Guests who withdrew before there was more than accepting or not were
+    /// stored like guests who never answered. Their history tells them apart.
+    fn upgrade_rsvps(&mut self) {
+        for attendee in self.attendees.iter_mut() {
+            let withdrew = attendee
+                .rsvp_history
+                .last()
+                .is_some_and(|change| change.rsvp == Rsvp::No);
+            if attendee.rsvp == Rsvp::Pending && withdrew {
+                attendee.rsvp = Rsvp::No;
+            }
+        }
+    }
+
+    /// Announcements, agenda items, custom fields, and expenses used to be
+    /// named by their place in the list, which shifts as they're removed
+    fn upgrade_numbers(&mut self) {
+        number_unnumbered(self.announcements.iter_mut().map(|a| &mut a.number));
+        number_unnumbered(self.agenda.iter_mut().map(|item| &mut item.number));
+        number_unnumbered(self.custom_fields.iter_mut().map(|f| &mut f.number));
+        number_unnumbered(self.expenses.iter_mut().map(|e| &mut e.number));
+    }
+
+    /// When the event starts, as far as anyone has said: at its start time,
+    /// at the start of its day if it's all-day, or without a date, at the
+    /// first item of its agenda
+    pub fn starts_at(&self) -> Option<SystemTime> {
+        let Some(date) = self.date else {
+            return self.agenda.first().map(|item| item.at);
+        };
+        self.resolve(date.and_time(self.start_time.unwrap_or_default()))
+    }
+
+    /// When the event ends, if it has a start and an end time
+    pub fn ends_at(&self) -> Option<SystemTime> {
+        let (date, start, end) = (self.date?, self.start_time?, self.end_time?);
+        let date = if end <= start { date.succ_opt()? } else { date };
+        self.resolve(date.and_time(end))
+    }
+
+    /// The point in time a date and time of the event's time zone stands for
+    fn resolve(&self, at: NaiveDateTime) -> Option<SystemTime> {
+        match self
+            .timezone
+            .as_deref()
+            .and_then(|tz| tz.parse::<Tz>().ok())
+        {
+            Some(tz) => at.and_local_timezone(tz).earliest().map(Into::into),
+            None => at.and_local_timezone(Local).earliest().map(Into::into),
+        }
+    }
+
+    /// The event's date and times for people, e.g., "March 4, 2027,
+    /// 19:30–22:00 (Europe/Berlin)". None without a date.
+    pub fn describe_when(&self, language: Language) -> Option<String> {
+        let mut when = language.calendar_date(self.date?);
+        match (self.start_time, self.end_time) {
+            (Some(start), Some(end)) => when.push_str(&format!(
+                ", {}–{}",
+                start.format("%H:%M"),
+                end.format("%H:%M")
+            )),
+            (Some(start), None) => {
+                when.push_str(&format!(", {}", start.format("%H:%M")))
+            }
+            _ => {}
+        }
+        if let Some(tz) = &self.timezone {
+            when.push_str(&format!(" ({tz})"));
+        }
+        Some(when)
+    }
+
+    /// Log a change to a detail guests rely on, if any of them have accepted
+    /// and so may be working from the old one. Returns when it was logged.
+    fn record_detail_change(
+        &mut self,
+        field: &str,
+        description: String,
+    ) -> Option<SystemTime> {
+        if !self.attendees.iter().any(|at| at.has_accepted()) {
+            return None;
+        }
+        let now = clock::now();
+        let recent = self.detail_changes.iter().rposition(|change| {
+            change.field == field && change.at + DETAIL_SETTLE_WINDOW > now
+        });
+        if let Some(recent) = recent {
+            self.detail_changes.remove(recent);
+        }
+        self.detail_changes.push(DetailChange {
+            at: now,
+            field: field.to_string(),
+            description,
+            notified: false,
+        });
+        Some(now)
+    }
+
+    /// Whether guests pick a time slot when accepting
+    pub fn appointment_mode(&self) -> bool {
+        !self.time_slots.is_empty()
+    }
+
+    pub fn time_slot(&self, number: u32) -> Option<&TimeSlot> {
+        self.time_slots.iter().find(|slot| slot.number == number)
+    }
+
+    /// The slot accepting would book for the attendee, if any. Without a pick,
+    /// the slot they booked before is kept.
+    fn booking(
+        &self,
+        at_id: Option<Id>,
+        time_slot: Option<u32>,
+    ) -> Result<Option<u32>, RsvpError> {
+        if !self.appointment_mode() {
+            return Ok(None);
+        }
+        let current = self
+            .attendees
+            .iter()
+            .find(|at| Some(at.id) == at_id && at.has_accepted())
+            .and_then(|at| at.time_slot);
+        let Some(number) = time_slot
+            .or(current)
+            .filter(|&number| self.time_slot(number).is_some())
+        else {
+            return Err(RsvpError::SlotRequired);
+        };
+        if current != Some(number) && self.places_left(number) == 0 {
+            return Err(RsvpError::SlotFull);
+        }
+        Ok(Some(number))
+    }
+
+    /// Guests who joined through the open RSVP link
+    pub fn joined_openly(&self) -> usize {
+        self.attendees.iter().filter(|at| at.joined_openly).count()
+    }
+
+    /// Accepted guests signed up for the slot
+    pub fn booked(&self, number: u32) -> impl Iterator<Item = &Attendee> {
+        self.attendees
+            .iter()
+            .filter(move |at| at.has_accepted() && at.time_slot == Some(number))
+    }
+
+    /// Room left in the slot
+    pub fn places_left(&self, number: u32) -> usize {
+        let Some(slot) = self.time_slot(number) else {
+            return 0;
+        };
+        (slot.capacity as usize).saturating_sub(self.booked(number).count())
+    }
+
+    /// The attendee's offer or request
+    pub fn carpool_entry(&self, at_id: Id) -> Option<&CarpoolEntry> {
+        self.carpool.iter().find(|entry| entry.attendee == at_id)
+    }
+
+    /// Riders going with the driver, in the order they joined
+    pub fn riders_of(&self, driver: Id) -> impl Iterator<Item = &CarpoolEntry> {
+        self.carpool
+            .iter()
+            .filter(move |entry| entry.driver == Some(driver))
+    }
+
+    /// Seats the driver has free, zero if they aren't driving
+    pub fn seats_left(&self, driver: Id) -> usize {
+        let Some(CarpoolRole::Driver { seats }) =
+            self.carpool_entry(driver).map(|entry| entry.role)
+        else {
+            return 0;
+        };
+        (seats as usize).saturating_sub(self.riders_of(driver).count())
+    }
+
+    /// Take the attendee out of the carpool. Anyone riding with them is left
+    /// looking for a ride again.
+    fn leave_carpool(&mut self, at_id: Id) {
+        self.carpool.retain(|entry| entry.attendee != at_id);
+        for entry in self.carpool.iter_mut() {
+            if entry.driver == Some(at_id) {
+                entry.driver = None;
+            }
+        }
+    }
+
+    /// Add to the access log, or count it again if it already happened in
+    /// the same period
+    fn log_access(&mut self, kind: AccessKind, attendee: Option<Id>) {
+        self.log_access_at(coarse_now(), kind, attendee, 1);
+    }
+
+    /// Log `count` accesses at `at`, a time from `coarse_now`, which may be
+    /// earlier than the latest entries
+    fn log_access_at(
+        &mut self,
+        at: SystemTime,
+        kind: AccessKind,
+        attendee: Option<Id>,
+        count: u32,
+    ) {
+        let again = self
+            .access_log
+            .iter_mut()
+            .rev()
+            .skip_while(|entry| entry.at > at)
+            .take_while(|entry| entry.at == at)
+            .find(|entry| entry.kind == kind && entry.attendee == attendee);
+        if let Some(entry) = again {
+            entry.count += count;
+            return;
+        }
+        let position = self.access_log.partition_point(|entry| entry.at <= at);
+        self.access_log.insert(
+            position,
+            AccessEntry {
+                at,
+                kind,
+                attendee,
+                count,
+            },
+        );
+        if self.access_log.len() > MAX_ACCESS_ENTRIES {
+            let excess = self.access_log.len() - MAX_ACCESS_ENTRIES;
+            self.access_log.drain(..excess);
+        }
+    }
+
+    /// Fails if the event is frozen
+    fn ensure_editable(&self) -> Result<(), UpdateEventError> {
+        if self.frozen {
+            return Err(UpdateEventError::Frozen);
+        }
+        Ok(())
+    }
+
+    /// Fails if an edit made against `revision` would overwrite later edits.
+    /// Edits which name no revision always apply.
+    fn ensure_revision(
+        &self,
+        revision: Option<u64>,
+    ) -> Result<(), UpdateEventError> {
+        if revision.is_some_and(|rev| rev != self.revision) {
+            return Err(UpdateEventError::Conflict(Box::new(self.clone())));
+        }
+        Ok(())
+    }
+
+    /// Whether guests can no longer change their response, because the
+    /// organizer locked responses, the deadline has passed, or the event is
+    /// frozen
+    pub fn responses_closed(&self, now: SystemTime) -> bool {
+        self.frozen
+            || self.responses_locked
+            || self.rsvp_deadline.is_some_and(|d| now >= d)
+    }
+
+    /// When the event becomes eligible for purging
+    pub fn expires_at(&self) -> SystemTime {
+        let start = self.extended.unwrap_or(self.created).max(self.created);
+        clock::add_clamped(start, retention().event_lifetime)
+    }
+
+    /// When the event will be purged, or `None` if it's pinned. An organizer
+    /// who was warned gets at least the full warning period.
+    pub fn purge_at(&self) -> Option<SystemTime> {
+        if self.pinned {
+            return None;
+        }
+        let expires = self.expires_at();
+        Some(match self.purge_warning_sent {
+            Some(warned) => expires.max(warned + PURGE_WARNING_PERIOD),
+            None => expires,
+        })
+    }
+
+    /// What a purge at `now` does with the event
+    fn purge_steps(&self, now: SystemTime) -> PurgeSteps {
+        let warn = !self.pinned
+            && self.purge_warning_sent.is_none()
+            && notify::has_channel(self.notify_webhook.as_deref())
+            && now + PURGE_WARNING_PERIOD >= self.expires_at();
+        let Some(purge_at) = self.purge_at() else {
+            return PurgeSteps { warn, purge: false };
+        };
+        let purge = if self.created > now {
+            let name = self.name.clone().unwrap_or("<Untitled>".to_string());
+            log::warn!(
+                "Purging event \"{name}\" with creation time after current \
+                 time"
+            );
+            true
+        } else {
+            // being warned now gives the organizer the full warning period
+            !warn && now >= purge_at
+        };
+        PurgeSteps { warn, purge }
+    }
+
+    /// Where a newly added attendee goes, i.e., the end of the list
+    fn next_order(&self) -> u32 {
+        self.attendees
+            .iter()
+            .map(|at| at.order + 1)
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or("Untitled Event".to_string())
+    }
+
+    pub fn decline_reasons(&self) -> Vec<String> {
+        match self.decline_reasons.as_ref() {
+            Some(reasons) => reasons.clone(),
+            None => DEFAULT_DECLINE_REASONS
+                .iter()
+                .map(|r| r.to_string())
+                .collect(),
+        }
+    }
+
+    /// The attendee's value of every custom field by name, null where they
+    /// have none
+    pub fn field_values(
+        &self,
+        attendee: &Attendee,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        self.custom_fields
+            .iter()
+            .map(|field| {
+                let value = attendee
+                    .fields
+                    .get(&field.name)
+                    .map_or(serde_json::Value::Null, |v| {
+                        field.kind.value_json(v)
+                    });
+                (field.name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// How many guests who aren't coming gave each reason, in the order the
+    /// reasons are offered. Reasons which are no longer offered come last.
+    pub fn decline_summary(&self) -> Vec<(String, usize)> {
+        let mut summary: Vec<(String, usize)> = self
+            .decline_reasons()
+            .into_iter()
+            .map(|reason| (reason, 0))
+            .collect();
+        let given = self
+            .attendees
+            .iter()
+            .filter(|at| !at.has_accepted())
+            .filter_map(|at| at.decline_reason.as_ref());
+        for reason in given {
+            match summary.iter_mut().find(|(r, _)| r == reason) {
+                Some((_, count)) => *count += 1,
+                None => summary.push((reason.clone(), 1)),
+            }
+        }
+        summary
+    }
+
+    /// Whether a digest is due now
+    fn digest_due(&self, now: SystemTime) -> bool {
+        let Some(period) = self.digest.period() else {
+            return false;
+        };
+        self.last_digest.unwrap_or(self.created) + period <= now
+    }
+
+    /// Summarize RSVP changes since the last digest. None if nothing changed.
+    fn digest(&self) -> Option<Notification> {
+        let since = self.last_digest.unwrap_or(self.created);
+        let changes = self
+            .attendees
+            .iter()
+            .flat_map(|at| at.rsvp_history.iter())
+            .filter(|change| change.at > since);
+        let (mut accepted, mut unsure, mut withdrew) = (0, 0, 0);
+        for change in changes {
+            match change.rsvp {
+                Rsvp::Yes => accepted += 1,
+                Rsvp::Maybe => unsure += 1,
+                Rsvp::No | Rsvp::Pending => withdrew += 1,
+            }
+        }
+        if accepted == 0 && unsure == 0 && withdrew == 0 {
+            return None;
+        }
+        let coming =
+            self.attendees.iter().filter(|at| at.has_accepted()).count();
+        let heads = self.headcount();
+        let bringing = if heads > coming {
+            format!(", {heads} people with whoever they bring")
+        } else {
+            String::new()
+        };
+
+        Some(Notification {
+            kind: NotificationKind::Digest,
+            priority: Priority::Normal,
+            event_id: self.id,
+            event_name: self.display_name(),
+            subject: format!("RSVP digest for \"{}\"", self.display_name()),
+            message: format!(
+                "Since the last digest {accepted} guests accepted, {unsure} \
+                 said maybe, and {withdrew} withdrew. {coming} of {} guests \
+                 are coming{bringing}.",
+                self.attendees.len()
+            ),
+            link: Some(self.manage_link()),
+            attendee_id: None,
+            event_webhook: self.notify_webhook.clone(),
+        })
+    }
+
+    fn purge_warning(&self, now: SystemTime) -> Notification {
+        let days = self
+            .expires_at()
+            .max(now + PURGE_WARNING_PERIOD)
+            .duration_since(now)
+            .unwrap_or_default()
+            .as_secs()
+            / (60 * 60 * 24);
+        Notification {
+            kind: NotificationKind::PurgeWarning,
+            priority: Priority::Normal,
+            event_id: self.id,
+            event_name: self.display_name(),
+            subject: format!(
+                "\"{}\" will be deleted soon",
+                self.display_name()
+            ),
+            message: format!(
+                "\"{}\" and all of its invitation links will be deleted in \
+                 {days} days. Its manage page can keep it for another {}.",
+                self.display_name(),
+                clock::describe_duration(retention().event_lifetime)
+            ),
+            link: Some(self.manage_link()),
+            attendee_id: None,
+            event_webhook: self.notify_webhook.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Attendee {
-    pub id: u64,
+    pub id: Id,
     pub name: String,
     pub custom_html: String,
-    pub has_accepted: bool,
+    /// An alternate invitation the organizer is trying out. Guests never see
+    /// it until it's promoted to `custom_html`.
+    #[serde(default)]
+    pub draft_html: Option<String>,
+    /// Called `has_accepted` and a bool before guests could answer maybe
+    #[serde(
+        default,
+        alias = "has_accepted",
+        deserialize_with = "rsvp_or_accepted"
+    )]
+    pub rsvp: Rsvp,
+    /// How many people the guest brings along
+    #[serde(default)]
+    pub plus_ones: u8,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Set when the attendee erased their data from the event
+    #[serde(default)]
+    pub anonymized: bool,
+    /// Every change to `rsvp`, oldest first
+    #[serde(default)]
+    pub rsvp_history: Vec<RsvpChange>,
+    /// Times the invitation page was loaded
+    #[serde(default)]
+    pub view_count: u64,
+    /// The most recent views, rounded down to `VIEW_GRANULARITY` so they can't
+    /// be used to track the guest closely. Oldest first.
+    #[serde(default)]
+    pub recent_views: Vec<SystemTime>,
+    /// When the invitation page was first loaded, at the same resolution as
+    /// `recent_views`
+    #[serde(default)]
+    pub first_viewed: Option<SystemTime>,
+    /// When the organizer last sent this guest a reminder
+    #[serde(default)]
+    pub last_nudged: Option<SystemTime>,
+    /// What the guest told the organizer when they withdrew
+    #[serde(default)]
+    pub withdraw_note: Option<String>,
+    /// Which of the event's decline reasons the guest chose when they withdrew
+    #[serde(default)]
+    pub decline_reason: Option<String>,
+    /// What the guest told the organizer when they accepted, e.g., "bringing
+    /// dessert"
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// The organizer's own notes about the guest, never shown to them
+    #[serde(default)]
+    pub notes: String,
+    /// Position on the manage page, lowest first. Ties keep the order the
+    /// attendees were added in.
+    #[serde(default)]
+    pub order: u32,
+    /// Language of the built-in pages the guest sees
+    #[serde(default)]
+    pub language: Language,
+    /// Number of the time slot the guest signed up for
+    #[serde(default)]
+    pub time_slot: Option<u32>,
+    /// Whether the guest added themselves through the open RSVP link
+    #[serde(default)]
+    pub joined_openly: bool,
+    /// When the organizer checked the guest in at the event
+    #[serde(default)]
+    pub checked_in: Option<SystemTime>,
+    /// The invitation link as registered with the URL shortener
+    #[serde(default)]
+    pub short_url: Option<String>,
+    /// When a scheduled send last delivered the invitation
+    #[serde(default)]
+    pub invitation_sent: Option<SystemTime>,
+    /// Values of the event's custom fields by name, as text. Fields the
+    /// organizer left empty are absent.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
 }
 
-/// Attempt to open the database. This function creates a new database if an
-/// existing one could not be read or if the data from the existing database
-/// could not be parsed.
-///
-/// To prevent multiple tasks from reading and writing back the file at the same
-/// time, a static lock is acquired before opening the file. The lock guard is
-/// then returned out for the caller to drop when they are done with the data.
-/// The save_db function consumes the lock as an argument, which it drops after
-/// writing.
-async fn open_db<'a>() -> Result<(EventDB, MutexGuard<'a, ()>), ()> {
-    let lock = DB_GUARD.lock().await;
-
-    let data = match tokio::fs::read(DB_PATH).await {
-        Ok(d) => d,
-        Err(_) => {
-            // if failed, it's probably the first run
-            log::info!("Unable to open an existing database. Creating new.");
-            let def_struct = EventDB::default();
-            let def = serde_cbor::to_vec(&def_struct)
-                .expect("Default structure is serializable");
-            if tokio::fs::write(DB_PATH, &def).await.is_err() {
-                log::error!("Could not create database file");
-                return Err(());
+/// A request to an RSVP endpoint which looked automated and was ignored
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BotFlag {
+    pub at: SystemTime,
+    pub attendee: Id,
+    pub reason: String,
+}
+
+/// A guest's invitation which failed to render, so they were shown the
+/// default one instead
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RenderError {
+    pub at: SystemTime,
+    pub attendee: Id,
+    pub message: String,
+}
+
+/// Something done with the event, as its access log shows it. Times are
+/// rounded down to `VIEW_GRANULARITY` like views, and the same thing done
+/// again within it is counted rather than logged again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccessEntry {
+    pub at: SystemTime,
+    pub kind: AccessKind,
+    /// None for the organizer
+    pub attendee: Option<Id>,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessKind {
+    InvitationViewed,
+    Accepted,
+    AnsweredMaybe,
+    Declined,
+    ManagePageOpened,
+}
+
+impl AccessKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AccessKind::InvitationViewed => "Opened the invitation",
+            AccessKind::Accepted => "Accepted",
+            AccessKind::AnsweredMaybe => "Answered maybe",
+            AccessKind::Declined => "Declined",
+            AccessKind::ManagePageOpened => "Opened the manage page",
+        }
+    }
+}
+
+/// News the organizer posted for the guests, e.g., a change of venue
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Announcement {
+    /// Names the announcement in links and forms
+    #[serde(default)]
+    pub number: u32,
+    pub at: SystemTime,
+    pub text: String,
+}
+
+/// Something bought for the event, and who paid for it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Expense {
+    /// Names the expense in links and forms
+    #[serde(default)]
+    pub number: u32,
+    pub item: String,
+    pub cents: u64,
+    /// Name of whoever paid, as the organizer wrote it
+    pub payer: String,
+}
+
+/// A place on Earth, in degrees
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Something on the event's agenda, e.g., doors opening or dinner
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AgendaItem {
+    /// Names the item in links and forms
+    #[serde(default)]
+    pub number: u32,
+    pub at: SystemTime,
+    pub title: String,
+    #[serde(default)]
+    pub details: String,
+}
+
+/// A column the organizer added to the guest list, e.g., "T-shirt size"
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CustomField {
+    /// Names the field in links and forms
+    #[serde(default)]
+    pub number: u32,
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    Text,
+    Number,
+    Boolean,
+}
+
+impl FieldKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldKind::Text => "text",
+            FieldKind::Number => "number",
+            FieldKind::Boolean => "boolean",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(FieldKind::Text),
+            "number" => Some(FieldKind::Number),
+            "boolean" => Some(FieldKind::Boolean),
+            _ => None,
+        }
+    }
+
+    /// The value as it's stored, e.g., "true" for a checked box. None if it
+    /// isn't a value of this kind.
+    fn normalize(&self, value: &str) -> Option<String> {
+        let value = value.trim();
+        match self {
+            FieldKind::Text => {
+                Some(value.chars().take(MAX_FIELD_VALUE_CHARS).collect())
             }
-            return Ok((def_struct, lock));
+            FieldKind::Number => {
+                value.parse::<f64>().ok().filter(|n| n.is_finite())?;
+                Some(value.to_string())
+            }
+            FieldKind::Boolean => match value {
+                "true" | "on" | "yes" | "1" => Some("true".to_string()),
+                "false" | "off" | "no" | "0" => Some("false".to_string()),
+                _ => None,
+            },
+        }
+    }
+
+    /// A stored value as JSON, so numbers and booleans compare as such in
+    /// templates and exports
+    fn value_json(&self, value: &str) -> serde_json::Value {
+        match self {
+            FieldKind::Text => value.into(),
+            FieldKind::Number => value
+                .parse::<serde_json::Number>()
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            FieldKind::Boolean => (value == "true").into(),
         }
+    }
+}
+
+/// An attendee merged into another as a duplicate
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MergedAttendee {
+    pub from: Id,
+    pub into: Id,
+}
+
+/// A link anyone can RSVP through, for events where whoever comes is welcome
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpenRsvp {
+    /// Names the link. Unlike attendee IDs, it's meant to be shared.
+    pub id: Id,
+    /// Most guests who can join through it, unlimited if `None`
+    pub capacity: Option<u32>,
+}
+
+/// Invitations to deliver at a later time, see `sending`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScheduledSend {
+    /// Names the send in links
+    pub number: u32,
+    pub at: SystemTime,
+    /// The one guest to send to. None for every guest whose invitation
+    /// hasn't gone out by then.
+    pub attendee: Option<Id>,
+}
+
+/// An invitation to deliver now, taken off the schedule by `take_due_sends`
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub event_id: Id,
+    pub event_name: String,
+    pub event_webhook: Option<String>,
+    pub attendee_id: Id,
+    pub name: String,
+    pub email: Option<String>,
+}
+
+/// A time guests can sign up for, e.g., a 15-minute visit or a shift
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimeSlot {
+    /// Names the slot in links and forms
+    pub number: u32,
+    pub start: SystemTime,
+    pub length: Duration,
+    /// How many guests it takes
+    pub capacity: u32,
+}
+
+impl TimeSlot {
+    pub fn end(&self) -> SystemTime {
+        self.start + self.length
+    }
+}
+
+/// An accepted guest's offer of seats or request for a ride
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CarpoolEntry {
+    /// Names the entry in links, since attendee IDs have to stay secret
+    pub number: u32,
+    pub attendee: Id,
+    pub role: CarpoolRole,
+    /// Where from and when, e.g., "Leaving downtown at 5pm"
+    pub note: String,
+    /// For riders, the driver they're going with
+    pub driver: Option<Id>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CarpoolRole {
+    Driver { seats: u8 },
+    Rider,
+}
+
+/// What a guest does on the carpool board
+#[derive(Debug)]
+pub enum CarpoolChange {
+    /// Offer seats, or change the offer. Riders beyond the seats are left
+    /// looking again.
+    Offer {
+        seats: u8,
+        note: String,
+    },
+    /// Ask for a ride, or change the request
+    Request {
+        note: String,
+    },
+    /// Ride with a driver who has seats left, by the number of their offer
+    Join {
+        offer: u32,
+    },
+    Leave,
+}
+
+#[derive(Debug)]
+pub enum CarpoolError {
+    Find(FindEventError),
+    /// The carpool isn't open, the event is frozen, or the guest hasn't
+    /// accepted
+    Unavailable,
+    /// The offer is gone or has no seats left
+    Full,
+}
+
+impl From<FindEventError> for CarpoolError {
+    fn from(value: FindEventError) -> Self {
+        CarpoolError::Find(value)
+    }
+}
+
+/// A change to a detail guests rely on, made after some had accepted
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DetailChange {
+    pub at: SystemTime,
+    /// Which detail changed, e.g., "name"
+    pub field: String,
+    /// For guests, e.g., "renamed to \"Picnic\""
+    pub description: String,
+    /// Whether guests were notified of it
+    #[serde(default)]
+    pub notified: bool,
+}
+
+/// A guest's answer to their invitation
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Rsvp {
+    /// The guest hasn't answered yet
+    #[default]
+    Pending,
+    Yes,
+    Maybe,
+    No,
+}
+
+impl Rsvp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rsvp::Pending => "pending",
+            Rsvp::Yes => "yes",
+            Rsvp::Maybe => "maybe",
+            Rsvp::No => "no",
+        }
+    }
+}
+
+/// Give the numbers left at 0 ones following the highest in use
+fn number_unnumbered<'a>(numbers: impl Iterator<Item = &'a mut u32>) {
+    let mut numbers: Vec<_> = numbers.collect();
+    let first = numbers.iter().map(|n| **n + 1).max().unwrap_or(1);
+    let unnumbered = numbers.iter_mut().filter(|n| ***n == 0);
+    for (number, next) in unnumbered.zip(first..) {
+        **number = next;
+    }
+}
+
+/// An `Rsvp`, or the bool stored before there was more than accepting or not
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredRsvp {
+    Rsvp(Rsvp),
+    Accepted(bool),
+}
+
+/// Read an attendee's answer. Not having accepted used to cover both not
+/// having answered and having withdrawn, see `Event::upgrade_rsvps`.
+fn rsvp_or_accepted<'de, D: Deserializer<'de>>(d: D) -> Result<Rsvp, D::Error> {
+    Ok(match StoredRsvp::deserialize(d)? {
+        StoredRsvp::Rsvp(rsvp) => rsvp,
+        StoredRsvp::Accepted(true) => Rsvp::Yes,
+        StoredRsvp::Accepted(false) => Rsvp::Pending,
+    })
+}
+
+/// Read the answer of an RSVP change, where not accepting always meant
+/// withdrawing
+fn rsvp_or_withdrawn<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Rsvp, D::Error> {
+    Ok(match StoredRsvp::deserialize(d)? {
+        StoredRsvp::Rsvp(rsvp) => rsvp,
+        StoredRsvp::Accepted(true) => Rsvp::Yes,
+        StoredRsvp::Accepted(false) => Rsvp::No,
+    })
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RsvpChange {
+    pub at: SystemTime,
+    /// Called `accepted` and a bool before guests could answer maybe
+    #[serde(alias = "accepted", deserialize_with = "rsvp_or_withdrawn")]
+    pub rsvp: Rsvp,
+}
+
+/// How many guests gave each answer
+#[derive(Serialize, Default, Debug)]
+pub struct RsvpCounts {
+    pub pending: usize,
+    pub yes: usize,
+    pub maybe: usize,
+    pub no: usize,
+}
+
+/// Everything stored about an attendee, as handed to them on request
+#[derive(Serialize, Debug)]
+pub struct AttendeeExport {
+    pub event_name: String,
+    pub attendee_id: Id,
+    pub name: String,
+    pub email: Option<String>,
+    pub invitation_html: String,
+    pub has_accepted: bool,
+    pub rsvp: Rsvp,
+    pub plus_ones: u8,
+    pub withdraw_note: Option<String>,
+    pub decline_reason: Option<String>,
+    pub comment: Option<String>,
+    pub rsvp_history: Vec<RsvpChangeExport>,
+    pub view_count: u64,
+    /// Seconds since the Unix epoch, rounded down to the hour
+    pub first_viewed: Option<u64>,
+    /// Seconds since the Unix epoch, rounded down to the hour
+    pub recent_views: Vec<u64>,
+    /// Seconds since the Unix epoch
+    pub checked_in: Option<u64>,
+    /// The event's custom fields, see `Event::field_values`
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RsvpChangeExport {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    pub accepted: bool,
+    pub rsvp: Rsvp,
+}
+
+impl Attendee {
+    /// A placeholder attendee for the organizer to fill in
+    pub fn new(id: Id) -> Self {
+        Self {
+            id,
+            name: "Unnamed".to_string(),
+            custom_html: "<html></html>".to_string(),
+            draft_html: None,
+            rsvp: Rsvp::Pending,
+            plus_ones: 0,
+            email: None,
+            anonymized: false,
+            rsvp_history: Vec::new(),
+            view_count: 0,
+            recent_views: Vec::new(),
+            first_viewed: None,
+            last_nudged: None,
+            withdraw_note: None,
+            decline_reason: None,
+            comment: None,
+            notes: String::new(),
+            order: 0,
+            language: Language::default(),
+            time_slot: None,
+            joined_openly: false,
+            checked_in: None,
+            short_url: None,
+            invitation_sent: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the guest said they're coming
+    pub fn has_accepted(&self) -> bool {
+        self.rsvp == Rsvp::Yes
+    }
+
+    /// Whether the guest hasn't opened their invitation and hasn't been
+    /// reminded recently
+    pub fn nudge_due(&self, now: SystemTime) -> bool {
+        self.first_viewed.is_none()
+            && self.rsvp == Rsvp::Pending
+            && !self.anonymized
+            && self
+                .last_nudged
+                .is_none_or(|at| at + nudge_cooldown() <= now)
+    }
+
+    /// Let the organizer know a guest who had accepted isn't coming after all
+    fn withdrawal(&self, event: &Event) -> Notification {
+        // guests who now say maybe haven't withdrawn yet, but may well
+        let withdrew = match self.rsvp {
+            Rsvp::Maybe => "is no longer sure about",
+            _ => "withdrew from",
+        };
+        let mut message = format!(
+            "{} had accepted but {withdrew} \"{}\".",
+            self.name,
+            event.display_name()
+        );
+        if let Some(reason) = self.decline_reason.as_ref() {
+            message += &format!(" Reason: {reason}.");
+        }
+        if let Some(note) = self.withdraw_note.as_ref() {
+            message += &format!(" They wrote: \"{note}\"");
+        }
+        Notification {
+            kind: NotificationKind::Withdrawal,
+            priority: Priority::High,
+            event_id: event.id,
+            event_name: event.display_name(),
+            subject: format!(
+                "{} {withdrew} \"{}\"",
+                self.name,
+                event.display_name()
+            ),
+            message,
+            link: Some(event.manage_link()),
+            attendee_id: Some(self.id),
+            event_webhook: event.notify_webhook.clone(),
+        }
+    }
+
+    fn details_changed(
+        &self,
+        event: &Event,
+        descriptions: &[String],
+    ) -> Notification {
+        Notification {
+            kind: NotificationKind::DetailsChanged,
+            priority: Priority::Normal,
+            event_id: event.id,
+            event_name: event.display_name(),
+            subject: format!("\"{}\" has changed", event.display_name()),
+            message: format!(
+                "{} accepted their invitation to \"{}\" before it changed: \
+                 {}. Forward them the link so they have the latest details.",
+                self.name,
+                event.display_name(),
+                descriptions.join("; ")
+            ),
+            link: Some(format!(
+                "{}/invite/attend/{}",
+                settings::public_url(),
+                self.id
+            )),
+            attendee_id: Some(self.id),
+            event_webhook: event.notify_webhook.clone(),
+        }
+    }
+
+    fn nudge(&self, event: &Event) -> Notification {
+        Notification {
+            kind: NotificationKind::Nudge,
+            priority: Priority::Normal,
+            event_id: event.id,
+            event_name: event.display_name(),
+            subject: format!(
+                "Reminder: you're invited to \"{}\"",
+                event.display_name()
+            ),
+            message: format!(
+                "{} hasn't opened their invitation to \"{}\" yet. Forward \
+                 them the link to remind them.",
+                self.name,
+                event.display_name()
+            ),
+            link: Some(format!(
+                "{}/invite/attend/{}",
+                settings::public_url(),
+                self.id
+            )),
+            attendee_id: Some(self.id),
+            event_webhook: event.notify_webhook.clone(),
+        }
+    }
+
+    pub fn export(&self, event: &Event) -> AttendeeExport {
+        AttendeeExport {
+            event_name: event.display_name(),
+            attendee_id: self.id,
+            name: self.name.clone(),
+            email: self.email.clone(),
+            invitation_html: self.custom_html.clone(),
+            has_accepted: self.has_accepted(),
+            rsvp: self.rsvp,
+            plus_ones: self.plus_ones,
+            withdraw_note: self.withdraw_note.clone(),
+            decline_reason: self.decline_reason.clone(),
+            comment: self.comment.clone(),
+            rsvp_history: self
+                .rsvp_history
+                .iter()
+                .map(|change| RsvpChangeExport {
+                    at: unix_secs(change.at),
+                    accepted: change.rsvp == Rsvp::Yes,
+                    rsvp: change.rsvp,
+                })
+                .collect(),
+            view_count: self.view_count,
+            first_viewed: self.first_viewed.map(unix_secs),
+            recent_views: self
+                .recent_views
+                .iter()
+                .map(|&at| unix_secs(at))
+                .collect(),
+            checked_in: self.checked_in.map(unix_secs),
+            fields: event.field_values(self),
+        }
+    }
+
+    /// The attendee's value of a custom field as text, as the manage page
+    /// shows it. Unset boxes are unchecked.
+    pub fn field_text(&self, field: &CustomField) -> String {
+        match self.fields.get(&field.name) {
+            Some(value) => value.clone(),
+            None if field.kind == FieldKind::Boolean => "false".to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Erase everything which identifies the attendee or was written for
+    /// them, keeping their RSVP so headcounts stay correct
+    fn anonymize(&mut self) {
+        self.name = "Anonymous guest".to_string();
+        self.custom_html = String::new();
+        self.draft_html = None;
+        self.email = None;
+        self.withdraw_note = None;
+        self.comment = None;
+        self.notes = String::new();
+        self.fields.clear();
+        self.anonymized = true;
+    }
+}
+
+/// Read a database file in an earlier layout and convert it. Unlike loading
+/// the current layout, this holds the whole file in memory, which is fine for
+/// the one time it's converted.
+fn read_legacy(path: &str) -> Option<(EventDB, &'static str)> {
+    let data = std::fs::read(path).ok()?;
+    legacy::convert(&format::decode(&data).ok()?)
+}
+
+/// Load the database as it is on disk and check it for problems, without
+/// creating or replacing it. Must be called before `start`, since it reads
+/// the file directly.
+pub async fn check_integrity() -> integrity::Report {
+    let check = || match store::storage() {
+        Storage::Cbor => check_cbor_file(),
+        Storage::Sqlite if Path::new(SQLITE_PATH).exists() => {
+            let bytes = std::fs::metadata(SQLITE_PATH)
+                .map(|m| m.len())
+                .unwrap_or_default();
+            check_size(bytes);
+            match sqlite::check(SQLITE_PATH) {
+                Ok(db) => integrity::check(&db, bytes),
+                Err(e) => integrity::Report::unloadable(bytes, e),
+            }
+        }
+        Storage::Sqlite => {
+            let mut report = check_cbor_file();
+            if Path::new(CBOR_PATH).exists() {
+                report.warnings.push(format!(
+                    "Database will be moved from {CBOR_PATH} into \
+                     {SQLITE_PATH}"
+                ));
+            }
+            report
+        }
+    };
+    tokio::task::spawn_blocking(check)
+        .await
+        .unwrap_or_else(|e| integrity::Report::unloadable(0, e.to_string()))
+}
+
+/// Check the CBOR file, as `check_integrity` does
+fn check_cbor_file() -> integrity::Report {
+    let file = match File::open(CBOR_PATH) {
+        Ok(file) => file,
+        // nothing has been saved yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return integrity::Report::default();
+        }
+        Err(e) => {
+            return integrity::Report::unloadable(
+                0,
+                format!("Database could not be read: {e}"),
+            );
+        }
+    };
+    let bytes = file.metadata().map(|m| m.len()).unwrap_or_default();
+    check_size(bytes);
+    match format::read(file) {
+        Ok(db) => {
+            let mut report = integrity::check(&db, bytes);
+            let log = format!("{CBOR_PATH}.log");
+            if Path::new(&log).exists() {
+                report.warnings.push(format!(
+                    "Changes in {log} weren't checked and will be replayed"
+                ));
+            }
+            report
+        }
+        Err(ReadError::Parse(e)) => match read_legacy(CBOR_PATH) {
+            Some((db, layout)) => {
+                let mut report = integrity::check(&db, bytes);
+                report.warnings.push(format!(
+                    "Database is in the earlier \"{layout}\" layout and \
+                     will be converted"
+                ));
+                report
+            }
+            None => integrity::Report::unloadable(
+                bytes,
+                format!("Database could not be parsed: {e}"),
+            ),
+        },
+        Err(e) => integrity::Report::unloadable(
+            bytes,
+            format!("Database could not be loaded: {e}"),
+        ),
+    }
+}
+
+/// Go through everything a change to the database involves without changing
+/// it: read and parse the file, serialize it again, and write the result to a
+/// scratch file next to it. Catches corruption, permission problems, and a
+/// full disk before they cost a guest their response.
+pub async fn check_storage() -> Result<(), String> {
+    // holding the database keeps saves from changing the file meanwhile
+    let mut db = open_db()
+        .await
+        .map_err(|_| "Database could not be loaded".to_string())?;
+    let mut copy = EventDB::default();
+    db.scan(Scan::All, |event| copy.events.push(event.clone()))
+        .map_err(|_| "Database could not be loaded".to_string())?;
+
+    let check = move || {
+        match store::storage() {
+            Storage::Cbor => match File::open(CBOR_PATH) {
+                Ok(file) => {
+                    format::read(file).map_err(|e| {
+                        format!("Database could not be loaded: {e}")
+                    })?;
+                }
+                // nothing has been saved yet
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(format!("Database could not be read: {e}"))
+                }
+            },
+            Storage::Sqlite => {
+                sqlite::check(SQLITE_PATH)?;
+            }
+        }
+        let written = File::create(PROBE_PATH)
+            .and_then(|file| format::write(file, &copy));
+        let _ = std::fs::remove_file(PROBE_PATH);
+        written
+            .map(|_| ())
+            .map_err(|e| format!("Database could not be written: {e}"))
+    };
+    let checked = tokio::task::spawn_blocking(check).await;
+    drop(db);
+    checked.map_err(|e| e.to_string())?
+}
+
+/// What a purge does with an event
+#[derive(Debug, PartialEq, Eq)]
+struct PurgeSteps {
+    /// Warn the organizer that the event is about to be purged
+    warn: bool,
+    purge: bool,
+}
+
+/// Open the event database and delete entries that are older than the
+/// configured lifetime. Returns the number of events deleted.
+///
+/// Organizers of events which will expire within `PURGE_WARNING_PERIOD` are
+/// warned first. Events are held back until a full warning period has passed
+/// since the warning, so nobody loses an event without notice.
+pub async fn purge_old_events() -> Result<usize, ()> {
+    let Ok(mut db) = open_db().await else {
+        log::warn!("Purge task could not open the database");
+        return Err(());
+    };
+
+    let now = clock::now();
+    let mut to_warn = Vec::new();
+    let mut expired = Vec::new();
+    let scanned = db.scan(Scan::All, |ev| {
+        let steps = ev.purge_steps(now);
+        if steps.warn {
+            to_warn.push(ev.id);
+        }
+        if steps.purge {
+            expired.push(ev.clone());
+        }
+    });
+    if scanned.is_err() {
+        log::warn!("Purge task could not read the database");
+        return Err(());
+    }
+
+    let mut warnings = Vec::new();
+    for ev_id in to_warn {
+        if let Some(ev) = db.event(ev_id).map_err(|_| ())? {
+            ev.purge_warning_sent = Some(now);
+            warnings.push(ev.purge_warning(now));
+        }
+    }
+
+    let mode = PURGE_MODE.get().copied().unwrap_or_default();
+    let mut purged = 0;
+    let mut covers = Vec::new();
+    for ev in expired {
+        match mode {
+            PurgeMode::Delete => covers.extend(ev.cover_image.clone()),
+            // archived events keep their covers, like the rest of their data
+            PurgeMode::Archive => match archive::write(&ev).await {
+                Ok(path) => {
+                    log::info!("Archived event {} to {}", ev.id, path.display())
+                }
+                Err(e) => {
+                    // better to keep an expired event than to lose it
+                    log::error!("Failed to archive event {}: {e}", ev.id);
+                    continue;
+                }
+            },
+        }
+        db.remove_event(ev.id);
+        purged += 1;
+    }
+
+    if save_db(db).await.is_err() {
+        log::warn!("Purge task could not save database");
+        return Err(());
+    }
+    for name in covers {
+        cover::remove(&name).await;
+    }
+
+    // warnings are recorded before they are sent so a failed delivery is not
+    // repeated on every purge
+    for warning in warnings.iter() {
+        notify::send(warning).await;
+    }
+    Ok(purged)
+}
+
+/// Give events made before there were organizer keys one, which shuts
+/// their pages to anyone without it. Returns the manage links of the events
+/// given keys, which is the only time they're told.
+pub async fn assign_organizer_keys() -> Result<Vec<String>, ()> {
+    let mut db = open_db().await?;
+    let mut keyless = Vec::new();
+    db.scan(Scan::All, |ev| {
+        if ev.organizer_key.is_none() {
+            keyless.push(ev.id);
+        }
+    })
+    .map_err(|_| ())?;
+    if keyless.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut links = Vec::new();
+    for ev_id in keyless {
+        if let Some(ev) = db.event(ev_id).map_err(|_| ())? {
+            ev.organizer_key = Some(organizer::new_key());
+            links.push(ev.manage_link());
+        }
+    }
+    save_db(db).await?;
+    Ok(links)
+}
+
+/// Draw IDs until one is found which `is_taken` rejects, up to a bounded
+/// number of attempts
+async fn generate_unique_id(is_taken: impl FnMut(Id) -> bool) -> Option<Id> {
+    ids::with_id_source(|source| unique_id(source, is_taken)).await
+}
+
+/// `generate_unique_id`, drawing from `source`
+fn unique_id(
+    source: &mut dyn IdSource,
+    mut is_taken: impl FnMut(Id) -> bool,
+) -> Option<Id> {
+    for _ in 0..MAX_ID_ATTEMPTS {
+        let id = source.next_id();
+        if !is_taken(id) {
+            return Some(id);
+        }
+        log::warn!("Generated ID collides with an existing record. Retrying.");
+    }
+    log::error!("Could not generate a unique ID in {MAX_ID_ATTEMPTS} attempts");
+    None
+}
+
+#[derive(Debug)]
+pub enum CreateEventError {
+    Database(String),
+    /// The database reached its size limit and new events are turned away
+    Full,
+    /// The event to copy doesn't exist
+    NotFound(String),
+}
+
+impl From<FindEventError> for CreateEventError {
+    fn from(value: FindEventError) -> Self {
+        match value {
+            FindEventError::Database(e) => CreateEventError::Database(e),
+            FindEventError::NotFound(e) => CreateEventError::NotFound(e),
+        }
+    }
+}
+
+/// Turn new events away if the database reached its size limit
+async fn refuse_if_full() -> Result<(), CreateEventError> {
+    if let Some(limit) = size_limit().filter(|l| l.refuse_new_events) {
+        let bytes = tokio::fs::metadata(store::path())
+            .await
+            .map(|m| m.len())
+            .unwrap_or_default();
+        if bytes >= limit.max_bytes {
+            log::warn!("Refused a new event since the database is full");
+            return Err(CreateEventError::Full);
+        }
+    }
+    Ok(())
+}
+
+/// An event just made, with the key to its organizer's pages
+#[derive(Debug)]
+pub struct NewEvent {
+    pub id: Id,
+    pub organizer_key: String,
+}
+
+pub async fn create_event() -> Result<NewEvent, CreateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        CreateEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    refuse_if_full().await?;
+
+    let ev_id = generate_unique_id(|id| db.event_id_taken(id)).await.ok_or(
+        CreateEventError::Database(
+            "Could not allocate a new event ID".to_string(),
+        ),
+    )?;
+    let organizer_key = organizer::new_key();
+    db.insert_event(Event {
+        organizer_key: Some(organizer_key.clone()),
+        ..Event::new(ev_id)
+    });
+
+    save_db(db).await.map_err(|_| {
+        CreateEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(NewEvent {
+        id: ev_id,
+        organizer_key,
+    })
+}
+
+/// Start a new event from a copy of another, e.g., for one held every year.
+/// The copy has the name, the organizer's pages, and the guests with their
+/// invitations, under new IDs, and every guest's response starts over.
+/// Returns the new event's ID.
+pub async fn clone_event(ev_id: Id) -> Result<NewEvent, CreateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        CreateEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    let Some(source) = db.get(Lookup::Event(ev_id))? else {
+        return Err(CreateEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        ));
+    };
+    refuse_if_full().await?;
+
+    let new_id = generate_unique_id(|id| db.event_id_taken(id)).await.ok_or(
+        CreateEventError::Database(
+            "Could not allocate a new event ID".to_string(),
+        ),
+    )?;
+    let organizer_key = organizer::new_key();
+    db.insert_event(Event {
+        name: source.name,
+        thanks_html: source.thanks_html,
+        withdraw_html: source.withdraw_html,
+        decline_reasons: source.decline_reasons,
+        organizer_key: Some(organizer_key.clone()),
+        ..Event::new(new_id)
+    });
+    let mut guests = source.attendees;
+    guests.retain(|at| !at.anonymized);
+    guests.sort_by_key(|at| at.order);
+    for guest in guests {
+        // IDs handed out in this loop are already in the copy, so they
+        // aren't handed out twice
+        let at_id = generate_unique_id(|id| db.attendee_id_taken(id))
+            .await
+            .ok_or(CreateEventError::Database(
+                "Could not allocate a new attendee ID".to_string(),
+            ))?;
+        let event = db.event(new_id)?.expect("The copy was just inserted");
+        event.attendees.push(Attendee {
+            name: guest.name,
+            custom_html: guest.custom_html,
+            language: guest.language,
+            order: event.next_order(),
+            ..Attendee::new(at_id)
+        });
+    }
+
+    save_db(db).await.map_err(|_| {
+        CreateEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(NewEvent {
+        id: new_id,
+        organizer_key,
+    })
+}
+
+/// Orderings available when listing events
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EventOrder {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+}
+
+/// Put events in the given order, by when they were created
+fn sort_events<T, K: Ord>(
+    events: &mut [T],
+    order: EventOrder,
+    created: impl FnMut(&T) -> K,
+) {
+    events.sort_by_key(created);
+    if let EventOrder::NewestFirst = order {
+        events.reverse();
+    }
+}
+
+/// All events in the given order
+pub async fn list_events(order: EventOrder) -> Result<Vec<Event>, String> {
+    let mut db = open_db()
+        .await
+        .map_err(|_| "Internal database was inaccessible".to_string())?;
+
+    let mut events = Vec::new();
+    db.scan(Scan::All, |ev| events.push(ev.clone()))
+        .map_err(|_| "Internal database was inaccessible".to_string())?;
+    sort_events(&mut events, order, |ev| ev.created);
+    Ok(events)
+}
+
+/// Condensed view of an event for listings
+#[derive(Serialize, Debug)]
+pub struct EventSummary {
+    pub id: Id,
+    pub name: Option<String>,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    pub attendees: usize,
+    pub accepted: usize,
+    pub pinned: bool,
+}
+
+impl From<&Event> for EventSummary {
+    fn from(ev: &Event) -> Self {
+        Self {
+            id: ev.id,
+            name: ev.name.clone(),
+            created: unix_secs(ev.created),
+            attendees: ev.attendees.len(),
+            accepted: ev
+                .attendees
+                .iter()
+                .filter(|at| at.has_accepted())
+                .count(),
+            pinned: ev.pinned,
+        }
+    }
+}
+
+/// Summaries of at most `limit` events starting at `offset` in the given
+/// order, along with the total number of events
+pub async fn list_event_summaries(
+    order: EventOrder,
+    offset: usize,
+    limit: usize,
+) -> Result<(usize, Vec<EventSummary>), String> {
+    let mut db = open_db()
+        .await
+        .map_err(|_| "Internal database was inaccessible".to_string())?;
+
+    let mut summaries = Vec::new();
+    db.scan(Scan::All, |ev| summaries.push(EventSummary::from(ev)))
+        .map_err(|_| "Internal database was inaccessible".to_string())?;
+    sort_events(&mut summaries, order, |summary| summary.created);
+    let total = summaries.len();
+    Ok((total, summaries.into_iter().skip(offset).take(limit).collect()))
+}
+
+/// Aggregate figures about the stored data
+pub struct DbStats {
+    pub events: usize,
+    pub attendees: usize,
+    pub accepted: usize,
+    pub db_bytes: u64,
+    /// Events created in each of the last N weeks, most recent first
+    pub events_per_week: Vec<usize>,
+}
+
+pub async fn stats(weeks: usize) -> Result<DbStats, String> {
+    let mut db = open_db()
+        .await
+        .map_err(|_| "Internal database was inaccessible".to_string())?;
+
+    let db_bytes = tokio::fs::metadata(store::path())
+        .await
+        .map(|m| m.len())
+        .unwrap_or_default();
+
+    let now = clock::now();
+    let week = Duration::from_days(7);
+    let mut stats = DbStats {
+        events: 0,
+        attendees: 0,
+        accepted: 0,
+        db_bytes,
+        events_per_week: vec![0; weeks],
+    };
+    db.scan(Scan::All, |event| {
+        let age = now.duration_since(event.created).unwrap_or_default();
+        let idx = (age.as_secs() / week.as_secs()) as usize;
+        if let Some(count) = stats.events_per_week.get_mut(idx) {
+            *count += 1;
+        }
+        stats.events += 1;
+        stats.attendees += event.attendees.len();
+        stats.accepted +=
+            event.attendees.iter().filter(|at| at.has_accepted()).count();
+    })
+    .map_err(|_| "Internal database was inaccessible".to_string())?;
+    Ok(stats)
+}
+
+#[derive(Debug)]
+pub enum FindEventError {
+    Database(String),
+    NotFound(String),
+}
+
+#[derive(Debug)]
+pub enum UpdateEventError {
+    Find(FindEventError),
+    /// The update was made against an older revision. Holds the event as it
+    /// is now.
+    Conflict(Box<Event>),
+    /// The event is frozen, so nothing was changed
+    Frozen,
+}
+
+#[derive(Debug)]
+pub enum RsvpError {
+    Find(FindEventError),
+    /// Responses to the event are closed, so nothing was changed
+    Closed,
+    /// The event is in appointment mode and no slot, or no existing one, was
+    /// picked
+    SlotRequired,
+    /// The slot picked has no room left
+    SlotFull,
+    /// As many guests as the open RSVP link takes have joined through it
+    Full,
+}
+
+impl From<FindEventError> for RsvpError {
+    fn from(value: FindEventError) -> Self {
+        RsvpError::Find(value)
+    }
+}
+
+impl From<FindEventError> for UpdateEventError {
+    fn from(value: FindEventError) -> Self {
+        UpdateEventError::Find(value)
+    }
+}
+
+pub async fn find_event_by_id(ev_id: Id) -> Result<Event, FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    db.get(Lookup::Event(ev_id))?.ok_or(FindEventError::NotFound(
+        "Event with given ID not found in database".to_string(),
+    ))
+}
+
+/// IDs of the guests with the given email address, ignoring case, in the
+/// given event or in every event
+pub async fn find_attendees_by_email(
+    email: &str,
+    ev_id: Option<Id>,
+) -> Result<Vec<Id>, FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    let email = email.trim();
+    let matches = |at: &&Attendee| {
+        at.email
+            .as_deref()
+            .is_some_and(|e| e.trim().eq_ignore_ascii_case(email))
+    };
+    let mut found = Vec::new();
+    match ev_id {
+        Some(ev_id) => {
+            if let Some(event) = db.get(Lookup::Event(ev_id))? {
+                found.extend(
+                    event.attendees.iter().filter(matches).map(|at| at.id),
+                );
+            }
+        }
+        None => db.scan(Scan::Email(email.to_string()), |ev| {
+            found.extend(ev.attendees.iter().filter(matches).map(|at| at.id))
+        })?,
+    }
+    Ok(found)
+}
+
+pub async fn find_event_by_attendee(
+    at_id: Id,
+) -> Result<(Event, Attendee), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    if let Some(event) = db.get(Lookup::Attendee(at_id))? {
+        for attendee in event.attendees.iter() {
+            if attendee.id == at_id {
+                return Ok((event.clone(), attendee.clone()));
+            }
+        }
+    }
+
+    Err(FindEventError::NotFound(
+        "Could not find event with the given attendee ID".to_string(),
+    ))
+}
+
+/// What a guest said when withdrawing
+#[derive(Default, Debug)]
+pub struct Decline {
+    /// Should be one of the event's decline reasons. Anything else is ignored.
+    pub reason: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Record an RSVP. Decline details only accompany a withdrawal and are cleared
+/// when the guest answers otherwise. Once responses are closed, nothing
+/// changes. Guests answering yes or maybe can leave out `plus_ones` to keep
+/// the number they gave before.
+///
+/// In appointment mode, accepting books `time_slot`. Guests who already
+/// accepted can leave it out to keep their slot, or give another to move.
+pub async fn set_rsvp(
+    at_id: Id,
+    rsvp: Rsvp,
+    plus_ones: Option<u8>,
+    time_slot: Option<u32>,
+    decline: Decline,
+) -> Result<(), RsvpError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        )
+        .into());
+    };
+    let now = clock::now();
+    if event.responses_closed(now) {
+        return Err(RsvpError::Closed);
+    }
+    let reasons = event.decline_reasons();
+    let accept = rsvp == Rsvp::Yes;
+    let booking = if accept {
+        event.booking(Some(at_id), time_slot)?
+    } else {
+        None
+    };
+    let attendee = event
+        .attendees
+        .iter_mut()
+        .find(|at| at.id == at_id)
+        .expect("Event was selected by this attendee");
+    let moved = attendee.time_slot != booking;
+    attendee.time_slot = booking;
+    let plus_ones = match rsvp {
+        Rsvp::Yes | Rsvp::Maybe => {
+            plus_ones.unwrap_or(attendee.plus_ones).min(MAX_PLUS_ONES)
+        }
+        Rsvp::Pending | Rsvp::No => 0,
+    };
+    let recounted = attendee.plus_ones != plus_ones;
+    attendee.plus_ones = plus_ones;
+
+    let mut withdrew_at = None;
+    let mut answered_at = None;
+    if attendee.rsvp != rsvp {
+        let mut before = std::mem::replace(&mut attendee.rsvp, rsvp);
+        let flapped = attendee
+            .rsvp_history
+            .last()
+            .is_some_and(|change| change.at + FLAP_WINDOW > now);
+        if flapped {
+            // the guest changed their mind within moments, so only the
+            // answer they settle on counts
+            attendee.rsvp_history.pop();
+            before = attendee
+                .rsvp_history
+                .last()
+                .map(|change| change.rsvp)
+                .unwrap_or_default();
+        }
+        if before != rsvp {
+            attendee.rsvp_history.push(RsvpChange { at: now, rsvp });
+            answered_at = Some(now);
+            if before == Rsvp::Yes {
+                withdrew_at = Some(now);
+            }
+        }
+    } else if rsvp != Rsvp::No && !moved && !recounted
+        || rsvp == Rsvp::No
+            && decline.note.is_none()
+            && decline.reason.is_none()
+    {
+        // nothing to write
+        return Ok(());
+    }
+    if rsvp != Rsvp::No {
+        attendee.withdraw_note = None;
+        attendee.decline_reason = None;
+    } else {
+        // what the guest was bringing along no longer applies
+        attendee.comment = None;
+        if let Some(note) = decline.note.as_ref() {
+            attendee.withdraw_note =
+                Some(note.chars().take(MAX_NOTE_CHARS).collect());
+        }
+        if let Some(reason) = decline.reason.as_ref() {
+            if reasons.contains(reason) {
+                attendee.decline_reason = Some(reason.clone());
+            }
+        }
+    }
+    if !accept {
+        // guests who aren't coming for sure neither drive nor ride
+        event.leave_carpool(at_id);
+    }
+    let kind = match rsvp {
+        Rsvp::Yes => Some(AccessKind::Accepted),
+        Rsvp::Maybe => Some(AccessKind::AnsweredMaybe),
+        Rsvp::No => Some(AccessKind::Declined),
+        Rsvp::Pending => None,
+    };
+    if let Some(kind) = kind {
+        event.log_access(kind, Some(at_id));
+    }
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    if let Some(at) = withdrew_at {
+        tokio::spawn(notify_withdrawal_when_settled(at_id, at));
+    }
+    if let Some(at) = answered_at.filter(|_| mail::enabled()) {
+        tokio::spawn(email_answer_when_settled(at_id, at));
+    }
+    Ok(())
+}
+
+/// Email the organizer the guest's answer once it's clear they aren't
+/// flipping back and forth. Only the answer which is still the latest gets
+/// one, and none if the guest ended up where they started.
+async fn email_answer_when_settled(at_id: Id, at: SystemTime) {
+    clock::sleep(FLAP_WINDOW).await;
+    let (event, attendee) = match find_event_by_attendee(at_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            log::error!("Answer of {at_id} went unemailed: \"{e}\"");
+            return;
+        }
+        Err(FindEventError::NotFound(_)) => return,
+    };
+    let Some(to) = event.organizer_email.as_deref() else {
+        return;
+    };
+    if attendee.rsvp_history.last().is_none_or(|c| c.at != at) {
+        return;
+    }
+    let event_name = event.display_name();
+    let name = &attendee.name;
+    let (subject, answer) = match attendee.rsvp {
+        Rsvp::Yes => (
+            format!("{name} accepted {event_name}"),
+            match attendee.plus_ones {
+                0 => format!("is coming to {event_name}"),
+                n => format!("is coming to {event_name} and brings {n} more"),
+            },
+        ),
+        Rsvp::Maybe => (
+            format!("{name} might come to {event_name}"),
+            format!("might come to {event_name}"),
+        ),
+        Rsvp::No => (
+            format!("{name} withdrew from {event_name}"),
+            format!("is not coming to {event_name}"),
+        ),
+        Rsvp::Pending => return,
+    };
+    let mut body = format!("{name} {answer}.\n");
+    if let (Rsvp::No, Some(note)) = (attendee.rsvp, &attendee.withdraw_note) {
+        body.push_str(&format!("\nTheir note: {note}\n"));
+    }
+    let counts = event.rsvp_counts();
+    body.push_str(&format!(
+        "\nSo far {} accepted ({} coming with plus-ones), {} might come, and \
+         {} withdrew.\n\nManage the event: {}\n",
+        counts.yes,
+        event.headcount(),
+        counts.maybe,
+        counts.no,
+        event.manage_link()
+    ));
+    mail::send(to, &subject, body).await;
+}
+
+/// Send the withdrawal notification once it's clear the guest isn't flipping
+/// back and forth. Only the withdrawal which is still the latest change gets
+/// one.
+async fn notify_withdrawal_when_settled(at_id: Id, at: SystemTime) {
+    clock::sleep(FLAP_WINDOW).await;
+    let (event, attendee) = match find_event_by_attendee(at_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            log::error!("Withdrawal of {at_id} went unnotified: \"{e}\"");
+            return;
+        }
+        Err(FindEventError::NotFound(_)) => return,
+    };
+    let settled = !attendee.has_accepted()
+        && attendee.rsvp_history.last().is_some_and(|c| c.at == at);
+    if settled {
+        notify::send(&attendee.withdrawal(&event)).await;
+    }
+}
+
+/// Notify the organizer of detail changes for each guest who had accepted, once
+/// the details have stopped changing. Only the task for the latest change
+/// notifies, covering every change not notified yet.
+async fn notify_detail_changes_when_settled(ev_id: Id, at: SystemTime) {
+    clock::sleep(DETAIL_SETTLE_WINDOW).await;
+    let Ok(mut db) = open_db().await else {
+        log::error!("Detail changes of {ev_id} went unnotified");
+        return;
+    };
+    let event = match db.event(ev_id) {
+        Ok(Some(event)) => event,
+        Ok(None) => return,
+        Err(_) => {
+            log::error!("Detail changes of {ev_id} went unnotified");
+            return;
+        }
+    };
+    if event
+        .detail_changes
+        .last()
+        .is_none_or(|change| change.at != at)
+    {
+        return;
+    }
+    let descriptions: Vec<String> = event
+        .detail_changes
+        .iter_mut()
+        .filter(|change| !change.notified)
+        .map(|change| {
+            change.notified = true;
+            change.description.clone()
+        })
+        .collect();
+    let notifications: Vec<Notification> = event
+        .attendees
+        .iter()
+        .filter(|at| at.has_accepted() && !at.anonymized)
+        .map(|at| at.details_changed(event, &descriptions))
+        .collect();
+    if save_db(db).await.is_err() {
+        log::error!("Detail changes of {ev_id} went unnotified");
+        return;
+    }
+    for notification in notifications {
+        notify::send(&notification).await;
+    }
+}
+
+/// Note an ignored bot request against the attendee's event so the organizer
+/// can see it
+pub async fn record_bot_flag(
+    at_id: Id,
+    reason: &str,
+) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        ));
+    };
+    event.bot_flags.push(BotFlag {
+        at: clock::now(),
+        attendee: at_id,
+        reason: reason.to_string(),
+    });
+    if event.bot_flags.len() > MAX_BOT_FLAGS {
+        let excess = event.bot_flags.len() - MAX_BOT_FLAGS;
+        event.bot_flags.drain(..excess);
+    }
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Set the comment the guest added to their acceptance, removing it if the
+/// comment is empty
+pub async fn set_comment(at_id: Id, comment: &str) -> Result<(), RsvpError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        )
+        .into());
+    };
+    if event.responses_closed(clock::now()) {
+        return Err(RsvpError::Closed);
+    }
+    let attendee = event
+        .attendees
+        .iter_mut()
+        .find(|at| at.id == at_id)
+        .expect("Event was selected by this attendee");
+    let comment = comment.trim();
+    let comment = Some(comment.chars().take(MAX_COMMENT_CHARS).collect())
+        .filter(|c: &String| !c.is_empty());
+    if attendee.comment == comment {
+        return Ok(());
+    }
+    attendee.comment = comment;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Remember that the attendee's invitation failed to render, for the
+/// organizer to fix
+pub async fn record_render_error(
+    at_id: Id,
+    message: &str,
+) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        ));
+    };
+    // a guest reloading the page is the same failure again
+    event.render_errors.retain(|e| e.attendee != at_id);
+    event.render_errors.push(RenderError {
+        at: clock::now(),
+        attendee: at_id,
+        message: message.to_string(),
+    });
+    if event.render_errors.len() > MAX_RENDER_ERRORS {
+        let excess = event.render_errors.len() - MAX_RENDER_ERRORS;
+        event.render_errors.drain(..excess);
+    }
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// The current time at the resolution views and the access log are kept at
+fn coarse_now() -> SystemTime {
+    let secs = unix_secs(clock::now());
+    let coarse = secs - secs % VIEW_GRANULARITY.as_secs();
+    SystemTime::UNIX_EPOCH + Duration::from_secs(coarse)
+}
+
+/// Note that the organizer opened the manage page. It's written to the
+/// access log by `view_task`, so opening the page doesn't wait for a save.
+pub fn record_manage_view(ev_id: Id) {
+    let mut views = MANAGE_VIEWS.lock().unwrap_or_else(|e| e.into_inner());
+    *views.entry((ev_id, coarse_now())).or_default() += 1;
+}
+
+/// Write the manage page views recorded so far to the events' access logs,
+/// and register their guests' missing short links. Views are kept for the
+/// next time if the database can't be written. Returns how many events were
+/// viewed.
+pub async fn flush_manage_views() -> Result<usize, ()> {
+    let views = std::mem::take(
+        &mut *MANAGE_VIEWS.lock().unwrap_or_else(|e| e.into_inner()),
+    );
+    if views.is_empty() {
+        return Ok(0);
+    }
+    let keep = |views: BTreeMap<_, _>| {
+        let mut pending =
+            MANAGE_VIEWS.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, count) in views {
+            *pending.entry(key).or_default() += count;
+        }
+    };
+    let Ok(mut db) = open_db().await else {
+        keep(views);
+        return Err(());
+    };
+    let mut viewed = Vec::new();
+    for (&(ev_id, at), &count) in views.iter() {
+        let Ok(found) = db.event(ev_id) else {
+            keep(views);
+            return Err(());
+        };
+        // the event may have been removed since
+        if let Some(event) = found {
+            event.log_access_at(at, AccessKind::ManagePageOpened, None, count);
+            if viewed.last() != Some(&ev_id) {
+                shortlink::register_missing(event);
+                viewed.push(ev_id);
+            }
+        }
+    }
+    if save_db(db).await.is_err() {
+        keep(views);
+        return Err(());
+    }
+    Ok(viewed.len())
+}
+
+pub async fn view_task() {
+    loop {
+        clock::sleep(VIEW_FLUSH_PERIOD).await;
+        if flush_manage_views().await.is_err() {
+            log::warn!(
+                "Failed to write down manage page views. Retrying in {} secs.",
+                VIEW_FLUSH_PERIOD.as_secs()
+            );
+        }
+    }
+}
+
+/// Count a load of the attendee's invitation page
+pub async fn record_view(at_id: Id) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        ));
+    };
+    event.log_access(AccessKind::InvitationViewed, Some(at_id));
+    let attendee = event
+        .attendees
+        .iter_mut()
+        .find(|at| at.id == at_id)
+        .expect("Event was selected by this attendee");
+    let coarse = coarse_now();
+    attendee.view_count += 1;
+    attendee.first_viewed.get_or_insert(coarse);
+    attendee.recent_views.push(coarse);
+    if attendee.recent_views.len() > MAX_RECENT_VIEWS {
+        let excess = attendee.recent_views.len() - MAX_RECENT_VIEWS;
+        attendee.recent_views.drain(..excess);
+    }
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Apply the organizer's edits. If `data` names a revision, it must be the
+/// current one. Returns the new revision.
+pub async fn update_event(
+    ev_id: Id,
+    data: ManagePageJson,
+) -> Result<u64, UpdateEventError> {
+    patch_event(ev_id, data.into()).await
+}
+
+/// Apply the fields present in `patch`. If it names a revision, it must be the
+/// current one. Returns the new revision.
+pub async fn patch_event(
+    ev_id: Id,
+    patch: EventPatch,
+) -> Result<u64, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.ensure_revision(patch.revision)?;
+    event.revision += 1;
+    let revision = event.revision;
+    let mut detail_changed = None;
+    if let Some(name) = patch.event_name {
+        if event.name.as_deref().unwrap_or_default() != name {
+            detail_changed = event
+                .record_detail_change("name", format!("renamed to \"{name}\""));
+        }
+        event.name = Some(name);
+    }
+    if let Some(webhook) = patch.notify_webhook {
+        event.notify_webhook =
+            Some(webhook.trim().to_string()).filter(|w| !w.is_empty());
+    }
+    if let Some(email) = patch.organizer_email {
+        event.organizer_email =
+            Some(email.trim().to_string()).filter(|e| !e.is_empty());
+    }
+    if let Some(thanks_html) = patch.thanks_html {
+        event.thanks_html =
+            Some(sanitize(thanks_html.trim())).filter(|h| !h.is_empty());
+    }
+    if let Some(withdraw_html) = patch.withdraw_html {
+        event.withdraw_html =
+            Some(sanitize(withdraw_html.trim())).filter(|h| !h.is_empty());
+    }
+    if let Some(reasons) = patch.decline_reasons {
+        event.decline_reasons = Some(
+            reasons
+                .split(',')
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty())
+                .collect(),
+        );
+    }
+    if let Some(deadline) = patch.rsvp_deadline {
+        match deadline.trim() {
+            "" => event.rsvp_deadline = None,
+            deadline => match clock::parse_local_input(deadline) {
+                Ok(at) => event.rsvp_deadline = Some(at),
+                // the browser's date picker only sends valid dates
+                Err(e) => log::warn!("Ignored RSVP deadline of {ev_id}: {e}"),
+            },
+        }
+    }
+    if let Some(digest) = patch.digest {
+        // a newly enabled digest only covers what happens from now
+        if event.digest == DigestFrequency::Off {
+            event.last_digest = Some(clock::now());
+        }
+        event.digest = digest;
+    }
+    for (at_id_str, at_patch) in patch.attendees {
+        let Ok(at_id) = ids::decode_id(&at_id_str) else {
+            continue;
+        };
+        let Some(attendee) =
+            event.attendees.iter_mut().find(|at| at.id == at_id)
+        else {
+            continue;
+        };
+        if at_patch.custom_html.is_some() {
+            event.render_errors.retain(|e| e.attendee != at_id);
+        }
+        patch_attendee(attendee, at_patch, &event.custom_fields);
+    }
+
+    let notify_webhook = event.notify_webhook.clone();
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    if let Some(at) = detail_changed {
+        if notify::has_channel(notify_webhook.as_deref()) {
+            tokio::spawn(notify_detail_changes_when_settled(ev_id, at));
+        }
+    }
+    Ok(revision)
+}
+
+/// Restart the lifetime of an event from now
+pub async fn extend_event(ev_id: Id) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        ));
+    };
+    event.extended = Some(clock::now());
+    event.purge_warning_sent = None;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Pin or unpin an event. Unpinning restarts its lifetime, so an event pinned
+/// for longer than that isn't deleted without warning right away.
+pub async fn set_pinned(ev_id: Id, pinned: bool) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        ));
+    };
+    if event.pinned == pinned {
+        return Ok(());
+    }
+    event.pinned = pinned;
+    event.purge_warning_sent = None;
+    if !pinned {
+        event.extended = Some(clock::now());
+    }
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Lock or unlock guests' responses
+pub async fn set_responses_locked(
+    ev_id: Id,
+    locked: bool,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.responses_locked = locked;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Post an announcement to the event's guests. Blank text is ignored and long
+/// text is cut to `MAX_ANNOUNCEMENT_CHARS` characters.
+pub async fn post_announcement(
+    ev_id: Id,
+    text: &str,
+) -> Result<(), UpdateEventError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let number = event
+        .announcements
+        .iter()
+        .map(|announcement| announcement.number + 1)
+        .max()
+        .unwrap_or(1);
+    event.announcements.push(Announcement {
+        number,
+        at: clock::now(),
+        text: text.chars().take(MAX_ANNOUNCEMENT_CHARS).collect(),
+    });
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Take down an announcement
+pub async fn remove_announcement(
+    ev_id: Id,
+    number: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(index) =
+        event.announcements.iter().position(|a| a.number == number)
+    else {
+        return Err(FindEventError::NotFound(
+            "Announcement not found in event".to_string(),
+        )
+        .into());
+    };
+    event.announcements.remove(index);
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Add `count` back to back slots of `length`, from `start` on, each taking
+/// `capacity` guests
+pub async fn add_time_slots(
+    ev_id: Id,
+    start: SystemTime,
+    length: Duration,
+    count: usize,
+    capacity: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let count =
+        count.min(MAX_TIME_SLOTS.saturating_sub(event.time_slots.len()));
+    let first = event
+        .time_slots
+        .iter()
+        .map(|slot| slot.number + 1)
+        .max()
+        .unwrap_or(1);
+    for i in 0..count as u32 {
+        event.time_slots.push(TimeSlot {
+            number: first + i,
+            start: start + length * i,
+            length,
+            capacity: capacity.max(1),
+        });
+    }
+    event.time_slots.sort_by_key(|slot| slot.start);
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Take a slot off the schedule. Guests who booked it have to pick another
+/// the next time they accept.
+pub async fn remove_time_slot(
+    ev_id: Id,
+    number: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if event.time_slot(number).is_none() {
+        return Err(FindEventError::NotFound(
+            "Time slot not found in event".to_string(),
+        )
+        .into());
+    }
+    event.time_slots.retain(|slot| slot.number != number);
+    for attendee in event.attendees.iter_mut() {
+        if attendee.time_slot == Some(number) {
+            attendee.time_slot = None;
+        }
+    }
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Schedule the invitation of `attendee`, or of every guest who hasn't been
+/// sent theirs yet, to be delivered at `at`
+pub async fn schedule_send(
+    ev_id: Id,
+    at: SystemTime,
+    attendee: Option<Id>,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if let Some(at_id) = attendee {
+        if !event.attendees.iter().any(|at| at.id == at_id) {
+            return Err(FindEventError::NotFound(
+                "Attendee not found in event".to_string(),
+            )
+            .into());
+        }
+    }
+    if event.scheduled_sends.len() >= MAX_SCHEDULED_SENDS {
+        return Ok(());
+    }
+    let number = event
+        .scheduled_sends
+        .iter()
+        .map(|send| send.number + 1)
+        .max()
+        .unwrap_or(1);
+    event.scheduled_sends.push(ScheduledSend {
+        number,
+        at,
+        attendee,
+    });
+    event.scheduled_sends.sort_by_key(|send| send.at);
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Take a send off the schedule before it goes out
+pub async fn cancel_send(
+    ev_id: Id,
+    number: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let before = event.scheduled_sends.len();
+    event.scheduled_sends.retain(|send| send.number != number);
+    if event.scheduled_sends.len() == before {
+        return Err(FindEventError::NotFound(
+            "Scheduled send not found in event".to_string(),
+        )
+        .into());
+    }
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// When the next scheduled send of any event is due
+pub async fn next_send_at() -> Result<Option<SystemTime>, ()> {
+    let mut db = open_db().await?;
+    let mut next = None;
+    db.scan(Scan::Scheduled, |ev| {
+        if let Some(send) = ev.scheduled_sends.first().filter(|_| !ev.frozen) {
+            next = Some(next.map_or(send.at, |at: SystemTime| at.min(send.at)));
+        }
+    })
+    .map_err(|_| ())?;
+    Ok(next)
+}
+
+/// Take every send due by `now` off the schedule and mark the invitations as
+/// sent. Returns what's left to deliver, which happens at most once: a
+/// delivery that fails isn't tried again.
+pub async fn take_due_sends(now: SystemTime) -> Result<Vec<Delivery>, ()> {
+    let mut db = open_db().await?;
+    let mut due_events = Vec::new();
+    db.scan(Scan::Scheduled, |ev| {
+        let due = ev.scheduled_sends.first().is_some_and(|s| s.at <= now);
+        if due && !ev.frozen {
+            due_events.push(ev.id);
+        }
+    })
+    .map_err(|_| ())?;
+    let mut deliveries = Vec::new();
+    let mut changed = false;
+    for ev_id in due_events {
+        let Some(event) = db.event(ev_id).map_err(|_| ())? else {
+            continue;
+        };
+        let due = event
+            .scheduled_sends
+            .iter()
+            .take_while(|send| send.at <= now)
+            .count();
+        if due == 0 {
+            continue;
+        }
+        let event_name = event.display_name();
+        for send in event.scheduled_sends.drain(..due) {
+            let recipients = event.attendees.iter_mut().filter(|at| {
+                !at.anonymized
+                    && match send.attendee {
+                        Some(id) => at.id == id,
+                        None => at.invitation_sent.is_none(),
+                    }
+            });
+            for attendee in recipients {
+                attendee.invitation_sent = Some(now);
+                deliveries.push(Delivery {
+                    event_id: event.id,
+                    event_name: event_name.clone(),
+                    event_webhook: event.notify_webhook.clone(),
+                    attendee_id: attendee.id,
+                    name: attendee.name.clone(),
+                    email: attendee.email.clone(),
+                });
+            }
+        }
+        event.revision += 1;
+        changed = true;
+    }
+    if changed {
+        save_db(db).await?;
+    }
+    Ok(deliveries)
+}
+
+/// Put an item on the event's agenda. Guests who accepted are told the
+/// schedule changed.
+pub async fn add_agenda_item(
+    ev_id: Id,
+    at: SystemTime,
+    title: &str,
+    details: &str,
+) -> Result<(), UpdateEventError> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Ok(());
+    }
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if event.agenda.len() >= MAX_AGENDA_ITEMS {
+        return Ok(());
+    }
+    let number = event
+        .agenda
+        .iter()
+        .map(|item| item.number + 1)
+        .max()
+        .unwrap_or(1);
+    event.agenda.push(AgendaItem {
+        number,
+        at,
+        title: title.chars().take(MAX_AGENDA_TITLE_CHARS).collect(),
+        details: details
+            .trim()
+            .chars()
+            .take(MAX_AGENDA_DETAILS_CHARS)
+            .collect(),
+    });
+    event.agenda.sort_by_key(|item| item.at);
+    event.revision += 1;
+    let changed =
+        event.record_detail_change("agenda", "schedule changed".to_string());
+    let notify_webhook = event.notify_webhook.clone();
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    if let Some(at) = changed {
+        if notify::has_channel(notify_webhook.as_deref()) {
+            tokio::spawn(notify_detail_changes_when_settled(ev_id, at));
+        }
+    }
+    Ok(())
+}
+
+/// Take an item off the event's agenda
+pub async fn remove_agenda_item(
+    ev_id: Id,
+    number: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(index) = event.agenda.iter().position(|i| i.number == number)
+    else {
+        return Err(FindEventError::NotFound(
+            "Agenda item not found in event".to_string(),
+        )
+        .into());
+    };
+    event.agenda.remove(index);
+    event.revision += 1;
+    let changed =
+        event.record_detail_change("agenda", "schedule changed".to_string());
+    let notify_webhook = event.notify_webhook.clone();
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    if let Some(at) = changed {
+        if notify::has_channel(notify_webhook.as_deref()) {
+            tokio::spawn(notify_detail_changes_when_settled(ev_id, at));
+        }
+    }
+    Ok(())
+}
+
+/// Add a column to the guest list. Names are unique regardless of case.
+pub async fn add_custom_field(
+    ev_id: Id,
+    name: &str,
+    kind: FieldKind,
+) -> Result<(), UpdateEventError> {
+    let name: String = name.trim().chars().take(MAX_FIELD_NAME_CHARS).collect();
+    if name.is_empty() {
+        return Ok(());
+    }
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if event.custom_fields.len() >= MAX_CUSTOM_FIELDS
+        || event
+            .custom_fields
+            .iter()
+            .any(|field| field.name.eq_ignore_ascii_case(&name))
+    {
+        return Ok(());
+    }
+    let number = event
+        .custom_fields
+        .iter()
+        .map(|field| field.number + 1)
+        .max()
+        .unwrap_or(1);
+    event
+        .custom_fields
+        .push(CustomField { number, name, kind });
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Take a field off the guest list, along with every guest's value of it
+pub async fn remove_custom_field(
+    ev_id: Id,
+    number: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(index) =
+        event.custom_fields.iter().position(|f| f.number == number)
+    else {
+        return Err(FindEventError::NotFound(
+            "Custom field not found in event".to_string(),
+        )
+        .into());
+    };
+    let field = event.custom_fields.remove(index);
+    for attendee in event.attendees.iter_mut() {
+        attendee.fields.remove(&field.name);
+    }
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Track an expense of the event
+pub async fn add_expense(
+    ev_id: Id,
+    item: &str,
+    cents: u64,
+    payer: &str,
+) -> Result<(), UpdateEventError> {
+    let item = item.trim();
+    if item.is_empty() {
+        return Ok(());
+    }
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if event.expenses.len() >= MAX_EXPENSES {
+        return Ok(());
+    }
+    let number = event
+        .expenses
+        .iter()
+        .map(|expense| expense.number + 1)
+        .max()
+        .unwrap_or(1);
+    event.expenses.push(Expense {
+        number,
+        item: item.chars().take(MAX_EXPENSE_TEXT_CHARS).collect(),
+        cents,
+        payer: payer.trim().chars().take(MAX_EXPENSE_TEXT_CHARS).collect(),
+    });
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Stop tracking an expense
+pub async fn remove_expense(
+    ev_id: Id,
+    number: u32,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(index) = event.expenses.iter().position(|e| e.number == number)
+    else {
+        return Err(FindEventError::NotFound(
+            "Expense not found in event".to_string(),
+        )
+        .into());
+    };
+    event.expenses.remove(index);
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Show guests who accepted the expenses and their share, or stop
+pub async fn set_expenses_shared(
+    ev_id: Id,
+    shared: bool,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.expenses_shared = shared;
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Set where the event's weather forecast is for, or stop showing one
+pub async fn set_weather_location(
+    ev_id: Id,
+    location: Option<Coordinates>,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.weather_location = location;
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// When and where an event takes place, see the fields of `Event`
+#[derive(Default, Debug)]
+pub struct WhenWhere {
+    pub date: Option<NaiveDate>,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    /// Should be an IANA time zone name. Anything else is ignored.
+    pub timezone: Option<String>,
+    pub location: Option<String>,
+}
+
+/// Set when and where the event takes place. Times without a date are
+/// ignored, as is an end without a start.
+pub async fn set_when_where(
+    ev_id: Id,
+    details: WhenWhere,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let date = details.date;
+    let start_time = details.start_time.filter(|_| date.is_some());
+    let end_time = details.end_time.filter(|_| start_time.is_some());
+    let timezone = details
+        .timezone
+        .map(|tz| tz.trim().to_string())
+        .filter(|tz| tz.parse::<Tz>().is_ok());
+    let location = details
+        .location
+        .map(|loc| loc.trim().chars().take(MAX_LOCATION_CHARS).collect())
+        .filter(|loc: &String| !loc.is_empty());
+    let moved = (event.date, event.start_time, event.end_time)
+        != (date, start_time, end_time)
+        || event.timezone != timezone;
+    let relocated = event.location != location;
+    if !moved && !relocated {
+        return Ok(());
+    }
+    event.date = date;
+    event.start_time = start_time;
+    event.end_time = end_time;
+    event.timezone = timezone;
+    event.location = location;
+    event.revision += 1;
+    let mut changed = None;
+    if moved {
+        let when = event
+            .describe_when(Language::English)
+            .unwrap_or_else(|| "no date".to_string());
+        changed =
+            event.record_detail_change("date", format!("moved to {when}"));
+    }
+    if relocated {
+        let description = match &event.location {
+            Some(location) => format!("moved to \"{location}\""),
+            None => "location removed".to_string(),
+        };
+        changed = event.record_detail_change("location", description);
+    }
+    let notify_webhook = event.notify_webhook.clone();
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    if let Some(at) = changed {
+        if notify::has_channel(notify_webhook.as_deref()) {
+            tokio::spawn(notify_detail_changes_when_settled(ev_id, at));
+        }
+    }
+    Ok(())
+}
+
+/// Create the event's open RSVP link, or change how many guests can join
+/// through it. Returns the link's ID, which stays the same while it's open.
+pub async fn open_rsvp_link(
+    ev_id: Id,
+    capacity: Option<u32>,
+) -> Result<Id, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let link_id = generate_unique_id(|id| db.open_rsvp_id_taken(id))
+        .await
+        .ok_or(FindEventError::Database(
+            "Could not allocate a new link ID".to_string(),
+        ))?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let link = event.open_rsvp.get_or_insert(OpenRsvp {
+        id: link_id,
+        capacity: None,
+    });
+    link.capacity = capacity;
+    let link_id = link.id;
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(link_id)
+}
+
+/// Stop the open RSVP link from working. Guests who joined through it stay.
+/// Opening it again makes a new link.
+pub async fn close_rsvp_link(ev_id: Id) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.open_rsvp = None;
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// The event an open RSVP link belongs to
+pub async fn find_event_by_rsvp_link(
+    link_id: Id,
+) -> Result<Event, FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    db.get(Lookup::RsvpLink(link_id))?
+        .ok_or(FindEventError::NotFound(
+            "Could not find event with the given link".to_string(),
+        ))
+}
+
+/// Add a guest who accepted through the open RSVP link, booking `time_slot`
+/// in appointment mode. Returns the new attendee's ID, which is their
+/// personal link from then on.
+pub async fn join_through_rsvp_link(
+    link_id: Id,
+    name: &str,
+    language: Language,
+    time_slot: Option<u32>,
+) -> Result<Id, RsvpError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let at_id = generate_unique_id(|id| db.attendee_id_taken(id))
+        .await
+        .ok_or(FindEventError::Database(
+            "Could not allocate a new attendee ID".to_string(),
+        ))?;
+
+    let Some(event) = db.find(Lookup::RsvpLink(link_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given link".to_string(),
+        )
+        .into());
+    };
+    let now = clock::now();
+    if event.responses_closed(now) {
+        return Err(RsvpError::Closed);
+    }
+    let capacity = event.open_rsvp.as_ref().and_then(|l| l.capacity);
+    if capacity.is_some_and(|max| event.joined_openly() >= max as usize) {
+        return Err(RsvpError::Full);
+    }
+    let booking = event.booking(None, time_slot)?;
+    let attendee = Attendee {
+        name: name.trim().chars().take(MAX_OPEN_NAME_CHARS).collect(),
+        rsvp: Rsvp::Yes,
+        rsvp_history: vec![RsvpChange {
+            at: now,
+            rsvp: Rsvp::Yes,
+        }],
+        order: event.next_order(),
+        language,
+        time_slot: booking,
+        joined_openly: true,
+        ..Attendee::new(at_id)
+    };
+    event.attendees.push(attendee);
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(at_id)
+}
+
+/// Keep the short link of a guest's invitation. Not an edit by the organizer,
+/// so the event's revision stays as it is.
+pub async fn set_short_url(
+    ev_id: Id,
+    at_id: Id,
+    short_url: String,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(attendee) = event.attendees.iter_mut().find(|at| at.id == at_id)
+    else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in event".to_string(),
+        )
+        .into());
+    };
+    attendee.short_url = Some(short_url);
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Check a guest in as they arrive, or undo it
+pub async fn set_checked_in(
+    ev_id: Id,
+    at_id: Id,
+    checked_in: bool,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(attendee) = event.attendees.iter_mut().find(|at| at.id == at_id)
+    else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in event".to_string(),
+        )
+        .into());
+    };
+    match (checked_in, attendee.checked_in) {
+        // checking in twice keeps when they first arrived
+        (true, Some(_)) => return Ok(()),
+        (true, None) => attendee.checked_in = Some(clock::now()),
+        (false, _) => attendee.checked_in = None,
+    }
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Save a draft of the attendee's invitation beside the live one, or discard
+/// it with `None`
+pub async fn set_draft(
+    ev_id: Id,
+    at_id: Id,
+    draft: Option<String>,
+) -> Result<(), UpdateEventError> {
+    let draft = draft.map(|draft| sanitize(&draft));
+    change_invitation(ev_id, at_id, |attendee| attendee.draft_html = draft)
+        .await
+}
+
+/// Make the attendee's draft their invitation, replacing the live one
+pub async fn promote_draft(
+    ev_id: Id,
+    at_id: Id,
+) -> Result<(), UpdateEventError> {
+    change_invitation(ev_id, at_id, |attendee| {
+        if let Some(draft) = attendee.draft_html.take() {
+            attendee.custom_html = draft;
+        }
+    })
+    .await
+}
+
+async fn change_invitation(
+    ev_id: Id,
+    at_id: Id,
+    change: impl FnOnce(&mut Attendee),
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(attendee) = event.attendees.iter_mut().find(|at| at.id == at_id)
+    else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in event".to_string(),
+        )
+        .into());
+    };
+    let before = attendee.custom_html.clone();
+    change(attendee);
+    if attendee.custom_html != before {
+        event.render_errors.retain(|e| e.attendee != at_id);
+    }
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Create the event's kiosk display. Returns its ID, which stays the same
+/// while it's open.
+pub async fn open_kiosk(ev_id: Id) -> Result<Id, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let kiosk_id = generate_unique_id(|id| db.kiosk_id_taken(id)).await.ok_or(
+        FindEventError::Database(
+            "Could not allocate a new kiosk ID".to_string(),
+        ),
+    )?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let kiosk_id = *event.kiosk.get_or_insert(kiosk_id);
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(kiosk_id)
+}
+
+/// Make a new API key for the event, replacing any earlier one. Returns the
+/// key.
+pub async fn create_api_key(ev_id: Id) -> Result<String, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let key = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+    event.api_key = Some(key.clone());
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(key)
+}
+
+/// Link the event to a contact book, or unlink it with None
+pub async fn set_contact_book(
+    ev_id: Id,
+    book: Option<Id>,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.contact_book = book;
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Stop the event's API key from working
+pub async fn revoke_api_key(ev_id: Id) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if event.api_key.take().is_none() {
+        return Ok(());
+    }
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// The event an API key belongs to
+pub async fn find_event_by_api_key(key: &str) -> Result<Event, FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    db.get(Lookup::ApiKey(key.to_string()))?
+        .ok_or(FindEventError::NotFound(
+            "No event has the given API key".to_string(),
+        ))
+}
+
+/// Stop the kiosk display from working. Opening it again makes a new link.
+pub async fn close_kiosk(ev_id: Id) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.kiosk = None;
+    event.revision += 1;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// The event a kiosk display belongs to
+pub async fn find_event_by_kiosk(
+    kiosk_id: Id,
+) -> Result<Event, FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    db.get(Lookup::Kiosk(kiosk_id))?
+        .ok_or(FindEventError::NotFound(
+            "Could not find event with the given kiosk".to_string(),
+        ))
+}
+
+/// Let accepted guests use the carpool board, or stop them. Offers and
+/// requests are kept while it's closed.
+pub async fn set_carpool_open(
+    ev_id: Id,
+    open: bool,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.carpool_open = open;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Apply a guest's change to the carpool board
+pub async fn update_carpool(
+    at_id: Id,
+    change: CarpoolChange,
+) -> Result<(), CarpoolError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        )
+        .into());
     };
+    let accepted = event
+        .attendees
+        .iter()
+        .any(|at| at.id == at_id && at.has_accepted() && !at.anonymized);
+    if !event.carpool_open || event.frozen || !accepted {
+        return Err(CarpoolError::Unavailable);
+    }
 
-    match serde_cbor::from_slice::<EventDB>(&data) {
-        Ok(db) => Ok((db, lock)),
-        Err(_) => {
-            log::warn!(
-                "Database is corrupted. Assuming database structure has \
-                 changed in the source code. Recreating."
+    match change {
+        CarpoolChange::Offer { seats, note } => {
+            let seats = seats.clamp(1, MAX_CARPOOL_SEATS);
+            set_carpool_entry(
+                event,
+                at_id,
+                CarpoolRole::Driver { seats },
+                note,
+            );
+            // riders who joined last lose their seats first
+            let overflow: Vec<Id> = event
+                .riders_of(at_id)
+                .skip(seats as usize)
+                .map(|entry| entry.attendee)
+                .collect();
+            for entry in event.carpool.iter_mut() {
+                if overflow.contains(&entry.attendee) {
+                    entry.driver = None;
+                }
+            }
+        }
+        CarpoolChange::Request { note } => {
+            let driving = matches!(
+                event.carpool_entry(at_id).map(|entry| entry.role),
+                Some(CarpoolRole::Driver { .. })
             );
-            let def_struct = EventDB::default();
-            let def = serde_cbor::to_vec(&def_struct)
-                .expect("Default structure is serializable");
-            if tokio::fs::write(DB_PATH, &def).await.is_err() {
-                log::error!("Could not create database file");
-                return Err(());
+            if driving {
+                // their riders need someone else
+                event.leave_carpool(at_id);
+            }
+            set_carpool_entry(event, at_id, CarpoolRole::Rider, note);
+        }
+        CarpoolChange::Join { offer } => {
+            let Some(driver) = event
+                .carpool
+                .iter()
+                .find(|entry| entry.number == offer)
+                .map(|entry| entry.attendee)
+            else {
+                return Err(CarpoolError::Full);
+            };
+            let already = event
+                .carpool_entry(at_id)
+                .is_some_and(|entry| entry.driver == Some(driver));
+            if driver == at_id || !already && event.seats_left(driver) == 0 {
+                return Err(CarpoolError::Full);
+            }
+            if event.carpool_entry(at_id).is_none_or(|entry| {
+                matches!(entry.role, CarpoolRole::Driver { .. })
+            }) {
+                event.leave_carpool(at_id);
+                set_carpool_entry(
+                    event,
+                    at_id,
+                    CarpoolRole::Rider,
+                    String::new(),
+                );
             }
-            Ok((def_struct, lock))
+            let entry = event
+                .carpool
+                .iter_mut()
+                .find(|entry| entry.attendee == at_id)
+                .expect("Entry was just made");
+            entry.driver = Some(driver);
         }
+        CarpoolChange::Leave => event.leave_carpool(at_id),
     }
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(())
 }
 
-/// `db` is moved into the function to prevent caller from accidentally writing
-/// again. The assumption is that each public function that interacts with the
-/// database is an atomic operation
-async fn save_db(db: EventDB, _lock: MutexGuard<'_, ()>) -> Result<(), ()> {
-    let d = match serde_cbor::to_vec(&db) {
-        Ok(d) => d,
-        Err(e) => {
-            log::error!(
-                "Data could not be serialized: \"{e}\". Should not happen."
-            );
-            return Err(());
+/// Add or replace the attendee's offer or request. A rider's driver is kept,
+/// but a driver rides with nobody.
+fn set_carpool_entry(
+    event: &mut Event,
+    at_id: Id,
+    role: CarpoolRole,
+    note: String,
+) {
+    let note = note.trim().chars().take(MAX_CARPOOL_NOTE_CHARS).collect();
+    match event
+        .carpool
+        .iter_mut()
+        .find(|entry| entry.attendee == at_id)
+    {
+        Some(entry) => {
+            entry.role = role;
+            entry.note = note;
+            if role != CarpoolRole::Rider {
+                entry.driver = None;
+            }
         }
+        None => event.carpool.push(CarpoolEntry {
+            number: event
+                .carpool
+                .iter()
+                .map(|entry| entry.number + 1)
+                .max()
+                .unwrap_or(1),
+            attendee: at_id,
+            role,
+            note,
+            driver: None,
+        }),
+    }
+}
+
+/// Set or clear the event's cover image. Returns the name of the cover it
+/// replaced, which the caller should delete.
+pub async fn set_cover_image(
+    ev_id: Id,
+    name: Option<String>,
+) -> Result<Option<String>, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
     };
+    event.ensure_editable()?;
+    let replaced = std::mem::replace(&mut event.cover_image, name);
+    event.revision += 1;
 
-    if tokio::fs::write(DB_PATH, &d).await.is_err() {
-        log::error!("Failed to write back database. Data is lost!");
-        return Err(());
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(replaced)
+}
+
+/// Freeze or unfreeze an event
+pub async fn set_frozen(ev_id: Id, frozen: bool) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        ));
     };
+    event.frozen = frozen;
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
     Ok(())
 }
 
-/// Open the event database and delete entries that are older than the
-/// configured lifetime
-async fn purge_old_events() -> Result<(), ()> {
-    let Ok((mut db, lock)) = open_db().await else {
-        log::warn!("Purge task could not open the database");
-        return Err(());
-    };
+/// Queue a reminder for every guest who is due one. Returns how many were
+/// queued, which is none if the event has no notification channel.
+pub async fn nudge_unopened(ev_id: Id) -> Result<usize, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
 
-    db.events.retain(|ev| {
-        let diff = match SystemTime::now().duration_since(ev.created) {
-            Ok(d) => d,
-            Err(_) => {
-                let name = ev.name.clone().unwrap_or("<Untitled>".to_string());
-                log::warn!(
-                    "Purging event \"{name}\" with creation time after \
-                     current time"
-                );
-                return false;
-            }
-        };
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    if !notify::has_channel(event.notify_webhook.as_deref()) {
+        return Ok(0);
+    }
+    let now = clock::now();
+    let due: Vec<usize> = (0..event.attendees.len())
+        .filter(|&i| event.attendees[i].nudge_due(now))
+        .collect();
+    let notifications: Vec<Notification> = due
+        .iter()
+        .map(|&i| event.attendees[i].nudge(event))
+        .collect();
+    for &i in due.iter() {
+        event.attendees[i].last_nudged = Some(now);
+    }
 
-        diff < EVENT_LIFETIME
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    let queued = notifications.len();
+    tokio::spawn(async move {
+        for notification in notifications {
+            notify::send(&notification).await;
+        }
     });
+    Ok(queued)
+}
 
-    if save_db(db, lock).await.is_err() {
-        log::warn!("Purge task could not save database");
-        return Err(());
+/// Anonymize an attendee at their own request and let the organizer know
+pub async fn forget_attendee(at_id: Id) -> Result<(), FindEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Could not find event with the given attendee ID".to_string(),
+        ));
+    };
+    let attendee = event
+        .attendees
+        .iter_mut()
+        .find(|at| at.id == at_id)
+        .expect("Event was selected by this attendee");
+    if attendee.anonymized {
+        return Ok(());
     }
+    attendee.anonymize();
+    event.leave_carpool(at_id);
+
+    let notification = Notification {
+        kind: NotificationKind::AttendeeDataRemoved,
+        priority: Priority::Normal,
+        event_id: event.id,
+        event_name: event.display_name(),
+        subject: format!(
+            "A guest removed their data from \"{}\"",
+            event.display_name()
+        ),
+        message: "One of your guests asked for their name and invitation to \
+                  be erased. They still count towards the headcount."
+            .to_string(),
+        link: Some(event.manage_link()),
+        attendee_id: None,
+        event_webhook: event.notify_webhook.clone(),
+    };
+
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    notify::send(&notification).await;
     Ok(())
 }
 
-pub async fn create_event() -> Result<u64, String> {
-    let (mut db, lock) = open_db()
-        .await
-        .map_err(|_| "Internal database was inaccessible".to_string())?;
+fn patch_attendee(
+    attendee: &mut Attendee,
+    patch: AttendeePatch,
+    custom_fields: &[CustomField],
+) {
+    if let Some(name) = patch.name {
+        attendee.name = name;
+    }
+    if let Some(custom_html) = patch.custom_html {
+        attendee.custom_html = sanitize(&custom_html);
+    }
+    if let Some(email) = patch.email {
+        attendee.email =
+            Some(email.trim().to_string()).filter(|e| !e.is_empty());
+    }
+    if let Some(notes) = patch.notes {
+        attendee.notes = notes;
+    }
+    if let Some(order) = patch.order {
+        attendee.order = order;
+    }
+    if let Some(language) = patch.language {
+        attendee.language = language;
+    }
+    // values of fields the event doesn't have, or of the wrong kind, are
+    // dropped
+    for (name, value) in patch.fields.into_iter().flatten() {
+        let Some(field) = custom_fields.iter().find(|f| f.name == name) else {
+            continue;
+        };
+        if value.trim().is_empty() {
+            attendee.fields.remove(&name);
+        } else if let Some(value) = field.kind.normalize(&value) {
+            attendee.fields.insert(name, value);
+        }
+    }
+}
 
-    let ev_id = RNG.lock().await.random();
-    let new_event = Event {
-        id: ev_id,
-        name: None,
-        attendees: Vec::new(),
-        created: SystemTime::now(),
-    };
-    db.events.push(new_event);
+/// Apply the fields present in `patch` to a single attendee of the event.
+/// Returns the event's new revision.
+pub async fn update_attendee(
+    ev_id: Id,
+    at_id: Id,
+    patch: AttendeePatch,
+) -> Result<u64, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
 
-    save_db(db, lock)
-        .await
-        .map_err(|_| "Internal database was inaccessible".to_string())?;
-    Ok(ev_id)
-}
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let Some(attendee) = event.attendees.iter_mut().find(|at| at.id == at_id)
+    else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in event".to_string(),
+        )
+        .into());
+    };
+    if patch.custom_html.is_some() {
+        event.render_errors.retain(|e| e.attendee != at_id);
+    }
+    patch_attendee(attendee, patch, &event.custom_fields);
+    event.revision += 1;
+    let revision = event.revision;
 
-pub enum FindEventError {
-    Database(String),
-    NotFound(String),
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(revision)
 }
 
-pub async fn find_event_by_id(ev_id: u64) -> Result<Event, FindEventError> {
-    let (db, _lock) = open_db().await.map_err(|_| {
+/// Arrange the event's attendees in the order given. Attendees missing from
+/// `order` keep their relative order after the ones listed. Returns the
+/// event's new revision.
+pub async fn reorder_attendees(
+    ev_id: Id,
+    order: Vec<Id>,
+) -> Result<u64, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
 
-    for event in db.events.iter() {
-        if event.id == ev_id {
-            return Ok(event.clone());
-        }
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    // the sort is stable, so unlisted attendees keep their current order
+    event.attendees.sort_by_key(|at| at.order);
+    event.attendees.sort_by_key(|at| {
+        order
+            .iter()
+            .position(|id| *id == at.id)
+            .unwrap_or(order.len())
+    });
+    for (position, attendee) in event.attendees.iter_mut().enumerate() {
+        attendee.order = position as u32;
     }
+    event.revision += 1;
+    let revision = event.revision;
 
-    Err(FindEventError::NotFound(
-        "Event with given ID not found in database".to_string(),
-    ))
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(revision)
 }
 
-pub async fn find_event_by_attendee(
-    at_id: u64,
-) -> Result<(Event, Attendee), FindEventError> {
-    let (db, _lock) = open_db().await.map_err(|_| {
+/// Add an attendee to the event, filled in from `new` where given. Returns
+/// the new attendee's ID.
+pub async fn add_attendee(
+    ev_id: Id,
+    new: NewAttendee,
+) -> Result<Id, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
 
-    for event in db.events.iter() {
-        for attendee in event.attendees.iter() {
-            if attendee.id == at_id {
-                return Ok((event.clone(), attendee.clone()));
-            }
-        }
+    let at_id = generate_unique_id(|id| db.attendee_id_taken(id))
+        .await
+        .ok_or(FindEventError::Database(
+            "Could not allocate a new attendee ID".to_string(),
+        ))?;
+
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let mut attendee = Attendee {
+        order: event.next_order(),
+        ..Attendee::new(at_id)
+    };
+    if let Some(template) = new.template {
+        let template = ids::decode_id(&template)
+            .ok()
+            .and_then(|id| event.attendees.iter().find(|at| at.id == id));
+        let Some(template) = template else {
+            return Err(FindEventError::NotFound(
+                "Template attendee not found in event".to_string(),
+            )
+            .into());
+        };
+        attendee.custom_html = template.custom_html.clone();
     }
+    patch_attendee(
+        &mut attendee,
+        AttendeePatch {
+            name: new.name,
+            email: new.email,
+            ..Default::default()
+        },
+        &event.custom_fields,
+    );
+    event.attendees.push(attendee);
+    event.revision += 1;
 
-    Err(FindEventError::NotFound(
-        "Could not find event with the given attendee ID".to_string(),
-    ))
+    save_db(db).await.map_err(|_| {
+        FindEventError::Database(
+            "Internal database was inaccessible".to_string(),
+        )
+    })?;
+    Ok(at_id)
 }
 
-pub async fn set_accepted(
-    at_id: u64,
-    accept: bool,
-) -> Result<(), FindEventError> {
-    let (mut db, lock) = open_db().await.map_err(|_| {
+/// Invite several people at once, each with the given details. Returns the
+/// new attendees' IDs in the same order.
+pub async fn add_attendees(
+    ev_id: Id,
+    guests: Vec<AttendeePatch>,
+) -> Result<Vec<Id>, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.revision += 1;
 
-    for event in db.events.iter_mut() {
-        for attendee in event.attendees.iter_mut() {
-            if attendee.id == at_id {
-                attendee.has_accepted = accept;
-            }
-        }
+    let mut added = Vec::with_capacity(guests.len());
+    for patch in guests {
+        // IDs handed out in this loop are already in the event, so they
+        // aren't handed out twice
+        let at_id = generate_unique_id(|id| db.attendee_id_taken(id))
+            .await
+            .ok_or(FindEventError::Database(
+                "Could not allocate a new attendee ID".to_string(),
+            ))?;
+        let event = db.event(ev_id)?.expect("Event was found above");
+        let mut attendee = Attendee {
+            order: event.next_order(),
+            ..Attendee::new(at_id)
+        };
+        patch_attendee(&mut attendee, patch, &event.custom_fields);
+        event.attendees.push(attendee);
+        added.push(at_id);
     }
 
-    save_db(db, lock).await.map_err(|_| {
+    save_db(db).await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
-    Ok(())
+    Ok(added)
 }
 
-pub async fn update_event(
-    ev_id: u64,
-    data: ManagePageJson,
-) -> Result<(), FindEventError> {
-    let (mut db, lock) = open_db().await.map_err(|_| {
+/// The attendee a merged attendee's link leads to now
+pub async fn merged_into(at_id: Id) -> Option<Id> {
+    let mut db = open_db().await.ok()?;
+    db.get(Lookup::MergedAttendee(at_id))
+        .ok()??
+        .merged
+        .iter()
+        .find(|merged| merged.from == at_id)
+        .map(|merged| merged.into)
+}
+
+/// Merge a duplicate attendee into the one kept. The kept attendee's details
+/// win, and the duplicate's fill in what's missing. Their RSVP histories and
+/// views are combined, and whichever responded last decides whether the
+/// merged attendee accepted. The duplicate's link leads to the kept one from
+/// then on.
+pub async fn merge_attendees(
+    ev_id: Id,
+    keep: Id,
+    duplicate: Id,
+) -> Result<(), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
 
-    for event in db.events.iter_mut() {
-        if ev_id == event.id {
-            event.name = Some(data.event_name.clone());
-            for attendee_db in event.attendees.iter_mut() {
-                for (at_id_str, at_update) in data.attendee_data.iter() {
-                    let Ok(at_id) = base62::decode(at_id_str) else {
-                        continue;
-                    };
-                    if at_id as u64 == attendee_db.id {
-                        attendee_db.custom_html = at_update.custom_html.clone();
-                        attendee_db.name = at_update.name.clone();
-                    }
-                }
+    let Some(event) = db.event(ev_id)? else {
+        return Err(FindEventError::NotFound(
+            "Event with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let found = |id| event.attendees.iter().position(|at| at.id == id);
+    let (Some(_), Some(index)) = (found(keep), found(duplicate)) else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in event".to_string(),
+        )
+        .into());
+    };
+    if keep == duplicate {
+        return Ok(());
+    }
+    let other = event.attendees.remove(index);
+    let attendee = event
+        .attendees
+        .iter_mut()
+        .find(|at| at.id == keep)
+        .expect("Attendee was found above");
+
+    // whoever responded last has the say
+    let latest = |at: &Attendee| at.rsvp_history.last().map(|change| change.at);
+    if latest(&other) > latest(attendee) {
+        attendee.rsvp = other.rsvp;
+        attendee.plus_ones = other.plus_ones;
+        attendee.time_slot = other.time_slot;
+        attendee.withdraw_note = other.withdraw_note.clone();
+        attendee.decline_reason = other.decline_reason.clone();
+        attendee.comment = other.comment.clone();
+    }
+    attendee.rsvp_history.extend(other.rsvp_history);
+    attendee.rsvp_history.sort_by_key(|change| change.at);
+    attendee.recent_views.extend(other.recent_views);
+    attendee.recent_views.sort();
+    let excess = attendee.recent_views.len().saturating_sub(MAX_RECENT_VIEWS);
+    attendee.recent_views.drain(..excess);
+    attendee.view_count += other.view_count;
+    attendee.first_viewed = match (attendee.first_viewed, other.first_viewed) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    attendee.last_nudged = attendee.last_nudged.max(other.last_nudged);
+    attendee.invitation_sent =
+        attendee.invitation_sent.max(other.invitation_sent);
+    attendee.checked_in = match (attendee.checked_in, other.checked_in) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    if attendee.name.is_empty() || attendee.name == "Unnamed" {
+        attendee.name = other.name;
+    }
+    if attendee.email.is_none() {
+        attendee.email = other.email;
+    }
+    if attendee.custom_html == Attendee::new(keep).custom_html {
+        attendee.custom_html = other.custom_html;
+    }
+    if attendee.draft_html.is_none() {
+        attendee.draft_html = other.draft_html;
+    }
+    for (name, value) in other.fields {
+        attendee.fields.entry(name).or_insert(value);
+    }
+    if attendee.notes.is_empty() {
+        attendee.notes = other.notes;
+    } else if !other.notes.is_empty() && other.notes != attendee.notes {
+        attendee.notes = format!("{}\n{}", attendee.notes, other.notes);
+    }
+    // only a guest who added nobody but themselves counts toward the open
+    // RSVP link's limit
+    attendee.joined_openly &= other.joined_openly;
+    let accepted = attendee.has_accepted();
+
+    // the duplicate's ride carries over unless the kept attendee has one
+    if event.carpool_entry(keep).is_none() && accepted {
+        for entry in event.carpool.iter_mut() {
+            if entry.attendee == duplicate {
+                entry.attendee = keep;
             }
+            if entry.driver == Some(duplicate) {
+                entry.driver = Some(keep);
+            }
+        }
+    }
+    event.leave_carpool(duplicate);
+    if !accepted {
+        event.leave_carpool(keep);
+    }
+    for flag in event.bot_flags.iter_mut() {
+        if flag.attendee == duplicate {
+            flag.attendee = keep;
         }
     }
+    for merged in event.merged.iter_mut() {
+        if merged.into == duplicate {
+            merged.into = keep;
+        }
+    }
+    event.merged.push(MergedAttendee {
+        from: duplicate,
+        into: keep,
+    });
+    event.revision += 1;
 
-    save_db(db, lock).await.map_err(|_| {
+    save_db(db).await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
@@ -265,90 +4902,313 @@ pub async fn update_event(
     Ok(())
 }
 
-pub async fn add_attendee(ev_id: u64) -> Result<(), FindEventError> {
-    let (mut db, lock) = open_db().await.map_err(|_| {
+/// Remove an attendee from whichever event they belong to. Returns the
+/// event's ID.
+pub async fn remove_attendee(at_id: Id) -> Result<Id, UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
 
-    for event in db.events.iter_mut() {
-        if ev_id == event.id {
-            let at_id = RNG.lock().await.random();
-            event.attendees.push(Attendee {
-                id: at_id,
-                name: "Unnamed".to_string(),
-                custom_html: "<html></html>".to_string(),
-                has_accepted: false,
-            })
-        }
-    }
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    event.attendees.retain(|at| at.id != at_id);
+    event.leave_carpool(at_id);
+    event.merged.retain(|merged| merged.into != at_id);
+    event.revision += 1;
+    let ev_id = event.id;
 
-    save_db(db, lock).await.map_err(|_| {
+    save_db(db).await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
-    Ok(())
+    Ok(ev_id)
 }
 
-pub async fn remove_attendee(at_id: u64) -> Result<(), FindEventError> {
-    let (mut db, lock) = open_db().await.map_err(|_| {
+/// Give an attendee a new ID, so the link with the old one stops working,
+/// e.g., after it was forwarded to the wrong person. Everything else about
+/// them stays as it was. Returns the event's ID and the new one.
+pub async fn rotate_attendee_id(
+    at_id: Id,
+) -> Result<(Id, Id), UpdateEventError> {
+    let mut db = open_db().await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
 
-    log::debug!("remove {at_id}");
-    for event in db.events.iter_mut() {
-        event.attendees.retain(|at| {
-            log::debug!("{}", at.id);
-            at.id != at_id
-        });
+    let new_id = generate_unique_id(|id| db.attendee_id_taken(id))
+        .await
+        .ok_or(FindEventError::Database(
+            "Could not allocate a new attendee ID".to_string(),
+        ))?;
+
+    let Some(event) = db.find(Lookup::Attendee(at_id))? else {
+        return Err(FindEventError::NotFound(
+            "Attendee with given ID not found in database".to_string(),
+        )
+        .into());
+    };
+    event.ensure_editable()?;
+    let attendee = event
+        .attendees
+        .iter_mut()
+        .find(|at| at.id == at_id)
+        .expect("Event was selected by this attendee");
+    attendee.id = new_id;
+    // the short link leads to the old ID
+    attendee.short_url = None;
+
+    for entry in event.carpool.iter_mut() {
+        if entry.attendee == at_id {
+            entry.attendee = new_id;
+        }
+        if entry.driver == Some(at_id) {
+            entry.driver = Some(new_id);
+        }
+    }
+    for send in event.scheduled_sends.iter_mut() {
+        if send.attendee == Some(at_id) {
+            send.attendee = Some(new_id);
+        }
+    }
+    for flag in event.bot_flags.iter_mut() {
+        if flag.attendee == at_id {
+            flag.attendee = new_id;
+        }
+    }
+    for error in event.render_errors.iter_mut() {
+        if error.attendee == at_id {
+            error.attendee = new_id;
+        }
     }
+    for entry in event.access_log.iter_mut() {
+        if entry.attendee == Some(at_id) {
+            entry.attendee = Some(new_id);
+        }
+    }
+    // links of duplicates merged into the attendee led to the old ID, so
+    // they're revoked along with it
+    event.merged.retain(|merged| merged.into != at_id);
+    event.revision += 1;
+    let ev_id = event.id;
 
-    save_db(db, lock).await.map_err(|_| {
+    save_db(db).await.map_err(|_| {
         FindEventError::Database(
             "Internal database was inaccessible".to_string(),
         )
     })?;
-    Ok(())
+    Ok((ev_id, new_id))
 }
 
 pub async fn purge_task() {
+    let retention = retention();
     loop {
-        log::info!("Next purge in {} secs.", PURGE_PERIOD.as_secs());
-        tokio::time::sleep(PURGE_PERIOD).await;
+        let next = retention.next_purge(clock::now());
+        log::info!("Next purge at {}.", clock::describe_local_time(next));
+        metrics::record_next_purge(next);
+        clock::sleep_until(next).await;
         log::info!("Performing scheduled purge of expired events");
-        while purge_old_events().await.is_err() {
+        loop {
+            match purge_old_events().await {
+                Ok(purged) => {
+                    log::info!("Purged {purged} expired events");
+                    metrics::record_purge_success(purged);
+                    break;
+                }
+                Err(_) => metrics::record_purge_failure(),
+            }
             log::warn!(
                 "Purge failed. Retrying in {} secs.",
-                PURGE_RETRY_PERIOD.as_secs()
+                retention.purge_retry_period.as_secs()
             );
-            tokio::time::sleep(PURGE_RETRY_PERIOD).await;
+            clock::sleep(retention.purge_retry_period).await;
+        }
+    }
+}
+
+/// Send every digest which is due. Returns how many were sent.
+async fn send_digests() -> Result<usize, ()> {
+    let mut db = open_db().await.map_err(|_| ())?;
+
+    let now = clock::now();
+    let mut due = Vec::new();
+    db.scan(Scan::All, |ev| {
+        if ev.digest_due(now) {
+            due.push(ev.id);
+        }
+    })
+    .map_err(|_| ())?;
+    let mut digests = Vec::new();
+    for ev_id in due {
+        if let Some(event) = db.event(ev_id).map_err(|_| ())? {
+            digests.extend(event.digest());
+            event.last_digest = Some(now);
+        }
+    }
+
+    save_db(db).await.map_err(|_| ())?;
+    for digest in digests.iter() {
+        notify::send(digest).await;
+    }
+    Ok(digests.len())
+}
+
+pub async fn digest_task() {
+    loop {
+        clock::sleep(DIGEST_CHECK_PERIOD).await;
+        match send_digests().await {
+            Ok(0) => {}
+            Ok(sent) => log::info!("Sent {sent} RSVP digests"),
+            Err(_) => log::warn!(
+                "Failed to send digests. Retrying in {} secs.",
+                DIGEST_CHECK_PERIOD.as_secs()
+            ),
         }
     }
 }
 
 pub async fn setup_test() {
-    let (mut db, lock) = open_db().await.unwrap();
+    let mut db = open_db().await.unwrap();
 
     log::info!("Setup");
-    let ev_id = base62::decode("test").unwrap() as u64;
+    let ev_id = ids::decode_id("test").unwrap();
     let new_event = Event {
         id: ev_id,
         name: Some("My Event".to_string()),
         attendees: vec![
-            Attendee { id: 1234567, name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), has_accepted: false },
-            Attendee { id: 1234568, name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), has_accepted: false },
-            Attendee { id: 1234569, name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), has_accepted: false },
-            Attendee { id: 1234570, name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), has_accepted: false },
+            Attendee { name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), ..Attendee::new(Id::from(1234567u64)) },
+            Attendee { name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), ..Attendee::new(Id::from(1234568u64)) },
+            Attendee { name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), ..Attendee::new(Id::from(1234569u64)) },
+            Attendee { name: "Blacepos".to_string(), custom_html: "hi i hope you're doing well. i'm doing alright. hey by the way do you want to hear me ramble a bit? I mean it's not like you have a choice in the matter. I need to write something in order to make this text really long".to_string(), ..Attendee::new(Id::from(1234570u64)) },
         ],
-        created: SystemTime::now(),
+        ..Event::new(ev_id)
     };
-    if !db.events.iter().any(|e| e.id == ev_id) {
-        db.events.push(new_event);
+    if !db.event_id_taken(ev_id) {
+        db.insert_event(new_event);
+    }
+
+    save_db(db).await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    /// An event created at a fixed time, so tests don't depend on the clock
+    fn event_created_at(created: SystemTime) -> Event {
+        Event {
+            created,
+            ..Event::new(Id::from(1u64))
+        }
+    }
+
+    #[test]
+    fn unique_ids_skip_taken_ones() {
+        let taken = Id::from(7u64);
+        let mut forced = [7u64, 7, 8].into_iter();
+        let mut source = move || Id::from(forced.next().unwrap_or(9));
+        let id = unique_id(&mut source, |id| id == taken);
+        assert_eq!(id, Some(Id::from(8u64)));
+
+        let mut colliding = move || taken;
+        assert_eq!(unique_id(&mut colliding, |id| id == taken), None);
+    }
+
+    #[test]
+    fn purges_expired_events() {
+        let created = UNIX_EPOCH + Duration::from_days(20_000);
+        let lifetime = retention().event_lifetime;
+        let event = event_created_at(created);
+        let keep = PurgeSteps {
+            warn: false,
+            purge: false,
+        };
+        let purge = PurgeSteps {
+            warn: false,
+            purge: true,
+        };
+        let expired = created + lifetime;
+        assert_eq!(event.purge_steps(expired - Duration::from_secs(1)), keep);
+        assert_eq!(event.purge_steps(expired), purge);
+
+        let extended = Event {
+            extended: Some(expired),
+            ..event_created_at(created)
+        };
+        assert_eq!(extended.purge_steps(expired), keep);
+        assert_eq!(extended.purge_steps(expired + lifetime), purge);
+
+        let pinned = Event {
+            pinned: true,
+            ..event_created_at(created)
+        };
+        assert_eq!(pinned.purge_steps(expired + lifetime), keep);
+
+        // a creation time ahead of the clock can't be trusted
+        assert_eq!(event.purge_steps(created - Duration::from_secs(1)), purge);
+    }
+
+    #[test]
+    fn warns_before_purging() {
+        let created = UNIX_EPOCH + Duration::from_days(20_000);
+        let expired = created + retention().event_lifetime;
+        let mut event = Event {
+            notify_webhook: Some("https://hooks.example.org".to_string()),
+            ..event_created_at(created)
+        };
+        let warned_at = expired - PURGE_WARNING_PERIOD;
+        assert!(!event.purge_steps(warned_at - Duration::from_secs(1)).warn);
+        assert_eq!(
+            event.purge_steps(warned_at),
+            PurgeSteps {
+                warn: true,
+                purge: false,
+            }
+        );
+
+        // a late warning holds the event back for the full period
+        let late = expired + Duration::from_days(1);
+        assert_eq!(
+            event.purge_steps(late),
+            PurgeSteps {
+                warn: true,
+                purge: false,
+            }
+        );
+        event.purge_warning_sent = Some(late);
+        assert!(!event.purge_steps(late + Duration::from_days(1)).purge);
+        assert_eq!(
+            event.purge_steps(late + PURGE_WARNING_PERIOD),
+            PurgeSteps {
+                warn: false,
+                purge: true,
+            }
+        );
     }
 
-    save_db(db, lock).await.unwrap();
+    #[test]
+    fn stale_revisions_conflict() {
+        let event = Event {
+            revision: 3,
+            ..Event::new(Id::from(1u64))
+        };
+        assert!(event.ensure_revision(None).is_ok());
+        assert!(event.ensure_revision(Some(3)).is_ok());
+        match event.ensure_revision(Some(2)) {
+            Err(UpdateEventError::Conflict(current)) => {
+                assert_eq!(current.revision, 3)
+            }
+            other => panic!("Expected a conflict, got {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,454 @@
+//! Layout of the database file
+//!
+//! The serialized database is prefixed with a header:
+//!
+//! | bytes | contents                                  |
+//! |-------|-------------------------------------------|
+//! | 8     | `MAGIC`                                   |
+//! | 2     | format version, little endian             |
+//! | 1     | compression of the payload (version 2 on) |
+//! | 4     | CRC-32 of the payload, little endian      |
+//! | 8     | length of the payload, little endian      |
+//!
+//! so a truncated, damaged, or foreign file is recognized as such before it
+//! reaches the parser. The checksum and length cover the payload as stored,
+//! i.e., after compression. The version is bumped whenever the header changes,
+//! and older versions stay readable. Files written before the header was
+//! introduced have no magic and are passed through as they are.
+//!
+//! The database is streamed to and from the file rather than serialized into
+//! memory first, so memory use doesn't grow with the size of the file. Since
+//! the checksum and length are only known at the end, they're filled into the
+//! header after the payload has been written, and checked after it has been
+//! parsed.
+
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    sync::OnceLock,
+};
+
+use super::EventDB;
+
+const MAGIC: &[u8; 8] = b"BXYZINV\0";
+/// Version written by this build
+const VERSION: u16 = 2;
+const HEADER_LEN: usize = 8 + 2 + 1 + 4 + 8;
+/// zstd level for the database. Every save compresses the whole database, so
+/// this favors speed over size.
+const COMPRESSION_LEVEL: i32 = 3;
+
+static COMPRESSION: OnceLock<Compression> = OnceLock::new();
+
+/// How the payload is stored. Only affects writing: reading detects it from
+/// the header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+        }
+    }
+}
+
+pub fn set_compression(compression: Compression) {
+    if COMPRESSION.set(compression).is_err() {
+        log::warn!("Database compression was already set");
+    }
+}
+
+fn compression() -> Compression {
+    COMPRESSION.get().copied().unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    /// The file ends within the header
+    TruncatedHeader,
+    /// The file is shorter than its header says
+    Truncated { expected: u64, found: u64 },
+    /// Written by a newer build
+    UnknownVersion(u16),
+    /// Compressed in a way this build doesn't know
+    UnknownCompression(u8),
+    /// The payload doesn't match its checksum
+    Checksum,
+    /// The payload matches its checksum but doesn't decompress
+    Decompress(std::io::Error),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::TruncatedHeader => {
+                write!(f, "file is truncated within its header")
+            }
+            FormatError::Truncated { expected, found } => {
+                write!(f, "file is truncated ({found} of {expected} bytes)")
+            }
+            FormatError::UnknownVersion(v) => {
+                write!(f, "file has unknown format version {v}")
+            }
+            FormatError::UnknownCompression(c) => {
+                write!(f, "file has unknown compression {c}")
+            }
+            FormatError::Checksum => write!(f, "file fails its checksum"),
+            FormatError::Decompress(e) => {
+                write!(f, "file could not be decompressed: {e}")
+            }
+        }
+    }
+}
+
+/// Errors reading a database file
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Format(FormatError),
+    /// The payload is intact but isn't a database in the current layout
+    Parse(serde_cbor::Error),
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "file could not be read: {e}"),
+            ReadError::Format(e) => e.fmt(f),
+            ReadError::Parse(e) => write!(f, "file could not be parsed: {e}"),
+        }
+    }
+}
+
+/// Passes data through, keeping count and a checksum of it
+struct Checksummed<T> {
+    inner: T,
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+        }
+    }
+
+    fn finish(self) -> (T, u32, u64) {
+        (self.inner, self.hasher.finalize(), self.len)
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+fn header(compression: Compression, checksum: u32, len: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.push(compression.to_byte());
+    header.extend_from_slice(&checksum.to_le_bytes());
+    header.extend_from_slice(&len.to_le_bytes());
+    header
+}
+
+/// Stream the database into a newly created file, compressed as configured.
+/// Returns the size of the file. Blocks, so call it where blocking is fine.
+pub fn write(file: File, db: &EventDB) -> io::Result<u64> {
+    write_compressed(file, db, compression())
+}
+
+fn write_compressed(
+    file: File,
+    db: &EventDB,
+    compression: Compression,
+) -> io::Result<u64> {
+    let mut file = BufWriter::new(file);
+    // room for the header, filled in once the payload is written
+    file.write_all(&[0; HEADER_LEN])?;
+
+    let mut payload = Checksummed::new(file);
+    match compression {
+        Compression::None => {
+            serde_cbor::to_writer(&mut payload, db).map_err(io::Error::other)?
+        }
+        Compression::Zstd => {
+            let mut encoder =
+                zstd::Encoder::new(&mut payload, COMPRESSION_LEVEL)?;
+            serde_cbor::to_writer(&mut encoder, db)
+                .map_err(io::Error::other)?;
+            encoder.finish()?;
+        }
+    }
+    let (file, checksum, len) = payload.finish();
+
+    let mut file = file.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header(compression, checksum, len))?;
+    Ok(HEADER_LEN as u64 + len)
+}
+
+/// Parse a header from the start of a file. Returns `None` if there's no
+/// header, and otherwise the header and its length.
+fn parse_header(
+    data: &[u8],
+) -> Result<Option<(Compression, u32, u64, usize)>, FormatError> {
+    let Some(rest) = data.strip_prefix(MAGIC.as_slice()) else {
+        return Ok(None);
+    };
+    let (version, rest) = rest
+        .split_first_chunk::<2>()
+        .ok_or(FormatError::TruncatedHeader)?;
+    let (compression, rest) = match u16::from_le_bytes(*version) {
+        // from before compression was supported
+        1 => (Compression::None, rest),
+        2 => {
+            let (byte, rest) =
+                rest.split_first().ok_or(FormatError::TruncatedHeader)?;
+            let compression = Compression::from_byte(*byte)
+                .ok_or(FormatError::UnknownCompression(*byte))?;
+            (compression, rest)
+        }
+        version => return Err(FormatError::UnknownVersion(version)),
+    };
+    let (checksum, rest) = rest
+        .split_first_chunk::<4>()
+        .ok_or(FormatError::TruncatedHeader)?;
+    let (len, rest) = rest
+        .split_first_chunk::<8>()
+        .ok_or(FormatError::TruncatedHeader)?;
+    Ok(Some((
+        compression,
+        u32::from_le_bytes(*checksum),
+        u64::from_le_bytes(*len),
+        data.len() - rest.len(),
+    )))
+}
+
+/// Stream the database out of a file, verifying its header if it has one.
+/// Blocks, so call it where blocking is fine.
+pub fn read(file: File) -> Result<EventDB, ReadError> {
+    let file_len = file.metadata().map_err(ReadError::Io)?.len();
+    let mut file = BufReader::new(file);
+    let mut head = Vec::with_capacity(HEADER_LEN);
+    (&mut file)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut head)
+        .map_err(ReadError::Io)?;
+
+    let Some((compression, checksum, len, header_len)) =
+        parse_header(&head).map_err(ReadError::Format)?
+    else {
+        // from before the header was introduced
+        return serde_cbor::from_reader(BufReader::new(head.chain(file)))
+            .map_err(ReadError::Parse);
+    };
+    if file_len < header_len as u64 + len {
+        return Err(ReadError::Format(FormatError::Truncated {
+            expected: header_len as u64 + len,
+            found: file_len,
+        }));
+    }
+
+    let stored = &head[header_len..];
+    let stored = BufReader::new(Checksummed::new(stored.chain(file).take(len)));
+    let (parsed, mut stored) = match compression {
+        Compression::None => {
+            let mut stored = stored;
+            (serde_cbor::from_reader(&mut stored), stored)
+        }
+        Compression::Zstd => {
+            let mut decoder =
+                zstd::Decoder::with_buffer(stored).map_err(ReadError::Io)?;
+            let parsed = serde_cbor::from_reader(BufReader::new(&mut decoder));
+            (parsed, decoder.finish())
+        }
+    };
+    // the checksum covers all of the payload, even what the parser didn't need
+    io::copy(&mut stored, &mut io::sink()).map_err(ReadError::Io)?;
+    let (_, actual, _) = stored.into_inner().finish();
+    if actual != checksum {
+        return Err(ReadError::Format(FormatError::Checksum));
+    }
+    parsed.map_err(ReadError::Parse)
+}
+
+/// Verify the header and return the serialized database behind it,
+/// decompressed. Data without a header is returned as is. Unlike `read`, this
+/// holds the whole file in memory, for when the raw data is needed, e.g., to
+/// convert it from an earlier layout.
+pub fn decode(data: &[u8]) -> Result<Cow<'_, [u8]>, FormatError> {
+    let Some((compression, checksum, len, header_len)) = parse_header(data)?
+    else {
+        return Ok(Cow::Borrowed(data));
+    };
+    let payload = &data[header_len..];
+    if (payload.len() as u64) < len {
+        return Err(FormatError::Truncated {
+            expected: header_len as u64 + len,
+            found: data.len() as u64,
+        });
+    }
+    let payload = &payload[..len as usize];
+    if crc32fast::hash(payload) != checksum {
+        return Err(FormatError::Checksum);
+    }
+    match compression {
+        Compression::None => Ok(Cow::Borrowed(payload)),
+        Compression::Zstd => zstd::decode_all(payload)
+            .map(Cow::Owned)
+            .map_err(FormatError::Decompress),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        event_db::{temp_path, Event},
+        ids::Id,
+    };
+
+    fn sample_db() -> EventDB {
+        let events = (1..=3u64)
+            .map(|id| Event {
+                name: Some(format!("Event {id}")),
+                ..Event::new(Id::from(id))
+            })
+            .collect();
+        EventDB { events }
+    }
+
+    /// The database as written with `compression`, i.e., the bytes of the file
+    fn written(name: &str, db: &EventDB, compression: Compression) -> Vec<u8> {
+        let path = temp_path(name);
+        let file = File::create(&path).unwrap();
+        let len = write_compressed(file, db, compression).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(len, data.len() as u64);
+        data
+    }
+
+    fn read_bytes(name: &str, data: &[u8]) -> Result<EventDB, ReadError> {
+        let path = temp_path(name);
+        fs::write(&path, data).unwrap();
+        let db = read(File::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        db
+    }
+
+    fn names(db: &EventDB) -> Vec<Option<String>> {
+        db.events.iter().map(|ev| ev.name.clone()).collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let db = sample_db();
+        let expected = serde_cbor::to_vec(&db).unwrap();
+        for (name, compression) in
+            [("plain", Compression::None), ("zstd", Compression::Zstd)]
+        {
+            let data = written(&format!("round-trip-{name}"), &db, compression);
+            assert_eq!(data[10], compression.to_byte());
+            let read = read_bytes(&format!("read-{name}"), &data).unwrap();
+            assert_eq!(names(&read), names(&db));
+            assert_eq!(decode(&data).unwrap().as_ref(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn reads_files_without_a_header() {
+        let data = serde_cbor::to_vec(&sample_db()).unwrap();
+        assert!(matches!(decode(&data), Ok(Cow::Borrowed(d)) if d == data));
+        let db = read_bytes("headerless", &data).unwrap();
+        assert_eq!(names(&db), names(&sample_db()));
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        for (name, compression) in
+            [("plain", Compression::None), ("zstd", Compression::Zstd)]
+        {
+            let name = format!("damaged-{name}");
+            let mut data = written(&name, &sample_db(), compression);
+            *data.last_mut().unwrap() ^= 0xff;
+            assert!(matches!(decode(&data), Err(FormatError::Checksum)));
+            assert!(matches!(
+                read_bytes(&format!("{name}-read"), &data),
+                Err(ReadError::Format(FormatError::Checksum))
+            ));
+        }
+
+        let data = written("truncated", &sample_db(), Compression::None);
+        let cut = &data[..data.len() - 1];
+        let expected = data.len() as u64;
+        assert!(matches!(
+            decode(cut),
+            Err(FormatError::Truncated { expected: e, .. }) if e == expected
+        ));
+        assert!(matches!(
+            read_bytes("truncated-read", cut),
+            Err(ReadError::Format(FormatError::Truncated { .. }))
+        ));
+        assert!(matches!(
+            decode(&data[..MAGIC.len() + 1]),
+            Err(FormatError::TruncatedHeader)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_layouts() {
+        let mut data =
+            written("unknown-version", &sample_db(), Compression::None);
+        data[8..10].copy_from_slice(&9u16.to_le_bytes());
+        assert!(matches!(decode(&data), Err(FormatError::UnknownVersion(9))));
+
+        let mut data =
+            written("unknown-compression", &sample_db(), Compression::None);
+        data[10] = 7;
+        assert!(matches!(
+            decode(&data),
+            Err(FormatError::UnknownCompression(7))
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+//! Lock coordinating instances which share the database file
+//!
+//! Instances started with `--shared-storage` hold the lock while they use the
+//! database, from loading or checking it to writing it back. The lock is an
+//! advisory lock the OS keeps on a file beside the database. The OS releases
+//! it when the holder closes the file or dies, so a crashed instance never
+//! leaves it behind. The file itself stays, naming the process which last
+//! held the lock.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::store;
+
+/// Longest to wait for another instance to finish
+const MAX_WAIT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+fn lock_path() -> String {
+    format!("{}.lock", store::path())
+}
+
+/// Releases the lock when dropped, as closing the file does
+pub struct FileLock {
+    _file: File,
+}
+
+/// Take the lock, waiting for other instances to release it. Blocks, so call
+/// it where blocking is fine.
+pub fn acquire() -> Result<FileLock, ()> {
+    let path = lock_path();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| {
+            log::error!("Could not open database lock {path}: {e}");
+        })?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {
+                if started.elapsed() > MAX_WAIT {
+                    log::error!(
+                        "Gave up waiting for another instance to release the \
+                         database lock"
+                    );
+                    return Err(());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(TryLockError::Error(e)) => {
+                log::error!("Could not take database lock {path}: {e}");
+                return Err(());
+            }
+        }
+    }
+    // for telling who holds it when investigating
+    let _ = file
+        .set_len(0)
+        .and_then(|()| writeln!(file, "{}", std::process::id()));
+    Ok(FileLock { _file: file })
+}
@@ -0,0 +1,264 @@
+//! The operation log beside the CBOR file
+//!
+//! Rewriting the whole CBOR file on every save is slow once the database is
+//! large. Instead, each save appends what changed to a log beside it: every
+//! event the save created or changed, e.g., by an RSVP, in full, and the IDs
+//! of events it removed. Loading reads the file
+//! and replays the log on top. Once the log outgrows the file, the database
+//! is written to the file in full and the log starts over, which is called
+//! compaction.
+//!
+//! The log is laid out as
+//!
+//! | bytes | contents                                               |
+//! |-------|--------------------------------------------------------|
+//! | 8     | `MAGIC`                                                |
+//! | 4     | CRC-32 of the file the log continues, little endian    |
+//!
+//! followed by one record per save:
+//!
+//! | bytes | contents                                  |
+//! |-------|-------------------------------------------|
+//! | 4     | length of the operations, little endian   |
+//! | 4     | CRC-32 of the operations, little endian   |
+//! | n     | the save's operations as a CBOR array     |
+//!
+//! Records are synced to disk before the save is answered. Being killed
+//! mid-append leaves a record which is cut off or fails its checksum, which
+//! ends the replay, so every save is either replayed whole or not at all. A
+//! log which continues another file than the one beside it, e.g., after the
+//! file was restored from a backup, is set aside rather than replayed.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Event;
+use crate::ids::Id;
+
+const MAGIC: &[u8; 8] = b"BXYZLOG\0";
+const HEADER_LEN: usize = 8 + 4;
+/// Longest record, as a guard against reading a damaged length
+const MAX_RECORD_LEN: u32 = 256 * 1024 * 1024;
+
+/// Something which happened to an event. Operations are replayed in order.
+#[derive(Deserialize, Debug)]
+pub enum Operation {
+    /// The event as it was saved, after being created or changed
+    Put(Box<Event>),
+    Remove(Id),
+}
+
+/// `Operation` as written, without copying the event
+#[derive(Serialize)]
+enum OperationRef<'a> {
+    Put(&'a Event),
+    Remove(Id),
+}
+
+/// Identifies a version of the file a log continues
+pub fn file_checksum(path: &str) -> io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finalize()),
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+}
+
+/// What a log held
+pub struct Replay {
+    pub operations: Vec<Operation>,
+    /// Whether the log ended in a record that was cut off or damaged
+    pub torn: bool,
+}
+
+/// Read the log continuing the file with the given checksum. Returns `None`
+/// if there's no log, and an error if the log continues another file.
+pub fn read(path: &str, file_checksum: u32) -> io::Result<Option<Replay>> {
+    let mut log = match File::open(path) {
+        Ok(log) => BufReader::new(log),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut header = [0; HEADER_LEN];
+    log.read_exact(&mut header)?;
+    let (magic, checksum) = header.split_at(MAGIC.len());
+    if magic != MAGIC {
+        return Err(io::Error::other("not an operation log"));
+    }
+    let checksum = u32::from_le_bytes(
+        checksum.try_into().expect("The header ends in 4 bytes"),
+    );
+    if checksum != file_checksum {
+        return Err(io::Error::other("the log continues another file"));
+    }
+
+    let mut replay = Replay {
+        operations: Vec::new(),
+        torn: false,
+    };
+    loop {
+        let mut head = [0; 8];
+        match read_all(&mut log, &mut head)? {
+            0 => return Ok(Some(replay)),
+            8 => {}
+            _ => break,
+        }
+        let len = u32::from_le_bytes(head[..4].try_into().expect("4 bytes"));
+        let checksum =
+            u32::from_le_bytes(head[4..].try_into().expect("4 bytes"));
+        if len > MAX_RECORD_LEN {
+            break;
+        }
+        let mut data = vec![0; len as usize];
+        if read_all(&mut log, &mut data)? < data.len()
+            || crc32fast::hash(&data) != checksum
+        {
+            break;
+        }
+        match serde_cbor::from_slice::<Vec<Operation>>(&data) {
+            Ok(operations) => replay.operations.extend(operations),
+            Err(_) => break,
+        }
+    }
+    replay.torn = true;
+    Ok(Some(replay))
+}
+
+/// Fill `buffer` as far as the log goes. Returns how much was read.
+fn read_all(log: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match log.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Append a save's operations to the log as one record, starting the log if
+/// there's none. Returns how many bytes were appended.
+pub fn append(
+    path: &str,
+    file_checksum: u32,
+    operations: &[Operation],
+) -> io::Result<u64> {
+    if operations.is_empty() {
+        return Ok(0);
+    }
+    let operations: Vec<OperationRef> = operations
+        .iter()
+        .map(|operation| match operation {
+            Operation::Put(event) => OperationRef::Put(event),
+            Operation::Remove(id) => OperationRef::Remove(*id),
+        })
+        .collect();
+    let data = serde_cbor::to_vec(&operations).map_err(io::Error::other)?;
+    let len = u32::try_from(data.len())
+        .ok()
+        .filter(|&len| len <= MAX_RECORD_LEN)
+        .ok_or_else(|| io::Error::other("the changes are too large"))?;
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let fresh = file.metadata()?.len() == 0;
+    let mut log = BufWriter::new(file);
+    let mut appended = 0;
+    if fresh {
+        log.write_all(MAGIC)?;
+        log.write_all(&file_checksum.to_le_bytes())?;
+        appended += HEADER_LEN as u64;
+    }
+    log.write_all(&len.to_le_bytes())?;
+    log.write_all(&crc32fast::hash(&data).to_le_bytes())?;
+    log.write_all(&data)?;
+    appended += 8 + u64::from(len);
+    log.into_inner().map_err(|e| e.into_error())?.sync_data()?;
+    Ok(appended)
+}
+
+/// Set a log aside which can't be replayed, keeping it for inspection
+pub fn set_aside(path: &str) {
+    let aside = format!("{path}.stale");
+    match fs::rename(path, &aside) {
+        Ok(()) => log::warn!("Moved the operation log to {aside}"),
+        Err(e) => log::error!("Could not move {path} to {aside}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_db::temp_path;
+
+    fn put(id: u64) -> Operation {
+        Operation::Put(Box::new(Event::new(Id::from(id))))
+    }
+
+    fn summary(operations: &[Operation]) -> Vec<String> {
+        operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Put(event) => format!("put {}", event.id),
+                Operation::Remove(id) => format!("remove {id}"),
+            })
+            .collect()
+    }
+
+    /// A log of two saves continuing a file with checksum 42
+    fn two_saves(name: &str) -> String {
+        let path = temp_path(name);
+        append(&path, 42, &[put(1), put(2)]).unwrap();
+        append(&path, 42, &[Operation::Remove(Id::from(1u64))]).unwrap();
+        path
+    }
+
+    #[test]
+    fn replays_saves_in_order() {
+        let path = temp_path("missing-log");
+        assert!(read(&path, 42).unwrap().is_none());
+        assert_eq!(append(&path, 42, &[]).unwrap(), 0);
+        assert!(read(&path, 42).unwrap().is_none());
+
+        let path = two_saves("log");
+        let replay = read(&path, 42).unwrap().unwrap();
+        assert!(!replay.torn);
+        assert_eq!(summary(&replay.operations), ["put 1", "put 2", "remove 1"]);
+        // the log belongs to another version of the file
+        assert!(read(&path, 43).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn stops_at_a_torn_save() {
+        let path = two_saves("cut-off-log");
+        let full = fs::read(&path).unwrap();
+        fs::write(&path, &full[..full.len() - 1]).unwrap();
+        let replay = read(&path, 42).unwrap().unwrap();
+        assert!(replay.torn);
+        assert_eq!(summary(&replay.operations), ["put 1", "put 2"]);
+
+        let mut damaged = full.clone();
+        *damaged.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, &damaged).unwrap();
+        let replay = read(&path, 42).unwrap().unwrap();
+        assert!(replay.torn);
+        assert_eq!(summary(&replay.operations), ["put 1", "put 2"]);
+
+        // a length which was cut off
+        let mut partial = full;
+        partial.extend_from_slice(&[1, 0]);
+        fs::write(&path, &partial).unwrap();
+        let replay = read(&path, 42).unwrap().unwrap();
+        assert!(replay.torn);
+        assert_eq!(summary(&replay.operations), ["put 1", "put 2", "remove 1"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
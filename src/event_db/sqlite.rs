@@ -0,0 +1,661 @@
+//! Events kept in SQLite, with rows for events and their attendees
+//!
+//! Each event is a row of `events`, holding the event as CBOR without its
+//! attendees, and each attendee a row of `attendees`, in the event's order.
+//! Columns beside the CBOR hold what events are looked up by, e.g., the open
+//! RSVP link, and are indexed, so a lookup reads the rows of one event
+//! rather than the whole database. Nothing is kept in memory between
+//! lookups. Saves replace the rows of the events they changed and delete
+//! those of the events they removed, all in one transaction, so a save is
+//! never half applied. Rows which can't be decoded are logged and left alone
+//! rather than dropped. The schema version is kept in SQLite's
+//! `user_version`. Moving the events of a CBOR file in is recorded in
+//! `imports`, in the same transaction as the rows, so it happens exactly
+//! once, even if the file can't be moved aside afterwards.
+
+use std::{fs, path::Path, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rusqlite::{
+    params, types::Type, Connection, OptionalExtension, Transaction,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    oplog::Operation,
+    store::{CborFile, EventStore, Lookup, Scan, CBOR_PATH},
+    Event, EventDB,
+};
+use crate::clock::{self, unix_secs};
+
+/// Version of the schema written by this build
+const SCHEMA_VERSION: i64 = 3;
+const SCHEMA: &str = "
+    CREATE TABLE events (
+        id TEXT PRIMARY KEY,
+        -- seconds since the Unix epoch
+        created INTEGER NOT NULL,
+        rsvp_link TEXT,
+        kiosk TEXT,
+        -- SHA-256 of the API key, so looking it up reveals nothing about it
+        api_key_hash TEXT,
+        -- when the first scheduled send is due, unless the event is frozen
+        next_send INTEGER,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX events_created ON events (created);
+    CREATE INDEX events_rsvp_link ON events (rsvp_link);
+    CREATE INDEX events_kiosk ON events (kiosk);
+    CREATE INDEX events_api_key_hash ON events (api_key_hash);
+    CREATE INDEX events_next_send ON events (next_send);
+    CREATE TABLE attendees (
+        id TEXT PRIMARY KEY,
+        event_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        -- trimmed and lowercased
+        email TEXT,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX attendees_event ON attendees (event_id, position);
+    CREATE INDEX attendees_email ON attendees (email);
+    -- attendees merged into another, whose links still lead to the event
+    CREATE TABLE merged_attendees (
+        id TEXT PRIMARY KEY,
+        event_id TEXT NOT NULL
+    );
+    CREATE INDEX merged_attendees_event ON merged_attendees (event_id);
+    -- CBOR files whose events were moved in
+    CREATE TABLE imports (
+        path TEXT PRIMARY KEY,
+        -- seconds since the Unix epoch
+        imported INTEGER NOT NULL
+    );
+";
+/// How long to wait for another instance's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Sqlite {
+    path: &'static str,
+    /// Opened on first use
+    connection: Option<Connection>,
+    /// SQLite's `data_version` as of the last load or write. It changes when
+    /// another connection commits.
+    seen: Option<i64>,
+}
+
+impl Sqlite {
+    pub fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            connection: None,
+            seen: None,
+        }
+    }
+
+    fn connection(&mut self) -> Result<&mut Connection, ()> {
+        if self.connection.is_none() {
+            let connection = open(self.path).map_err(|e| {
+                log::error!("Could not open database {}: {e}", self.path);
+            })?;
+            self.connection = Some(connection);
+        }
+        self.connection.as_mut().ok_or(())
+    }
+
+    fn data_version(&mut self) -> Option<i64> {
+        self.connection()
+            .ok()?
+            .pragma_query_value(None, "data_version", |row| row.get(0))
+            .ok()
+    }
+
+    /// Run a query, logging why it failed
+    fn query<T>(
+        &mut self,
+        query: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, ()> {
+        let path = self.path;
+        query(self.connection()?).map_err(|e| {
+            log::error!("Could not read database {path}: {e}");
+        })
+    }
+
+    /// Move the events of the CBOR file at `from` in, keeping the file as a
+    /// backup, unless they were moved in before
+    fn migrate(&mut self, from: &'static str) -> Result<(), ()> {
+        if !Path::new(from).exists()
+            || self.query(|connection| imported(connection, from))?
+        {
+            return Ok(());
+        }
+        log::info!("Moving the database from {from} into {}", self.path);
+        let mut cbor = CborFile::new(from);
+        cbor.load()?;
+        let db = cbor.into_db();
+        let count = db.events.len();
+        let changes = db
+            .events
+            .into_iter()
+            .map(|event| Operation::Put(Box::new(event)))
+            .collect();
+        let path = self.path;
+        self.connection().and_then(|connection| {
+            import_rows(connection, changes, from).map_err(|e| {
+                log::error!("Could not move the database into {path}: {e}");
+            })
+        })?;
+        let backup = format!("{from}.migrated.bak");
+        match fs::rename(from, &backup) {
+            Ok(_) => log::info!(
+                "Moved {count} events. The original is kept as {backup}."
+            ),
+            Err(e) => log::error!(
+                "Moved {count} events, but could not rename {from} to \
+                 {backup}: {e}"
+            ),
+        }
+        Ok(())
+    }
+}
+
+impl EventStore for Sqlite {
+    fn load(&mut self) -> Result<(), ()> {
+        if !Path::new(self.path).exists() {
+            // e.g., the file was removed to start over
+            self.connection = None;
+        }
+        self.connection()?;
+        self.migrate(CBOR_PATH)?;
+        self.seen = self.data_version();
+        Ok(())
+    }
+
+    fn find(&mut self, lookup: &Lookup) -> Result<Option<Event>, ()> {
+        self.query(|connection| match event_id(connection, lookup)? {
+            Some(id) => read_event(connection, &id),
+            None => Ok(None),
+        })
+    }
+
+    fn contains(&mut self, lookup: &Lookup) -> Result<bool, ()> {
+        self.query(|connection| Ok(event_id(connection, lookup)?.is_some()))
+    }
+
+    fn scan(
+        &mut self,
+        scan: &Scan,
+        visit: &mut dyn FnMut(&Event),
+    ) -> Result<(), ()> {
+        self.query(|connection| {
+            let (sql, key) = match scan {
+                Scan::All => ("SELECT id, data FROM events", None),
+                Scan::Scheduled => (
+                    "SELECT id, data FROM events WHERE next_send IS NOT NULL",
+                    None,
+                ),
+                Scan::Email(email) => (
+                    "SELECT id, data FROM events WHERE id IN
+                     (SELECT event_id FROM attendees WHERE email = ?1)",
+                    Some(normalize_email(email)),
+                ),
+            };
+            let mut statement = connection.prepare_cached(sql)?;
+            let mut rows = match key {
+                Some(key) => statement.query([key])?,
+                None => statement.query([])?,
+            };
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                let data: Vec<u8> = row.get(1)?;
+                if let Some(event) = decode_event(connection, &id, &data)? {
+                    visit(&event);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn write(&mut self, changes: Vec<Operation>) -> Result<u64, ()> {
+        let path = self.path;
+        let result = self.connection().and_then(|connection| {
+            write_rows(connection, changes).map_err(|e| {
+                log::error!(
+                    "Failed to write back database ({e}). Data is lost!"
+                );
+            })
+        });
+        self.seen = self.data_version();
+        result?;
+        fs::metadata(path).map(|m| m.len()).map_err(|_| ())
+    }
+
+    fn changed_elsewhere(&mut self) -> bool {
+        self.data_version() != self.seen
+    }
+}
+
+/// Open the database, creating the schema if it's new, or bringing it up to
+/// date
+fn open(path: &str) -> Result<Connection, String> {
+    let setup = || -> rusqlite::Result<(Connection, i64)> {
+        let mut connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        // readers and the writer don't block each other, and a commit only
+        // appends to the log
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        let version =
+            connection
+                .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        match version {
+            0 => {
+                connection.execute_batch(SCHEMA)?;
+                connection
+                    .pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            }
+            1 => upgrade(&mut connection, path)?,
+            2 => add_imports(&mut connection)?,
+            _ => {}
+        }
+        Ok((connection, version))
+    };
+    let (connection, version) = setup().map_err(|e| e.to_string())?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "its schema version {version} is newer than this build knows"
+        ));
+    }
+    Ok(connection)
+}
+
+/// Split the events of the first schema, which kept each event whole in one
+/// row, into rows of events and attendees
+fn upgrade(connection: &mut Connection, path: &str) -> rusqlite::Result<()> {
+    log::info!("Splitting the events in {path} into rows of attendees");
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "ALTER TABLE events RENAME TO events_v1;
+         DROP INDEX events_created;",
+    )?;
+    transaction.execute_batch(SCHEMA)?;
+    let rows = read_rows_v1(&transaction, "events_v1")?;
+    let mut changes = Vec::with_capacity(rows.len());
+    let mut unreadable = 0;
+    for (id, data) in rows {
+        match decode::<Event>(&data) {
+            Ok(event) => changes.push(Operation::Put(Box::new(event))),
+            Err(e) => {
+                log::error!("Event {id} in {path} could not be read ({e})");
+                unreadable += 1;
+            }
+        }
+    }
+    put_rows(&transaction, changes)?;
+    if unreadable == 0 {
+        transaction.execute_batch("DROP TABLE events_v1")?;
+    } else {
+        log::error!(
+            "{unreadable} events in {path} could not be read. They're left \
+             in the table events_v1, but won't be served."
+        );
+    }
+    transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    transaction.commit()
+}
+
+/// Add the table of imports of the third schema. A CBOR file beside a
+/// database with events in it was moved in by an earlier build, which only
+/// did so into a new database.
+fn add_imports(connection: &mut Connection) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "CREATE TABLE imports (
+            path TEXT PRIMARY KEY,
+            imported INTEGER NOT NULL
+        )",
+    )?;
+    let has_events = transaction.query_row(
+        "SELECT EXISTS (SELECT 1 FROM events)",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_events && Path::new(CBOR_PATH).exists() {
+        record_import(&transaction, CBOR_PATH)?;
+    }
+    transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    transaction.commit()
+}
+
+/// Every event's ID and encoding in `table`, of the first schema
+fn read_rows_v1(
+    connection: &Connection,
+    table: &str,
+) -> rusqlite::Result<Vec<(String, Vec<u8>)>> {
+    let mut statement = connection
+        .prepare(&format!("SELECT id, data FROM {table} ORDER BY created"))?;
+    let rows = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect();
+    rows
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> serde_cbor::Result<T> {
+    serde_cbor::from_slice(data)
+}
+
+fn encode(value: &impl Serialize) -> rusqlite::Result<Vec<u8>> {
+    serde_cbor::to_vec(value)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))
+}
+
+fn hash_api_key(key: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, key.as_bytes());
+    URL_SAFE_NO_PAD.encode(digest)
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_ascii_lowercase()
+}
+
+/// The ID of the event `lookup` leads to
+fn event_id(
+    connection: &Connection,
+    lookup: &Lookup,
+) -> rusqlite::Result<Option<String>> {
+    let (sql, key) = match lookup {
+        Lookup::Event(id) => {
+            ("SELECT id FROM events WHERE id = ?1", id.encode())
+        }
+        Lookup::Attendee(id) => (
+            "SELECT event_id FROM attendees WHERE id = ?1",
+            id.encode(),
+        ),
+        Lookup::MergedAttendee(id) => (
+            "SELECT event_id FROM merged_attendees WHERE id = ?1",
+            id.encode(),
+        ),
+        Lookup::RsvpLink(id) => {
+            ("SELECT id FROM events WHERE rsvp_link = ?1", id.encode())
+        }
+        Lookup::Kiosk(id) => {
+            ("SELECT id FROM events WHERE kiosk = ?1", id.encode())
+        }
+        Lookup::ApiKey(key) => (
+            "SELECT id FROM events WHERE api_key_hash = ?1",
+            hash_api_key(key),
+        ),
+    };
+    connection
+        .prepare_cached(sql)?
+        .query_row([key], |row| row.get(0))
+        .optional()
+}
+
+/// The event with the given ID, with its attendees
+fn read_event(
+    connection: &Connection,
+    id: &str,
+) -> rusqlite::Result<Option<Event>> {
+    let data: Option<Vec<u8>> = connection
+        .prepare_cached("SELECT data FROM events WHERE id = ?1")?
+        .query_row([id], |row| row.get(0))
+        .optional()?;
+    match data {
+        Some(data) => decode_event(connection, id, &data),
+        None => Ok(None),
+    }
+}
+
+/// Decode an event's row and read its attendees. Returns `None` if either
+/// can't be decoded.
+fn decode_event(
+    connection: &Connection,
+    id: &str,
+    data: &[u8],
+) -> rusqlite::Result<Option<Event>> {
+    let mut event = match decode::<Event>(data) {
+        Ok(event) => event,
+        Err(e) => {
+            log::error!(
+                "Event {id} could not be read ({e}). It's left as it is, but \
+                 won't be served."
+            );
+            return Ok(None);
+        }
+    };
+    let mut statement = connection.prepare_cached(
+        "SELECT data FROM attendees WHERE event_id = ?1 ORDER BY position",
+    )?;
+    let attendees = statement.query_map([id], |row| {
+        let data: Vec<u8> = row.get(0)?;
+        decode(&data).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Blob, e.into())
+        })
+    })?;
+    for attendee in attendees {
+        match attendee {
+            Ok(attendee) => event.attendees.push(attendee),
+            Err(rusqlite::Error::FromSqlConversionFailure(_, _, e)) => {
+                log::error!(
+                    "An attendee of event {id} could not be read ({e}). The \
+                     event is left as it is, but won't be served."
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    event.upgrade();
+    Ok(Some(event))
+}
+
+fn write_rows(
+    connection: &mut Connection,
+    changes: Vec<Operation>,
+) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    put_rows(&transaction, changes)?;
+    transaction.commit()
+}
+
+/// Replace the rows of the events put and delete those of the events removed
+/// Write the events of the CBOR file at `from`, and record that they were,
+/// in one transaction
+fn import_rows(
+    connection: &mut Connection,
+    changes: Vec<Operation>,
+    from: &str,
+) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    put_rows(&transaction, changes)?;
+    record_import(&transaction, from)?;
+    transaction.commit()
+}
+
+fn record_import(connection: &Connection, from: &str) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO imports (path, imported) VALUES (?1, ?2)",
+        params![from, unix_secs(clock::now()) as i64],
+    )?;
+    Ok(())
+}
+
+/// Whether the events of the CBOR file at `from` were moved in
+fn imported(connection: &Connection, from: &str) -> rusqlite::Result<bool> {
+    connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM imports WHERE path = ?1)",
+        [from],
+        |row| row.get(0),
+    )
+}
+
+fn put_rows(
+    transaction: &Transaction,
+    changes: Vec<Operation>,
+) -> rusqlite::Result<()> {
+    let mut upsert = transaction.prepare_cached(
+        "INSERT INTO events
+         (id, created, rsvp_link, kiosk, api_key_hash, next_send, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (id) DO UPDATE
+         SET created = excluded.created, rsvp_link = excluded.rsvp_link,
+             kiosk = excluded.kiosk, api_key_hash = excluded.api_key_hash,
+             next_send = excluded.next_send, data = excluded.data",
+    )?;
+    let mut delete =
+        transaction.prepare_cached("DELETE FROM events WHERE id = ?1")?;
+    let mut delete_attendees = transaction
+        .prepare_cached("DELETE FROM attendees WHERE event_id = ?1")?;
+    let mut insert_attendee = transaction.prepare_cached(
+        "INSERT INTO attendees (id, event_id, position, email, data)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    let mut delete_merged = transaction
+        .prepare_cached("DELETE FROM merged_attendees WHERE event_id = ?1")?;
+    let mut insert_merged = transaction.prepare_cached(
+        "INSERT INTO merged_attendees (id, event_id) VALUES (?1, ?2)",
+    )?;
+    for change in changes {
+        match change {
+            Operation::Put(mut event) => {
+                let id = event.id.encode();
+                let attendees = std::mem::take(&mut event.attendees);
+                let next_send = event
+                    .scheduled_sends
+                    .first()
+                    .filter(|_| !event.frozen)
+                    .map(|send| unix_secs(send.at) as i64);
+                upsert.execute(params![
+                    id,
+                    unix_secs(event.created) as i64,
+                    event.open_rsvp.as_ref().map(|link| link.id.encode()),
+                    event.kiosk.map(|kiosk| kiosk.encode()),
+                    event.api_key.as_deref().map(hash_api_key),
+                    next_send,
+                    encode(&event)?,
+                ])?;
+                delete_attendees.execute([&id])?;
+                for (position, attendee) in attendees.iter().enumerate() {
+                    insert_attendee.execute(params![
+                        attendee.id.encode(),
+                        id,
+                        position as i64,
+                        attendee.email.as_deref().map(normalize_email),
+                        encode(attendee)?,
+                    ])?;
+                }
+                delete_merged.execute([&id])?;
+                for merged in event.merged.iter() {
+                    insert_merged.execute(params![merged.from.encode(), id])?;
+                }
+            }
+            Operation::Remove(id) => {
+                let id = id.encode();
+                delete_attendees.execute([&id])?;
+                delete_merged.execute([&id])?;
+                delete.execute([&id])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the database file without changing it: SQLite's own integrity check,
+/// then whether every event can be read. Returns the events which could be.
+pub fn check(path: &str) -> Result<EventDB, String> {
+    let connection = Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )
+    .map_err(|e| format!("Database could not be opened: {e}"))?;
+    let verdict: String = connection
+        .pragma_query_value(None, "integrity_check", |row| row.get(0))
+        .map_err(|e| format!("Database could not be checked: {e}"))?;
+    if verdict != "ok" {
+        return Err(format!("Database is damaged: {verdict}"));
+    }
+    let version: i64 = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| format!("Database could not be checked: {e}"))?;
+    let mut db = EventDB::default();
+    if version == 0 {
+        // nothing written yet, so the schema is created on opening
+        return Ok(db);
+    }
+    if version == 1 {
+        let rows = read_rows_v1(&connection, "events")
+            .map_err(|e| format!("Database could not be read: {e}"))?;
+        for (id, data) in rows {
+            let event = decode::<Event>(&data)
+                .map_err(|e| format!("Event {id} could not be parsed: {e}"))?;
+            db.events.push(event);
+        }
+        return Ok(db);
+    }
+    let read = || -> rusqlite::Result<Vec<(String, Vec<u8>)>> {
+        let mut statement = connection
+            .prepare("SELECT id, data FROM events ORDER BY created")?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        rows
+    };
+    let rows = read().map_err(|e| format!("Database could not be read: {e}"))?;
+    for (id, data) in rows {
+        let event = decode_event(&connection, &id, &data)
+            .map_err(|e| format!("Database could not be read: {e}"))?
+            .ok_or(format!("Event {id} could not be parsed"))?;
+        db.events.push(event);
+    }
+    Ok(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event_db::temp_path, ids::Id};
+
+    fn put(id: u64, name: &str) -> Operation {
+        Operation::Put(Box::new(Event {
+            name: Some(name.to_string()),
+            ..Event::new(Id::from(id))
+        }))
+    }
+
+    fn name(store: &mut Sqlite, id: u64) -> Option<String> {
+        store
+            .find(&Lookup::Event(Id::from(id)))
+            .unwrap()
+            .and_then(|event| event.name)
+    }
+
+    #[test]
+    fn migrates_once() {
+        let cbor_path: &'static str = temp_path("migrate.cbor").leak();
+        let path: &'static str = temp_path("migrate.sqlite").leak();
+        let backup = format!("{cbor_path}.migrated.bak");
+        let mut cbor = CborFile::new(cbor_path);
+        cbor.load().unwrap();
+        cbor.write(vec![put(1, "Picnic")]).unwrap();
+        cbor.compact().unwrap();
+
+        let mut store = Sqlite::new(path);
+        store.migrate(cbor_path).unwrap();
+        assert_eq!(name(&mut store, 1).as_deref(), Some("Picnic"));
+        assert!(!Path::new(cbor_path).exists());
+
+        // as if moving the file aside had failed
+        fs::rename(&backup, cbor_path).unwrap();
+        store.write(vec![put(1, "Barbecue")]).unwrap();
+        let mut reopened = Sqlite::new(path);
+        reopened.migrate(cbor_path).unwrap();
+        assert_eq!(name(&mut reopened, 1).as_deref(), Some("Barbecue"));
+
+        drop((store, reopened));
+        for file in [cbor_path, path] {
+            fs::remove_file(file).unwrap();
+        }
+        for suffix in ["-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+}
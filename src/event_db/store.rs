@@ -0,0 +1,591 @@
+//! Where the database is kept between runs
+//!
+//! The database thread looks events up in an `EventStore` and hands it the
+//! events each save changed. Two are built in:
+//!
+//! - `CborFile` keeps everything in one file, and the database in memory, so
+//!   lookups never touch the disk. It's simple to back up and inspect, and
+//!   fine while the database is small.
+//! - `Sqlite` keeps each event in a row of its own and writes the events
+//!   which changed in one transaction, so a save costs about the same however
+//!   large the database grows.
+//!
+//! Starting with `--storage sqlite` while only the CBOR file exists moves its
+//! events into SQLite once, keeping the file as a backup.
+//!
+//! The CBOR file is never rewritten in place. Saves are appended to the
+//! operation log beside it, see `oplog`, until the log has grown as large as
+//! the file. The file is then written in full to a temporary file beside it,
+//! which replaces it once it's complete and on disk, so being killed mid-save
+//! leaves the previous version rather than half of the new one. The log is
+//! also folded into the file when the server shuts down, so other tools find
+//! the whole database in the file.
+
+use std::{
+    fs::{self, File},
+    io,
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use super::{
+    format::{self, ReadError},
+    oplog::{self, Operation},
+    read_legacy, sqlite, Event, EventDB,
+};
+use crate::{admin::constant_time_eq, ids::Id};
+
+/// The CBOR file, also where SQLite takes over from
+pub(super) const CBOR_PATH: &str = "events.db";
+pub(super) const SQLITE_PATH: &str = "events.sqlite";
+/// Smallest operation log which is folded into the CBOR file, however small
+/// the file
+const MIN_COMPACTED_LOG_BYTES: u64 = 1024 * 1024;
+
+static STORAGE: OnceLock<Storage> = OnceLock::new();
+
+/// How the database is stored
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Storage {
+    /// One CBOR file, rewritten on every save
+    #[default]
+    Cbor,
+    /// An SQLite database with a row per event
+    Sqlite,
+}
+
+pub fn set_storage(storage: Storage) {
+    if STORAGE.set(storage).is_err() {
+        log::warn!("Storage was already set");
+    }
+}
+
+pub fn storage() -> Storage {
+    STORAGE.get().copied().unwrap_or_default()
+}
+
+/// The file the database is kept in
+pub fn path() -> &'static str {
+    match storage() {
+        Storage::Cbor => CBOR_PATH,
+        Storage::Sqlite => SQLITE_PATH,
+    }
+}
+
+/// What an event can be looked up by
+#[derive(Clone, Debug)]
+pub enum Lookup {
+    Event(Id),
+    Attendee(Id),
+    /// An attendee who was merged into another one of the event
+    MergedAttendee(Id),
+    RsvpLink(Id),
+    Kiosk(Id),
+    ApiKey(String),
+}
+
+impl Lookup {
+    /// Whether the key leads to `event`
+    pub fn matches(&self, event: &Event) -> bool {
+        match self {
+            Lookup::Event(id) => event.id == *id,
+            Lookup::Attendee(id) => {
+                event.attendees.iter().any(|at| at.id == *id)
+            }
+            Lookup::MergedAttendee(id) => {
+                event.merged.iter().any(|merged| merged.from == *id)
+            }
+            Lookup::RsvpLink(id) => {
+                event.open_rsvp.as_ref().is_some_and(|l| l.id == *id)
+            }
+            Lookup::Kiosk(id) => event.kiosk == Some(*id),
+            Lookup::ApiKey(key) => {
+                event.api_key.as_deref().is_some_and(|expected| {
+                    constant_time_eq(key.as_bytes(), expected.as_bytes())
+                })
+            }
+        }
+    }
+}
+
+/// Which events a scan visits. Stores may visit more than asked for, so
+/// callers still check each event.
+#[derive(Clone, Debug)]
+pub enum Scan {
+    All,
+    /// Events with sends scheduled, unless they're frozen
+    Scheduled,
+    /// Events with an attendee of this email address, in any case
+    Email(String),
+}
+
+impl Scan {
+    /// Whether the scan visits `event`
+    pub fn includes(&self, event: &Event) -> bool {
+        match self {
+            Scan::All => true,
+            Scan::Scheduled => {
+                !event.frozen && !event.scheduled_sends.is_empty()
+            }
+            Scan::Email(email) => event.attendees.iter().any(|at| {
+                at.email.as_deref().is_some_and(|address| {
+                    address.trim().eq_ignore_ascii_case(email.trim())
+                })
+            }),
+        }
+    }
+}
+
+/// Keeps the database between runs. Used by the database thread and the loans
+/// it hands out, one at a time, so its methods may block.
+pub trait EventStore: Send {
+    /// Get the database ready, converting it if it's in an earlier layout,
+    /// and forgetting what was read of it before. Creates an empty one if
+    /// there's none, or if what's there can't be read.
+    fn load(&mut self) -> Result<(), ()>;
+
+    /// The event `key` leads to
+    fn find(&mut self, key: &Lookup) -> Result<Option<Event>, ()>;
+
+    /// Whether `key` leads to an event
+    fn contains(&mut self, key: &Lookup) -> Result<bool, ()> {
+        Ok(self.find(key)?.is_some())
+    }
+
+    /// Show `visit` the events `scan` includes, in no particular order
+    fn scan(
+        &mut self,
+        scan: &Scan,
+        visit: &mut dyn FnMut(&Event),
+    ) -> Result<(), ()>;
+
+    /// Make the changes, all of them or none. Returns how large the database
+    /// now is on disk, in bytes.
+    fn write(&mut self, changes: Vec<Operation>) -> Result<u64, ()>;
+
+    /// Whether another instance wrote the database since this one last
+    /// loaded or wrote it
+    fn changed_elsewhere(&mut self) -> bool;
+
+    /// Write the database so it can be read without replaying anything, e.g.,
+    /// before shutting down
+    fn compact(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+impl EventDB {
+    /// The event `key` leads to
+    pub(super) fn find(&self, key: &Lookup) -> Option<&Event> {
+        self.events.iter().find(|ev| key.matches(ev))
+    }
+
+    /// Make the changes. Returns the changes which undo them, in the order
+    /// to make them in.
+    pub(super) fn apply(&mut self, changes: Vec<Operation>) -> Vec<Operation> {
+        let mut undo = Vec::with_capacity(changes.len());
+        for change in changes {
+            let id = match &change {
+                Operation::Put(event) => event.id,
+                Operation::Remove(id) => *id,
+            };
+            let old = match self.events.iter().position(|ev| ev.id == id) {
+                Some(pos) => Some(self.events.remove(pos)),
+                None => None,
+            };
+            undo.push(match old {
+                Some(old) => Operation::Put(Box::new(old)),
+                None => Operation::Remove(id),
+            });
+            if let Operation::Put(event) = change {
+                self.insert_event(*event);
+            }
+        }
+        undo.reverse();
+        undo
+    }
+
+    /// Add an event while keeping the creation time ordering
+    pub fn insert_event(&mut self, event: Event) {
+        let pos = self
+            .events
+            .partition_point(|ev| ev.created <= event.created);
+        self.events.insert(pos, event);
+    }
+}
+
+/// Bring a database just read up to what this build expects
+pub(super) fn tidy(db: &mut EventDB) {
+    // databases written before ordering was maintained may be out of order.
+    // Already sorted data makes this cheap.
+    db.events.sort_by_key(|ev| ev.created);
+    for event in db.events.iter_mut() {
+        event.upgrade();
+    }
+}
+
+/// The store for the configured backend
+pub(super) fn open() -> Box<dyn EventStore> {
+    match storage() {
+        Storage::Cbor => Box::new(CborFile::new(CBOR_PATH)),
+        Storage::Sqlite => Box::new(sqlite::Sqlite::new(SQLITE_PATH)),
+    }
+}
+
+/// Identifies a version of a file, to notice when another instance has
+/// written it
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &str) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Write the database to `path` through a temporary file, see
+/// `replace_file`
+fn write_atomically(path: &str, db: &EventDB) -> io::Result<u64> {
+    replace_file(path, |file| format::write(file.try_clone()?, db))
+}
+
+/// Write a file through a temporary file beside it, which takes its place
+/// only once it's complete and synced to disk, so being killed mid-write
+/// leaves the previous version
+pub fn replace_file<T>(
+    path: &str,
+    write: impl FnOnce(&mut File) -> io::Result<T>,
+) -> io::Result<T> {
+    let temp = format!("{path}.tmp");
+    let written = File::create(&temp).and_then(|mut file| {
+        let written = write(&mut file)?;
+        file.sync_all()?;
+        Ok(written)
+    });
+    let written = match written {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+    };
+    fs::rename(&temp, path)?;
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    {
+        use std::path::Path;
+        let dir = Path::new(path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(dir).and_then(|dir| dir.sync_all())?;
+    }
+    Ok(written)
+}
+
+/// The whole database in one file, in the layout of `format`, and the
+/// changes since it was written in an operation log beside it
+pub struct CborFile {
+    path: &'static str,
+    log_path: String,
+    /// The events as the file and log have them
+    db: EventDB,
+    /// The file as this instance last loaded or wrote it
+    seen: Stamp,
+    log_seen: Stamp,
+    /// Checksum of the file the log continues. None to write the file in
+    /// full on the next save, e.g., after appending failed.
+    file_checksum: Option<u32>,
+    file_bytes: u64,
+    log_bytes: u64,
+}
+
+impl CborFile {
+    pub fn new(path: &'static str) -> Self {
+        let log_path = format!("{path}.log");
+        Self {
+            path,
+            db: EventDB::default(),
+            seen: stamp(path),
+            log_seen: stamp(&log_path),
+            log_path,
+            file_checksum: None,
+            file_bytes: 0,
+            log_bytes: 0,
+        }
+    }
+
+    /// The database as loaded
+    pub fn into_db(self) -> EventDB {
+        self.db
+    }
+
+    /// Replay the operation log onto the database just read from the file,
+    /// then fold it into the file
+    fn replay(&mut self) {
+        self.log_seen = stamp(&self.log_path);
+        let checksum = match oplog::file_checksum(self.path) {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                log::error!("Could not read {} back ({e})", self.path);
+                self.file_checksum = None;
+                return;
+            }
+        };
+        match oplog::read(&self.log_path, checksum) {
+            Ok(None) => {
+                self.file_checksum = Some(checksum);
+                self.file_bytes = fs::metadata(self.path)
+                    .map(|m| m.len())
+                    .unwrap_or_default();
+                self.log_bytes = 0;
+            }
+            Ok(Some(replay)) => {
+                if replay.torn {
+                    log::warn!(
+                        "{} ends in a change that was cut off, probably by a \
+                         crash while saving. It's left out.",
+                        self.log_path
+                    );
+                }
+                log::info!(
+                    "Replaying {} changes from {}",
+                    replay.operations.len(),
+                    self.log_path
+                );
+                self.db.apply(replay.operations);
+                // the next save folds it in if this fails
+                let _ = self.compact();
+            }
+            Err(e) => {
+                log::error!("{} can't be replayed ({e})", self.log_path);
+                oplog::set_aside(&self.log_path);
+                self.file_checksum = Some(checksum);
+                self.log_bytes = 0;
+            }
+        }
+    }
+
+    /// Write the database to the file in full and start the log over
+    fn write_file(&mut self) -> Result<u64, ()> {
+        let written = write_atomically(self.path, &self.db);
+        self.seen = stamp(self.path);
+        let bytes = written.map_err(|e| {
+            log::error!("Failed to write back database ({e}). Data is lost!");
+            self.file_checksum = None;
+        })?;
+        self.file_bytes = bytes;
+        // the file has every change in the log now
+        self.file_checksum = match fs::remove_file(&self.log_path) {
+            Ok(()) => oplog::file_checksum(self.path).ok(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                oplog::file_checksum(self.path).ok()
+            }
+            Err(e) => {
+                log::error!("Could not remove {} ({e})", self.log_path);
+                None
+            }
+        };
+        self.log_seen = stamp(&self.log_path);
+        self.log_bytes = 0;
+        Ok(bytes)
+    }
+
+    /// Make the changes and write the database to the file in full. Leaves
+    /// the database as it was if that fails.
+    fn write_file_with(&mut self, changes: Vec<Operation>) -> Result<u64, ()> {
+        let undo = self.db.apply(changes);
+        let written = self.write_file();
+        if written.is_err() {
+            self.db.apply(undo);
+        }
+        written
+    }
+
+    /// Load the database from the file alone, as `load` does otherwise
+    fn load_file(&mut self) -> Result<EventDB, ()> {
+        let path = self.path;
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => {
+                // if failed, it's probably the first run
+                log::info!(
+                    "Unable to open an existing database. Creating new."
+                );
+                return self.create();
+            }
+        };
+        self.seen = stamp(path);
+
+        match format::read(file) {
+            Ok(db) => Ok(db),
+            Err(e) => {
+                let converted = match e {
+                    ReadError::Parse(_) => read_legacy(path),
+                    _ => None,
+                };
+                if let Some((db, layout)) = converted {
+                    log::warn!(
+                        "Database is in the earlier \"{layout}\" layout ({e}). \
+                         Converting it."
+                    );
+                    // the original is kept as is until it's safely backed up
+                    let backup = format!("{path}.{layout}.bak");
+                    if let Err(e) = fs::copy(path, &backup) {
+                        log::error!(
+                            "Could not back up database to {backup}: {e}"
+                        );
+                        return Ok(db);
+                    }
+                    log::info!("Backed up the original database to {backup}");
+                    self.db = db;
+                    if self.write_file().is_err() {
+                        log::error!("Could not write the converted database");
+                    }
+                    return Ok(std::mem::take(&mut self.db));
+                }
+                log::warn!(
+                    "Database is corrupted ({e}) and matches no earlier \
+                     layout. Recreating."
+                );
+                self.create()
+            }
+        }
+    }
+
+    fn create(&mut self) -> Result<EventDB, ()> {
+        self.db = EventDB::default();
+        self.write_file().map_err(|_| {
+            log::error!("Could not create database file");
+        })?;
+        Ok(EventDB::default())
+    }
+}
+
+impl EventStore for CborFile {
+    fn load(&mut self) -> Result<(), ()> {
+        let mut db = self.load_file()?;
+        tidy(&mut db);
+        self.db = db;
+        self.replay();
+        Ok(())
+    }
+
+    fn find(&mut self, key: &Lookup) -> Result<Option<Event>, ()> {
+        Ok(self.db.find(key).cloned())
+    }
+
+    fn contains(&mut self, key: &Lookup) -> Result<bool, ()> {
+        Ok(self.db.find(key).is_some())
+    }
+
+    fn scan(
+        &mut self,
+        scan: &Scan,
+        visit: &mut dyn FnMut(&Event),
+    ) -> Result<(), ()> {
+        self.db
+            .events
+            .iter()
+            .filter(|event| scan.includes(event))
+            .for_each(visit);
+        Ok(())
+    }
+
+    fn write(&mut self, changes: Vec<Operation>) -> Result<u64, ()> {
+        let log_limit = self.file_bytes.max(MIN_COMPACTED_LOG_BYTES);
+        let Some(checksum) =
+            self.file_checksum.filter(|_| self.log_bytes < log_limit)
+        else {
+            return self.write_file_with(changes);
+        };
+        let appended = oplog::append(&self.log_path, checksum, &changes);
+        self.log_seen = stamp(&self.log_path);
+        match appended {
+            Ok(bytes) => {
+                self.db.apply(changes);
+                self.log_bytes += bytes;
+                Ok(self.file_bytes + self.log_bytes)
+            }
+            Err(e) => {
+                // what was appended may be cut off, so nothing after it
+                // would be replayed
+                log::warn!(
+                    "Could not append to {} ({e}). Writing the whole \
+                     database instead.",
+                    self.log_path
+                );
+                self.write_file_with(changes)
+            }
+        }
+    }
+
+    fn changed_elsewhere(&mut self) -> bool {
+        stamp(self.path) != self.seen || stamp(&self.log_path) != self.log_seen
+    }
+
+    fn compact(&mut self) -> Result<(), ()> {
+        if self.log_bytes > 0 || self.file_checksum.is_none() {
+            self.write_file()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::event_db::temp_path;
+
+    fn put(id: u64) -> Operation {
+        Operation::Put(Box::new(Event {
+            name: Some(format!("Event {id}")),
+            ..Event::new(Id::from(id))
+        }))
+    }
+
+    fn ids(db: &EventDB) -> Vec<Id> {
+        db.events.iter().map(|ev| ev.id).collect()
+    }
+
+    #[test]
+    fn applies_and_undoes_changes() {
+        let mut db = EventDB::default();
+        db.apply(vec![put(1), put(2)]);
+        let undo = db.apply(vec![Operation::Remove(Id::from(1u64)), put(3)]);
+        assert_eq!(ids(&db), [Id::from(2u64), Id::from(3u64)]);
+        db.apply(undo);
+        assert_eq!(ids(&db), [Id::from(1u64), Id::from(2u64)]);
+    }
+
+    #[test]
+    fn replays_the_log_on_load() {
+        // stores keep the path they were made with
+        let path: &'static str = temp_path("store").leak();
+        let log_path = format!("{path}.log");
+        let mut store = CborFile::new(path);
+        store.load().unwrap();
+        store.write(vec![put(1), put(2)]).unwrap();
+        store
+            .write(vec![Operation::Remove(Id::from(1u64))])
+            .unwrap();
+        // small saves only append to the log
+        assert!(Path::new(&log_path).exists());
+        let on_disk = format::read(File::open(path).unwrap()).unwrap();
+        assert!(on_disk.events.is_empty());
+
+        // a save cut off by a crash is left out
+        let mut log = fs::read(&log_path).unwrap();
+        log.extend_from_slice(&[1, 0]);
+        fs::write(&log_path, log).unwrap();
+
+        let mut reloaded = CborFile::new(path);
+        reloaded.load().unwrap();
+        assert_eq!(ids(&reloaded.db), [Id::from(2u64)]);
+        // and folded into the file
+        assert!(!Path::new(&log_path).exists());
+        let on_disk = format::read(File::open(path).unwrap()).unwrap();
+        assert_eq!(ids(&on_disk), [Id::from(2u64)]);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,484 @@
+//! The thread which owns the database
+//!
+//! A single thread, started by `start`, owns the `EventStore` and loads and
+//! writes it, off the async runtime. Every operation asks the thread for a
+//! `Loan` of it over a bounded channel, so operations run one at a time in
+//! the order they asked, and callers wait rather than pile up when the thread
+//! falls behind. A loan looks events up in the store and works on copies of
+//! them. Saving it hands the copies back to be written, while dropping it
+//! discards them, so nothing a failed operation did is kept. Saves which
+//! arrive back to back are written to disk together, and each caller is
+//! answered once its change is on disk. Until then, later loans see the
+//! change as saved. If writing fails, every save of the batch is answered
+//! with the error and forgotten.
+//!
+//! Every write is also announced to `watch_changes`, so pages which show an
+//! event live know when to look again.
+//!
+//! With shared storage, other instances may change the database too. Each
+//! loan then holds the lock file from lending to giving back, the database is
+//! loaded again first if another instance wrote it since this one last
+//! touched it, and saves are written before the lock is released rather than
+//! batched.
+
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, OnceLock},
+    thread,
+};
+
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    sync::{mpsc, oneshot, watch},
+    task::block_in_place,
+};
+
+use super::{
+    check_size, lock,
+    oplog::Operation,
+    shared_storage,
+    store::{self, EventStore, Lookup, Scan},
+    Event, FindEventError,
+};
+use crate::ids::Id;
+
+/// Operations which may wait for the database before callers have to wait to
+/// even ask
+const QUEUE_CAPACITY: usize = 256;
+/// Most saves written to disk at once
+const MAX_BATCH: usize = 32;
+
+/// Set once the thread is started
+static COMMANDS: OnceLock<mpsc::Sender<Command>> = OnceLock::new();
+
+/// Counts the writes so far
+static CHANGES: LazyLock<watch::Sender<u64>> =
+    LazyLock::new(|| watch::Sender::new(0));
+
+enum Command {
+    /// Lend the database out until the loan comes back
+    Lend {
+        reply: oneshot::Sender<Result<Loan, ()>>,
+    },
+    /// Write pending saves, then load the database from disk again
+    Reload {
+        reply: oneshot::Sender<Result<(), ()>>,
+    },
+    /// Write pending saves and compact the store
+    Flush {
+        reply: oneshot::Sender<Result<(), ()>>,
+    },
+}
+
+/// Events as saves left them, keyed by ID. None where an event was removed.
+type Changes = BTreeMap<Id, Option<Event>>;
+
+/// What the thread lends out with every loan
+struct Lent {
+    store: Box<dyn EventStore>,
+    /// Changes saved, but not written yet
+    unwritten: Changes,
+}
+
+/// How a loan came back
+struct Returned {
+    lent: Lent,
+    /// Set if the loan was saved: its changes, and who to tell once they're
+    /// on disk
+    saved: Option<(Changes, oneshot::Sender<Result<(), ()>>)>,
+}
+
+/// Exclusive access to the database until saved or dropped. Events looked up
+/// through `find` are copies, which saving the loan writes back. Dropping it
+/// discards them.
+pub struct Loan {
+    lent: Option<Lent>,
+    /// The events this loan looked up to change, inserted, or removed
+    changes: Changes,
+    give_back: Option<oneshot::Sender<Returned>>,
+}
+
+/// Start the thread which owns the database, which loads it. Operations on
+/// the database fail until this is called.
+pub fn start() {
+    let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+    if COMMANDS.set(sender).is_err() {
+        log::warn!("Database thread was already started");
+        return;
+    }
+    thread::Builder::new()
+        .name("database".to_string())
+        .spawn(move || run(receiver))
+        .expect("Database thread can be started");
+}
+
+/// Ask the thread for something
+async fn send(command: Command) -> Result<(), ()> {
+    let Some(commands) = COMMANDS.get() else {
+        log::error!("Database thread isn't started");
+        return Err(());
+    };
+    commands.send(command).await.map_err(|_| {
+        log::error!("Database thread is gone");
+    })
+}
+
+/// Run a store call from a loan, which is used by async code, letting the
+/// runtime move other tasks off this worker meanwhile where it can
+fn blocking<T>(call: impl FnOnce() -> T) -> T {
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => block_in_place(call),
+        // there's no other worker to move tasks to
+        _ => call(),
+    }
+}
+
+fn inaccessible() -> FindEventError {
+    FindEventError::Database("Internal database was inaccessible".to_string())
+}
+
+/// The event among `changes` which `key` leads to
+fn changed<'a>(changes: &'a Changes, key: &Lookup) -> Option<&'a Event> {
+    match key {
+        Lookup::Event(id) => changes.get(id)?.as_ref(),
+        key => changes.values().flatten().find(|ev| key.matches(ev)),
+    }
+}
+
+impl Loan {
+    fn give_back(&mut self, saved: Option<oneshot::Sender<Result<(), ()>>>) {
+        if let (Some(lent), Some(give_back)) =
+            (self.lent.take(), self.give_back.take())
+        {
+            let changes = std::mem::take(&mut self.changes);
+            let _ = give_back.send(Returned {
+                lent,
+                saved: saved.map(|saved| (changes, saved)),
+            });
+        }
+    }
+
+    fn lent(&mut self) -> &mut Lent {
+        self.lent
+            .as_mut()
+            .expect("A loan holds the database until given back")
+    }
+
+    /// A copy of the event `key` leads to, with the changes saved so far.
+    /// Changing it changes nothing; see `find` for that.
+    pub fn get(
+        &mut self,
+        key: Lookup,
+    ) -> Result<Option<Event>, FindEventError> {
+        let Self { lent, changes, .. } = self;
+        let Lent { store, unwritten } = lent
+            .as_mut()
+            .expect("A loan holds the database until given back");
+        if let Some(event) =
+            changed(changes, &key).or_else(|| changed(unwritten, &key))
+        {
+            return Ok(Some(event.clone()));
+        }
+        let found = blocking(|| store.find(&key))
+            .map_err(|_| inaccessible())?;
+        // the store has changed events as they were before, and those are
+        // looked up above
+        Ok(found.filter(|ev| {
+            !changes.contains_key(&ev.id) && !unwritten.contains_key(&ev.id)
+        }))
+    }
+
+    /// The event `key` leads to, to change. The changes are saved with the
+    /// loan.
+    pub fn find(
+        &mut self,
+        key: Lookup,
+    ) -> Result<Option<&mut Event>, FindEventError> {
+        let id = match changed(&self.changes, &key) {
+            Some(event) => event.id,
+            None => {
+                let Some(event) = self.get(key)? else {
+                    return Ok(None);
+                };
+                let id = event.id;
+                self.changes.insert(id, Some(event));
+                id
+            }
+        };
+        Ok(self.changes.get_mut(&id).and_then(Option::as_mut))
+    }
+
+    /// The event with the given ID, to change, as `find` does
+    pub fn event(
+        &mut self,
+        ev_id: Id,
+    ) -> Result<Option<&mut Event>, FindEventError> {
+        self.find(Lookup::Event(ev_id))
+    }
+
+    /// Show `visit` the events `scan` includes, with the changes saved so
+    /// far, in no particular order. Use `find` to change any of them.
+    pub fn scan(
+        &mut self,
+        scan: Scan,
+        mut visit: impl FnMut(&Event),
+    ) -> Result<(), FindEventError> {
+        let Self { lent, changes, .. } = self;
+        let Lent { store, unwritten } = lent
+            .as_mut()
+            .expect("A loan holds the database until given back");
+        blocking(|| {
+            store.scan(&scan, &mut |event| {
+                if !changes.contains_key(&event.id)
+                    && !unwritten.contains_key(&event.id)
+                {
+                    visit(event);
+                }
+            })
+        })
+        .map_err(|_| inaccessible())?;
+        let unwritten = unwritten
+            .iter()
+            .filter(|(id, _)| !changes.contains_key(id))
+            .map(|(_, event)| event);
+        let overlay = unwritten.chain(changes.values()).flatten();
+        for event in overlay.filter(|event| scan.includes(event)) {
+            visit(event);
+        }
+        Ok(())
+    }
+
+    /// Whether `key` leads to an event. Events which can't be looked up
+    /// count as taken, so they're never handed out twice.
+    fn taken(&mut self, key: Lookup) -> bool {
+        if changed(&self.changes, &key).is_some() {
+            return true;
+        }
+        let Lent { store, unwritten } = self.lent();
+        changed(unwritten, &key).is_some()
+            || blocking(|| store.contains(&key)).unwrap_or(true)
+    }
+
+    pub fn event_id_taken(&mut self, id: Id) -> bool {
+        self.taken(Lookup::Event(id))
+    }
+
+    pub fn open_rsvp_id_taken(&mut self, id: Id) -> bool {
+        self.taken(Lookup::RsvpLink(id))
+    }
+
+    pub fn kiosk_id_taken(&mut self, id: Id) -> bool {
+        self.taken(Lookup::Kiosk(id))
+    }
+
+    pub fn attendee_id_taken(&mut self, id: Id) -> bool {
+        // merged IDs still lead somewhere, so they're never handed out again
+        self.taken(Lookup::Attendee(id))
+            || self.taken(Lookup::MergedAttendee(id))
+    }
+
+    /// Add an event, saved with the loan
+    pub fn insert_event(&mut self, event: Event) {
+        self.changes.insert(event.id, Some(event));
+    }
+
+    /// Remove an event when the loan is saved
+    pub fn remove_event(&mut self, ev_id: Id) {
+        self.changes.insert(ev_id, None);
+    }
+}
+
+impl Drop for Loan {
+    fn drop(&mut self) {
+        self.give_back(None);
+    }
+}
+
+/// Borrow the database. Fails if it couldn't be loaded.
+pub async fn open_db() -> Result<Loan, ()> {
+    let (reply, receive) = oneshot::channel();
+    send(Command::Lend { reply }).await?;
+    receive.await.map_err(|_| ())?
+}
+
+/// Give the database back and wait until the changes are on disk. The loan is
+/// moved in so the caller can't keep changing it afterwards.
+pub async fn save_db(mut db: Loan) -> Result<(), ()> {
+    let (saved, written) = oneshot::channel();
+    db.give_back(Some(saved));
+    written.await.map_err(|_| ())?
+}
+
+/// Load the database from disk again, e.g., after the file was replaced.
+/// Changes saved before are written first.
+pub async fn reload_db() -> Result<(), ()> {
+    let (reply, receive) = oneshot::channel();
+    send(Command::Reload { reply }).await?;
+    receive.await.map_err(|_| ())?
+}
+
+/// Write every save asked for so far, e.g., before shutting down, and leave
+/// the store so it loads without replaying anything. Operations still waiting
+/// for the database go first.
+pub async fn flush_db() -> Result<(), ()> {
+    let (reply, receive) = oneshot::channel();
+    send(Command::Flush { reply }).await?;
+    receive.await.map_err(|_| ())?
+}
+
+/// Be told whenever the database may have changed. Changes in quick
+/// succession may be told as one.
+pub fn watch_changes() -> watch::Receiver<u64> {
+    CHANGES.subscribe()
+}
+
+/// Write the unwritten changes and answer the saves waiting for them
+fn flush(lent: &mut Lent, pending: &mut Vec<oneshot::Sender<Result<(), ()>>>) {
+    if pending.is_empty() {
+        return;
+    }
+    let changes = std::mem::take(&mut lent.unwritten)
+        .into_iter()
+        .map(|(id, event)| match event {
+            Some(event) => Operation::Put(Box::new(event)),
+            None => Operation::Remove(id),
+        })
+        .collect();
+    let result = write(&mut *lent.store, changes);
+    if result.is_ok() {
+        CHANGES.send_modify(|changes| *changes += 1);
+    }
+    for saved in pending.drain(..) {
+        let _ = saved.send(result);
+    }
+}
+
+fn run(mut commands: mpsc::Receiver<Command>) {
+    let mut store = store::open();
+    // with shared storage, another instance may be writing the database, so
+    // it's only loaded while locked
+    let mut loaded = !shared_storage() && store.load().is_ok();
+    let mut lent = Some(Lent {
+        store,
+        unwritten: Changes::new(),
+    });
+    // saves answered once the batch is written
+    let mut pending: Vec<oneshot::Sender<Result<(), ()>>> = Vec::new();
+
+    loop {
+        let command = if pending.is_empty() {
+            commands.blocking_recv()
+        } else if pending.len() >= MAX_BATCH {
+            None
+        } else {
+            commands.try_recv().ok()
+        };
+        let held = lent.as_mut().expect("Loans are given back before this");
+        let Some(command) = command else {
+            if pending.is_empty() {
+                // every sender is gone, so no more commands can arrive
+                return;
+            }
+            flush(held, &mut pending);
+            continue;
+        };
+
+        match command {
+            Command::Lend { reply } => {
+                let lock = if shared_storage() {
+                    let Ok(lock) = lock::acquire() else {
+                        let _ = reply.send(Err(()));
+                        continue;
+                    };
+                    if held.store.changed_elsewhere() {
+                        loaded = false;
+                    }
+                    Some(lock)
+                } else {
+                    None
+                };
+                if !loaded {
+                    // e.g., the disk was full at startup, or another instance
+                    // changed the database
+                    loaded = held.store.load().is_ok();
+                }
+                if !loaded {
+                    let _ = reply.send(Err(()));
+                    continue;
+                }
+                let (give_back, returned) = oneshot::channel();
+                let loan = Loan {
+                    lent: lent.take(),
+                    changes: Changes::new(),
+                    give_back: Some(give_back),
+                };
+                // the caller stopped waiting if this fails, and dropping the
+                // loan takes it straight back
+                let _ = reply.send(Ok(loan));
+                match returned.blocking_recv() {
+                    Ok(Returned { lent: back, saved }) => {
+                        let held = lent.insert(back);
+                        if let Some((changes, saved)) = saved {
+                            held.unwritten.extend(changes);
+                            pending.push(saved);
+                        }
+                        if lock.is_some() {
+                            // others can't see it until it's written
+                            flush(held, &mut pending);
+                        }
+                    }
+                    // a loan always gives the database back when dropped, so
+                    // this only happens if the borrower's thread died mid-drop
+                    Err(_) => {
+                        log::error!("Database loan was lost. Reloading.");
+                        for saved in pending.drain(..) {
+                            let _ = saved.send(Err(()));
+                        }
+                        let mut store = store::open();
+                        loaded = store.load().is_ok();
+                        lent = Some(Lent {
+                            store,
+                            unwritten: Changes::new(),
+                        });
+                    }
+                }
+            }
+            Command::Reload { reply } => {
+                let _lock = if shared_storage() {
+                    lock::acquire().ok()
+                } else {
+                    None
+                };
+                flush(held, &mut pending);
+                loaded = held.store.load().is_ok();
+                CHANGES.send_modify(|changes| *changes += 1);
+                let _ = reply.send(if loaded { Ok(()) } else { Err(()) });
+            }
+            Command::Flush { reply } => {
+                let mut result = if pending.is_empty() {
+                    Ok(())
+                } else {
+                    let (saved, written) = oneshot::channel();
+                    pending.push(saved);
+                    flush(held, &mut pending);
+                    written.blocking_recv().unwrap_or(Err(()))
+                };
+                // with shared storage, another instance may be using what
+                // would be compacted, and saves were written as they came
+                if result.is_ok() && loaded && !shared_storage() {
+                    result = held.store.compact();
+                }
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
+
+fn write(
+    store: &mut dyn EventStore,
+    changes: Vec<Operation>,
+) -> Result<(), ()> {
+    let bytes = store.write(changes)?;
+    check_size(bytes);
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! Readiness checks for whatever runs the module, e.g., a container
+//! orchestrator or the Slot server
+//!
+//! `GET /invite/ready` answers as soon as the server is taking requests.
+//! `GET /invite/ready?deep=1` (or `?deep`, or `?deep=true`) also goes through
+//! a full round trip on the database, so a broken disk shows up as unready
+//! instead of as lost RSVPs.
+
+use std::time::Duration;
+
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::event_db;
+
+/// How long the deep check may take before the module counts as unready
+const DEEP_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ReadyQuery {
+    #[serde(default, deserialize_with = "flag")]
+    deep: bool,
+}
+
+/// A flag in the query, set by "1", "true", "yes", "on", or no value at all
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match String::deserialize(deserializer)?.to_ascii_lowercase().as_str() {
+        "" | "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        other => Err(de::Error::invalid_value(
+            de::Unexpected::Str(other),
+            &"1, 0, true, or false",
+        )),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Readiness {
+    ready: bool,
+    /// Why the module isn't ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// `GET /invite/ready?deep=`
+pub async fn ready(Query(query): Query<ReadyQuery>) -> Response {
+    let error = if query.deep {
+        deep_check().await.err()
+    } else {
+        None
+    };
+    let Some(error) = error else {
+        return Json(Readiness {
+            ready: true,
+            error: None,
+        })
+        .into_response();
+    };
+
+    log::warn!("Deep readiness check failed: {error}");
+    let readiness = Readiness {
+        ready: false,
+        error: Some(error),
+    };
+    (StatusCode::SERVICE_UNAVAILABLE, Json(readiness)).into_response()
+}
+
+async fn deep_check() -> Result<(), String> {
+    tokio::time::timeout(DEEP_CHECK_TIMEOUT, event_db::check_storage())
+        .await
+        .map_err(|_| "Database check timed out".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Uri;
+
+    use super::*;
+
+    fn deep(uri: &str) -> Option<bool> {
+        let uri: Uri = uri.parse().unwrap();
+        Query::<ReadyQuery>::try_from_uri(&uri)
+            .ok()
+            .map(|Query(query)| query.deep)
+    }
+
+    #[test]
+    fn deep_flag_spellings() {
+        assert_eq!(deep("/invite/ready"), Some(false));
+        assert_eq!(deep("/invite/ready?deep=1"), Some(true));
+        assert_eq!(deep("/invite/ready?deep=true"), Some(true));
+        assert_eq!(deep("/invite/ready?deep"), Some(true));
+        assert_eq!(deep("/invite/ready?deep=0"), Some(false));
+        assert_eq!(deep("/invite/ready?deep=false"), Some(false));
+        assert_eq!(deep("/invite/ready?deep=maybe"), None);
+    }
+}
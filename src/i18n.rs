@@ -0,0 +1,952 @@
+//! Languages of the built-in pages guests see
+//!
+//! Organizers write invitations in whatever language they like, but the pages
+//! and buttons around them are built in. Each attendee has a language those
+//! are rendered in, and custom templates get its code as `language` so a
+//! single template can serve guests of several languages. Adding a language
+//! means adding a variant, its month names, and its `Phrases`.
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "es")]
+    Spanish,
+    #[serde(rename = "de")]
+    German,
+    #[serde(rename = "fr")]
+    French,
+}
+
+const MONTHS: [[&str; 12]; 4] = [
+    [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+];
+
+impl Language {
+    pub const ALL: [Language; 4] = [
+        Language::English,
+        Language::Spanish,
+        Language::German,
+        Language::French,
+    ];
+
+    /// ISO 639-1 code, e.g., "en"
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::German => "de",
+            Language::French => "fr",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lang| lang.code() == code)
+    }
+
+    /// The language a browser prefers most of those built in, from its
+    /// Accept-Language header, e.g., "de-CH, de;q=0.9, en;q=0.8"
+    pub fn from_accept_language(header: &str) -> Self {
+        let mut best = None;
+        for range in header.split(',') {
+            let mut params = range.split(';');
+            let tag = params.next().unwrap_or_default().trim();
+            let quality = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let primary = tag.split('-').next().unwrap_or_default();
+            let Some(lang) = Self::parse(&primary.to_ascii_lowercase()) else {
+                continue;
+            };
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((lang, quality));
+            }
+        }
+        best.map(|(lang, _)| lang).unwrap_or_default()
+    }
+
+    /// Name of the language in itself, for picking it from a list
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Español",
+            Language::German => "Deutsch",
+            Language::French => "Français",
+        }
+    }
+
+    /// The local date of a point in time, e.g., "March 4, 2027"
+    pub fn date(self, time: SystemTime) -> String {
+        self.calendar_date(DateTime::<Local>::from(time).date_naive())
+    }
+
+    /// A date of the calendar, e.g., "March 4, 2027"
+    pub fn calendar_date(self, date: NaiveDate) -> String {
+        let month = MONTHS[self as usize][date.month0() as usize];
+        let (day, year) = (date.day(), date.year());
+        match self {
+            Language::English => format!("{month} {day}, {year}"),
+            Language::Spanish => format!("{day} de {month} de {year}"),
+            Language::German => format!("{day}. {month} {year}"),
+            Language::French => format!("{day} {month} {year}"),
+        }
+    }
+
+    /// A span of time on one day, e.g., "March 4, 2027, 14:00–14:15"
+    pub fn time_range(self, start: SystemTime, end: SystemTime) -> String {
+        let (from, to) =
+            (DateTime::<Local>::from(start), DateTime::<Local>::from(end));
+        format!(
+            "{}, {}–{}",
+            self.date(start),
+            from.format("%H:%M"),
+            to.format("%H:%M")
+        )
+    }
+
+    /// A point in local time, e.g., "March 4, 2027, 14:00"
+    pub fn date_time(self, time: SystemTime) -> String {
+        let local = DateTime::<Local>::from(time);
+        format!("{}, {}", self.date(time), local.format("%H:%M"))
+    }
+
+    /// Weather conditions of a WMO weather interpretation code, e.g.,
+    /// "Partly cloudy"
+    pub fn weather(self, code: u8) -> &'static str {
+        let kind = match code {
+            0 => 0,
+            1 | 2 => 1,
+            3 => 2,
+            45 | 48 => 3,
+            51..=57 => 4,
+            61..=67 | 80..=82 => 5,
+            71..=77 | 85 | 86 => 6,
+            95..=99 => 7,
+            _ => 8,
+        };
+        let names = match self {
+            Language::English => [
+                "Clear",
+                "Partly cloudy",
+                "Overcast",
+                "Fog",
+                "Drizzle",
+                "Rain",
+                "Snow",
+                "Thunderstorms",
+                "Mixed weather",
+            ],
+            Language::Spanish => [
+                "Despejado",
+                "Parcialmente nublado",
+                "Nublado",
+                "Niebla",
+                "Llovizna",
+                "Lluvia",
+                "Nieve",
+                "Tormentas",
+                "Tiempo variable",
+            ],
+            Language::German => [
+                "Klar",
+                "Teilweise bewölkt",
+                "Bedeckt",
+                "Nebel",
+                "Nieselregen",
+                "Regen",
+                "Schnee",
+                "Gewitter",
+                "Wechselhaft",
+            ],
+            Language::French => [
+                "Dégagé",
+                "Partiellement nuageux",
+                "Couvert",
+                "Brouillard",
+                "Bruine",
+                "Pluie",
+                "Neige",
+                "Orages",
+                "Temps variable",
+            ],
+        };
+        names[kind]
+    }
+
+    /// Chance of rain or snow, e.g., "20% chance of rain"
+    pub fn rain_chance(self, percent: u8) -> String {
+        match self {
+            Language::English => format!("{percent}% chance of rain"),
+            Language::Spanish => {
+                format!("{percent}\u{a0}% de probabilidad de lluvia")
+            }
+            Language::German => format!("Regenrisiko {percent}\u{a0}%"),
+            Language::French => format!("{percent}\u{a0}% de risque de pluie"),
+        }
+    }
+
+    /// An amount of money given in cents, e.g., "12.50"
+    pub fn amount(self, cents: u64) -> String {
+        let separator = match self {
+            Language::English => '.',
+            Language::Spanish | Language::German | Language::French => ',',
+        };
+        format!("{}{separator}{:02}", cents / 100, cents % 100)
+    }
+
+    /// How long until something happens, e.g., "in 3 hours"
+    pub fn until(self, time: SystemTime) -> String {
+        let secs = time
+            .duration_since(clock::now())
+            .unwrap_or_default()
+            .as_secs();
+        let (amount, unit) = match secs {
+            s if s < 60 => {
+                return match self {
+                    Language::English => "in a moment",
+                    Language::Spanish => "en un momento",
+                    Language::German => "gleich",
+                    Language::French => "dans un instant",
+                }
+                .to_string();
+            }
+            s if s < 60 * 60 => (s / 60, 0),
+            s if s < 60 * 60 * 24 => (s / (60 * 60), 1),
+            s => (s / (60 * 60 * 24), 2),
+        };
+        let unit = match self {
+            Language::English => ["minutes", "hours", "days"],
+            Language::Spanish => ["minutos", "horas", "días"],
+            Language::German => ["Minuten", "Stunden", "Tagen"],
+            Language::French => ["minutes", "heures", "jours"],
+        }[unit];
+        match self {
+            Language::English => format!("in {amount} {unit}"),
+            Language::Spanish => format!("en {amount} {unit}"),
+            Language::German => format!("in {amount} {unit}"),
+            Language::French => format!("dans {amount} {unit}"),
+        }
+    }
+
+    /// How long ago something happened, e.g., "3 hours ago"
+    pub fn ago(self, time: SystemTime) -> String {
+        let secs = clock::now()
+            .duration_since(time)
+            .unwrap_or_default()
+            .as_secs();
+        let (amount, unit) = match secs {
+            s if s < 60 => {
+                return match self {
+                    Language::English => "just now",
+                    Language::Spanish => "hace un momento",
+                    Language::German => "gerade eben",
+                    Language::French => "à l'instant",
+                }
+                .to_string();
+            }
+            s if s < 60 * 60 => (s / 60, 0),
+            s if s < 60 * 60 * 24 => (s / (60 * 60), 1),
+            s => (s / (60 * 60 * 24), 2),
+        };
+        let unit = match self {
+            Language::English => ["minutes", "hours", "days"],
+            Language::Spanish => ["minutos", "horas", "días"],
+            Language::German => ["Minuten", "Stunden", "Tagen"],
+            Language::French => ["minutes", "heures", "jours"],
+        }[unit];
+        match self {
+            Language::English => format!("{amount} {unit} ago"),
+            Language::Spanish => format!("hace {amount} {unit}"),
+            Language::German => format!("vor {amount} {unit}"),
+            Language::French => format!("il y a {amount} {unit}"),
+        }
+    }
+
+    /// Precedes the date of a change to the event's details
+    pub fn updated_on(self) -> &'static str {
+        match self {
+            Language::English => "Updated on",
+            Language::Spanish => "Actualizado el",
+            Language::German => "Aktualisiert am",
+            Language::French => "Mis à jour le",
+        }
+    }
+
+    /// The built-in text of guests' pages about the named event
+    pub fn phrases(self, event_name: &str) -> Phrases {
+        match self {
+            Language::English => english(event_name),
+            Language::Spanish => spanish(event_name),
+            Language::German => german(event_name),
+            Language::French => french(event_name),
+        }
+    }
+}
+
+/// Text of the built-in pages guests see, in one language
+#[derive(Serialize, Debug)]
+pub struct Phrases {
+    /// For the page's `lang` attribute
+    pub lang: &'static str,
+    pub accept: &'static str,
+    pub maybe: &'static str,
+    pub withdraw: &'static str,
+    /// Shown to guests who answered maybe
+    pub maybe_heading: &'static str,
+    /// Label of the field for how many people a guest brings along
+    pub plus_ones_label: &'static str,
+    pub note_placeholder: &'static str,
+    /// Placeholder of the box for a comment when accepting
+    pub comment_placeholder: &'static str,
+    pub download_data: &'static str,
+    pub remove_data: &'static str,
+    pub thanks_title: &'static str,
+    pub thanks_heading: &'static str,
+    pub thanks_message: String,
+    pub withdraw_title: &'static str,
+    pub withdraw_heading: &'static str,
+    /// Heading of the withdraw page once responses are closed
+    pub accepted_heading: &'static str,
+    pub closed_message: String,
+    pub confirm_title: &'static str,
+    pub confirm_heading: String,
+    pub forget_title: &'static str,
+    pub forget_heading: String,
+    pub forget_message: &'static str,
+    pub forget_button: &'static str,
+    pub removed_title: &'static str,
+    pub removed_message: &'static str,
+    pub too_many_title: &'static str,
+    pub too_many_message: &'static str,
+    pub closed_title: &'static str,
+    pub closed_error: &'static str,
+    pub invited_heading: String,
+    /// Followed by the guest's name on the default invitation
+    pub greeting: &'static str,
+    /// Heading of the withdraw form on the plain invitation
+    pub cant_make_it: &'static str,
+    pub honeypot_label: &'static str,
+    pub full_invitation: &'static str,
+    pub carpool_heading: &'static str,
+    pub carpool_intro: &'static str,
+    pub carpool_note_placeholder: &'static str,
+    pub carpool_seats: &'static str,
+    pub carpool_offer: &'static str,
+    pub carpool_request: &'static str,
+    pub carpool_offers: &'static str,
+    pub carpool_requests: &'static str,
+    /// Precedes how many seats a driver has free
+    pub carpool_seats_left: &'static str,
+    pub carpool_join: &'static str,
+    /// Precedes the names of the driver's riders
+    pub carpool_your_riders: &'static str,
+    /// Precedes the name of the rider's driver
+    pub carpool_your_driver: &'static str,
+    pub carpool_leave: &'static str,
+    pub carpool_empty: &'static str,
+    pub carpool_full_title: &'static str,
+    pub carpool_full_message: &'static str,
+    pub carpool_closed_title: &'static str,
+    pub carpool_closed_message: &'static str,
+    pub pick_slot_title: &'static str,
+    pub pick_slot_heading: String,
+    /// Shown when the slot picked filled up in the meantime
+    pub slot_full_message: &'static str,
+    /// Precedes how many more guests a slot takes
+    pub places_left: &'static str,
+    pub slot_full: &'static str,
+    /// Precedes the time slot the guest booked
+    pub your_slot: &'static str,
+    pub join_title: &'static str,
+    pub join_heading: String,
+    pub join_message: &'static str,
+    pub your_name: &'static str,
+    pub join_full_title: &'static str,
+    pub join_full_message: &'static str,
+    pub schedule_title: &'static str,
+    pub schedule_heading: String,
+    /// Precedes how long until the event starts, e.g., "in 3 days"
+    pub schedule_starts: &'static str,
+    pub schedule_empty: &'static str,
+    pub schedule_link: &'static str,
+    pub schedule_back: &'static str,
+    /// Precedes the weather forecast for the day of the event
+    pub forecast: &'static str,
+    pub costs_heading: &'static str,
+    pub costs_item: &'static str,
+    pub costs_amount: &'static str,
+    pub costs_payer: &'static str,
+    pub costs_total: &'static str,
+    /// Precedes what each guest who accepted owes
+    pub costs_share: &'static str,
+    pub certificate_title: &'static str,
+    /// Follows the guest's name
+    pub certificate_accepted: &'static str,
+    /// Precedes when the event starts
+    pub certificate_starts: &'static str,
+    pub certificate_scan: &'static str,
+    /// Precedes when the confirmation was downloaded
+    pub certificate_issued: &'static str,
+    pub certificate_download: &'static str,
+    /// Precedes when the event takes place
+    pub when: &'static str,
+    /// Precedes where the event takes place
+    pub location: &'static str,
+    pub calendar_download: &'static str,
+    pub kiosk_title: &'static str,
+    pub kiosk_heading: String,
+    /// Precedes how many guests arrived of how many are expected
+    pub kiosk_arrived: &'static str,
+    pub kiosk_recent: &'static str,
+    pub kiosk_nobody: &'static str,
+}
+
+fn english(event: &str) -> Phrases {
+    Phrases {
+        lang: "en",
+        accept: "Accept",
+        maybe: "Maybe",
+        withdraw: "Withdraw",
+        maybe_heading: "You said you might come",
+        plus_ones_label: "People you're bringing along",
+        note_placeholder: "Let the organizer know why (optional)",
+        comment_placeholder: "Anything the organizer should know, e.g., \
+                              bringing dessert (optional)",
+        download_data: "Download my data",
+        remove_data: "Remove my data from this event",
+        thanks_title: "Thanks",
+        thanks_heading: "Your response has been recorded",
+        thanks_message: format!(
+            "Thank you for RSVP'ing to {event}. If you wish to withdraw from \
+             the event click below"
+        ),
+        withdraw_title: "Withdraw",
+        withdraw_heading: "You have already accepted this invitation. Would \
+                           you like to withdraw?",
+        accepted_heading: "You have already accepted this invitation",
+        closed_message: format!(
+            "Responses to {event} are closed, so yours can no longer be \
+             changed."
+        ),
+        confirm_title: "Confirm",
+        confirm_heading: format!("Please confirm your response to {event}"),
+        forget_title: "Remove My Data",
+        forget_heading: format!("Remove your data from {event}?"),
+        forget_message: "Your name and the invitation written for you will be \
+                         permanently erased. Your response will still count \
+                         towards the headcount, but nobody will be able to \
+                         tell it was yours. The organizer will be told that a \
+                         guest removed their data.",
+        forget_button: "Remove my data",
+        removed_title: "Your data has been removed",
+        removed_message: "Your details were erased from this event at your \
+                          request.",
+        too_many_title: "Too many responses",
+        too_many_message: "Your response has changed too many times in a \
+                           short while. Please wait a few minutes and try \
+                           again.",
+        closed_title: "Responses are closed",
+        closed_error: "The organizer is no longer taking changes to \
+                       responses, so yours stays as it was. If your plans \
+                       have changed, please let them know directly.",
+        invited_heading: format!("You're invited to {event}"),
+        greeting: "Hi",
+        cant_make_it: "Can't make it?",
+        honeypot_label: "Leave this field empty",
+        full_invitation: "View the full invitation",
+        carpool_heading: "Carpool",
+        carpool_intro: "Offer seats in your car, or ask for a ride.",
+        carpool_note_placeholder: "Where from and when, e.g., Leaving \
+                                   downtown at 5pm",
+        carpool_seats: "Seats",
+        carpool_offer: "Offer seats",
+        carpool_request: "Ask for a ride",
+        carpool_offers: "Rides offered",
+        carpool_requests: "Looking for a ride",
+        carpool_seats_left: "Seats left:",
+        carpool_join: "Ride along",
+        carpool_your_riders: "Riding with you:",
+        carpool_your_driver: "You're riding with",
+        carpool_leave: "Leave the carpool",
+        carpool_empty: "Nobody has offered or asked for a ride yet.",
+        carpool_full_title: "That ride is full",
+        carpool_full_message: "The driver has no seats left. Please pick \
+                               another ride or ask for one.",
+        carpool_closed_title: "The carpool is closed",
+        carpool_closed_message: "The organizer isn't taking carpool offers or \
+                                 requests right now.",
+        pick_slot_title: "Pick a time",
+        pick_slot_heading: format!("Pick a time for {event}"),
+        slot_full_message: "That time just filled up. Please pick another.",
+        places_left: "Places left:",
+        slot_full: "Full",
+        your_slot: "Your time:",
+        join_title: "RSVP",
+        join_heading: format!("Coming to {event}?"),
+        join_message: "Enter your name to let the organizer know you're \
+                       coming. You'll then get a personal page to change your \
+                       answer later, so keep its address.",
+        your_name: "Your name",
+        join_full_title: "The guest list is full",
+        join_full_message: "No more guests can join through this link.",
+        schedule_title: "Schedule",
+        schedule_heading: format!("Schedule for {event}"),
+        schedule_starts: "Starts",
+        schedule_empty: "The organizer hasn't shared a schedule yet.",
+        schedule_link: "See the schedule",
+        schedule_back: "Back to your invitation",
+        forecast: "Weather forecast:",
+        costs_heading: "Costs",
+        costs_item: "What",
+        costs_amount: "Amount",
+        costs_payer: "Paid by",
+        costs_total: "Total:",
+        costs_share: "Split between everyone coming, each share is",
+        certificate_title: "RSVP confirmation",
+        certificate_accepted: "has accepted the invitation.",
+        certificate_starts: "Starts:",
+        certificate_scan: "Scan the code to check the RSVP is still current.",
+        certificate_issued: "Issued:",
+        certificate_download: "Download your confirmation (PDF)",
+        when: "When:",
+        location: "Where:",
+        calendar_download: "Add to your calendar",
+        kiosk_title: "Welcome",
+        kiosk_heading: format!("Welcome to {event}"),
+        kiosk_arrived: "Arrived:",
+        kiosk_recent: "Just arrived",
+        kiosk_nobody: "Nobody has arrived yet.",
+    }
+}
+
+fn spanish(event: &str) -> Phrases {
+    Phrases {
+        lang: "es",
+        accept: "Aceptar",
+        maybe: "Quizás",
+        withdraw: "Retirarme",
+        maybe_heading: "Dijiste que quizás vendrás",
+        plus_ones_label: "Personas que traes contigo",
+        note_placeholder: "Cuéntale al organizador por qué (opcional)",
+        comment_placeholder: "Algo que el organizador deba saber, p. ej., \
+                              llevo el postre (opcional)",
+        download_data: "Descargar mis datos",
+        remove_data: "Eliminar mis datos de este evento",
+        thanks_title: "Gracias",
+        thanks_heading: "Tu respuesta ha quedado registrada",
+        thanks_message: format!(
+            "Gracias por responder a {event}. Si quieres retirarte del \
+             evento, haz clic abajo"
+        ),
+        withdraw_title: "Retirarme",
+        withdraw_heading: "Ya aceptaste esta invitación. ¿Quieres retirarte?",
+        accepted_heading: "Ya aceptaste esta invitación",
+        closed_message: format!(
+            "Las respuestas a {event} están cerradas, así que la tuya ya no \
+             se puede cambiar."
+        ),
+        confirm_title: "Confirmar",
+        confirm_heading: format!("Confirma tu respuesta a {event}"),
+        forget_title: "Eliminar mis datos",
+        forget_heading: format!("¿Eliminar tus datos de {event}?"),
+        forget_message: "Tu nombre y la invitación escrita para ti se \
+                         borrarán de forma permanente. Tu respuesta seguirá \
+                         contando para el número de asistentes, pero nadie \
+                         podrá saber que fue tuya. Se avisará al organizador \
+                         de que un invitado eliminó sus datos.",
+        forget_button: "Eliminar mis datos",
+        removed_title: "Tus datos han sido eliminados",
+        removed_message: "Tus datos se borraron de este evento a petición \
+                          tuya.",
+        too_many_title: "Demasiadas respuestas",
+        too_many_message: "Tu respuesta ha cambiado demasiadas veces en poco \
+                           tiempo. Espera unos minutos y vuelve a intentarlo.",
+        closed_title: "Las respuestas están cerradas",
+        closed_error: "El organizador ya no acepta cambios en las \
+                       respuestas, así que la tuya se queda como estaba. Si \
+                       tus planes han cambiado, avísale directamente.",
+        invited_heading: format!("Estás invitado a {event}"),
+        greeting: "Hola",
+        cant_make_it: "¿No puedes ir?",
+        honeypot_label: "Deja este campo vacío",
+        full_invitation: "Ver la invitación completa",
+        carpool_heading: "Compartir coche",
+        carpool_intro: "Ofrece plazas en tu coche o pide que te lleven.",
+        carpool_note_placeholder: "Desde dónde y cuándo, p. ej., Salgo del \
+                                   centro a las 17:00",
+        carpool_seats: "Plazas",
+        carpool_offer: "Ofrecer plazas",
+        carpool_request: "Pedir que me lleven",
+        carpool_offers: "Viajes ofrecidos",
+        carpool_requests: "Buscan quien les lleve",
+        carpool_seats_left: "Plazas libres:",
+        carpool_join: "Ir en este coche",
+        carpool_your_riders: "Van contigo:",
+        carpool_your_driver: "Vas con",
+        carpool_leave: "Salir del coche compartido",
+        carpool_empty: "Nadie ha ofrecido ni pedido un viaje todavía.",
+        carpool_full_title: "Ese coche está lleno",
+        carpool_full_message: "Al conductor no le quedan plazas. Elige otro \
+                               viaje o pide uno.",
+        carpool_closed_title: "El coche compartido está cerrado",
+        carpool_closed_message: "El organizador no acepta ofertas ni \
+                                 peticiones de viaje por ahora.",
+        pick_slot_title: "Elige una hora",
+        pick_slot_heading: format!("Elige una hora para {event}"),
+        slot_full_message: "Esa hora se acaba de llenar. Elige otra, por \
+                            favor.",
+        places_left: "Plazas libres:",
+        slot_full: "Completo",
+        your_slot: "Tu hora:",
+        join_title: "Confirmar asistencia",
+        join_heading: format!("¿Vienes a {event}?"),
+        join_message: "Escribe tu nombre para avisar al organizador de que \
+                       vienes. Después recibirás una página personal para \
+                       cambiar tu respuesta más adelante, así que guarda su \
+                       dirección.",
+        your_name: "Tu nombre",
+        join_full_title: "La lista de invitados está completa",
+        join_full_message: "No pueden unirse más invitados con este enlace.",
+        schedule_title: "Programa",
+        schedule_heading: format!("Programa de {event}"),
+        schedule_starts: "Empieza",
+        schedule_empty: "El organizador aún no ha compartido el programa.",
+        schedule_link: "Ver el programa",
+        schedule_back: "Volver a tu invitación",
+        forecast: "Previsión del tiempo:",
+        costs_heading: "Gastos",
+        costs_item: "Concepto",
+        costs_amount: "Importe",
+        costs_payer: "Pagado por",
+        costs_total: "Total:",
+        costs_share: "Repartido entre todos los asistentes, cada parte es de",
+        certificate_title: "Confirmación de asistencia",
+        certificate_accepted: "ha aceptado la invitación.",
+        certificate_starts: "Comienza:",
+        certificate_scan:
+            "Escanea el código para comprobar que la respuesta sigue vigente.",
+        certificate_issued: "Emitido:",
+        certificate_download: "Descargar tu confirmación (PDF)",
+        when: "Cuándo:",
+        location: "Dónde:",
+        calendar_download: "Añadir a tu calendario",
+        kiosk_title: "Bienvenida",
+        kiosk_heading: format!("Bienvenidos a {event}"),
+        kiosk_arrived: "Han llegado:",
+        kiosk_recent: "Recién llegados",
+        kiosk_nobody: "Todavía no ha llegado nadie.",
+    }
+}
+
+fn german(event: &str) -> Phrases {
+    Phrases {
+        lang: "de",
+        accept: "Zusagen",
+        maybe: "Vielleicht",
+        withdraw: "Absagen",
+        maybe_heading: "Du hast mit Vielleicht geantwortet",
+        plus_ones_label: "Begleitpersonen",
+        note_placeholder: "Sag dem Veranstalter, warum (optional)",
+        comment_placeholder: "Was der Veranstalter wissen sollte, z. B. \
+                              ich bringe den Nachtisch mit (optional)",
+        download_data: "Meine Daten herunterladen",
+        remove_data: "Meine Daten aus dieser Veranstaltung entfernen",
+        thanks_title: "Danke",
+        thanks_heading: "Deine Antwort wurde gespeichert",
+        thanks_message: format!(
+            "Danke für deine Antwort auf {event}. Wenn du doch nicht \
+             teilnehmen möchtest, klicke unten"
+        ),
+        withdraw_title: "Absagen",
+        withdraw_heading: "Du hast diese Einladung bereits angenommen. \
+                           Möchtest du absagen?",
+        accepted_heading: "Du hast diese Einladung bereits angenommen",
+        closed_message: format!(
+            "Antworten auf {event} sind geschlossen, deine kann also nicht \
+             mehr geändert werden."
+        ),
+        confirm_title: "Bestätigen",
+        confirm_heading: format!("Bitte bestätige deine Antwort auf {event}"),
+        forget_title: "Meine Daten entfernen",
+        forget_heading: format!("Deine Daten aus {event} entfernen?"),
+        forget_message: "Dein Name und die für dich geschriebene Einladung \
+                         werden endgültig gelöscht. Deine Antwort zählt \
+                         weiterhin zur Teilnehmerzahl, aber niemand kann \
+                         erkennen, dass sie von dir war. Der Veranstalter \
+                         erfährt, dass ein Gast seine Daten entfernt hat.",
+        forget_button: "Meine Daten entfernen",
+        removed_title: "Deine Daten wurden entfernt",
+        removed_message: "Deine Angaben wurden auf deinen Wunsch aus dieser \
+                          Veranstaltung gelöscht.",
+        too_many_title: "Zu viele Antworten",
+        too_many_message: "Deine Antwort wurde in kurzer Zeit zu oft \
+                           geändert. Bitte warte ein paar Minuten und \
+                           versuche es erneut.",
+        closed_title: "Antworten sind geschlossen",
+        closed_error: "Der Veranstalter nimmt keine Änderungen an Antworten \
+                       mehr an, deine bleibt also, wie sie war. Falls sich \
+                       deine Pläne geändert haben, sag ihm bitte direkt \
+                       Bescheid.",
+        invited_heading: format!("Du bist zu {event} eingeladen"),
+        greeting: "Hallo",
+        cant_make_it: "Du kannst nicht kommen?",
+        honeypot_label: "Dieses Feld leer lassen",
+        full_invitation: "Vollständige Einladung ansehen",
+        carpool_heading: "Fahrgemeinschaft",
+        carpool_intro: "Biete Plätze in deinem Auto an oder frag nach einer \
+                        Mitfahrt.",
+        carpool_note_placeholder: "Von wo und wann, z. B. Abfahrt in der \
+                                   Innenstadt um 17 Uhr",
+        carpool_seats: "Plätze",
+        carpool_offer: "Plätze anbieten",
+        carpool_request: "Nach einer Mitfahrt fragen",
+        carpool_offers: "Angebotene Fahrten",
+        carpool_requests: "Suchen eine Mitfahrt",
+        carpool_seats_left: "Freie Plätze:",
+        carpool_join: "Mitfahren",
+        carpool_your_riders: "Fahren bei dir mit:",
+        carpool_your_driver: "Du fährst mit bei",
+        carpool_leave: "Fahrgemeinschaft verlassen",
+        carpool_empty: "Noch hat niemand eine Fahrt angeboten oder gesucht.",
+        carpool_full_title: "Diese Fahrt ist voll",
+        carpool_full_message: "Beim Fahrer ist kein Platz mehr frei. Wähl \
+                               eine andere Fahrt oder frag nach einer.",
+        carpool_closed_title: "Die Fahrgemeinschaft ist geschlossen",
+        carpool_closed_message: "Der Veranstalter nimmt gerade keine \
+                                 Angebote oder Anfragen für Fahrten an.",
+        pick_slot_title: "Wähl eine Zeit",
+        pick_slot_heading: format!("Wähl eine Zeit für {event}"),
+        slot_full_message: "Diese Zeit ist gerade voll geworden. Bitte wähl \
+                            eine andere.",
+        places_left: "Freie Plätze:",
+        slot_full: "Voll",
+        your_slot: "Deine Zeit:",
+        join_title: "Zusagen",
+        join_heading: format!("Kommst du zu {event}?"),
+        join_message: "Gib deinen Namen ein, damit der Veranstalter weiß, \
+                       dass du kommst. Danach bekommst du eine persönliche \
+                       Seite, um deine Antwort später zu ändern, also merk \
+                       dir ihre Adresse.",
+        your_name: "Dein Name",
+        join_full_title: "Die Gästeliste ist voll",
+        join_full_message: "Über diesen Link können keine weiteren Gäste \
+                            zusagen.",
+        schedule_title: "Ablauf",
+        schedule_heading: format!("Ablauf von {event}"),
+        schedule_starts: "Beginnt",
+        schedule_empty: "Der Veranstalter hat noch keinen Ablauf geteilt.",
+        schedule_link: "Zum Ablauf",
+        schedule_back: "Zurück zu deiner Einladung",
+        forecast: "Wettervorhersage:",
+        costs_heading: "Kosten",
+        costs_item: "Was",
+        costs_amount: "Betrag",
+        costs_payer: "Bezahlt von",
+        costs_total: "Gesamt:",
+        costs_share: "Aufgeteilt auf alle, die kommen, zahlt jede Person",
+        certificate_title: "Teilnahmebestätigung",
+        certificate_accepted: "hat die Einladung angenommen.",
+        certificate_starts: "Beginn:",
+        certificate_scan: "Der Code zeigt, ob die Zusage noch gilt.",
+        certificate_issued: "Ausgestellt:",
+        certificate_download: "Bestätigung herunterladen (PDF)",
+        when: "Wann:",
+        location: "Wo:",
+        calendar_download: "Zum Kalender hinzufügen",
+        kiosk_title: "Willkommen",
+        kiosk_heading: format!("Willkommen zu {event}"),
+        kiosk_arrived: "Angekommen:",
+        kiosk_recent: "Gerade angekommen",
+        kiosk_nobody: "Noch ist niemand angekommen.",
+    }
+}
+
+fn french(event: &str) -> Phrases {
+    Phrases {
+        lang: "fr",
+        accept: "Accepter",
+        maybe: "Peut-être",
+        withdraw: "Me désister",
+        maybe_heading: "Vous avez répondu peut-être",
+        plus_ones_label: "Personnes qui vous accompagnent",
+        note_placeholder: "Dites à l'organisateur pourquoi (facultatif)",
+        comment_placeholder: "Ce que l'organisateur devrait savoir, par ex. \
+                              j'apporte le dessert (facultatif)",
+        download_data: "Télécharger mes données",
+        remove_data: "Supprimer mes données de cet événement",
+        thanks_title: "Merci",
+        thanks_heading: "Votre réponse a été enregistrée",
+        thanks_message: format!(
+            "Merci d'avoir répondu à {event}. Si vous souhaitez vous \
+             désister, cliquez ci-dessous"
+        ),
+        withdraw_title: "Se désister",
+        withdraw_heading: "Vous avez déjà accepté cette invitation. \
+                           Souhaitez-vous vous désister ?",
+        accepted_heading: "Vous avez déjà accepté cette invitation",
+        closed_message: format!(
+            "Les réponses à {event} sont closes, la vôtre ne peut donc plus \
+             être modifiée."
+        ),
+        confirm_title: "Confirmer",
+        confirm_heading: format!("Veuillez confirmer votre réponse à {event}"),
+        forget_title: "Supprimer mes données",
+        forget_heading: format!("Supprimer vos données de {event} ?"),
+        forget_message: "Votre nom et l'invitation rédigée pour vous seront \
+                         définitivement effacés. Votre réponse comptera \
+                         toujours dans le nombre de participants, mais \
+                         personne ne pourra savoir qu'elle était la vôtre. \
+                         L'organisateur sera informé qu'un invité a supprimé \
+                         ses données.",
+        forget_button: "Supprimer mes données",
+        removed_title: "Vos données ont été supprimées",
+        removed_message: "Vos informations ont été effacées de cet événement \
+                          à votre demande.",
+        too_many_title: "Trop de réponses",
+        too_many_message: "Votre réponse a changé trop de fois en peu de \
+                           temps. Veuillez patienter quelques minutes et \
+                           réessayer.",
+        closed_title: "Les réponses sont closes",
+        closed_error: "L'organisateur n'accepte plus de modifications des \
+                       réponses, la vôtre reste donc inchangée. Si vos projets \
+                       ont changé, prévenez-le directement.",
+        invited_heading: format!("Vous êtes invité à {event}"),
+        greeting: "Bonjour",
+        cant_make_it: "Vous ne pouvez pas venir ?",
+        honeypot_label: "Laissez ce champ vide",
+        full_invitation: "Voir l'invitation complète",
+        carpool_heading: "Covoiturage",
+        carpool_intro: "Proposez des places dans votre voiture, ou demandez \
+                        à être emmené.",
+        carpool_note_placeholder: "D'où et quand, par ex. Départ du centre à \
+                                   17 h",
+        carpool_seats: "Places",
+        carpool_offer: "Proposer des places",
+        carpool_request: "Demander un trajet",
+        carpool_offers: "Trajets proposés",
+        carpool_requests: "Cherchent un trajet",
+        carpool_seats_left: "Places libres :",
+        carpool_join: "Monter à bord",
+        carpool_your_riders: "Voyagent avec vous :",
+        carpool_your_driver: "Vous voyagez avec",
+        carpool_leave: "Quitter le covoiturage",
+        carpool_empty: "Personne n'a encore proposé ni demandé de trajet.",
+        carpool_full_title: "Ce trajet est complet",
+        carpool_full_message: "Le conducteur n'a plus de place. Choisissez un \
+                               autre trajet ou demandez-en un.",
+        carpool_closed_title: "Le covoiturage est fermé",
+        carpool_closed_message: "L'organisateur n'accepte pas de propositions \
+                                 ni de demandes de trajet pour le moment.",
+        pick_slot_title: "Choisissez un horaire",
+        pick_slot_heading: format!("Choisissez un horaire pour {event}"),
+        slot_full_message: "Cet horaire vient d'être complet. Veuillez en \
+                            choisir un autre.",
+        places_left: "Places libres :",
+        slot_full: "Complet",
+        your_slot: "Votre horaire :",
+        join_title: "Répondre",
+        join_heading: format!("Venez-vous à {event} ?"),
+        join_message: "Saisissez votre nom pour prévenir l'organisateur de \
+                       votre venue. Vous recevrez ensuite une page \
+                       personnelle pour modifier votre réponse plus tard, \
+                       conservez donc son adresse.",
+        your_name: "Votre nom",
+        join_full_title: "La liste des invités est complète",
+        join_full_message: "Plus aucun invité ne peut s'inscrire avec ce \
+                            lien.",
+        schedule_title: "Programme",
+        schedule_heading: format!("Programme de {event}"),
+        schedule_starts: "Commence",
+        schedule_empty: "L'organisateur n'a pas encore partagé le programme.",
+        schedule_link: "Voir le programme",
+        schedule_back: "Retour à votre invitation",
+        forecast: "Prévisions météo :",
+        costs_heading: "Dépenses",
+        costs_item: "Quoi",
+        costs_amount: "Montant",
+        costs_payer: "Payé par",
+        costs_total: "Total :",
+        costs_share: "Partagé entre tous les participants, chaque part est de",
+        certificate_title: "Confirmation de participation",
+        certificate_accepted: "a accepté l'invitation.",
+        certificate_starts: "Début :",
+        certificate_scan:
+            "Scannez le code pour vérifier que la réponse est toujours valable.",
+        certificate_issued: "Émis le :",
+        certificate_download: "Télécharger votre confirmation (PDF)",
+        when: "Quand :",
+        location: "Où :",
+        calendar_download: "Ajouter à votre agenda",
+        kiosk_title: "Bienvenue",
+        kiosk_heading: format!("Bienvenue à {event}"),
+        kiosk_arrived: "Arrivés :",
+        kiosk_recent: "Tout juste arrivés",
+        kiosk_nobody: "Personne n'est encore arrivé.",
+    }
+}
@@ -0,0 +1,110 @@
+//! Calendar files for guests and organizers
+//!
+//! Guests who accepted can download the event as an iCalendar file (RFC 5545)
+//! to add it to their calendar, and `caldav` publishes the organizer's view of
+//! it. The event's ID is the calendar entry's UID and its revision the
+//! SEQUENCE, so downloading it again after the organizer moved the event
+//! updates the entry rather than adding another.
+
+use chrono::{DateTime, Utc};
+
+use crate::{clock, event_db::Event, settings};
+
+/// Longest a content line may be, in bytes, before it's folded
+const MAX_LINE_OCTETS: usize = 75;
+
+/// The event as an iCalendar file for guests. None if it has no date to put
+/// in a calendar. `url` is where the guest can see the event.
+pub fn event_calendar(event: &Event, url: &str) -> Option<String> {
+    calendar(event, vec![format!("URL:{url}")])
+}
+
+/// The event as an iCalendar file for the organizer, describing who's coming.
+/// None if it has no date to put in a calendar.
+pub fn organizer_calendar(event: &Event) -> Option<String> {
+    let counts = event.rsvp_counts();
+    let description = format!(
+        "{} coming ({} with plus-ones), {} maybe, {} declined, {} not \
+         answered yet",
+        counts.yes,
+        event.headcount(),
+        counts.maybe,
+        counts.no,
+        counts.pending
+    );
+    calendar(event, vec![format!("DESCRIPTION:{}", escape(&description))])
+}
+
+/// The event's calendar with the given properties added to its entry
+fn calendar(event: &Event, properties: Vec<String>) -> Option<String> {
+    let date = event.date?;
+    let public_url = settings::public_url();
+    let host = public_url
+        .split_once("://")
+        .map_or(public_url.as_str(), |(_, host)| host);
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//{host}//invite//EN"),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@{host}", event.id.encode()),
+        format!("DTSTAMP:{}", utc(clock::now().into())),
+        format!("SEQUENCE:{}", event.revision),
+    ];
+    match (event.start_time, event.starts_at()) {
+        (Some(_), Some(start)) => {
+            lines.push(format!("DTSTART:{}", utc(start.into())));
+            if let Some(end) = event.ends_at() {
+                lines.push(format!("DTEND:{}", utc(end.into())));
+            }
+        }
+        _ => {
+            // all-day, which ends where the next day starts
+            let next = date.succ_opt()?;
+            lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+            lines.push(format!("DTEND;VALUE=DATE:{}", next.format("%Y%m%d")));
+        }
+    }
+    let name = event.name.as_deref().unwrap_or("Untitled Event");
+    lines.push(format!("SUMMARY:{}", escape(name)));
+    if let Some(location) = &event.location {
+        lines.push(format!("LOCATION:{}", escape(location)));
+    }
+    lines.extend(properties);
+    lines.push("END:VEVENT".to_string());
+    lines.push("END:VCALENDAR".to_string());
+    Some(lines.iter().map(|line| fold(line)).collect())
+}
+
+fn utc(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape a TEXT value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// End a content line, folding it onto continuation lines if it's too long.
+/// Lines are only split between characters, never inside one.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 4);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // the space counts towards the continuation line
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
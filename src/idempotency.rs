@@ -0,0 +1,253 @@
+//! Idempotency keys for mutating requests
+//!
+//! A client may send an `Idempotency-Key` header with a request which changes
+//! an event. The response is remembered for a day, and a retry with the same
+//! key gets the remembered response instead of repeating the change, so a
+//! double-submitted form doesn't add two attendees. A retry arriving while the
+//! first request is still running is rejected with 409 Conflict.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    api_error::{ApiError, ErrorCode},
+    clock,
+};
+
+pub const HEADER: &str = "idempotency-key";
+/// How long responses are remembered
+const KEY_LIFETIME: Duration = Duration::from_days(1);
+/// Upper bound on remembered responses. The oldest are forgotten first.
+const MAX_KEYS: usize = 10_000;
+/// Longest accepted key
+const MAX_KEY_LEN: usize = 255;
+/// Largest response body which is remembered
+const MAX_STORED_BODY: usize = 64 * 1024;
+
+enum Entry {
+    InFlight { since: SystemTime },
+    Done { at: SystemTime, response: Stored },
+}
+
+impl Entry {
+    fn time(&self) -> SystemTime {
+        match self {
+            Entry::InFlight { since } => *since,
+            Entry::Done { at, .. } => *at,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Stored {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Stored {
+    fn response(self) -> Response {
+        let mut response = (self.status, self.body).into_response();
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+static KEYS: LazyLock<Mutex<HashMap<String, Entry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn forget_old(keys: &mut HashMap<String, Entry>, now: SystemTime) {
+    keys.retain(|_, entry| entry.time() + KEY_LIFETIME > now);
+    while keys.len() >= MAX_KEYS {
+        let oldest = keys
+            .iter()
+            .min_by_key(|(_, entry)| entry.time())
+            .map(|(key, _)| key.clone())
+            .expect("Map is not empty");
+        keys.remove(&oldest);
+    }
+}
+
+/// Middleware replaying the response of an earlier request with the same key
+pub async fn idempotent(req: Request, next: Next) -> Response {
+    let Some(key) = req.headers().get(HEADER) else {
+        return next.run(req).await;
+    };
+    let Some(key) = key.to_str().ok().filter(|k| k.len() <= MAX_KEY_LEN) else {
+        return ApiError::new(
+            ErrorCode::InvalidRequest,
+            "Invalid idempotency key",
+        )
+        .into_response();
+    };
+    // the same key may be reused for a different request
+    let key = format!("{} {} {key}", req.method(), req.uri().path());
+
+    let now = clock::now();
+    {
+        let mut keys = KEYS.lock().unwrap_or_else(|e| e.into_inner());
+        match keys.get(&key) {
+            Some(Entry::Done { response, .. }) => {
+                return response.clone().response();
+            }
+            Some(Entry::InFlight { .. }) => {
+                return ApiError::new(
+                    ErrorCode::Conflict,
+                    "A request with this idempotency key is in progress",
+                )
+                .into_response();
+            }
+            None => {}
+        }
+        forget_old(&mut keys, now);
+        keys.insert(key.clone(), Entry::InFlight { since: now });
+    }
+
+    let response = next.run(req).await;
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, MAX_STORED_BODY).await;
+
+    let mut keys = KEYS.lock().unwrap_or_else(|e| e.into_inner());
+    let Ok(body) = body else {
+        // too large to remember, let a retry go through again
+        keys.remove(&key);
+        return ApiError::new(ErrorCode::Internal, "Response was too large")
+            .into_response();
+    };
+    // failures may be transient, so only successes are replayed
+    if parts.status.is_server_error() {
+        keys.remove(&key);
+    } else {
+        let stored = Stored {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        };
+        keys.insert(
+            key,
+            Entry::Done {
+                at: now,
+                response: stored,
+            },
+        );
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// A router counting the calls which reach it, answering with `status`
+    fn counting_router(status: StatusCode) -> (Router, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let handler = move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { (status, format!("call {n}")) }
+        };
+        let router = Router::new()
+            .route("/add", post(handler.clone()))
+            .route("/other", post(handler))
+            .route_layer(middleware::from_fn(idempotent));
+        (router, calls)
+    }
+
+    async fn send(
+        router: &Router,
+        path: &str,
+        key: Option<&str>,
+    ) -> (StatusCode, String) {
+        let mut req = Request::post(path);
+        if let Some(key) = key {
+            req = req.header(HEADER, key);
+        }
+        let response = router
+            .clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn replays_retries() {
+        let (router, calls) = counting_router(StatusCode::CREATED);
+        let first = send(&router, "/add", Some("replay")).await;
+        assert_eq!(first, (StatusCode::CREATED, "call 1".to_string()));
+        assert_eq!(send(&router, "/add", Some("replay")).await, first);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // keys are per route, and requests without one always go through
+        send(&router, "/other", Some("replay")).await;
+        send(&router, "/add", None).await;
+        send(&router, "/add", None).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (router, calls) = counting_router(StatusCode::SERVICE_UNAVAILABLE);
+        send(&router, "/add", Some("server-error")).await;
+        send(&router, "/add", Some("server-error")).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_retries_in_flight_and_bad_keys() {
+        let (router, calls) = counting_router(StatusCode::CREATED);
+        KEYS.lock().unwrap().insert(
+            "POST /add in-flight".to_string(),
+            Entry::InFlight {
+                since: clock::now(),
+            },
+        );
+        let (status, _) = send(&router, "/add", Some("in-flight")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let long = "k".repeat(MAX_KEY_LEN + 1);
+        let (status, _) = send(&router, "/add", Some(&long)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn forgets_old_keys() {
+        let now = clock::now();
+        let mut keys = HashMap::from([
+            (
+                "old".to_string(),
+                Entry::InFlight {
+                    since: now - KEY_LIFETIME,
+                },
+            ),
+            ("new".to_string(), Entry::InFlight { since: now }),
+        ]);
+        forget_old(&mut keys, now);
+        assert!(keys.contains_key("new"));
+        assert!(!keys.contains_key("old"));
+    }
+}
@@ -0,0 +1,291 @@
+//! Generation and decoding of the base62 IDs used in URLs
+//!
+//! IDs are either random 64 bit numbers (the default) or UUIDv7s, selected
+//! with `--id-format`. Both are held in the 128 bit `Id` type so the rest of
+//! the module doesn't care which is in use, and records created under one
+//! format stay reachable after switching to the other.
+//!
+//! Every ID taken from user input should go through `decode_id`, which rejects
+//! over-length and out-of-range strings before they reach the database, and
+//! spellings other than the one `Id::encode` gives, e.g., with leading zeros,
+//! so each ID has exactly one URL.
+//!
+//! New IDs are drawn from a replaceable `IdSource` which defaults to an OS
+//! seeded `StdRng`. Tests can install their own source with `set_id_source` to
+//! force specific IDs.
+
+use std::{fmt, sync::LazyLock};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::Mutex;
+
+use crate::clock;
+
+/// Length of `u128::MAX` encoded in base62. No valid ID is longer than this.
+const MAX_ENCODED_LEN: usize = 22;
+
+/// An event or attendee ID
+///
+/// In CBOR, IDs which fit in a u64 are stored as plain integers (which is how
+/// all IDs were stored before UUIDs were supported) and larger ones as 16 big
+/// endian bytes. In human readable formats such as JSON they are base62
+/// strings, matching the URLs.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Id(u128);
+
+impl Id {
+    pub fn encode(&self) -> String {
+        base62::encode(self.0)
+    }
+
+    /// The ID as a UUID, for interop with external systems. Only meaningful
+    /// for IDs generated in UUID mode.
+    pub fn as_uuid(&self) -> uuid::Uuid {
+        uuid::Uuid::from_u128(self.0)
+    }
+}
+
+impl From<u64> for Id {
+    fn from(value: u64) -> Self {
+        Id(value.into())
+    }
+}
+
+impl From<uuid::Uuid> for Id {
+    fn from(value: uuid::Uuid) -> Self {
+        Id(value.as_u128())
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&self.encode());
+        }
+        match u64::try_from(self.0) {
+            Ok(small) => serializer.serialize_u64(small),
+            Err(_) => serializer.serialize_bytes(&self.0.to_be_bytes()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        struct IdVisitor;
+
+        impl de::Visitor<'_> for IdVisitor {
+            type Value = Id;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an integer, 16 bytes, or a base62 string")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Id, E> {
+                Ok(Id::from(v))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Id, E> {
+                let bytes: [u8; 16] = v
+                    .try_into()
+                    .map_err(|_| E::invalid_length(v.len(), &self))?;
+                Ok(Id(u128::from_be_bytes(bytes)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Id, E> {
+                decode_id(v).map_err(E::custom)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(IdVisitor)
+        } else {
+            deserializer.deserialize_any(IdVisitor)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Empty,
+    TooLong,
+    InvalidCharacter,
+    OutOfRange,
+    /// Spelled other than `Id::encode` spells it, e.g., "0abc" for "abc"
+    NotCanonical,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            DecodeError::Empty => "ID is empty",
+            DecodeError::TooLong => "ID is too long",
+            DecodeError::InvalidCharacter => "ID contains invalid characters",
+            DecodeError::OutOfRange => "ID is out of range",
+            DecodeError::NotCanonical => "ID is not in its usual form",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// Decode a base62 ID, rejecting input which does not fit in an `Id` or
+/// isn't spelled as `Id::encode` spells it
+pub fn decode_id(encoded: &str) -> Result<Id, DecodeError> {
+    if encoded.len() > MAX_ENCODED_LEN {
+        return Err(DecodeError::TooLong);
+    }
+
+    let value = base62::decode(encoded).map_err(|e| match e {
+        base62::DecodeError::EmptyInput => DecodeError::Empty,
+        base62::DecodeError::ArithmeticOverflow => DecodeError::OutOfRange,
+        base62::DecodeError::InvalidBase62Byte(_, _) => {
+            DecodeError::InvalidCharacter
+        }
+    })?;
+
+    let id = Id(value);
+    if id.encode() != encoded {
+        return Err(DecodeError::NotCanonical);
+    }
+    Ok(id)
+}
+
+/// Which kind of ID new records receive
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum IdFormat {
+    /// Random 64 bit numbers
+    #[default]
+    Random,
+    /// Time-ordered UUIDv7s
+    Uuid7,
+}
+
+pub trait IdSource: Send {
+    fn next_id(&mut self) -> Id;
+}
+
+impl IdSource for StdRng {
+    fn next_id(&mut self) -> Id {
+        Id::from(self.random::<u64>())
+    }
+}
+
+/// Generates UUIDv7s stamped with the module clock
+pub struct Uuid7Source {
+    rng: StdRng,
+}
+
+impl Uuid7Source {
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::from_os_rng(),
+        }
+    }
+}
+
+impl Default for Uuid7Source {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdSource for Uuid7Source {
+    fn next_id(&mut self) -> Id {
+        let millis = clock::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let random_bytes: [u8; 10] = self.rng.random();
+        uuid::Builder::from_unix_timestamp_millis(millis, &random_bytes)
+            .into_uuid()
+            .into()
+    }
+}
+
+/// Any closure producing IDs can be used as a source
+impl<F: FnMut() -> Id + Send> IdSource for F {
+    fn next_id(&mut self) -> Id {
+        self()
+    }
+}
+
+static ID_SOURCE: LazyLock<Mutex<Box<dyn IdSource>>> =
+    LazyLock::new(|| Mutex::new(Box::new(StdRng::from_os_rng())));
+
+/// Replace the source used for all new IDs
+pub async fn set_id_source(source: Box<dyn IdSource>) {
+    *ID_SOURCE.lock().await = source;
+}
+
+/// Install the default source for the given format
+pub async fn set_id_format(format: IdFormat) {
+    match format {
+        IdFormat::Random => {
+            set_id_source(Box::new(StdRng::from_os_rng())).await
+        }
+        IdFormat::Uuid7 => set_id_source(Box::new(Uuid7Source::new())).await,
+    }
+}
+
+/// Draw a new ID from the installed source
+pub async fn generate_id() -> Id {
+    ID_SOURCE.lock().await.next_id()
+}
+
+/// Draw IDs from the installed source with `draw`, which has it to itself
+/// meanwhile
+pub async fn with_id_source<T>(
+    draw: impl FnOnce(&mut dyn IdSource) -> T,
+) -> T {
+    draw(ID_SOURCE.lock().await.as_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_round_trips() {
+        for value in [0, 1, 61, 62, u64::MAX.into(), u128::MAX] {
+            let id = Id(value);
+            assert_eq!(decode_id(&id.encode()), Ok(id));
+        }
+    }
+
+    #[test]
+    fn decode_rejects_bad_input() {
+        assert_eq!(decode_id(""), Err(DecodeError::Empty));
+        assert_eq!(decode_id("abc-def"), Err(DecodeError::InvalidCharacter));
+        assert_eq!(
+            decode_id(&"z".repeat(MAX_ENCODED_LEN + 1)),
+            Err(DecodeError::TooLong)
+        );
+        // one past u128::MAX, still 22 characters
+        assert_eq!(
+            decode_id(&"z".repeat(MAX_ENCODED_LEN)),
+            Err(DecodeError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn decode_rejects_leading_zeros() {
+        let id = Id(12345);
+        assert_eq!(
+            decode_id(&format!("0{id}")),
+            Err(DecodeError::NotCanonical)
+        );
+        assert_eq!(decode_id("00"), Err(DecodeError::NotCanonical));
+        assert_eq!(decode_id("0"), Ok(Id(0)));
+    }
+}
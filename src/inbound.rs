@@ -0,0 +1,132 @@
+//! RSVPs collected elsewhere
+//!
+//! With `--inbound-token`, external systems such as a mail parser or an SMS
+//! gateway can post the answers they collected to `/invite/inbound/rsvp`,
+//! sending the token as `Authorization: Bearer <token>`. Answers are recorded
+//! as if the guest had given them on their invitation, so the history,
+//! withdrawal notifications, and digests treat them the same.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::constant_time_eq,
+    api_error::{ApiError, ErrorCode},
+    event_db::{self, Decline, Rsvp},
+    ids::{self, Id},
+};
+
+/// Middleware rejecting requests without the inbound token
+pub async fn require_token(
+    State(expected): State<Option<String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = expected else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match provided {
+        Some(token)
+            if constant_time_eq(token.as_bytes(), expected.as_bytes()) =>
+        {
+            next.run(req).await
+        }
+        _ => ApiError::new(
+            ErrorCode::Unauthorized,
+            "Missing or invalid inbound token",
+        )
+        .into_response(),
+    }
+}
+
+/// An answer collected elsewhere. The guest is named by `attendee_id`, or by
+/// `email` together with `event_id` if the address may be invited to more
+/// than one event.
+#[derive(Deserialize, Debug)]
+pub struct InboundRsvp {
+    attendee_id: Option<String>,
+    event_id: Option<String>,
+    email: Option<String>,
+    rsvp: Rsvp,
+    plus_ones: Option<u8>,
+    time_slot: Option<u32>,
+    reason: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InboundResponse {
+    attendee_id: Id,
+    rsvp: Rsvp,
+}
+
+/// `POST /invite/inbound/rsvp`
+pub async fn post_rsvp(
+    Json(inbound): Json<InboundRsvp>,
+) -> Result<Json<InboundResponse>, ApiError> {
+    if inbound.rsvp == Rsvp::Pending {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "rsvp must be \"yes\", \"maybe\", or \"no\"",
+        ));
+    }
+    let at_id = find_attendee(&inbound).await?;
+    let decline = Decline {
+        reason: inbound.reason,
+        note: inbound.note,
+    };
+    event_db::set_rsvp(
+        at_id,
+        inbound.rsvp,
+        inbound.plus_ones,
+        inbound.time_slot,
+        decline,
+    )
+    .await?;
+    log::info!("Recorded an inbound RSVP for {at_id}");
+    Ok(Json(InboundResponse {
+        attendee_id: at_id,
+        rsvp: inbound.rsvp,
+    }))
+}
+
+async fn find_attendee(inbound: &InboundRsvp) -> Result<Id, ApiError> {
+    let decode = |id: &str| {
+        ids::decode_id(id).map_err(|_| {
+            ApiError::new(ErrorCode::NotFound, format!("No such ID: {id}"))
+        })
+    };
+    if let Some(at_id) = &inbound.attendee_id {
+        return decode(at_id);
+    }
+    let Some(email) = inbound.email.as_deref().filter(|e| !e.is_empty()) else {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "Either attendee_id or email is required",
+        ));
+    };
+    let ev_id = inbound.event_id.as_deref().map(decode).transpose()?;
+    match event_db::find_attendees_by_email(email, ev_id).await?[..] {
+        [at_id] => Ok(at_id),
+        [] => Err(ApiError::new(
+            ErrorCode::NotFound,
+            "No guest has the given email address",
+        )),
+        _ => Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "Several guests have the given email address. Give attendee_id, \
+             or event_id to pick the event.",
+        )),
+    }
+}
@@ -23,6 +23,12 @@ pub fn initialize() -> (cli::Args, LoggerHandle) {
         }
     };
 
+    // log panics along with a backtrace instead of only printing to stderr
+    std::panic::set_hook(Box::new(|info| {
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        log::error!("{info}\n{backtrace}");
+    }));
+
     // logger handle must not be dropped per docs
     (args, logger_handle)
 }
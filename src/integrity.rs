@@ -0,0 +1,156 @@
+//! Startup integrity check of the database
+//!
+//! Before serving, the database is read and checked for problems which would
+//! otherwise surface one request at a time. Problems are either errors (the
+//! data can't be trusted, e.g., two events with the same ID) or warnings
+//! (something looks off, e.g., an event created in the future). By default,
+//! errors keep the module from starting so nothing gets overwritten before the
+//! operator has had a look.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use crate::{clock, event_db::EventDB};
+
+/// Timestamps further ahead than this count as being in the future, to allow
+/// for clock adjustments
+const CLOCK_SLACK: Duration = Duration::from_days(1);
+/// Attendees per event above which the event is reported
+const MAX_ATTENDEES: usize = 10_000;
+/// Size of an invitation or page override above which it is reported
+const MAX_HTML_BYTES: usize = 1024 * 1024;
+
+/// What to do when the check finds errors
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnCorruption {
+    /// Exit without touching the database
+    #[default]
+    Refuse,
+    /// Start anyway. A database which can't be parsed is replaced with an
+    /// empty one.
+    Continue,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub bytes: u64,
+    pub events: usize,
+    pub attendees: usize,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    /// A report for a database which couldn't be loaded at all
+    pub fn unloadable(bytes: u64, error: impl Display) -> Self {
+        Self {
+            bytes,
+            errors: vec![error.to_string()],
+            ..Default::default()
+        }
+    }
+
+    pub fn is_corrupt(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn log(&self) {
+        let summary = format!(
+            "Database integrity: bytes={} events={} attendees={} errors={} \
+             warnings={}",
+            self.bytes,
+            self.events,
+            self.attendees,
+            self.errors.len(),
+            self.warnings.len()
+        );
+        if self.is_corrupt() {
+            log::error!("{summary}");
+        } else if !self.warnings.is_empty() {
+            log::warn!("{summary}");
+        } else {
+            log::info!("{summary}");
+        }
+        for error in self.errors.iter() {
+            log::error!("Database integrity error: {error}");
+        }
+        for warning in self.warnings.iter() {
+            log::warn!("Database integrity warning: {warning}");
+        }
+    }
+}
+
+/// Check a loaded database which was `bytes` large on disk
+pub fn check(db: &EventDB, bytes: u64) -> Report {
+    let mut report = Report {
+        bytes,
+        events: db.events.len(),
+        attendees: db.events.iter().map(|ev| ev.attendees.len()).sum(),
+        ..Default::default()
+    };
+    let future = clock::now() + CLOCK_SLACK;
+    let is_future = |at: &SystemTime| *at > future;
+
+    let mut event_ids = HashSet::new();
+    let mut attendee_ids = HashSet::new();
+    for event in db.events.iter() {
+        if !event_ids.insert(event.id) {
+            report
+                .errors
+                .push(format!("Event ID {} is used twice", event.id));
+        }
+        if is_future(&event.created)
+            || event.extended.is_some_and(|at| is_future(&at))
+        {
+            report.warnings.push(format!(
+                "Event {} has timestamps in the future",
+                event.id
+            ));
+        }
+        if event.attendees.len() > MAX_ATTENDEES {
+            report.warnings.push(format!(
+                "Event {} has {} attendees",
+                event.id,
+                event.attendees.len()
+            ));
+        }
+        let pages = [event.thanks_html.as_ref(), event.withdraw_html.as_ref()];
+        if pages
+            .into_iter()
+            .flatten()
+            .any(|html| html.len() > MAX_HTML_BYTES)
+        {
+            report
+                .warnings
+                .push(format!("Event {} has an oversized page", event.id));
+        }
+
+        for attendee in event.attendees.iter() {
+            if !attendee_ids.insert(attendee.id) {
+                report
+                    .errors
+                    .push(format!("Attendee ID {} is used twice", attendee.id));
+            }
+            if attendee
+                .rsvp_history
+                .iter()
+                .any(|change| is_future(&change.at))
+            {
+                report.warnings.push(format!(
+                    "Attendee {} of event {} has responses dated in the future",
+                    attendee.id, event.id
+                ));
+            }
+            if attendee.custom_html.len() > MAX_HTML_BYTES {
+                report.warnings.push(format!(
+                    "Attendee {} of event {} has an oversized invitation",
+                    attendee.id, event.id
+                ));
+            }
+        }
+    }
+    report
+}
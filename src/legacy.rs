@@ -0,0 +1,87 @@
+//! Earlier layouts of the database
+//!
+//! New fields are added with `#[serde(default)]`, so most old databases load
+//! into the current structs as they are. When they don't, e.g., after a field
+//! changed type, the data is tried against the layouts here, newest first, and
+//! converted forward. A layout only needs to describe the fields it had, since
+//! unknown fields are skipped when parsing. That also makes the oldest layout a
+//! last resort for a database whose newer fields are damaged: whatever it
+//! covers is kept.
+//!
+//! When the current layout changes incompatibly, copy the old structs here as
+//! a new module and add it to `LAYOUTS`.
+
+use crate::event_db::{Attendee, Event, EventDB, Rsvp};
+
+/// Parses the data as a layout and converts it to the current one
+type Converter = fn(&[u8]) -> Option<EventDB>;
+
+/// Known layouts, newest first
+const LAYOUTS: &[(&str, Converter)] = &[("v1", v1::convert)];
+
+/// Convert data in an earlier layout. Returns the converted database and the
+/// name of the layout it was in.
+pub fn convert(data: &[u8]) -> Option<(EventDB, &'static str)> {
+    LAYOUTS
+        .iter()
+        .find_map(|(name, convert)| convert(data).map(|db| (db, *name)))
+}
+
+/// The first released layout: numeric IDs and nothing but names, invitations,
+/// and whether each attendee accepted
+mod v1 {
+    use std::time::SystemTime;
+
+    use serde::Deserialize;
+
+    use crate::ids::Id;
+
+    #[derive(Deserialize)]
+    struct EventDB {
+        events: Vec<Event>,
+    }
+
+    #[derive(Deserialize)]
+    struct Event {
+        id: u64,
+        name: Option<String>,
+        attendees: Vec<Attendee>,
+        created: SystemTime,
+    }
+
+    #[derive(Deserialize)]
+    struct Attendee {
+        id: u64,
+        name: String,
+        custom_html: String,
+        has_accepted: bool,
+    }
+
+    pub fn convert(data: &[u8]) -> Option<super::EventDB> {
+        let db = serde_cbor::from_slice::<EventDB>(data).ok()?;
+        let mut converted = super::EventDB::default();
+        for event in db.events {
+            let attendees = event
+                .attendees
+                .into_iter()
+                .map(|at| super::Attendee {
+                    name: at.name,
+                    custom_html: at.custom_html,
+                    rsvp: if at.has_accepted {
+                        super::Rsvp::Yes
+                    } else {
+                        super::Rsvp::Pending
+                    },
+                    ..super::Attendee::new(Id::from(at.id))
+                })
+                .collect();
+            converted.insert_event(super::Event {
+                name: event.name,
+                attendees,
+                created: event.created,
+                ..super::Event::new(Id::from(event.id))
+            });
+        }
+        Some(converted)
+    }
+}
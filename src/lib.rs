@@ -0,0 +1,54 @@
+#![feature(duration_constructors, duration_constructors_lite)]
+//! The blacepos.xyz "invite" module
+//!
+//! The `bxyz-invite` binary serves the module on its own port behind the Slot
+//! proxy. Other servers can mount it in-process with [`invite_router`].
+
+pub mod admin;
+pub mod announce;
+pub mod api;
+pub mod api_error;
+pub mod archive;
+pub mod automation;
+pub mod bot_filter;
+pub mod caldav;
+pub mod certificate;
+pub mod cli;
+pub mod clock;
+pub mod contacts;
+pub mod content;
+pub mod cover;
+pub mod error_report;
+pub mod event_db;
+pub mod health;
+pub mod i18n;
+pub mod ical;
+pub mod idempotency;
+pub mod ids;
+pub mod inbound;
+pub mod init;
+pub mod integrity;
+pub mod legacy;
+pub mod mail;
+pub mod merge;
+pub mod metrics;
+pub mod network;
+pub mod notify;
+pub mod oidc;
+pub mod organizer;
+pub mod passkeys;
+pub mod plain_text;
+pub mod rate_limit;
+pub mod routes;
+pub mod rsvp_form;
+pub mod sanitize;
+pub mod sending;
+pub mod settings;
+pub mod shortlink;
+pub mod slot;
+pub mod structured_data;
+pub mod template_lint;
+pub mod templates;
+pub mod weather;
+
+pub use routes::{invite_router, AppState};
@@ -0,0 +1,126 @@
+//! Email to organizers and guests
+//!
+//! With `--smtp-host`, organizers who give their email address on the manage
+//! page are emailed when a guest accepts, answers maybe, or withdraws. Like
+//! the withdrawal notification, the email waits until the guest has stopped
+//! changing their mind, and tells the answer they settled on. Scheduled
+//! invitations are emailed to guests with an address, see `sending`. Delivery
+//! failures are logged but never retried.
+
+use std::sync::OnceLock;
+
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+static MAILER: OnceLock<Mailer> = OnceLock::new();
+
+/// How the connection to the SMTP server is secured
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Security {
+    /// Upgrade a plain connection with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// No encryption, e.g., for a relay on the same host
+    None,
+}
+
+#[derive(Clone, Debug)]
+pub struct Smtp {
+    pub host: String,
+    /// The security's usual port if unset
+    pub port: Option<u16>,
+    pub security: Security,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender of every email, e.g., "Invites <invite@example.org>"
+    pub from: String,
+}
+
+struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+/// Set up sending email. Logs why and leaves email off if the settings are
+/// unusable.
+pub fn initialize(smtp: Smtp) {
+    let from = match smtp.from.parse::<Mailbox>() {
+        Ok(from) => from,
+        Err(e) => {
+            log::error!("Sender \"{}\" is not an address ({e})", smtp.from);
+            return;
+        }
+    };
+    let builder = match smtp.security {
+        Security::Starttls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)
+        }
+        Security::Tls => {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)
+        }
+        Security::None => Ok(
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host),
+        ),
+    };
+    let mut builder = match builder {
+        Ok(builder) => builder,
+        Err(e) => {
+            log::error!("Could not set up SMTP for {} ({e})", smtp.host);
+            return;
+        }
+    };
+    if let Some(port) = smtp.port {
+        builder = builder.port(port);
+    }
+    if let Some(username) = smtp.username {
+        let password = smtp.password.unwrap_or_default();
+        builder = builder.credentials(Credentials::new(username, password));
+    }
+    let mailer = Mailer {
+        transport: builder.build(),
+        from,
+    };
+    if MAILER.set(mailer).is_err() {
+        log::warn!("SMTP server was already set");
+    }
+}
+
+/// Whether email can be sent
+pub fn enabled() -> bool {
+    MAILER.get().is_some()
+}
+
+/// Send a plain text email. Does nothing if email is off.
+pub async fn send(to: &str, subject: &str, body: String) {
+    let Some(mailer) = MAILER.get() else {
+        return;
+    };
+    let to = match to.parse::<Mailbox>() {
+        Ok(to) => to,
+        Err(e) => {
+            log::warn!("Not emailing \"{to}\", which is not an address ({e})");
+            return;
+        }
+    };
+    let message = Message::builder()
+        .from(mailer.from.clone())
+        .to(to)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body);
+    let message = match message {
+        Ok(message) => message,
+        Err(e) => {
+            log::error!("Could not write email \"{subject}\": {e}");
+            return;
+        }
+    };
+    if let Err(e) = mailer.transport.send(message).await {
+        log::warn!("Could not send email \"{subject}\": {e}");
+    }
+}
@@ -1,333 +1,218 @@
-#![feature(duration_constructors, duration_constructors_lite)]
-use std::{net::SocketAddr, str::FromStr};
-
-use askama::Template;
-use axum::{
-    extract::{Json, Path},
-    http::StatusCode,
-    response::{Html, IntoResponse, Redirect, Response},
-    routing::{get, post},
-    Router,
+use std::{
+    net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc, time::Duration,
 };
-use init::initialize;
-use tokio::fs;
-use tower_http::{services::ServeDir, trace::TraceLayer};
-
-use crate::{event_db::FindEventError, templates::ManagePageJson};
 
-pub mod cli;
-pub mod event_db;
-pub mod init;
-pub mod templates;
+use bxyz_invite::{
+    announce, caldav, error_report, event_db, ids, init::initialize,
+    integrity::OnCorruption, invite_router, mail, network, oidc, passkeys,
+    sending, shortlink, slot, structured_data, templates, weather, AppState,
+};
+use tokio::task::JoinHandle;
 
 const MODULE_NAME: &str = "invite";
-const CONTENT_DIR: &str = "content";
 
 #[tokio::main]
 async fn main() {
     let (args, _logger_handle) = initialize();
     log::debug!("Completed initialization");
 
+    if let Some(dsn) = &args.sentry_dsn {
+        error_report::initialize(dsn);
+    }
+    ids::set_id_format(args.id_format).await;
+    if let Some(origin) = &args.admin_passkey_origin {
+        passkeys::initialize(origin);
+        if args.register_admin_passkey {
+            if let Some(link) = passkeys::bootstrap_link() {
+                // printed rather than logged so it doesn't linger in log files
+                println!("Register an admin passkey at {link}");
+            }
+        }
+    }
+    if let (Some(issuer), Some(client_id), Some(client_secret)) = (
+        args.oidc_issuer.clone(),
+        args.oidc_client_id.clone(),
+        args.oidc_client_secret.clone(),
+    ) {
+        oidc::initialize(oidc::Provider {
+            issuer,
+            client_id,
+            client_secret,
+            admins: args.oidc_admins.clone(),
+        });
+    }
+    if let Some(service) = args.shortener {
+        shortlink::initialize(shortlink::Shortener {
+            service,
+            endpoint: args.shortener_url.clone(),
+            key: args.shortener_key.clone(),
+        });
+    }
+    if let Some(url) = args.caldav_url.clone() {
+        caldav::initialize(caldav::Calendar {
+            url,
+            username: args.caldav_user.clone(),
+            password: args.caldav_password.clone(),
+        });
+    }
+    if let (Some(host), Some(from)) =
+        (args.smtp_host.clone(), args.smtp_from.clone())
+    {
+        mail::initialize(mail::Smtp {
+            host,
+            port: args.smtp_port,
+            security: args.smtp_security,
+            username: args.smtp_user.clone(),
+            password: args.smtp_password.clone(),
+            from,
+        });
+    }
+    if let Some(dir) = args.live_templates.clone() {
+        templates::live::initialize(dir);
+    }
+    event_db::set_retention(event_db::Retention {
+        event_lifetime: args.event_lifetime,
+        purge_period: args.purge_period,
+        purge_at: args.purge_at,
+        purge_retry_period: args.purge_retry_period,
+    });
+    event_db::set_purge_mode(args.purge_mode);
+    event_db::set_shared_storage(args.shared_storage);
+    event_db::set_organizer_pinning(args.organizer_pinning);
+    if let Some(max_mib) = args.max_db_mib {
+        event_db::set_size_limit(event_db::SizeLimit {
+            max_bytes: max_mib * 1024 * 1024,
+            refuse_new_events: args.refuse_when_full,
+        });
+    }
+    if args.compress_db {
+        event_db::format::set_compression(event_db::format::Compression::Zstd);
+    }
+    event_db::set_nudge_cooldown(Duration::from_hours(
+        args.nudge_cooldown_hours,
+    ));
+    structured_data::set_on_invitations(args.json_ld_on_invitations);
+    weather::set_enabled(args.weather_forecasts);
+    // sets up the storage and settings, which the checks below need
+    let routes = invite_router(AppState {
+        admin_token: args.admin_token.clone(),
+        inbound_token: args.inbound_token.clone(),
+        network: Arc::new(network::Config {
+            admin_networks: args.admin_allow.clone(),
+            forwarded_header: args.forwarded_header,
+            trusted_proxies: args.trusted_proxies.clone(),
+        }),
+        notify_webhook: args.notify_webhook.clone(),
+        storage: args.storage,
+        settings: args.settings.clone(),
+        banner: args.banner.clone(),
+        ..AppState::default()
+    });
+
+    let report = event_db::check_integrity().await;
+    report.log();
+    if report.is_corrupt() && args.on_corruption == OnCorruption::Refuse {
+        log::error!(
+            "Refusing to start with a corrupted database. Fix or move it, or \
+             pass --on-corruption continue to start anyway."
+        );
+        std::process::exit(1);
+    }
+
+    event_db::start();
+    match event_db::assign_organizer_keys().await {
+        Ok(links) => {
+            for link in links {
+                log::warn!(
+                    "Made an organizer key for an event which had none. Hand \
+                     its organizer this manage link: {link}"
+                );
+            }
+        }
+        Err(()) => log::error!("Could not give events organizer keys"),
+    }
+
     let addr = SocketAddr::new(args.web_addr, args.http_port);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
 
     event_db::setup_test().await;
+    tokio::spawn(event_db::purge_task());
+    tokio::spawn(event_db::digest_task());
+    tokio::spawn(event_db::view_task());
+    tokio::spawn(caldav::sync_task());
+    tokio::spawn(sending::send_task());
 
     // if defined, register with the slot server
-    if let Some(slot_port) = args.slot_port {
+    let slot_heartbeat = args.slot_port.map(|slot_port| {
         let module_name =
             slot_client::protocol::ValidName::from_str(MODULE_NAME)
                 .expect("The constant module name is valid");
 
-        slot_client::client_impl::run_client(
+        tokio::spawn(slot::heartbeat_task(
             slot_port,
             module_name,
             listener.local_addr().expect("HTTP socket is bound").port(),
-        );
-    }
-
-    // set up webserver
-    let routes = Router::new()
-        .route("/invite/index", get(index_page))
-        .nest_service("/invite/content", ServeDir::new(CONTENT_DIR))
-        .layer(TraceLayer::new_for_http())
-        // invite module specific routes
-        .route("/invite/organize", get(create_new_event))
-        .route("/invite/manage/{ev_id}", get(manage_event))
-        .route("/invite/update/{ev_id}", post(update_event))
-        .route("/invite/add/{ev_id}", post(add_attendee))
-        .route("/invite/remove/{at_id}", post(remove_attendee))
-        .route("/invite/attend/{at_id}", get(view_invitation))
-        .route("/invite/accept/{at_id}", get(accept_invitation))
-        .route("/invite/withdraw/{at_id}", get(withdraw_invitation))
-        .route("/invite/thanks/{at_id}", get(view_event))
-        .route("/invite", get(index_page));
-    axum::serve(listener, routes).await.unwrap();
-}
+        ))
+    });
 
-async fn create_new_event() -> Response {
-    let ev_id = match event_db::create_event().await {
-        Ok(v) => v,
-        Err(e) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
-        }
-    };
-    let encoded_id = base62::encode(ev_id);
-    let redirect_url = format!("/invite/manage/{encoded_id}");
-    Redirect::to(&redirect_url).into_response()
-}
-
-async fn manage_event(Path(id): Path<String>) -> Response {
-    // find event
-    let ev_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            return (StatusCode::NOT_FOUND, "Event does not exist")
-                .into_response();
-        }
-    };
-    let event = match event_db::find_event_by_id(ev_id as u64).await {
-        Ok(v) => v,
-        Err(FindEventError::Database(e)) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
-        }
-        Err(FindEventError::NotFound(e)) => {
-            return (StatusCode::NOT_FOUND, e).into_response();
-        }
-    };
-
-    // render response
-    let event_name = event.name.unwrap_or("Untitled Event".to_string());
-    let Ok(template) = templates::ManagePage {
-        event_name: &event_name,
-        attendees: event
-            .attendees
-            .into_iter()
-            .map(templates::Attendee::from)
-            .collect(),
-        update_link: &format!("/invite/update/{}", id),
-        add_link: &format!("/invite/add/{}", id),
-    }
-    .render() else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
-            .into_response();
-    };
-    Html(template).into_response()
-}
-
-async fn update_event(
-    Path(id): Path<String>,
-    Json(form): Json<ManagePageJson>,
-) -> Redirect {
-    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
-    // find event
-    let ev_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            log::error!("Event does not exist");
-            return redirect;
-        }
-    };
-
-    match event_db::update_event(ev_id as u64, form).await {
-        Ok(_) => {}
-        Err(event_db::FindEventError::Database(e)) => {
-            log::error!("{e}");
-        }
-        Err(_) => {}
+    if let Some(path) = &args.announce_file {
+        let addr = listener.local_addr().expect("HTTP socket is bound");
+        announce::write(path, MODULE_NAME, addr).await;
     }
 
-    redirect
-}
-
-async fn add_attendee(Path(id): Path<String>) -> Redirect {
-    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
-    // find event
-    let ev_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            log::error!("Event does not exist");
-            return redirect;
-        }
-    };
-
-    match event_db::add_attendee(ev_id as u64).await {
-        Ok(_) => {}
-        Err(event_db::FindEventError::Database(e)) => {
-            log::error!("{e}");
-        }
-        Err(_) => {}
+    axum::serve(
+        listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal(slot_heartbeat, args.announce_file))
+    .await
+    .unwrap();
+    if event_db::flush_manage_views().await.is_err() {
+        log::error!("Could not write down manage page views before exiting");
     }
-
-    redirect
-}
-
-async fn remove_attendee(Path(id): Path<String>) -> Redirect {
-    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
-    // find event
-    let at_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            log::error!("Event does not exist");
-            return redirect;
-        }
-    };
-
-    match event_db::remove_attendee(at_id as u64).await {
-        Ok(_) => {}
-        Err(event_db::FindEventError::Database(e)) => {
-            log::error!("{e}");
-        }
-        Err(_) => {}
+    // background tasks may still have saves queued
+    if event_db::flush_db().await.is_err() {
+        log::error!("Could not write the database before exiting");
     }
-
-    redirect
 }
 
-async fn view_invitation(Path(id): Path<String>) -> Response {
-    // find event
-    let at_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            return (StatusCode::NOT_FOUND, "Event does not exist")
-                .into_response();
+/// Resolve on Ctrl+C or SIGTERM, after stopping the Slot heartbeat or
+/// withdrawing the announcement
+async fn shutdown_signal(
+    slot_heartbeat: Option<JoinHandle<()>>,
+    announce_file: Option<PathBuf>,
+) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("Failed to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
         }
     };
-    let (event, attendee) =
-        match event_db::find_event_by_attendee(at_id as u64).await {
-            Ok(v) => v,
-            Err(FindEventError::Database(e)) => {
-                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
             }
-            Err(FindEventError::NotFound(e)) => {
-                return (StatusCode::NOT_FOUND, e).into_response();
+            Err(e) => {
+                log::error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
             }
-        };
-    let event_name = event.name.unwrap_or("Untitled Event".to_string());
-
-    // if accepted, show withdraw page instead
-    if attendee.has_accepted {
-        let Ok(template) = templates::WithdrawPage {
-            event_name: &event_name,
-            withdraw_link: &format!("/invite/withdraw/{}", id),
         }
-        .render() else {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to render page",
-            )
-                .into_response();
-        };
-        return Html(template).into_response();
-    }
-
-    // render template
-    let mut ctx = tera::Context::new();
-    ctx.insert("event_name", &event_name);
-    ctx.insert("attendee_name", &attendee.name);
-    ctx.insert("accept_link", &format!("/invite/accept/{}", id));
-    let Ok(page) = tera::Tera::one_off(&attendee.custom_html, &ctx, true)
-    else {
-        // TODO: replace with a default page
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to render the custom invitation. Please contact the event \
-             organizer and let them know.",
-        )
-            .into_response();
     };
-    Html(page).into_response()
-}
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-async fn accept_invitation(Path(id): Path<String>) -> Response {
-    // find event
-    let at_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            return (StatusCode::NOT_FOUND, "Event does not exist")
-                .into_response();
-        }
-    };
-    match event_db::set_accepted(at_id as u64, true).await {
-        Ok(_) => {}
-        Err(FindEventError::Database(e)) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
-        }
-        Err(FindEventError::NotFound(e)) => {
-            return (StatusCode::NOT_FOUND, e).into_response();
-        }
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
-
-    // redirect
-    Redirect::to(&format!("/invite/thanks/{}", id)).into_response()
-}
-
-async fn withdraw_invitation(Path(id): Path<String>) -> Response {
-    // find event
-    let at_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            return (StatusCode::NOT_FOUND, "Event does not exist")
-                .into_response();
-        }
-    };
-    match event_db::set_accepted(at_id as u64, false).await {
-        Ok(_) => {}
-        Err(FindEventError::Database(e)) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
-        }
-        Err(FindEventError::NotFound(e)) => {
-            return (StatusCode::NOT_FOUND, e).into_response();
-        }
+    log::info!("Shutting down");
+    if let Some(heartbeat) = slot_heartbeat {
+        slot::stop_heartbeat(heartbeat);
     }
-
-    // redirect
-    Redirect::to(&format!("/invite/attend/{}", id)).into_response()
-}
-
-async fn view_event(Path(id): Path<String>) -> Response {
-    // find event
-    let at_id = match base62::decode(&id) {
-        Ok(v) => v,
-        Err(_) => {
-            return (StatusCode::NOT_FOUND, "Event does not exist")
-                .into_response();
-        }
-    };
-    let (event, attendee) =
-        match event_db::find_event_by_attendee(at_id as u64).await {
-            Ok(v) => v,
-            Err(FindEventError::Database(e)) => {
-                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
-            }
-            Err(FindEventError::NotFound(e)) => {
-                return (StatusCode::NOT_FOUND, e).into_response();
-            }
-        };
-    
-    // if not accepted, redirect to invitation
-    if !attendee.has_accepted {
-        let redirect_url = format!("/invite/attend/{id}");
-        return Redirect::to(&redirect_url).into_response();
-    }
-
-    // render response
-    let event_name = event.name.unwrap_or("Untitled Event".to_string());
-    let Ok(template) = templates::ThanksPage {
-        event_name: &event_name,
-        withdraw_link: &format!("/invite/withdraw/{}", id)
+    if let Some(path) = announce_file {
+        announce::withdraw(path).await;
     }
-    .render() else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
-            .into_response();
-    };
-    Html(template).into_response()
-}
-
-async fn index_page() -> Html<Vec<u8>> {
-    Html(
-        fs::read(std::path::Path::new(CONTENT_DIR).join("pages/index.html"))
-            .await
-            .expect("index.html exists"),
-    )
 }
@@ -0,0 +1,199 @@
+//! Three-way merge of manage page edits
+//!
+//! When a save is rejected because the event changed since the page was loaded,
+//! the organizer's edits are compared field by field against the version the
+//! page was loaded with (the base) and the event as it is now. Fields changed
+//! on only one side are merged automatically. Fields changed differently on
+//! both sides are conflicts for the organizer to pick between.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    event_db::DigestFrequency,
+    i18n::Language,
+    templates::{ManagePageAttendeeJson, ManagePageJson},
+};
+
+/// Every editable field of an event by key, e.g., "event_name" or
+/// "attendee.<id>.name"
+pub type Fields = BTreeMap<String, String>;
+
+const ATTENDEE_PREFIX: &str = "attendee.";
+/// Follows the attendee's ID in keys of custom field values
+const FIELD_PREFIX: &str = "field.";
+
+pub struct Conflict {
+    pub key: String,
+    pub mine: String,
+    pub theirs: String,
+}
+
+pub fn flatten(data: &ManagePageJson) -> Fields {
+    let mut fields = Fields::new();
+    let mut insert = |key: &str, value: Option<&str>| {
+        fields.insert(key.to_string(), value.unwrap_or_default().to_string());
+    };
+    insert("event_name", Some(&data.event_name));
+    insert("notify_webhook", data.notify_webhook.as_deref());
+    insert("organizer_email", data.organizer_email.as_deref());
+    insert("thanks_html", data.thanks_html.as_deref());
+    insert("withdraw_html", data.withdraw_html.as_deref());
+    insert("decline_reasons", data.decline_reasons.as_deref());
+    insert("rsvp_deadline", data.rsvp_deadline.as_deref());
+    insert("digest", data.digest.map(|d| d.as_str()));
+    for (id, attendee) in data.attendee_data.iter() {
+        fields.insert(
+            format!("{ATTENDEE_PREFIX}{id}.name"),
+            attendee.name.clone(),
+        );
+        fields.insert(
+            format!("{ATTENDEE_PREFIX}{id}.custom_html"),
+            attendee.custom_html.clone(),
+        );
+        fields.insert(
+            format!("{ATTENDEE_PREFIX}{id}.email"),
+            attendee.email.clone().unwrap_or_default(),
+        );
+        fields.insert(
+            format!("{ATTENDEE_PREFIX}{id}.notes"),
+            attendee.notes.clone(),
+        );
+        fields.insert(
+            format!("{ATTENDEE_PREFIX}{id}.language"),
+            attendee.language.unwrap_or_default().code().to_string(),
+        );
+        for (name, value) in attendee.fields.iter() {
+            fields.insert(
+                format!("{ATTENDEE_PREFIX}{id}.{FIELD_PREFIX}{name}"),
+                value.clone(),
+            );
+        }
+    }
+    fields
+}
+
+/// Turn merged fields back into an update against `revision`
+pub fn unflatten(fields: &Fields, revision: u64) -> ManagePageJson {
+    let get = |key: &str| fields.get(key).cloned();
+
+    let mut attendee_data = HashMap::new();
+    for key in fields.keys() {
+        let Some(id) = key
+            .strip_prefix(ATTENDEE_PREFIX)
+            .and_then(|rest| rest.strip_suffix(".name"))
+        else {
+            continue;
+        };
+        let (Some(name), Some(custom_html)) =
+            (get(key), get(&format!("{ATTENDEE_PREFIX}{id}.custom_html")))
+        else {
+            continue;
+        };
+        let email = get(&format!("{ATTENDEE_PREFIX}{id}.email"));
+        let notes =
+            get(&format!("{ATTENDEE_PREFIX}{id}.notes")).unwrap_or_default();
+        let language = get(&format!("{ATTENDEE_PREFIX}{id}.language"))
+            .as_deref()
+            .and_then(Language::parse);
+        let field_prefix = format!("{ATTENDEE_PREFIX}{id}.{FIELD_PREFIX}");
+        let custom_fields = fields
+            .iter()
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix(&field_prefix)?;
+                Some((name.to_string(), value.clone()))
+            })
+            .collect();
+        attendee_data.insert(
+            id.to_string(),
+            ManagePageAttendeeJson {
+                name,
+                custom_html,
+                email,
+                notes,
+                order: None,
+                language,
+                fields: custom_fields,
+            },
+        );
+    }
+
+    ManagePageJson {
+        event_name: get("event_name").unwrap_or_default(),
+        notify_webhook: get("notify_webhook"),
+        organizer_email: get("organizer_email"),
+        digest: get("digest").as_deref().and_then(DigestFrequency::parse),
+        thanks_html: get("thanks_html"),
+        withdraw_html: get("withdraw_html"),
+        decline_reasons: get("decline_reasons"),
+        rsvp_deadline: get("rsvp_deadline"),
+        attendee_data,
+        revision: Some(revision),
+    }
+}
+
+/// Merge the organizer's edits into the current fields. Fields which no
+/// longer exist (e.g., of a removed attendee) are dropped.
+pub fn merge(
+    base: &Fields,
+    mine: &Fields,
+    theirs: &Fields,
+) -> (Fields, Vec<Conflict>) {
+    let mut merged = theirs.clone();
+    let mut conflicts = Vec::new();
+    for (key, mine) in mine.iter() {
+        let Some(theirs) = theirs.get(key) else {
+            continue;
+        };
+        let base = base.get(key);
+        if base == Some(mine) || mine == theirs {
+            continue;
+        }
+        if base == Some(theirs) {
+            merged.insert(key.clone(), mine.clone());
+            continue;
+        }
+        conflicts.push(Conflict {
+            key: key.clone(),
+            mine: mine.clone(),
+            theirs: theirs.clone(),
+        });
+    }
+    (merged, conflicts)
+}
+
+/// Human readable name of a field
+pub fn label(key: &str, fields: &Fields) -> String {
+    match key {
+        "event_name" => "Event name".to_string(),
+        "notify_webhook" => "Notification webhook".to_string(),
+        "organizer_email" => "Email for responses".to_string(),
+        "thanks_html" => "Thanks page".to_string(),
+        "withdraw_html" => "Withdraw page".to_string(),
+        "decline_reasons" => "Decline reasons".to_string(),
+        "digest" => "RSVP digest".to_string(),
+        "rsvp_deadline" => "RSVP deadline".to_string(),
+        _ => {
+            // IDs have no dots, but custom field names can
+            let Some((id, field)) = key
+                .strip_prefix(ATTENDEE_PREFIX)
+                .and_then(|rest| rest.split_once('.'))
+            else {
+                return key.to_string();
+            };
+            let name = fields
+                .get(&format!("{ATTENDEE_PREFIX}{id}.name"))
+                .cloned()
+                .unwrap_or_default();
+            if let Some(custom) = field.strip_prefix(FIELD_PREFIX) {
+                return format!("{custom} of {name}");
+            }
+            match field {
+                "name" => format!("Name of {name}"),
+                "email" => format!("Email of {name}"),
+                "notes" => format!("Notes on {name}"),
+                "language" => format!("Language of {name}"),
+                _ => format!("Invitation of {name}"),
+            }
+        }
+    }
+}
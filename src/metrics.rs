@@ -0,0 +1,219 @@
+//! Instance-wide metrics
+//!
+//! Counters for things that happen in the background (purges, notification
+//! failures) live in statics here. Everything else is computed from the
+//! database when a snapshot is taken. Both the Prometheus endpoint and the admin
+//! dashboard render from the same `Snapshot`.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    clock::{self, unix_secs},
+    event_db,
+};
+
+/// How many weeks of event creation history are reported
+pub const HISTORY_WEEKS: usize = 12;
+
+static PURGES_SUCCEEDED: AtomicU64 = AtomicU64::new(0);
+static PURGES_FAILED: AtomicU64 = AtomicU64::new(0);
+static EVENTS_PURGED: AtomicU64 = AtomicU64::new(0);
+static NOTIFICATION_FAILURES: AtomicU64 = AtomicU64::new(0);
+/// Unix seconds, 0 if never
+static LAST_PURGE: AtomicU64 = AtomicU64::new(0);
+/// Unix seconds, 0 if no purge is scheduled
+static NEXT_PURGE: AtomicU64 = AtomicU64::new(0);
+static SLOT_REGISTERED: AtomicBool = AtomicBool::new(false);
+static SLOT_REGISTRATIONS: AtomicU64 = AtomicU64::new(0);
+
+fn from_unix_secs(secs: u64) -> Option<SystemTime> {
+    (secs != 0).then(|| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+pub fn record_purge_success(purged: usize) {
+    PURGES_SUCCEEDED.fetch_add(1, Ordering::Relaxed);
+    EVENTS_PURGED.fetch_add(purged as u64, Ordering::Relaxed);
+    LAST_PURGE.store(unix_secs(clock::now()), Ordering::Relaxed);
+}
+
+pub fn record_purge_failure() {
+    PURGES_FAILED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_next_purge(at: SystemTime) {
+    NEXT_PURGE.store(unix_secs(at), Ordering::Relaxed);
+}
+
+pub fn record_notification_failure() {
+    NOTIFICATION_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_slot_registration() {
+    SLOT_REGISTERED.store(true, Ordering::Relaxed);
+    SLOT_REGISTRATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_slot_lost() {
+    SLOT_REGISTERED.store(false, Ordering::Relaxed);
+}
+
+pub struct Snapshot {
+    pub events: usize,
+    pub attendees: usize,
+    pub accepted: usize,
+    pub db_bytes: u64,
+    /// The configured size limit, if any
+    pub db_max_bytes: Option<u64>,
+    /// Events created in each of the last `HISTORY_WEEKS` weeks, most recent
+    /// week first
+    pub events_per_week: Vec<usize>,
+    pub purges_succeeded: u64,
+    pub purges_failed: u64,
+    pub events_purged: u64,
+    pub last_purge: Option<SystemTime>,
+    pub next_purge: Option<SystemTime>,
+    pub notification_failures: u64,
+    /// Whether the module is currently registered with a Slot server
+    pub slot_registered: bool,
+    /// Times the module became registered since startup, not counting the
+    /// heartbeat registering again. More than one means the Slot server went
+    /// away at some point.
+    pub slot_registrations: u64,
+}
+
+impl Snapshot {
+    /// Fraction of invited attendees who have accepted
+    pub fn rsvp_rate(&self) -> f64 {
+        if self.attendees == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / self.attendees as f64
+    }
+}
+
+pub async fn snapshot() -> Result<Snapshot, String> {
+    let stats = event_db::stats(HISTORY_WEEKS).await?;
+
+    Ok(Snapshot {
+        events: stats.events,
+        attendees: stats.attendees,
+        accepted: stats.accepted,
+        db_bytes: stats.db_bytes,
+        db_max_bytes: event_db::size_limit().map(|l| l.max_bytes),
+        events_per_week: stats.events_per_week,
+        purges_succeeded: PURGES_SUCCEEDED.load(Ordering::Relaxed),
+        purges_failed: PURGES_FAILED.load(Ordering::Relaxed),
+        events_purged: EVENTS_PURGED.load(Ordering::Relaxed),
+        last_purge: from_unix_secs(LAST_PURGE.load(Ordering::Relaxed)),
+        next_purge: from_unix_secs(NEXT_PURGE.load(Ordering::Relaxed)),
+        notification_failures: NOTIFICATION_FAILURES.load(Ordering::Relaxed),
+        slot_registered: SLOT_REGISTERED.load(Ordering::Relaxed),
+        slot_registrations: SLOT_REGISTRATIONS.load(Ordering::Relaxed),
+    })
+}
+
+/// Render a snapshot in the Prometheus text exposition format
+pub fn render_prometheus(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        let _ = writeln!(out, "# HELP invite_{name} {help}");
+        let _ = writeln!(out, "# TYPE invite_{name} {kind}");
+        let _ = writeln!(out, "invite_{name} {value}");
+    };
+
+    metric(
+        "events",
+        "gauge",
+        "Events currently stored",
+        snapshot.events.to_string(),
+    );
+    metric(
+        "attendees",
+        "gauge",
+        "Attendees across all events",
+        snapshot.attendees.to_string(),
+    );
+    metric(
+        "attendees_accepted",
+        "gauge",
+        "Attendees who have accepted their invitation",
+        snapshot.accepted.to_string(),
+    );
+    metric(
+        "database_bytes",
+        "gauge",
+        "Size of the database file",
+        snapshot.db_bytes.to_string(),
+    );
+    metric(
+        "database_max_bytes",
+        "gauge",
+        "Size limit of the database file, 0 if unlimited",
+        snapshot.db_max_bytes.unwrap_or_default().to_string(),
+    );
+    metric(
+        "events_created_this_week",
+        "gauge",
+        "Events created in the last 7 days",
+        snapshot
+            .events_per_week
+            .first()
+            .copied()
+            .unwrap_or_default()
+            .to_string(),
+    );
+    metric(
+        "purges_total",
+        "counter",
+        "Purges completed since startup",
+        snapshot.purges_succeeded.to_string(),
+    );
+    metric(
+        "purge_failures_total",
+        "counter",
+        "Purge attempts which failed since startup",
+        snapshot.purges_failed.to_string(),
+    );
+    metric(
+        "events_purged_total",
+        "counter",
+        "Events removed by purges since startup",
+        snapshot.events_purged.to_string(),
+    );
+    metric(
+        "last_purge_timestamp_seconds",
+        "gauge",
+        "Time of the last successful purge, 0 if none",
+        snapshot.last_purge.map(unix_secs).unwrap_or(0).to_string(),
+    );
+    metric(
+        "next_purge_timestamp_seconds",
+        "gauge",
+        "Time of the next scheduled purge, 0 if none",
+        snapshot.next_purge.map(unix_secs).unwrap_or(0).to_string(),
+    );
+    metric(
+        "notification_failures_total",
+        "counter",
+        "Notifications which could not be delivered since startup",
+        snapshot.notification_failures.to_string(),
+    );
+    metric(
+        "slot_registered",
+        "gauge",
+        "1 if registered with a Slot server, otherwise 0",
+        u8::from(snapshot.slot_registered).to_string(),
+    );
+    metric(
+        "slot_registrations_total",
+        "counter",
+        "Registrations with the Slot server since startup",
+        snapshot.slot_registrations.to_string(),
+    );
+
+    out
+}
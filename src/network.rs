@@ -0,0 +1,126 @@
+//! Who a request comes from, and which networks may reach the admin routes
+//!
+//! The module usually sits behind the Slot reverse proxy, so the address of
+//! the connection is the proxy's, not the client's. The client's address is
+//! then taken from a forwarded header, but only from the proxies listed with
+//! `--trusted-proxy`, since anyone else could set it to whatever they like.
+//! Which header is believed is configured explicitly with `--forwarded-header`
+//! rather than guessed.
+//!
+//! With `--admin-allow`, the admin routes answer only clients within the
+//! listed networks. Others are told the routes don't exist, as when admin
+//! routes are disabled.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+
+/// Proxy header carrying the client's address
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// Believe no header. The connection's address is the client's.
+    #[default]
+    None,
+    /// `X-Forwarded-For`, as appended to by each proxy on the way
+    XForwardedFor,
+    /// `X-Real-IP`, as set by the last proxy
+    XRealIp,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Networks which may reach the admin routes. Empty for any.
+    pub admin_networks: Vec<IpNet>,
+    pub forwarded_header: ForwardedHeader,
+    /// Proxies whose forwarded header is believed
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Config {
+    fn is_trusted_proxy(&self, addr: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&addr))
+    }
+
+    /// The client's address, given the address of the connection and the
+    /// request's headers
+    pub fn client_addr(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted_proxy(peer) {
+            return peer;
+        }
+        let header = |name| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        match self.forwarded_header {
+            ForwardedHeader::None => peer,
+            ForwardedHeader::XRealIp => {
+                header("x-real-ip").trim().parse().unwrap_or(peer)
+            }
+            ForwardedHeader::XForwardedFor => {
+                // each proxy appends who it heard from, so walk back from the
+                // nearest until the first address not of a trusted proxy
+                let mut client = peer;
+                for hop in header("x-forwarded-for").rsplit(',') {
+                    let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+                        break;
+                    };
+                    client = hop;
+                    if !self.is_trusted_proxy(hop) {
+                        break;
+                    }
+                }
+                client
+            }
+        }
+    }
+}
+
+/// Parse a network, e.g., "10.0.0.0/8". A single address is a network of its
+/// own.
+pub fn parse_network(text: &str) -> Result<IpNet, String> {
+    let text = text.trim();
+    text.parse::<IpNet>()
+        .or_else(|_| text.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("\"{text}\" is not an address or network"))
+}
+
+/// Middleware answering only clients within `--admin-allow`
+pub async fn restrict_admin(
+    State(config): State<Arc<Config>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let networks = &config.admin_networks;
+    if networks.is_empty() {
+        return next.run(req).await;
+    }
+    let Some(ConnectInfo(peer)) =
+        req.extensions().get::<ConnectInfo<SocketAddr>>()
+    else {
+        // e.g., mounted by a server which doesn't pass on connection info
+        log::warn!(
+            "Refused admin request because the client's address is unknown"
+        );
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let client = config.client_addr(peer.ip(), req.headers());
+    if networks.iter().any(|net| net.contains(&client)) {
+        next.run(req).await
+    } else {
+        log::info!("Refused admin request from {client}");
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
@@ -0,0 +1,98 @@
+//! Organizer notifications
+//!
+//! Notifications are posted as JSON to the instance-wide webhook given with
+//! `--notify-webhook` and to the event's own webhook if the organizer set one
+//! on the manage page. Delivery failures are logged and counted in the metrics
+//! but never retried, so a broken endpoint can't cause a notification storm.
+
+use std::sync::{LazyLock, OnceLock};
+
+use serde::Serialize;
+
+use crate::{ids::Id, metrics};
+
+static INSTANCE_WEBHOOK: OnceLock<String> = OnceLock::new();
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Set the webhook which receives every notification on the instance
+pub fn initialize(webhook: String) {
+    if INSTANCE_WEBHOOK.set(webhook).is_err() {
+        log::warn!("Notification webhook was already set");
+    }
+}
+
+/// Whether a notification for an event with the given webhook would be
+/// delivered anywhere
+pub fn has_channel(event_webhook: Option<&str>) -> bool {
+    INSTANCE_WEBHOOK.get().is_some()
+        || event_webhook.is_some_and(|w| !w.is_empty())
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    PurgeWarning,
+    AttendeeDataRemoved,
+    Nudge,
+    Digest,
+    Withdrawal,
+    DetailsChanged,
+    /// A scheduled invitation for a guest without email, for integrations
+    /// which deliver it some other way
+    Invitation,
+}
+
+/// How urgently the organizer should see a notification. Integrations can use
+/// it to page instead of batching.
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Normal,
+    High,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub priority: Priority,
+    pub event_id: Id,
+    pub event_name: String,
+    pub subject: String,
+    pub message: String,
+    /// Where the organizer can act on the notification
+    pub link: Option<String>,
+    /// The guest the notification is about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attendee_id: Option<Id>,
+    #[serde(skip)]
+    pub event_webhook: Option<String>,
+}
+
+/// Deliver a notification to every configured channel. Returns whether all
+/// deliveries succeeded.
+pub async fn send(notification: &Notification) -> bool {
+    let targets = INSTANCE_WEBHOOK
+        .get()
+        .cloned()
+        .into_iter()
+        .chain(notification.event_webhook.clone())
+        .filter(|w| !w.is_empty());
+
+    let mut all_ok = true;
+    for target in targets {
+        let result = CLIENT.post(&target).json(notification).send().await;
+        let error = match result {
+            Ok(resp) if resp.status().is_success() => continue,
+            Ok(resp) => format!("responded with {}", resp.status()),
+            Err(e) => e.to_string(),
+        };
+        log::warn!(
+            "Failed to deliver {:?} notification for event {}: \"{error}\"",
+            notification.kind,
+            notification.event_id
+        );
+        metrics::record_notification_failure();
+        all_ok = false;
+    }
+    all_ok
+}
@@ -0,0 +1,683 @@
+//! Sign-in through an OpenID Connect provider
+//!
+//! With `--oidc-issuer`, `--oidc-client-id`, and `--oidc-client-secret`,
+//! people can sign in with the accounts of a provider they already run, e.g.,
+//! Authentik or Keycloak, at `/invite/login`. A sign-in whose email address
+//! the provider has verified opens the organizer pages of every event with
+//! that organizer email, without the organizer key. Operators listed with
+//! `--oidc-admin`, by email address or subject, get an admin session as a
+//! passkey sign-in would.
+//!
+//! Sign-ins use the authorization code flow with PKCE. The provider's
+//! endpoints come from its discovery document, the code is exchanged with
+//! the client secret, and the ID token is checked against the provider's
+//! published RS256 keys, its issuer, audience, expiry, and the nonce the
+//! sign-in started with. Sign-ins in progress and sessions only live in
+//! memory, so a restart signs everyone out.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use askama::Template;
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    digest::{digest, SHA256},
+    signature::{self, RsaPublicKeyComponents},
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{clock, i18n::Language, passkeys, settings, templates};
+
+/// Name of the cookie holding the sign-in
+const SESSION_COOKIE: &str = "invite_oidc_session";
+/// Name of the cookie tying a sign-in in progress to the browser it started
+/// in
+const STATE_COOKIE: &str = "invite_oidc_state";
+const SESSION_LIFETIME: Duration = Duration::from_days(7);
+/// Time the user has to sign in at the provider
+const SIGN_IN_LIFETIME: Duration = Duration::from_mins(10);
+/// How far the provider's clock may be ahead or behind
+const CLOCK_SKEW: Duration = Duration::from_mins(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const CALLBACK_PATH: &str = "/invite/oidc/callback";
+
+static PROVIDER: OnceLock<Provider> = OnceLock::new();
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+/// The provider's discovery document and keys, once fetched
+static METADATA: tokio::sync::Mutex<Option<Metadata>> =
+    tokio::sync::Mutex::const_new(None);
+/// Sign-ins in progress by their state
+static SIGN_INS: LazyLock<Mutex<HashMap<String, SignIn>>> =
+    LazyLock::new(Default::default);
+/// Session tokens, with the verified email address they're for and when
+/// they expire
+static SESSIONS: LazyLock<Mutex<HashMap<String, (String, SystemTime)>>> =
+    LazyLock::new(Default::default);
+
+/// The provider to sign in with
+pub struct Provider {
+    /// e.g., "https://auth.example.org/realms/home"
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Email addresses or subjects which get an admin session
+    pub admins: Vec<String>,
+}
+
+/// What of the discovery document is used, and the keys at its `jwks_uri`
+#[derive(Deserialize, Clone)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(skip)]
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// A key the provider signs ID tokens with. Only RSA keys are used.
+#[derive(Deserialize, Clone, Debug)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// A sign-in waiting for the provider to send the user back
+struct SignIn {
+    nonce: String,
+    /// PKCE code verifier
+    verifier: String,
+    /// Where to go once signed in
+    next: String,
+    expires: SystemTime,
+}
+
+#[derive(Deserialize)]
+struct TokenHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Claims {
+    iss: String,
+    aud: Audience,
+    sub: String,
+    /// Seconds since the Unix epoch
+    exp: u64,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+impl Claims {
+    /// The email address, if the provider verified it, trimmed and
+    /// lowercased
+    fn verified_email(&self) -> Option<String> {
+        let email = self.email.as_deref().filter(|_| self.email_verified)?;
+        Some(email.trim().to_ascii_lowercase())
+    }
+}
+
+/// What an ID token must say
+struct Expected<'a> {
+    issuer: &'a str,
+    client_id: &'a str,
+    nonce: &'a str,
+    now: SystemTime,
+}
+
+/// Enable signing in with the given provider
+pub fn initialize(provider: Provider) {
+    if PROVIDER.set(provider).is_err() {
+        log::warn!("OpenID Connect provider was already set");
+    }
+}
+
+pub fn enabled() -> bool {
+    PROVIDER.get().is_some()
+}
+
+/// Whether signing in can give an admin session
+pub fn admins_enabled() -> bool {
+    PROVIDER.get().is_some_and(|p| !p.admins.is_empty())
+}
+
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+fn cookie<'h>(headers: &'h HeaderMap, name: &str) -> Option<&'h str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value)
+}
+
+/// The verified email address the request is signed in with
+pub fn signed_in_email(headers: &HeaderMap) -> Option<String> {
+    let token = cookie(headers, SESSION_COOKIE)?;
+    let now = clock::now();
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.retain(|_, (_, expires)| *expires > now);
+    sessions.get(token).map(|(email, _)| email.clone())
+}
+
+/// Where to sign in before going to `next`
+pub fn login_link(next: &str) -> String {
+    let mut url = reqwest::Url::parse("http://localhost/invite/login")
+        .expect("The sign-in address is a URL");
+    url.query_pairs_mut().append_pair("next", next);
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+/// Attributes of the cookies, which browsers only keep from plain HTTP
+/// without `Secure`. Lax, since the provider sends the user back from
+/// another site.
+fn cookie_attributes(max_age: Duration) -> String {
+    let secure = settings::public_url().starts_with("https://");
+    format!(
+        "Path=/invite; Max-Age={}; HttpOnly;{} SameSite=Lax",
+        max_age.as_secs(),
+        if secure { " Secure;" } else { "" },
+    )
+}
+
+async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, String> {
+    let response = CLIENT
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Could not fetch {url}: {e}"))?;
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Could not parse {url}: {e}"))
+}
+
+/// The provider's endpoints and keys. The keys are fetched again if none has
+/// the ID `kid`, e.g., after the provider rotated them.
+async fn metadata(kid: Option<&str>) -> Result<Metadata, String> {
+    let provider = PROVIDER.get().ok_or("Sign-in isn't enabled")?;
+    let mut cached = METADATA.lock().await;
+    let metadata = match cached.take() {
+        Some(metadata) => metadata,
+        None => {
+            let issuer = provider.issuer.trim_end_matches('/');
+            let metadata: Metadata =
+                get_json(&format!("{issuer}/.well-known/openid-configuration"))
+                    .await?;
+            if metadata.issuer.trim_end_matches('/') != issuer {
+                return Err(format!(
+                    "Provider says its issuer is {}, not {issuer}",
+                    metadata.issuer
+                ));
+            }
+            metadata
+        }
+    };
+    let metadata = cached.insert(metadata);
+    let known =
+        |kid: &str| metadata.keys.iter().any(|k| k.kid.as_deref() == Some(kid));
+    if metadata.keys.is_empty() || kid.is_some_and(|kid| !known(kid)) {
+        let set: JwkSet = get_json(&metadata.jwks_uri).await?;
+        metadata.keys = set.keys;
+    }
+    Ok(metadata.clone())
+}
+
+/// Check an ID token's signature and claims
+fn verify_id_token(
+    token: &str,
+    keys: &[Jwk],
+    expected: &Expected,
+) -> Result<Claims, String> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(sig), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("ID token is malformed".to_string());
+    };
+    let decode = |part: &str| {
+        URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| "ID token is malformed".to_string())
+    };
+    let head: TokenHeader = serde_json::from_slice(&decode(header)?)
+        .map_err(|_| "ID token is malformed")?;
+    if head.alg != "RS256" {
+        return Err(format!("ID token is signed with {}, not RS256", head.alg));
+    }
+    let mut rsa_keys = keys.iter().filter(|k| k.kty == "RSA");
+    let key = match &head.kid {
+        Some(kid) => rsa_keys.find(|k| k.kid.as_ref() == Some(kid)),
+        // without an ID, the provider must have only the one key
+        None => rsa_keys.next().filter(|_| rsa_keys.next().is_none()),
+    };
+    let (Some(n), Some(e)) =
+        key.map_or((None, None), |k| (k.n.as_ref(), k.e.as_ref()))
+    else {
+        return Err("ID token is signed with an unknown key".to_string());
+    };
+    let message = &token[..header.len() + 1 + payload.len()];
+    RsaPublicKeyComponents {
+        n: decode(n)?,
+        e: decode(e)?,
+    }
+    .verify(
+        &signature::RSA_PKCS1_2048_8192_SHA256,
+        message.as_bytes(),
+        &decode(sig)?,
+    )
+    .map_err(|_| "ID token's signature doesn't match")?;
+
+    let claims: Claims = serde_json::from_slice(&decode(payload)?)
+        .map_err(|e| format!("ID token's claims are malformed: {e}"))?;
+    if claims.iss != expected.issuer {
+        return Err(format!("ID token is from {}", claims.iss));
+    }
+    if !claims.aud.contains(expected.client_id) {
+        return Err("ID token is for another client".to_string());
+    }
+    let expires = clock::add_clamped(
+        SystemTime::UNIX_EPOCH,
+        Duration::from_secs(claims.exp),
+    );
+    if expires + CLOCK_SKEW < expected.now {
+        return Err("ID token has expired".to_string());
+    }
+    if claims.nonce.as_deref() != Some(expected.nonce) {
+        return Err("ID token is for another sign-in".to_string());
+    }
+    Ok(claims)
+}
+
+/// Only paths of the module, so signing in can't send anyone elsewhere
+fn safe_next(next: Option<&str>) -> String {
+    next.filter(|next| next.starts_with("/invite/"))
+        .unwrap_or("/invite/index")
+        .to_string()
+}
+
+fn error_page(status: StatusCode, message: &str) -> Response {
+    let page = templates::ErrorPage {
+        lang: Language::default().code(),
+        title: "Sign-in failed",
+        message,
+    };
+    match page.render() {
+        Ok(page) => (status, Html(page)).into_response(),
+        Err(_) => (status, "Sign-in failed").into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+/// `GET /invite/login?next=`
+///
+/// Sends the user to the provider to sign in
+pub async fn login(Query(query): Query<LoginQuery>) -> Response {
+    let Some(provider) = PROVIDER.get() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let metadata = match metadata(None).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            log::error!("Could not reach the sign-in provider: {e}");
+            return error_page(
+                StatusCode::BAD_GATEWAY,
+                "The sign-in provider can't be reached right now.",
+            );
+        }
+    };
+    let (state, nonce, verifier) =
+        (random_token(), random_token(), random_token());
+    let challenge =
+        URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()));
+    let redirect_uri = format!("{}{CALLBACK_PATH}", settings::public_url());
+    let url = reqwest::Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        [
+            ("response_type", "code"),
+            ("client_id", &provider.client_id),
+            ("redirect_uri", &redirect_uri),
+            ("scope", "openid email"),
+            ("state", &state),
+            ("nonce", &nonce),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    );
+    let Ok(url) = url else {
+        log::error!(
+            "Sign-in provider's authorization endpoint {} isn't a URL",
+            metadata.authorization_endpoint
+        );
+        return error_page(
+            StatusCode::BAD_GATEWAY,
+            "The sign-in provider is misconfigured.",
+        );
+    };
+
+    let now = clock::now();
+    let mut sign_ins = SIGN_INS.lock().unwrap();
+    sign_ins.retain(|_, sign_in| sign_in.expires > now);
+    sign_ins.insert(
+        state.clone(),
+        SignIn {
+            nonce,
+            verifier,
+            next: safe_next(query.next.as_deref()),
+            expires: now + SIGN_IN_LIFETIME,
+        },
+    );
+    let cookie = format!(
+        "{STATE_COOKIE}={state}; {}",
+        cookie_attributes(SIGN_IN_LIFETIME)
+    );
+    ([(header::SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "signed_in.html")]
+struct SignedInPage<'a> {
+    next: &'a str,
+}
+
+/// `GET /invite/oidc/callback?code=&state=`
+///
+/// Where the provider sends the user back. Starts a session if the sign-in
+/// checks out.
+pub async fn callback(
+    headers: HeaderMap,
+    Query(query): Query<Callback>,
+) -> Response {
+    let Some(provider) = PROVIDER.get() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Some(error) = &query.error {
+        log::warn!("Sign-in provider turned a sign-in away: {error}");
+        return error_page(StatusCode::UNAUTHORIZED, "You weren't signed in.");
+    }
+    // the state must be the one this browser started with, so nobody can
+    // sign someone else in as themselves
+    let state = query.state.as_deref().unwrap_or_default();
+    let sign_in = match cookie(&headers, STATE_COOKIE) {
+        Some(started) if started == state => {
+            SIGN_INS.lock().unwrap().remove(state)
+        }
+        _ => None,
+    };
+    let (Some(sign_in), Some(code)) = (sign_in, query.code) else {
+        return error_page(
+            StatusCode::UNAUTHORIZED,
+            "This sign-in has expired or was started elsewhere. Try again.",
+        );
+    };
+    if sign_in.expires <= clock::now() {
+        return error_page(
+            StatusCode::UNAUTHORIZED,
+            "This sign-in has expired. Try again.",
+        );
+    }
+    let claims = match finish(provider, &sign_in, &code).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            log::warn!("Failed sign-in: {e}");
+            return error_page(
+                StatusCode::UNAUTHORIZED,
+                "The sign-in provider's answer couldn't be checked.",
+            );
+        }
+    };
+
+    let email = claims.verified_email();
+    let mut cookies = vec![format!(
+        "{STATE_COOKIE}=; {}",
+        cookie_attributes(Duration::ZERO)
+    )];
+    if let Some(email) = &email {
+        let token = random_token();
+        SESSIONS.lock().unwrap().insert(
+            token.clone(),
+            (email.clone(), clock::now() + SESSION_LIFETIME),
+        );
+        cookies.push(format!(
+            "{SESSION_COOKIE}={token}; {}",
+            cookie_attributes(SESSION_LIFETIME)
+        ));
+    }
+    let admin = provider.admins.iter().any(|admin| {
+        *admin == claims.sub
+            || email.as_deref() == Some(admin.to_ascii_lowercase().as_str())
+    });
+    if admin {
+        log::info!("Admin {} signed in through the provider", claims.sub);
+        cookies.push(passkeys::start_session());
+    }
+
+    // a page rather than a redirect, so the session cookies are sent along,
+    // which browsers don't do on redirects which started at the provider
+    let page = SignedInPage {
+        next: &sign_in.next,
+    };
+    let Ok(page) = page.render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    let mut response = Html(page).into_response();
+    for cookie in cookies {
+        if let Ok(cookie) = cookie.parse() {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+    }
+    response
+}
+
+/// Exchange the code for an ID token, and check it
+async fn finish(
+    provider: &Provider,
+    sign_in: &SignIn,
+    code: &str,
+) -> Result<Claims, String> {
+    let metadata = metadata(None).await?;
+    let redirect_uri = format!("{}{CALLBACK_PATH}", settings::public_url());
+    let response = CLIENT
+        .post(&metadata.token_endpoint)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &redirect_uri),
+            ("client_id", &provider.client_id),
+            ("client_secret", &provider.client_secret),
+            ("code_verifier", &sign_in.verifier),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Could not exchange the code: {e}"))?;
+    let tokens: TokenResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Could not parse the tokens: {e}"))?;
+
+    let kid = tokens
+        .id_token
+        .split('.')
+        .next()
+        .and_then(|header| URL_SAFE_NO_PAD.decode(header).ok())
+        .and_then(|header| serde_json::from_slice::<TokenHeader>(&header).ok())
+        .and_then(|header| header.kid);
+    let metadata = self::metadata(kid.as_deref()).await?;
+    verify_id_token(
+        &tokens.id_token,
+        &metadata.keys,
+        &Expected {
+            issuer: &metadata.issuer,
+            client_id: &provider.client_id,
+            nonce: &sign_in.nonce,
+            now: clock::now(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ID token for "invite" from "https://id.example.org", signed with
+    /// the RSA key `N`, made with openssl
+    const TOKEN: &str = "eyJhbGciOiJSUzI1NiIsImtpZCI6InRlc3Qta2V5IiwidHlwIjoi\
+        SldUIn0.eyJpc3MiOiJodHRwczovL2lkLmV4YW1wbGUub3JnIiwiYXVkIjoiaW52aXRlIi\
+        wic3ViIjoidXNlci0xIiwiZXhwIjo0MTAyNDQ0ODAwLCJpYXQiOjE3MDAwMDAwMDAsIm5v\
+        bmNlIjoibm9uY2UtMSIsImVtYWlsIjoiQWRhQEV4YW1wbGUub3JnIiwiZW1haWxfdmVyaW\
+        ZpZWQiOnRydWV9.Rqa6k6HvL1BJi9x72mS0M1euA2T8P-qeLd3r_F4-ea58ynBxs4EBVtgI\
+        chahQF18ly3u-d15tRWZsWicstrJxhzb8kzvllRHJw3rxJuzk2CC9Ig9VhnF5yW31MIncH\
+        KHsaHng58JP-W6KGZ8c5ruV1SQadYXIyS5vr-BVoR4zXy7XLuU3wmbc2daxp03UFGit8jK\
+        LLXdffh0vt6MAjEDpSNIRbvb0rwYkfkhyJGadGys2-qq58n-5ZOtNsAdXluFXWhMmLzEib\
+        Oa3cOha4-NcP20OxjgrYOplRkBJDFhPNfo8Wfev5HzUhzjACW4k1NPZAJ_8MHGMcHzDlmX\
+        ja1Siw";
+    const N: &str =
+        "uvo1to_RVm3h-_ziikKTBVuWWD7asq3ZWdh5BRuhW7n43C2VGCNdMt06d4\
+        lDchCB19OgD9MWFrvRWXrAR_bcvzwEe0G86HRRuK9q9YUe14IxqrK0-34n6IcGaolHrton\
+        HAOhocl02iFv9y3nYIUhriSpjxgGLdrbCyhwaEAGw4vMYdQWQPQHpQa-UfAtZyF3twS__I\
+        D10_tcM2470-1XG2gEJlgSqL_l4K6EwHhnDuijcx6pQhj32a49R8OgvU54F9OfO6qJ885A\
+        XLzMgPIL3q7FUwMrGjiDGsXRdNO6GUBrZOYCPZvbLvCr6eAA4czJRJ-F-1v_UuNcxIzNKc\
+        VVOQ";
+
+    fn key(kid: &str) -> Jwk {
+        Jwk {
+            kty: "RSA".to_string(),
+            kid: Some(kid.to_string()),
+            n: Some(N.to_string()),
+            e: Some("AQAB".to_string()),
+        }
+    }
+
+    fn expected(now: SystemTime) -> Expected<'static> {
+        Expected {
+            issuer: "https://id.example.org",
+            client_id: "invite",
+            nonce: "nonce-1",
+            now,
+        }
+    }
+
+    #[test]
+    fn id_tokens() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let keys = [key("other-key"), key("test-key")];
+        let claims = verify_id_token(TOKEN, &keys, &expected(now)).unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.verified_email().as_deref(), Some("ada@example.org"));
+
+        let checks = [
+            Expected {
+                issuer: "https://id.example.com",
+                ..expected(now)
+            },
+            Expected {
+                client_id: "other",
+                ..expected(now)
+            },
+            Expected {
+                nonce: "nonce-2",
+                ..expected(now)
+            },
+            expected(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(4_200_000_000),
+            ),
+        ];
+        for expected in checks {
+            assert!(verify_id_token(TOKEN, &keys, &expected).is_err());
+        }
+
+        let err = verify_id_token(TOKEN, &[key("other-key")], &expected(now));
+        assert_eq!(err.unwrap_err(), "ID token is signed with an unknown key");
+        let (signed, _) = TOKEN.rsplit_once('.').unwrap();
+        let tampered = format!("{signed}.{}", URL_SAFE_NO_PAD.encode([0; 256]));
+        let err = verify_id_token(&tampered, &keys, &expected(now));
+        assert_eq!(err.unwrap_err(), "ID token's signature doesn't match");
+        assert!(verify_id_token("a.b", &keys, &expected(now)).is_err());
+    }
+
+    #[test]
+    fn unverified_emails_are_ignored() {
+        let claims = Claims {
+            iss: String::new(),
+            aud: Audience::Many(vec!["invite".to_string()]),
+            sub: "user-1".to_string(),
+            exp: 0,
+            nonce: None,
+            email: Some("ada@example.org".to_string()),
+            email_verified: false,
+        };
+        assert_eq!(claims.verified_email(), None);
+        assert!(claims.aud.contains("invite"));
+    }
+
+    #[test]
+    fn stays_within_the_module() {
+        assert_eq!(safe_next(Some("/invite/manage/abc")), "/invite/manage/abc");
+        for next in [None, Some("https://evil.example"), Some("//evil")] {
+            assert_eq!(safe_next(next), "/invite/index");
+        }
+    }
+}
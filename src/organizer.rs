@@ -0,0 +1,231 @@
+//! Organizer access to an event's pages
+//!
+//! Every event gets an organizer key when it's created, which the link to
+//! its manage page carries as a `key` query parameter. Opening a page with
+//! the key keeps it in a cookie for the event and, served over HTTPS, leaves
+//! it out of the address, so the page's own links and forms work without it.
+//! Over plain HTTP the cookie isn't marked secure, and the key stays in the
+//! address. The organizer routes turn away requests carrying neither. Events
+//! created before there were keys are given one on startup, see
+//! `event_db::assign_organizer_keys`. Organizers signed in through the
+//! OpenID Connect provider, see `oidc`, need no key for events with their
+//! verified email address. Attendee links don't need the key.
+
+use std::collections::HashMap;
+
+use askama::Template;
+use axum::{
+    extract::{Path, Request},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::{
+    admin::constant_time_eq,
+    api_error::{ApiError, ErrorCode},
+    event_db::{self, Event, FindEventError},
+    i18n::Language,
+    ids, oidc, settings, templates,
+};
+
+/// How long browsers keep the key
+const COOKIE_MAX_AGE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// A key for a new event
+pub fn new_key() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+fn cookie_name(event: &Event) -> String {
+    format!("invite_organizer_{}", event.id)
+}
+
+/// The key the request carries for `event`, and whether it came in the query
+fn provided_key<'r>(
+    req: &'r Request,
+    event: &Event,
+) -> Option<(&'r str, bool)> {
+    let from_query = req.uri().query().and_then(|q| {
+        q.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == "key")
+            .map(|(_, v)| v)
+    });
+    if let Some(key) = from_query {
+        return Some((key, true));
+    }
+    let name = cookie_name(event);
+    req.headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| (value, false))
+}
+
+/// The event the route's `ev_id` or `at_id` names. None if it names none, so
+/// the handler answers as it does for any unknown ID.
+async fn event_of(
+    params: &HashMap<String, String>,
+) -> Result<Option<Event>, FindEventError> {
+    let found = if let Some(id) = params.get("ev_id") {
+        let Ok(ev_id) = ids::decode_id(id) else {
+            return Ok(None);
+        };
+        event_db::find_event_by_id(ev_id).await
+    } else if let Some(id) = params.get("at_id") {
+        let Ok(at_id) = ids::decode_id(id) else {
+            return Ok(None);
+        };
+        event_db::find_event_by_attendee(at_id)
+            .await
+            .map(|(event, _)| event)
+    } else {
+        return Ok(None);
+    };
+    match found {
+        Ok(event) => Ok(Some(event)),
+        Err(FindEventError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The request's path and query without the `key` parameter
+fn without_key(uri: &Uri) -> String {
+    let rest: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split_once('=').map_or(*pair, |(name, _)| name);
+            !pair.is_empty() && name != "key"
+        })
+        .collect();
+    match rest.is_empty() {
+        true => uri.path().to_string(),
+        false => format!("{}?{}", uri.path(), rest.join("&")),
+    }
+}
+
+fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+}
+
+/// Middleware rejecting requests to an organizer route without the event's
+/// organizer key
+pub async fn require_organizer(
+    Path(params): Path<HashMap<String, String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let event = match event_of(&params).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return next.run(req).await,
+        Err(e) => return ApiError::from(e).into_response(),
+    };
+    // organizers signed in through the provider need no key for their own
+    // events
+    let signed_in = oidc::signed_in_email(req.headers());
+    let organizer = event
+        .organizer_email
+        .as_deref()
+        .map(|email| email.trim().to_ascii_lowercase());
+    if signed_in.is_some() && signed_in == organizer {
+        return next.run(req).await;
+    }
+    let browser = wants_html(req.headers());
+    let Some(expected) = &event.organizer_key else {
+        // only until keys are given out on startup
+        return turn_away(&req, signed_in.is_some());
+    };
+    match provided_key(&req, &event) {
+        Some((key, from_query))
+            if constant_time_eq(key.as_bytes(), expected.as_bytes()) =>
+        {
+            if !from_query {
+                return next.run(req).await;
+            }
+            // browsers don't keep secure cookies from plain HTTP
+            let https = settings::public_url().starts_with("https://");
+            let cookie = format!(
+                "{}={expected}; Path=/invite; Max-Age={COOKIE_MAX_AGE_SECS}; \
+                 HttpOnly;{} SameSite=Strict",
+                cookie_name(&event),
+                if https { " Secure;" } else { "" },
+            );
+            let cookie = HeaderValue::from_str(&cookie)
+                .expect("Keys and IDs are valid in headers");
+            // pages get the key out of the address, where it would leak
+            // through the history and referrers, once the cookie has it
+            let redirect = https && browser && req.method() == Method::GET;
+            let mut response = if redirect {
+                Redirect::to(&without_key(req.uri())).into_response()
+            } else {
+                next.run(req).await
+            };
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+            response
+        }
+        _ => turn_away(&req, signed_in.is_some()),
+    }
+}
+
+/// Answer a request without the organizer key, with a page for browsers.
+/// Browsers are sent to sign in through the provider first, if it's enabled
+/// and they haven't.
+fn turn_away(req: &Request, signed_in: bool) -> Response {
+    let browser = wants_html(req.headers());
+    if browser && !signed_in && oidc::enabled() && req.method() == Method::GET {
+        return Redirect::to(&oidc::login_link(&without_key(req.uri())))
+            .into_response();
+    }
+    if !browser {
+        return ApiError::new(
+            ErrorCode::Unauthorized,
+            "Missing or invalid organizer key",
+        )
+        .into_response();
+    }
+    let Ok(page) = templates::ErrorPage {
+        lang: Language::default().code(),
+        title: "Organizer link needed",
+        message: "Open this event through the organizer link you got when it \
+                  was created, which lets you manage it.",
+    }
+    .render() else {
+        return (StatusCode::UNAUTHORIZED, "Organizer link needed")
+            .into_response();
+    };
+    (StatusCode::UNAUTHORIZED, Html(page)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_only_the_key() {
+        let cases = [
+            ("/invite/manage/abc?key=secret", "/invite/manage/abc"),
+            (
+                "/invite/manage/abc?lang=de&key=secret",
+                "/invite/manage/abc?lang=de",
+            ),
+            (
+                "/invite/manage/abc?key=secret&tab=guests&x",
+                "/invite/manage/abc?tab=guests&x",
+            ),
+            ("/invite/manage/abc?keys=1&key", "/invite/manage/abc?keys=1"),
+        ];
+        for (uri, expected) in cases {
+            assert_eq!(without_key(&uri.parse().unwrap()), expected);
+        }
+    }
+}
@@ -0,0 +1,973 @@
+//! Passkey sign-in for the admin area
+//!
+//! With `--admin-passkey-origin`, operators can sign in to the admin pages
+//! with a passkey instead of pasting the admin token into their browser. A
+//! sign-in starts a session kept in a cookie, which the admin routes accept
+//! alongside the token. The first passkey is registered through a one-time
+//! link printed by `--register-admin-passkey`. Once signed in, more can be
+//! registered from the same page.
+//!
+//! Only what the admin area needs of WebAuthn is implemented: no attestation
+//! is requested, ES256 and RS256 keys are accepted, which covers the
+//! authenticators in use today, and the authenticator must verify the user.
+//! Registered passkeys are kept in `admin_passkeys.json` beside the
+//! database. Challenges and sessions only
+//! live in memory, so a restart signs everyone out.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{LazyLock, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use askama::Template;
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    digest::{digest, SHA256},
+    signature::{self, RsaPublicKeyComponents, UnparsedPublicKey},
+};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+
+use crate::{
+    admin::constant_time_eq,
+    api_error::{ApiError, ErrorCode},
+    clock, event_db, oidc,
+};
+
+const PASSKEYS_PATH: &str = "admin_passkeys.json";
+/// Name of the cookie holding the admin session
+const SESSION_COOKIE: &str = "invite_admin_session";
+const SESSION_LIFETIME: Duration = Duration::from_hours(12);
+/// Time the operator has to answer their authenticator
+const CHALLENGE_LIFETIME: Duration = Duration::from_mins(5);
+/// Every passkey belongs to the one operator account
+const USER_ID: &[u8] = b"admin";
+
+/// COSE algorithm identifiers
+const ES256: i128 = -7;
+const RS256: i128 = -257;
+
+/// Flags of the authenticator data
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL: u8 = 0x40;
+
+static RELYING_PARTY: OnceLock<RelyingParty> = OnceLock::new();
+static CHALLENGES: LazyLock<Mutex<HashMap<String, (Ceremony, SystemTime)>>> =
+    LazyLock::new(Default::default);
+/// Session tokens and when they expire
+static SESSIONS: LazyLock<Mutex<HashMap<String, SystemTime>>> =
+    LazyLock::new(Default::default);
+/// Lets whoever holds it register a passkey without signing in
+static BOOTSTRAP_CODE: Mutex<Option<String>> = Mutex::new(None);
+/// Held while the passkey file is read and written back
+static STORE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// The site passkeys are bound to
+struct RelyingParty {
+    /// e.g., "https://blacepos.xyz"
+    origin: String,
+    /// The origin's host, e.g., "blacepos.xyz"
+    id: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Ceremony {
+    Register,
+    SignIn,
+}
+
+impl Ceremony {
+    /// `type` of the client data the browser signs
+    fn client_data_type(self) -> &'static str {
+        match self {
+            Ceremony::Register => "webauthn.create",
+            Ceremony::SignIn => "webauthn.get",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "alg", rename_all = "lowercase")]
+enum PublicKey {
+    /// Uncompressed P-256 point, base64url
+    Es256 { point: String },
+    /// RSA modulus and exponent, base64url
+    Rs256 { n: String, e: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StoredPasskey {
+    /// Credential ID, base64url
+    id: String,
+    key: PublicKey,
+    sign_count: u32,
+    created: SystemTime,
+}
+
+/// Enable passkeys for the given origin, e.g., "https://blacepos.xyz"
+pub fn initialize(origin: &str) {
+    let origin = origin.trim_end_matches('/').to_string();
+    let host = origin.split_once("://").map_or(origin.as_str(), |(_, h)| h);
+    let id = host
+        .split([':', '/'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if id.is_empty() {
+        log::warn!("Passkey origin {origin} has no host. Passkeys disabled.");
+        return;
+    }
+    if RELYING_PARTY.set(RelyingParty { origin, id }).is_err() {
+        log::warn!("Passkey origin was already set");
+    }
+}
+
+pub fn enabled() -> bool {
+    RELYING_PARTY.get().is_some()
+}
+
+fn relying_party() -> Result<&'static RelyingParty, ApiError> {
+    RELYING_PARTY
+        .get()
+        .ok_or_else(|| ApiError::new(ErrorCode::NotFound, "Not found"))
+}
+
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// A one-time link for registering a passkey without signing in, e.g., the
+/// first one. Replaces any earlier link.
+pub fn bootstrap_link() -> Option<String> {
+    let rp = RELYING_PARTY.get()?;
+    let code = random_token();
+    let link =
+        format!("{}/invite/admin/passkeys/register?code={code}", rp.origin);
+    *BOOTSTRAP_CODE.lock().unwrap() = Some(code);
+    Some(link)
+}
+
+/// Whether the request carries a live admin session
+pub fn has_session(headers: &HeaderMap) -> bool {
+    let now = clock::now();
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.retain(|_, expires| *expires > now);
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .any(|(name, value)| {
+            name == SESSION_COOKIE && sessions.contains_key(value)
+        })
+}
+
+fn new_challenge(ceremony: Ceremony) -> String {
+    let now = clock::now();
+    let challenge = random_token();
+    let mut challenges = CHALLENGES.lock().unwrap();
+    challenges.retain(|_, (_, expires)| *expires > now);
+    challenges.insert(challenge.clone(), (ceremony, now + CHALLENGE_LIFETIME));
+    challenge
+}
+
+/// Use up a challenge. Fails if it wasn't issued for this ceremony or has
+/// expired.
+fn take_challenge(challenge: &str, ceremony: Ceremony) -> Result<(), ApiError> {
+    match CHALLENGES.lock().unwrap().remove(challenge) {
+        Some((issued_for, expires))
+            if issued_for == ceremony && expires > clock::now() =>
+        {
+            Ok(())
+        }
+        _ => Err(unauthorized("Unknown or expired challenge")),
+    }
+}
+
+fn unauthorized(message: &str) -> ApiError {
+    ApiError::new(ErrorCode::Unauthorized, message)
+}
+
+fn invalid(message: &str) -> ApiError {
+    ApiError::new(ErrorCode::InvalidRequest, message)
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, ApiError> {
+    URL_SAFE_NO_PAD
+        .decode(data)
+        .map_err(|_| invalid("Malformed base64url"))
+}
+
+/// The registered passkeys. Fails if the file can't be parsed, so it isn't
+/// written over with only the passkeys added since.
+async fn load() -> Result<Vec<StoredPasskey>, ApiError> {
+    let data = match tokio::fs::read(PASSKEYS_PATH).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(e) => {
+            log::error!("Could not read {PASSKEYS_PATH}: {e}");
+            return Err(unreadable());
+        }
+    };
+    serde_json::from_slice(&data).map_err(|e| {
+        log::error!("Could not parse {PASSKEYS_PATH}: {e}");
+        unreadable()
+    })
+}
+
+fn unreadable() -> ApiError {
+    ApiError::new(ErrorCode::Internal, "Could not read the passkeys")
+}
+
+/// Replace the passkey file. Replaced whole, so a crash mid-write leaves the
+/// previous passkeys.
+async fn save(passkeys: &[StoredPasskey]) -> Result<(), ApiError> {
+    let data =
+        serde_json::to_vec_pretty(passkeys).expect("Passkeys are serializable");
+    let write = move || {
+        event_db::replace_file(PASSKEYS_PATH, |file| file.write_all(&data))
+    };
+    let result = tokio::task::spawn_blocking(write)
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+    result.map_err(|e| {
+        log::error!("Could not write {PASSKEYS_PATH}: {e}");
+        ApiError::new(ErrorCode::Internal, "Could not save the passkey")
+    })
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// Check what the browser says it signed, using up its challenge
+fn check_client_data(raw: &[u8], ceremony: Ceremony) -> Result<(), ApiError> {
+    let rp = relying_party()?;
+    let data: ClientData = serde_json::from_slice(raw)
+        .map_err(|_| invalid("Malformed client data"))?;
+    if data.kind != ceremony.client_data_type() {
+        return Err(invalid("Client data is for another ceremony"));
+    }
+    if data.origin != rp.origin {
+        return Err(unauthorized("Passkey was used on another site"));
+    }
+    take_challenge(&data.challenge, ceremony)
+}
+
+/// The fixed start of the authenticator data, and what follows it
+struct AuthenticatorData<'a> {
+    flags: u8,
+    sign_count: u32,
+    rest: &'a [u8],
+}
+
+fn parse_authenticator_data(
+    data: &[u8],
+) -> Result<AuthenticatorData<'_>, ApiError> {
+    let rp = relying_party()?;
+    let (rp_id_hash, rest) = data
+        .split_first_chunk::<32>()
+        .ok_or_else(|| invalid("Authenticator data is truncated"))?;
+    let (flags, rest) = rest
+        .split_first()
+        .ok_or_else(|| invalid("Authenticator data is truncated"))?;
+    let (sign_count, rest) = rest
+        .split_first_chunk::<4>()
+        .ok_or_else(|| invalid("Authenticator data is truncated"))?;
+    if rp_id_hash.as_slice() != digest(&SHA256, rp.id.as_bytes()).as_ref() {
+        return Err(unauthorized("Passkey belongs to another site"));
+    }
+    if flags & USER_PRESENT == 0 {
+        return Err(unauthorized("Authenticator didn't check for a user"));
+    }
+    // a passkey is the only factor, so it has to be unlocked, e.g., by PIN
+    if flags & USER_VERIFIED == 0 {
+        return Err(unauthorized("Authenticator didn't verify the user"));
+    }
+    Ok(AuthenticatorData {
+        flags: *flags,
+        sign_count: u32::from_be_bytes(*sign_count),
+        rest,
+    })
+}
+
+/// The public key of a credential, from its COSE encoding
+fn parse_public_key(cose: &Value) -> Result<PublicKey, ApiError> {
+    let Value::Map(map) = cose else {
+        return Err(invalid("Malformed public key"));
+    };
+    let field = |key: i128| map.get(&Value::Integer(key));
+    let bytes = |key: i128| match field(key) {
+        Some(Value::Bytes(b)) => Ok(URL_SAFE_NO_PAD.encode(b)),
+        _ => Err(invalid("Malformed public key")),
+    };
+    match field(3) {
+        Some(Value::Integer(ES256)) => {
+            let (Some(Value::Bytes(x)), Some(Value::Bytes(y))) =
+                (field(-2), field(-3))
+            else {
+                return Err(invalid("Malformed public key"));
+            };
+            let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+            Ok(PublicKey::Es256 {
+                point: URL_SAFE_NO_PAD.encode(point),
+            })
+        }
+        Some(Value::Integer(RS256)) => Ok(PublicKey::Rs256 {
+            n: bytes(-1)?,
+            e: bytes(-2)?,
+        }),
+        _ => Err(invalid("Only ES256 and RS256 passkeys are supported")),
+    }
+}
+
+fn verify(key: &PublicKey, message: &[u8], sig: &[u8]) -> Result<(), ApiError> {
+    let result = match key {
+        PublicKey::Es256 { point } => UnparsedPublicKey::new(
+            &signature::ECDSA_P256_SHA256_ASN1,
+            decode(point)?,
+        )
+        .verify(message, sig),
+        PublicKey::Rs256 { n, e } => RsaPublicKeyComponents {
+            n: decode(n)?,
+            e: decode(e)?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig),
+    };
+    result.map_err(|_| unauthorized("Signature doesn't match the passkey"))
+}
+
+#[derive(Template)]
+#[template(path = "admin_login.html")]
+struct LoginPage {
+    /// Whether operators can sign in through the OpenID Connect provider
+    oidc: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin_passkey_register.html")]
+struct RegisterPage;
+
+/// `GET /invite/admin/login`
+pub async fn login_page() -> Response {
+    if !enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let page = LoginPage {
+        oidc: oidc::admins_enabled(),
+    };
+    match page.render() {
+        Ok(page) => Html(page).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response(),
+    }
+}
+
+/// `GET /invite/admin/passkeys/register?code=`
+pub async fn register_page() -> Response {
+    if !enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match RegisterPage.render() {
+        Ok(page) => Html(page).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct CeremonyOptions {
+    challenge: String,
+    rp_id: &'static str,
+    /// For registering, the operator's user handle, base64url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+    /// IDs of the registered passkeys, base64url
+    credentials: Vec<String>,
+    user_verification: &'static str,
+}
+
+async fn options(ceremony: Ceremony) -> Result<CeremonyOptions, ApiError> {
+    let rp = relying_party()?;
+    Ok(CeremonyOptions {
+        challenge: new_challenge(ceremony),
+        rp_id: &rp.id,
+        user_id: (ceremony == Ceremony::Register)
+            .then(|| URL_SAFE_NO_PAD.encode(USER_ID)),
+        credentials: load().await?.into_iter().map(|p| p.id).collect(),
+        user_verification: "required",
+    })
+}
+
+#[derive(Deserialize)]
+pub struct Bootstrap {
+    #[serde(default)]
+    code: String,
+}
+
+/// Whether the caller may register a passkey
+fn may_register(headers: &HeaderMap, code: &str) -> bool {
+    has_session(headers)
+        || is_bootstrap_code(&BOOTSTRAP_CODE.lock().unwrap(), code)
+}
+
+fn is_bootstrap_code(bootstrap: &Option<String>, code: &str) -> bool {
+    bootstrap.as_deref().is_some_and(|expected| {
+        !code.is_empty()
+            && constant_time_eq(expected.as_bytes(), code.as_bytes())
+    })
+}
+
+/// Use up the registration link's code if `code` is it, so two registrations
+/// can't both use it
+fn take_bootstrap_code(code: &str) -> Option<String> {
+    let mut bootstrap = BOOTSTRAP_CODE.lock().unwrap();
+    if is_bootstrap_code(&bootstrap, code) {
+        bootstrap.take()
+    } else {
+        None
+    }
+}
+
+/// `POST /invite/admin/passkeys/register/options`
+pub async fn register_options(
+    headers: HeaderMap,
+    Json(body): Json<Bootstrap>,
+) -> Response {
+    if !may_register(&headers, &body.code) {
+        return unauthorized("Sign in or use a registration link first")
+            .into_response();
+    }
+    match options(Ceremony::Register).await {
+        Ok(options) => Json(options).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Registration {
+    #[serde(default)]
+    code: String,
+    /// Credential ID, base64url
+    id: String,
+    /// base64url
+    client_data: String,
+    /// base64url
+    attestation_object: String,
+}
+
+/// `POST /invite/admin/passkeys/register`
+pub async fn register(
+    headers: HeaderMap,
+    Json(body): Json<Registration>,
+) -> Response {
+    // a registration link only works once
+    let code = if has_session(&headers) {
+        None
+    } else if let Some(code) = take_bootstrap_code(&body.code) {
+        Some(code)
+    } else {
+        return unauthorized("Sign in or use a registration link first")
+            .into_response();
+    };
+    match add_passkey(&body).await {
+        Ok(()) => {
+            log::info!("Registered an admin passkey");
+            StatusCode::CREATED.into_response()
+        }
+        Err(e) => {
+            // unless a newer link replaced it meanwhile
+            if let Some(code) = code {
+                BOOTSTRAP_CODE.lock().unwrap().get_or_insert(code);
+            }
+            e.into_response()
+        }
+    }
+}
+
+async fn add_passkey(body: &Registration) -> Result<(), ApiError> {
+    let passkey = read_registration(body)?;
+    let _store = STORE.lock().await;
+    let mut passkeys = load().await?;
+    if passkeys.iter().any(|p| p.id == passkey.id) {
+        return Err(invalid("Passkey is already registered"));
+    }
+    passkeys.push(passkey);
+    save(&passkeys).await
+}
+
+/// The passkey a registration carries, using up its challenge
+fn read_registration(body: &Registration) -> Result<StoredPasskey, ApiError> {
+    check_client_data(&decode(&body.client_data)?, Ceremony::Register)?;
+    let attestation: Value =
+        serde_cbor::from_slice(&decode(&body.attestation_object)?)
+            .map_err(|_| invalid("Malformed attestation object"))?;
+    let auth_data = match &attestation {
+        Value::Map(map) => map.get(&Value::Text("authData".to_string())),
+        _ => None,
+    };
+    let Some(Value::Bytes(auth_data)) = auth_data else {
+        return Err(invalid("Attestation object has no authenticator data"));
+    };
+    let auth_data = parse_authenticator_data(auth_data)?;
+    if auth_data.flags & ATTESTED_CREDENTIAL == 0 {
+        return Err(invalid("Authenticator data has no credential"));
+    }
+
+    // AAGUID, then the length-prefixed credential ID, then its public key
+    let credential = auth_data
+        .rest
+        .get(16..)
+        .and_then(|rest| rest.split_first_chunk::<2>())
+        .and_then(|(len, rest)| {
+            rest.split_at_checked(u16::from_be_bytes(*len) as usize)
+        });
+    let Some((credential_id, cose)) = credential else {
+        return Err(invalid("Authenticator data is truncated"));
+    };
+    if URL_SAFE_NO_PAD.encode(credential_id) != body.id {
+        return Err(invalid("Credential ID doesn't match"));
+    }
+    let mut cose = serde_cbor::Deserializer::from_slice(cose);
+    let cose = Value::deserialize(&mut cose)
+        .map_err(|_| invalid("Malformed public key"))?;
+
+    Ok(StoredPasskey {
+        id: body.id.clone(),
+        key: parse_public_key(&cose)?,
+        sign_count: auth_data.sign_count,
+        created: clock::now(),
+    })
+}
+
+/// `POST /invite/admin/passkeys/login/options`
+pub async fn login_options() -> Response {
+    match options(Ceremony::SignIn).await {
+        Ok(options) => Json(options).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Assertion {
+    /// Credential ID, base64url
+    id: String,
+    /// base64url
+    client_data: String,
+    /// base64url
+    authenticator_data: String,
+    /// base64url
+    signature: String,
+}
+
+/// `POST /invite/admin/passkeys/login`
+///
+/// Starts a session if the passkey checks out
+pub async fn login(Json(body): Json<Assertion>) -> Response {
+    if let Err(e) = check_assertion(&body).await {
+        log::warn!("Failed admin passkey sign-in: {}", e.message);
+        return e.into_response();
+    }
+    (
+        [(header::SET_COOKIE, start_session())],
+        StatusCode::NO_CONTENT,
+    )
+        .into_response()
+}
+
+/// Start an admin session. Gives the cookie to set for it.
+pub fn start_session() -> String {
+    let token = random_token();
+    SESSIONS
+        .lock()
+        .unwrap()
+        .insert(token.clone(), clock::now() + SESSION_LIFETIME);
+    format!(
+        "{SESSION_COOKIE}={token}; Path=/invite/admin; Max-Age={}; HttpOnly; \
+         Secure; SameSite=Strict",
+        SESSION_LIFETIME.as_secs()
+    )
+}
+
+async fn check_assertion(body: &Assertion) -> Result<(), ApiError> {
+    let _store = STORE.lock().await;
+    let mut passkeys = load().await?;
+    let Some(passkey) = passkeys.iter_mut().find(|p| p.id == body.id) else {
+        return Err(unauthorized("Unknown passkey"));
+    };
+    let sign_count = verify_assertion(passkey, body)?;
+    if sign_count != passkey.sign_count {
+        passkey.sign_count = sign_count;
+        save(&passkeys).await?;
+    }
+    Ok(())
+}
+
+/// Check an assertion made with `passkey`, using up its challenge. Gives the
+/// passkey's signature count after it.
+fn verify_assertion(
+    passkey: &StoredPasskey,
+    body: &Assertion,
+) -> Result<u32, ApiError> {
+    let client_data = decode(&body.client_data)?;
+    check_client_data(&client_data, Ceremony::SignIn)?;
+    let raw_auth_data = decode(&body.authenticator_data)?;
+    let auth_data = parse_authenticator_data(&raw_auth_data)?;
+    let message = [
+        raw_auth_data.as_slice(),
+        digest(&SHA256, &client_data).as_ref(),
+    ]
+    .concat();
+    verify(&passkey.key, &message, &decode(&body.signature)?)?;
+
+    // authenticators which count signatures never go backwards, unless the
+    // passkey was copied
+    let counted = auth_data.sign_count != 0 || passkey.sign_count != 0;
+    if counted && auth_data.sign_count <= passkey.sign_count {
+        return Err(unauthorized("Passkey may have been cloned"));
+    }
+    Ok(auth_data.sign_count)
+}
+
+/// Where browsers without a session are sent to sign in
+pub fn login_redirect() -> Response {
+    Redirect::to("/invite/admin/login").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    use super::*;
+
+    const ORIGIN: &str = "https://example.org";
+    /// Flags of an authenticator which checked for and verified the user
+    const VERIFIED: u8 = USER_PRESENT | USER_VERIFIED;
+
+    /// An RSA-2048 key and its SHA-256 PKCS #1 signature of `RSA_MESSAGE`,
+    /// made with openssl
+    const RSA_N: &str = "tOZ6IjDcXH1SdXh7e-bkH6BtS9MefSj3lfuCsfZTYnoveas0-aI2\
+        Gzu4LZYhYtF0MqbhupL-p3c4Kc84XNVzmGXSBN6Eh-mB_WfZFWKFry72MxFpZDpUM8fe9Z\
+        ZxpXM58ef5irTdhtF2JXMWSM7zqI3M1tlC4rmOVZCg7kqspjeNbZW0XzCa7LPN4j-Hd-vs\
+        wzNCYYVUcq-857RlXgaHV1gn1w69xZmj5dDcxF8t0IaVo5MDTXsmsFQy8mfq3IOxHnd9ei\
+        jCyDd_5-VHROPjV2UH19P9rlCU93Ci4Kw49wpte5AXH8y8rWEIPVEyUIv-QS0ZDYBPTD8C\
+        sEhmllyzKw";
+    const RSA_E: &str = "AQAB";
+    const RSA_MESSAGE: &[u8] = b"passkey test vector";
+    const RSA_SIGNATURE: &str = "FKkPUDmrOU6FMoBYEZUPbEPDovuk52f9xK-4yW3bIVe\
+        qmG8y6daUdnF8c45pI0g3P8p_jWj60nDfbKtMf8QNFhVtWSGimNuE4gMJ3I9cCwUzn3enB\
+        h8JHuaMhBqbNnRkv2yRzOlcIh5evj16bz6lYME_ggIukBLhxqA3WYnkIPK8wjkk_e9VjyU\
+        3DoMs98oe2i26p1VLL3docw-TEFKJV824loZHpPp0gfSfgTFbguqKW0FBMmthurN66Yf6x\
+        2Xs7p34RxYhCRmdGVs6uL5MuWGlZvleETdFJw2b8FTCGpjwJrj0nY7XQ22jueShuIfdmjX\
+        Xu2dfC7tQE_zbWqy6GA";
+
+    fn setup() {
+        if !enabled() {
+            initialize(ORIGIN);
+        }
+    }
+
+    fn auth_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+        [
+            digest(&SHA256, rp_id.as_bytes()).as_ref(),
+            &[flags],
+            &sign_count.to_be_bytes(),
+        ]
+        .concat()
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    fn cose_key(entries: Vec<(i128, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (Value::Integer(k), v))
+                .collect(),
+        )
+    }
+
+    /// A P-256 key pair and its COSE public key
+    fn es256_key() -> (EcdsaKeyPair, Value) {
+        let rng = SystemRandom::new();
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+                .unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            pkcs8.as_ref(),
+            &rng,
+        )
+        .unwrap();
+        let point = pair.public_key().as_ref();
+        let cose = cose_key(vec![
+            (1, Value::Integer(2)),
+            (3, Value::Integer(ES256)),
+            (-1, Value::Integer(1)),
+            (-2, Value::Bytes(point[1..33].to_vec())),
+            (-3, Value::Bytes(point[33..].to_vec())),
+        ]);
+        (pair, cose)
+    }
+
+    fn sign(pair: &EcdsaKeyPair, message: &[u8]) -> Vec<u8> {
+        pair.sign(&SystemRandom::new(), message)
+            .unwrap()
+            .as_ref()
+            .to_vec()
+    }
+
+    #[test]
+    fn authenticator_data() {
+        setup();
+        let mut data = auth_data("example.org", VERIFIED, 7);
+        data.extend_from_slice(b"extensions");
+        let parsed = parse_authenticator_data(&data).unwrap();
+        assert_eq!(parsed.flags, VERIFIED);
+        assert_eq!(parsed.sign_count, 7);
+        assert_eq!(parsed.rest, b"extensions");
+
+        let data = auth_data("example.org", VERIFIED, 7);
+        for len in [0, 31, 32, 36] {
+            let err = parse_authenticator_data(&data[..len]).err().unwrap();
+            assert_eq!(err.message, "Authenticator data is truncated");
+        }
+        let elsewhere = auth_data("example.com", VERIFIED, 7);
+        let err = parse_authenticator_data(&elsewhere).err().unwrap();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+        for flags in [0, USER_PRESENT, USER_VERIFIED] {
+            let data = auth_data("example.org", flags, 7);
+            let err = parse_authenticator_data(&data).err().unwrap();
+            assert_eq!(err.code, ErrorCode::Unauthorized);
+        }
+    }
+
+    #[test]
+    fn public_keys() {
+        let (pair, cose) = es256_key();
+        let PublicKey::Es256 { point } = parse_public_key(&cose).unwrap()
+        else {
+            panic!("Expected an ES256 key");
+        };
+        assert_eq!(decode(&point).unwrap(), pair.public_key().as_ref());
+
+        let rsa = cose_key(vec![
+            (3, Value::Integer(RS256)),
+            (-1, Value::Bytes(decode(RSA_N).unwrap())),
+            (-2, Value::Bytes(decode(RSA_E).unwrap())),
+        ]);
+        let PublicKey::Rs256 { n, e } = parse_public_key(&rsa).unwrap() else {
+            panic!("Expected an RS256 key");
+        };
+        assert_eq!((n.as_str(), e.as_str()), (RSA_N, RSA_E));
+
+        let eddsa = cose_key(vec![(3, Value::Integer(-8))]);
+        let no_y = cose_key(vec![
+            (3, Value::Integer(ES256)),
+            (-2, Value::Bytes(vec![0; 32])),
+        ]);
+        let no_e = cose_key(vec![
+            (3, Value::Integer(RS256)),
+            (-1, Value::Bytes(decode(RSA_N).unwrap())),
+        ]);
+        let not_map = Value::Array(vec![Value::Integer(ES256)]);
+        for key in [eddsa, no_y, no_e, not_map] {
+            let err = parse_public_key(&key).err().unwrap();
+            assert_eq!(err.code, ErrorCode::InvalidRequest);
+        }
+    }
+
+    #[test]
+    fn es256_signatures() {
+        let (pair, cose) = es256_key();
+        let key = parse_public_key(&cose).unwrap();
+        let sig = sign(&pair, b"signed");
+        assert!(verify(&key, b"signed", &sig).is_ok());
+        assert!(verify(&key, b"tampered", &sig).is_err());
+        assert!(verify(&key, b"signed", &sig[1..]).is_err());
+    }
+
+    #[test]
+    fn rs256_signatures() {
+        let key = PublicKey::Rs256 {
+            n: RSA_N.to_string(),
+            e: RSA_E.to_string(),
+        };
+        let sig = decode(RSA_SIGNATURE).unwrap();
+        assert!(verify(&key, RSA_MESSAGE, &sig).is_ok());
+        assert!(verify(&key, b"passkey test vectors", &sig).is_err());
+    }
+
+    #[test]
+    fn client_data_checks() {
+        setup();
+        let challenge = new_challenge(Ceremony::SignIn);
+        let wrong_type = client_data("webauthn.create", &challenge, ORIGIN);
+        let err = check_client_data(&wrong_type, Ceremony::SignIn)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::InvalidRequest);
+        let elsewhere =
+            client_data("webauthn.get", &challenge, "https://example.com");
+        let err = check_client_data(&elsewhere, Ceremony::SignIn)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+        assert!(check_client_data(b"{", Ceremony::SignIn).is_err());
+
+        let valid = client_data("webauthn.get", &challenge, ORIGIN);
+        assert!(check_client_data(&valid, Ceremony::SignIn).is_ok());
+        // challenges work once, and only for the ceremony they were made for
+        assert!(check_client_data(&valid, Ceremony::SignIn).is_err());
+        let challenge = new_challenge(Ceremony::Register);
+        let valid = client_data("webauthn.get", &challenge, ORIGIN);
+        assert!(check_client_data(&valid, Ceremony::SignIn).is_err());
+    }
+
+    /// A registration of a fresh ES256 passkey, and its key pair
+    fn registration(credential_id: &[u8]) -> (Registration, EcdsaKeyPair) {
+        let (pair, cose) = es256_key();
+        let mut data =
+            auth_data("example.org", VERIFIED | ATTESTED_CREDENTIAL, 3);
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+        data.extend_from_slice(credential_id);
+        data.extend_from_slice(&serde_cbor::to_vec(&cose).unwrap());
+        let attestation = Value::Map(BTreeMap::from([
+            (Value::Text("fmt".into()), Value::Text("none".into())),
+            (Value::Text("attStmt".into()), Value::Map(BTreeMap::new())),
+            (Value::Text("authData".into()), Value::Bytes(data)),
+        ]));
+        let challenge = new_challenge(Ceremony::Register);
+        let registration = Registration {
+            code: String::new(),
+            id: URL_SAFE_NO_PAD.encode(credential_id),
+            client_data: URL_SAFE_NO_PAD.encode(client_data(
+                "webauthn.create",
+                &challenge,
+                ORIGIN,
+            )),
+            attestation_object: URL_SAFE_NO_PAD
+                .encode(serde_cbor::to_vec(&attestation).unwrap()),
+        };
+        (registration, pair)
+    }
+
+    #[test]
+    fn registrations() {
+        setup();
+        let (body, pair) = registration(b"credential");
+        let passkey = read_registration(&body).unwrap();
+        assert_eq!(passkey.id, body.id);
+        assert_eq!(passkey.sign_count, 3);
+        let PublicKey::Es256 { point } = passkey.key else {
+            panic!("Expected an ES256 key");
+        };
+        assert_eq!(decode(&point).unwrap(), pair.public_key().as_ref());
+        // the challenge is used up
+        assert!(read_registration(&body).is_err());
+
+        let (mut body, _) = registration(b"credential");
+        body.id = URL_SAFE_NO_PAD.encode(b"another");
+        let err = read_registration(&body).err().unwrap();
+        assert_eq!(err.message, "Credential ID doesn't match");
+
+        let (mut body, _) = registration(b"credential");
+        body.attestation_object = URL_SAFE_NO_PAD
+            .encode(serde_cbor::to_vec(&Value::Map(BTreeMap::new())).unwrap());
+        let err = read_registration(&body).err().unwrap();
+        assert_eq!(err.message, "Attestation object has no authenticator data");
+    }
+
+    /// An assertion by `pair` with the given signature count
+    fn assertion(pair: &EcdsaKeyPair, sign_count: u32) -> Assertion {
+        let challenge = new_challenge(Ceremony::SignIn);
+        let client_data = client_data("webauthn.get", &challenge, ORIGIN);
+        let auth_data = auth_data("example.org", VERIFIED, sign_count);
+        let message =
+            [auth_data.as_slice(), digest(&SHA256, &client_data).as_ref()]
+                .concat();
+        Assertion {
+            id: "credential".to_string(),
+            client_data: URL_SAFE_NO_PAD.encode(&client_data),
+            authenticator_data: URL_SAFE_NO_PAD.encode(&auth_data),
+            signature: URL_SAFE_NO_PAD.encode(sign(pair, &message)),
+        }
+    }
+
+    #[test]
+    fn assertions() {
+        setup();
+        let (pair, cose) = es256_key();
+        let mut passkey = StoredPasskey {
+            id: "credential".to_string(),
+            key: parse_public_key(&cose).unwrap(),
+            sign_count: 4,
+            created: SystemTime::UNIX_EPOCH,
+        };
+        let sign_count = verify_assertion(&passkey, &assertion(&pair, 5));
+        assert_eq!(sign_count.unwrap(), 5);
+        for behind in [4, 3, 0] {
+            let err = verify_assertion(&passkey, &assertion(&pair, behind))
+                .err()
+                .unwrap();
+            assert_eq!(err.message, "Passkey may have been cloned");
+        }
+
+        // authenticators which don't count always say zero
+        passkey.sign_count = 0;
+        let sign_count = verify_assertion(&passkey, &assertion(&pair, 0));
+        assert_eq!(sign_count.unwrap(), 0);
+
+        let (other, _) = es256_key();
+        let err = verify_assertion(&passkey, &assertion(&other, 1))
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "Signature doesn't match the passkey");
+    }
+
+    #[test]
+    fn bootstrap_code() {
+        let headers = HeaderMap::new();
+        *BOOTSTRAP_CODE.lock().unwrap() = None;
+        assert!(!may_register(&headers, ""));
+        assert!(!may_register(&headers, "code"));
+        *BOOTSTRAP_CODE.lock().unwrap() = Some("code".to_string());
+        assert!(may_register(&headers, "code"));
+        assert!(!may_register(&headers, "cod"));
+        assert!(!may_register(&headers, "codes"));
+        assert!(!may_register(&headers, ""));
+
+        assert_eq!(take_bootstrap_code("cod"), None);
+        assert_eq!(take_bootstrap_code("code").as_deref(), Some("code"));
+        assert_eq!(take_bootstrap_code("code"), None);
+        assert!(!may_register(&headers, "code"));
+    }
+}
@@ -0,0 +1,322 @@
+//! Plain-text versions of rendered pages
+//!
+//! Invitations are written in HTML, but mail is best sent with a text/plain
+//! alternative for clients which don't show HTML. The conversion keeps what a
+//! reader needs: text is laid out by block, list items are bulleted, and links
+//! are kept by writing their address after their text. Forms can't be
+//! submitted from plain text, so each button becomes a link to where its form
+//! would have been sent. Scripts, styles, and form fields are dropped.
+
+/// Elements which are set apart from the text around them by a blank line
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "main",
+    "aside",
+    "nav",
+    "blockquote",
+    "ul",
+    "ol",
+    "table",
+    "form",
+    "fieldset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+/// Elements whose content is never shown as text
+const HIDDEN_TAGS: &[&str] =
+    &["head", "script", "style", "template", "textarea"];
+
+/// A readable plain-text version of an HTML page. Relative links are resolved
+/// against `base`, e.g., "https://example.com".
+pub fn from_html(html: &str, base: &str) -> String {
+    let mut out = Converter {
+        base,
+        text: String::new(),
+        hidden: None,
+        link: None,
+        form_action: None,
+        button: None,
+    };
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_text(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        out.tag(&rest[1..end]);
+        rest = &rest[end + 1..];
+    }
+    out.push_text(rest);
+    out.finish()
+}
+
+struct Converter<'a> {
+    base: &'a str,
+    text: String,
+    /// The hidden element being skipped
+    hidden: Option<String>,
+    /// Address of the link being written
+    link: Option<String>,
+    /// Where the form being written is sent
+    form_action: Option<String>,
+    /// Label of the button being written
+    button: Option<String>,
+}
+
+impl Converter<'_> {
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        if let Some(hidden) = &self.hidden {
+            if closing && *hidden == name {
+                self.hidden = None;
+            }
+            return;
+        }
+        if !closing && HIDDEN_TAGS.contains(&name.as_str()) {
+            self.hidden = Some(name);
+            return;
+        }
+
+        match (name.as_str(), closing) {
+            ("br", _) => self.text.push('\n'),
+            ("hr", _) => {
+                self.new_line(true);
+                self.text += "----";
+                self.new_line(true);
+            }
+            ("li", false) => {
+                self.new_line(false);
+                self.text += "- ";
+            }
+            ("li" | "tr", true) => self.new_line(false),
+            ("td" | "th", false) => self.text.push(' '),
+            ("a", false) => {
+                self.link = attribute(tag, "href").map(|href| self.url(&href));
+            }
+            ("a", true) => {
+                if let Some(link) = self.link.take() {
+                    // links written out as their address need no repeating
+                    if !self.text.trim_end().ends_with(&link) {
+                        self.text += &format!(" ({link})");
+                    }
+                }
+            }
+            ("form", false) => {
+                self.new_line(true);
+                self.form_action =
+                    attribute(tag, "action").map(|action| self.url(&action));
+            }
+            ("form", true) => {
+                self.form_action = None;
+                self.new_line(true);
+            }
+            ("button", false) => self.button = Some(String::new()),
+            ("button", true) => {
+                let label = self.button.take().unwrap_or_default();
+                let label = label.trim();
+                if let Some(action) = self.form_action.clone() {
+                    self.new_line(false);
+                    if !label.is_empty() {
+                        self.text += &format!("{label}: ");
+                    }
+                    self.text += &action;
+                    self.new_line(false);
+                }
+            }
+            (name, _) if BLOCK_TAGS.contains(&name) => self.new_line(true),
+            _ => {}
+        }
+    }
+
+    fn push_text(&mut self, raw: &str) {
+        if self.hidden.is_some() {
+            return;
+        }
+        let decoded = decode_entities(raw);
+        if let Some(button) = &mut self.button {
+            *button += &decoded;
+            return;
+        }
+        // within a form, only the buttons mean anything without the fields
+        if self.form_action.is_some() {
+            return;
+        }
+        for c in decoded.chars() {
+            if !c.is_whitespace() {
+                self.text.push(c);
+            } else if !self.text.is_empty() && !self.text.ends_with([' ', '\n'])
+            {
+                self.text.push(' ');
+            }
+        }
+    }
+
+    /// End the line being written, if any, and leave a blank line after it
+    /// if `blank`
+    fn new_line(&mut self, blank: bool) {
+        let trimmed = self.text.trim_end_matches(' ').len();
+        self.text.truncate(trimmed);
+        if self.text.is_empty() {
+            return;
+        }
+        if !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+        if blank {
+            self.text.push('\n');
+        }
+    }
+
+    fn url(&self, href: &str) -> String {
+        if href.starts_with('/') && !href.starts_with("//") {
+            format!("{}{href}", self.base.trim_end_matches('/'))
+        } else {
+            href.to_string()
+        }
+    }
+
+    /// The text with trailing spaces removed and no more than one blank line
+    /// in a row
+    fn finish(self) -> String {
+        let mut out = String::new();
+        let mut blank = true;
+        for line in self.text.lines().map(str::trim_end) {
+            if line.trim().is_empty() {
+                if !blank {
+                    out.push('\n');
+                }
+                blank = true;
+                continue;
+            }
+            out += line.trim_start();
+            out.push('\n');
+            blank = false;
+        }
+        out.trim_end().to_string()
+    }
+}
+
+/// The value of an attribute within the inside of a tag, entities decoded
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(at) = lower[from..].find(name) {
+        let at = from + at;
+        from = at + name.len();
+        let preceded = lower[..at].ends_with(char::is_whitespace);
+        let rest = lower[from..].trim_start();
+        if !preceded || !rest.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &value[1..];
+                &value[..value.find(quote).unwrap_or(value.len())]
+            }
+            _ => value.split_whitespace().next().unwrap_or_default(),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out += &rest[..start];
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let decoded = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, decoded) {
+            (Some(entity), Some(c)) => {
+                out.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://example.com";
+
+    #[test]
+    fn lays_out_blocks_and_links() {
+        let html = "<html><head><title>Title</title><style>p {}</style>\
+                    </head><body><h1>Party</h1><p>Come &amp; see \
+                    <a href=\"/invite/x\">the page</a>.</p><ul><li>One</li>\
+                    <li>Two</li></ul></body></html>";
+        assert_eq!(
+            from_html(html, BASE),
+            "Party\n\nCome & see the page (https://example.com/invite/x).\n\n\
+             - One\n- Two"
+        );
+    }
+
+    #[test]
+    fn turns_buttons_into_links() {
+        let html = "<form method=\"post\" action=\"/invite/accept/abc\">\
+                    <label>Note <input name=\"note\"></label>\
+                    <button type=\"submit\">Accept</button></form>";
+        assert_eq!(
+            from_html(html, BASE),
+            "Accept: https://example.com/invite/accept/abc"
+        );
+    }
+
+    #[test]
+    fn decodes_entities() {
+        let text = "caf&#233; &#x41; &bogus; &lt;3";
+        assert_eq!(from_html(text, BASE), "café A &bogus; <3");
+        let link = "<a href=\"https://example.org\">https://example.org</a>";
+        assert_eq!(from_html(link, BASE), "https://example.org");
+    }
+}
@@ -0,0 +1,56 @@
+//! In-memory rate limiting
+//!
+//! Limits are kept per key in a sliding window and are lost on restart, which
+//! is fine since they only exist to stop loops and scripts from hammering the
+//! database.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{LazyLock, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{clock, ids::Id};
+
+/// RSVP changes per attendee
+pub static RSVP: LazyLock<RateLimiter<Id>> =
+    LazyLock::new(|| RateLimiter::new(6, Duration::from_mins(10)));
+
+/// Guests joining per open RSVP link
+pub static OPEN_RSVP: LazyLock<RateLimiter<Id>> =
+    LazyLock::new(|| RateLimiter::new(20, Duration::from_mins(10)));
+
+pub struct RateLimiter<K> {
+    max: usize,
+    window: Duration,
+    hits: Mutex<HashMap<K, Vec<SystemTime>>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a hit for `key`. Returns false if the key is over its limit, in
+    /// which case the hit isn't counted.
+    pub fn check(&self, key: K) -> bool {
+        let now = clock::now();
+        let mut hits = self.hits.lock().unwrap_or_else(|e| e.into_inner());
+        // forget keys which have gone quiet so the map doesn't grow forever
+        hits.retain(|_, times| {
+            times.retain(|&at| at + self.window > now);
+            !times.is_empty()
+        });
+        let times = hits.entry(key).or_default();
+        if times.len() >= self.max {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
@@ -0,0 +1,3992 @@
+//! HTTP routes of the invite module
+//!
+//! `invite_router` builds the complete module, so it can be served on its own
+//! (as the `bxyz-invite` binary does) or merged into a parent server alongside
+//! other modules. Everything under `/invite` belongs to this module.
+
+use std::{
+    collections::HashMap, convert::Infallible, future::Future, path::PathBuf,
+    sync::Arc, time::Duration,
+};
+
+use askama::Template;
+use axum::{
+    extract::{DefaultBodyLimit, Form, Json, Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{
+        sse::{self, KeepAlive, Sse},
+        Html, IntoResponse, Redirect, Response,
+    },
+    routing::{get, patch, post, put},
+    Router,
+};
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    request_id::{
+        MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer,
+    },
+    trace::TraceLayer,
+};
+
+use crate::{
+    admin, api,
+    api_error::{self, ApiError, ErrorCode},
+    automation,
+    bot_filter::{self, Verdict},
+    certificate, clock, contacts, content, cover, error_report,
+    event_db::{
+        self, CarpoolChange, CarpoolError, CarpoolRole, CreateEventError,
+        Decline, FindEventError, Rsvp, RsvpError, UpdateEventError,
+    },
+    health,
+    i18n::{Language, Phrases},
+    ical, idempotency, ids, inbound, mail, merge, network, notify, organizer,
+    oidc, passkeys, plain_text, rate_limit,
+    rsvp_form::{self, RsvpForm},
+    sanitize, settings, structured_data, template_lint,
+    templates::{
+        self, live::LivePage, AttendeePatch, EventPatch, ManagePageJson,
+        NewAttendee,
+    },
+    weather,
+};
+
+const DEFAULT_CONTENT_DIR: &str = "content";
+/// Answer to changes of a frozen event
+const FROZEN_MESSAGE: &str = "This event is archived and can't be changed";
+
+/// Configuration of the invite module, as the command line options give it
+/// when it runs on its own
+#[derive(Clone, Debug)]
+pub struct AppState {
+    /// Static files served under `/invite/content`
+    pub content_dir: PathBuf,
+    /// Guards the admin routes, see `admin`. Without it or passkeys they're
+    /// disabled.
+    pub admin_token: Option<String>,
+    /// Guards the inbound RSVP endpoint, see `inbound`. Without it the
+    /// endpoint is disabled.
+    pub inbound_token: Option<String>,
+    /// Who may reach the admin routes, and which proxies are believed
+    pub network: Arc<network::Config>,
+    /// Webhook or Apprise URL notified of changes to every event
+    pub notify_webhook: Option<String>,
+    pub storage: event_db::Storage,
+    /// Settings to change at startup, by name, see `settings`
+    pub settings: Vec<(String, String)>,
+    /// Banner to show at startup. Empty removes it, absent keeps the saved
+    /// one.
+    pub banner: Option<String>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            content_dir: PathBuf::from(DEFAULT_CONTENT_DIR),
+            admin_token: None,
+            inbound_token: None,
+            network: Arc::default(),
+            notify_webhook: None,
+            storage: event_db::Storage::default(),
+            settings: Vec::new(),
+            banner: None,
+        }
+    }
+}
+
+/// Build the invite module's router. Configuration which background work
+/// needs too, like the storage and the settings, takes effect here, so build
+/// it before opening the database. Background work (digests, purges) and the
+/// remaining integrations (mail, CalDAV, link shortening) are set up
+/// separately by whoever serves it.
+pub fn invite_router(state: AppState) -> Router {
+    cover::set_uploads_dir(state.content_dir.join("uploads"));
+    event_db::set_storage(state.storage);
+    if let Some(webhook) = state.notify_webhook.clone() {
+        notify::initialize(webhook);
+    }
+    settings::initialize(&state.settings, state.banner.as_deref());
+    let admin_token = nonempty(state.admin_token.clone(), "Admin routes");
+    let inbound_token =
+        nonempty(state.inbound_token.clone(), "Inbound RSVPs");
+    let network = state.network.clone();
+    let admin_routes = Router::new()
+        .route("/invite/admin", get(admin::dashboard))
+        .route("/invite/admin/metrics", get(admin::prometheus))
+        .route("/invite/admin/api/events", get(admin::list_events))
+        .route("/invite/api/v1/events", get(admin::list_events))
+        .route(
+            "/invite/admin/api/events/{ev_id}/pin",
+            post(admin::pin_event).delete(admin::unpin_event),
+        )
+        .route(
+            "/invite/admin/api/events/{ev_id}/freeze",
+            post(admin::freeze_event).delete(admin::unfreeze_event),
+        )
+        .route("/invite/admin/banner", post(admin::set_banner))
+        .route("/invite/admin/settings", post(admin::set_settings))
+        .route(
+            "/invite/admin/api/settings",
+            get(admin::get_settings).patch(admin::patch_settings),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            admin_token,
+            admin::require_admin,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            network.clone(),
+            network::restrict_admin,
+        ));
+    let passkey_routes = Router::new()
+        .route("/invite/admin/login", get(passkeys::login_page))
+        .route(
+            "/invite/admin/passkeys/login/options",
+            post(passkeys::login_options),
+        )
+        .route("/invite/admin/passkeys/login", post(passkeys::login))
+        .route(
+            "/invite/admin/passkeys/register",
+            get(passkeys::register_page).post(passkeys::register),
+        )
+        .route(
+            "/invite/admin/passkeys/register/options",
+            post(passkeys::register_options),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            network,
+            network::restrict_admin,
+        ));
+    let inbound_routes = Router::new()
+        .route("/invite/inbound/rsvp", post(inbound::post_rsvp))
+        .route_layer(middleware::from_fn_with_state(
+            inbound_token,
+            inbound::require_token,
+        ));
+
+    // changes which a flaky connection might submit twice
+    let idempotent_routes = Router::new()
+        .route("/invite/update/{ev_id}", post(update_event))
+        .route("/invite/add/{ev_id}", post(add_attendee))
+        .route("/invite/clone/{ev_id}", post(clone_event))
+        .route("/invite/remove/{at_id}", post(remove_attendee))
+        .route("/invite/rotate/{at_id}", post(rotate_attendee))
+        .route("/invite/api/events/{ev_id}", patch(patch_event))
+        .route("/invite/rename/{ev_id}", post(rename_event))
+        .route("/invite/attendee/{at_id}", post(update_attendee))
+        .route("/invite/reorder/{ev_id}", post(reorder_attendees))
+        .route("/invite/api/v1/events/{ev_id}", patch(api::patch_event))
+        .route(
+            "/invite/api/v1/events/{ev_id}/attendees",
+            post(api::add_attendee),
+        )
+        .route_layer(middleware::from_fn(idempotency::idempotent));
+    let open_idempotent_routes = Router::new()
+        .route("/invite/api/v1/events", post(api::create_event))
+        .route("/invite/api/v1/attendees/{at_id}/rsvp", put(api::set_rsvp))
+        .route_layer(middleware::from_fn(idempotency::idempotent));
+    // the organizer's pages and changes, see `organizer`
+    let organizer_routes = Router::new()
+        .route("/invite/manage/{ev_id}", get(manage_event))
+        .route("/invite/conflict/{ev_id}", post(show_conflict))
+        .route("/invite/resolve/{ev_id}", post(resolve_conflict))
+        .route("/invite/extend/{ev_id}", post(extend_event))
+        .route("/invite/nudge/{ev_id}", post(nudge_event))
+        .route("/invite/pin/{ev_id}", post(pin_event))
+        .route("/invite/unpin/{ev_id}", post(unpin_event))
+        .route("/invite/lock/{ev_id}", post(lock_responses))
+        .route("/invite/unlock/{ev_id}", post(unlock_responses))
+        .route("/invite/freeze/{ev_id}", post(freeze_event))
+        .route("/invite/announce/{ev_id}", post(post_announcement))
+        .route(
+            "/invite/cover/{ev_id}",
+            post(upload_cover)
+                .layer(DefaultBodyLimit::max(cover::MAX_UPLOAD_BYTES)),
+        )
+        .route("/invite/uncover/{ev_id}", post(remove_cover))
+        .route("/invite/slots/{ev_id}", post(add_time_slots))
+        .route("/invite/unslot/{ev_id}/{number}", post(remove_time_slot))
+        .route("/invite/schedule-send/{ev_id}", post(schedule_send))
+        .route("/invite/cancel-send/{ev_id}/{number}", post(cancel_send))
+        .route("/invite/merge/{ev_id}", post(merge_attendees))
+        .route("/invite/weather/{ev_id}", post(set_weather_location))
+        .route("/invite/when-where/{ev_id}", post(set_when_where))
+        .route("/invite/add-expense/{ev_id}", post(add_expense))
+        .route(
+            "/invite/remove-expense/{ev_id}/{number}",
+            post(remove_expense),
+        )
+        .route("/invite/share-expenses/{ev_id}", post(share_expenses))
+        .route("/invite/unshare-expenses/{ev_id}", post(unshare_expenses))
+        .route("/invite/add-agenda-item/{ev_id}", post(add_agenda_item))
+        .route(
+            "/invite/remove-agenda-item/{ev_id}/{number}",
+            post(remove_agenda_item),
+        )
+        .route("/invite/add-field/{ev_id}", post(add_custom_field))
+        .route(
+            "/invite/remove-field/{ev_id}/{number}",
+            post(remove_custom_field),
+        )
+        .route("/invite/open-rsvp/{ev_id}", post(open_rsvp_link))
+        .route("/invite/close-rsvp/{ev_id}", post(close_rsvp_link))
+        .route("/invite/preview/{ev_id}", post(check_template))
+        .route("/invite/preview/{ev_id}/{at_id}", get(preview_invitation))
+        .route("/invite/save-draft/{ev_id}/{at_id}", post(save_draft))
+        .route("/invite/promote-draft/{ev_id}/{at_id}", post(promote_draft))
+        .route("/invite/discard-draft/{ev_id}/{at_id}", post(discard_draft))
+        .route("/invite/check-in/{ev_id}/{at_id}", post(check_in))
+        .route("/invite/undo-check-in/{ev_id}/{at_id}", post(undo_check_in))
+        .route("/invite/open-kiosk/{ev_id}", post(open_kiosk))
+        .route("/invite/close-kiosk/{ev_id}", post(close_kiosk))
+        .route("/invite/new-api-key/{ev_id}", post(new_api_key))
+        .route("/invite/revoke-api-key/{ev_id}", post(revoke_api_key))
+        .route("/invite/new-contact-book/{ev_id}", post(new_contact_book))
+        .route("/invite/use-contact-book/{ev_id}", post(use_contact_book))
+        .route(
+            "/invite/unlink-contact-book/{ev_id}",
+            post(unlink_contact_book),
+        )
+        .route("/invite/add-from-contacts/{ev_id}", post(add_from_contacts))
+        .route("/invite/save-to-contacts/{ev_id}", post(save_to_contacts))
+        .route("/invite/api/v1/events/{ev_id}", get(api::get_event))
+        .route(
+            "/invite/api/v1/events/{ev_id}/attendees/{at_id}",
+            get(api::get_attendee).delete(api::remove_attendee),
+        )
+        .route("/invite/updates/{ev_id}", get(manage_updates))
+        .route("/invite/open-carpool/{ev_id}", post(open_carpool))
+        .route("/invite/close-carpool/{ev_id}", post(close_carpool))
+        .route(
+            "/invite/unannounce/{ev_id}/{number}",
+            post(remove_announcement),
+        )
+        .merge(idempotent_routes)
+        .route_layer(middleware::from_fn(organizer::require_organizer));
+
+    Router::new()
+        .route("/invite/index", get(index_page))
+        .route("/invite/ready", get(health::ready))
+        .nest_service("/invite/content", content::service(&state.content_dir))
+        .layer(TraceLayer::new_for_http())
+        // invite module specific routes
+        .route("/invite/organize", get(create_new_event))
+        .route("/invite/login", get(oidc::login))
+        .route("/invite/oidc/callback", get(oidc::callback))
+        .route("/invite/contacts/{book_id}", get(contacts::view_book))
+        .route("/invite/add-contact/{book_id}", post(contacts::add_contact))
+        .route(
+            "/invite/edit-contact/{book_id}/{number}",
+            post(contacts::edit_contact),
+        )
+        .route(
+            "/invite/remove-contact/{book_id}/{number}",
+            post(contacts::remove_contact),
+        )
+        .route("/invite/automation/me", get(automation::me))
+        .route("/invite/automation/rsvps", get(automation::recent_rsvps))
+        .route(
+            "/invite/automation/attendees",
+            get(automation::list_attendees).post(automation::create_attendee),
+        )
+        .route("/invite/automation/rsvp", post(automation::set_rsvp))
+        .route("/invite/kiosk/{kiosk_id}", get(view_kiosk))
+        .route("/invite/kiosk/{kiosk_id}/updates", get(kiosk_updates))
+        .route("/invite/attend/{at_id}", get(view_invitation))
+        .route(
+            "/invite/accept/{at_id}",
+            get(accept_invitation).post(accept_invitation_form),
+        )
+        .route("/invite/respond/{at_id}", post(respond_to_invitation))
+        .route(
+            "/invite/maybe/{at_id}",
+            get(maybe_invitation).post(maybe_invitation_form),
+        )
+        .route(
+            "/invite/withdraw/{at_id}",
+            get(withdraw_invitation).post(withdraw_invitation_form),
+        )
+        .route("/invite/thanks/{at_id}", get(view_event))
+        .route("/invite/schedule/{at_id}", get(view_schedule))
+        .route("/invite/join/{link_id}", get(view_join).post(join))
+        .route("/invite/carpool/{at_id}", post(post_carpool))
+        .route("/invite/carpool/{at_id}/join/{offer}", post(join_carpool))
+        .route("/invite/carpool/{at_id}/leave", post(leave_carpool))
+        .route("/invite/export/{at_id}", get(export_attendee))
+        .route("/invite/certificate/{at_id}", get(download_certificate))
+        .route("/invite/ical/{at_id}", get(download_calendar))
+        .route(
+            "/invite/forget/{at_id}",
+            get(confirm_forget_attendee).post(forget_attendee),
+        )
+        .route("/invite", get(index_page))
+        .merge(organizer_routes)
+        .merge(open_idempotent_routes)
+        .merge(admin_routes)
+        .merge(passkey_routes)
+        .merge(inbound_routes)
+        .layer(middleware::from_fn(settings::show_banner))
+        .layer(middleware::from_fn(error_report::capture_errors))
+        .layer(middleware::from_fn(api_error::track_request_id))
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state)
+}
+
+/// The token, unless it's empty, which would let anyone through
+fn nonempty(token: Option<String>, guarded: &str) -> Option<String> {
+    token.filter(|token| {
+        if token.is_empty() {
+            log::warn!("{guarded} stay disabled because the token is empty.");
+        }
+        !token.is_empty()
+    })
+}
+
+/// Respond with the themed 500 page when a handler panics. The panic itself is
+/// logged (and reported) by the panic hook.
+fn handle_panic(_err: Box<dyn std::any::Any + Send + 'static>) -> Response {
+    let Ok(page) = templates::ErrorPage {
+        lang: Language::default().code(),
+        title: "Something went wrong",
+        message: "The server ran into an unexpected problem. Please try \
+                  again later.",
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            .into_response();
+    };
+    (StatusCode::INTERNAL_SERVER_ERROR, Html(page)).into_response()
+}
+
+async fn create_new_event() -> Response {
+    redirect_to_new_event(event_db::create_event().await)
+}
+
+/// Copy an event into a new one with fresh links, see
+/// `event_db::clone_event`
+async fn clone_event(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_new_event(event_db::clone_event(ev_id).await)
+}
+
+/// Send the organizer to the manage page of the event just created
+fn redirect_to_new_event(
+    result: Result<event_db::NewEvent, CreateEventError>,
+) -> Response {
+    let new = match result {
+        Ok(v) => v,
+        Err(CreateEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(CreateEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        Err(CreateEventError::Full) => {
+            let Ok(page) = templates::ErrorPage {
+                lang: Language::default().code(),
+                title: "No room for new events",
+                message: "This server isn't taking new events right now. \
+                          Existing events still work as usual. Please try \
+                          again later.",
+            }
+            .render() else {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to render page",
+                )
+                    .into_response();
+            };
+            return (StatusCode::INSUFFICIENT_STORAGE, Html(page))
+                .into_response();
+        }
+    };
+    let redirect_url =
+        format!("/invite/manage/{}?key={}", new.id, new.organizer_key);
+    Redirect::to(&redirect_url).into_response()
+}
+
+async fn manage_event(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_id(ev_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    };
+    event_db::record_manage_view(ev_id);
+    render_manage_page(event).await
+}
+
+/// The guest's name, if they're still on the guest list
+fn guest_name(event: &event_db::Event, at_id: ids::Id) -> Option<String> {
+    event
+        .attendees
+        .iter()
+        .find(|at| at.id == at_id)
+        .map(|at| at.name.clone())
+}
+
+/// Who a log entry is about, which may be a guest removed since
+fn logged_guest(event: &event_db::Event, at_id: ids::Id) -> String {
+    guest_name(event, at_id).unwrap_or("Removed attendee".to_string())
+}
+
+fn render_error_rows(
+    event: &event_db::Event,
+) -> Vec<templates::RenderErrorRow> {
+    event
+        .render_errors
+        .iter()
+        .rev()
+        .map(|error| templates::RenderErrorRow {
+            when: templates::relative_time(Some(error.at), ""),
+            attendee: logged_guest(event, error.attendee),
+            message: error.message.clone(),
+        })
+        .collect()
+}
+
+fn access_rows(event: &event_db::Event) -> Vec<templates::AccessRow> {
+    event
+        .access_log
+        .iter()
+        .rev()
+        .map(|entry| templates::AccessRow {
+            when: Language::English.date_time(entry.at),
+            what: entry.kind.describe(),
+            who: match entry.attendee {
+                Some(id) => logged_guest(event, id),
+                None => "You".to_string(),
+            },
+            count: entry.count,
+        })
+        .collect()
+}
+
+fn bot_flag_rows(event: &event_db::Event) -> Vec<templates::BotFlagRow> {
+    event
+        .bot_flags
+        .iter()
+        .rev()
+        .map(|flag| templates::BotFlagRow {
+            when: templates::relative_time(Some(flag.at), ""),
+            attendee: logged_guest(event, flag.attendee),
+            reason: flag.reason.clone(),
+        })
+        .collect()
+}
+
+/// How many guests gave each reason for not coming, empty until someone
+/// gave one
+fn decline_rows(event: &event_db::Event) -> Vec<templates::DeclineRow> {
+    let summary = event.decline_summary();
+    if summary.iter().all(|(_, count)| *count == 0) {
+        return Vec::new();
+    }
+    summary
+        .into_iter()
+        .map(|(reason, count)| templates::DeclineRow { reason, count })
+        .collect()
+}
+
+fn carpool_ride_rows(
+    event: &event_db::Event,
+) -> Vec<templates::ManageCarpoolRow> {
+    let name_of = |id| guest_name(event, id).unwrap_or_default();
+    event
+        .carpool
+        .iter()
+        .filter_map(|entry| match entry.role {
+            CarpoolRole::Driver { seats } => {
+                Some(templates::ManageCarpoolRow {
+                    driver: name_of(entry.attendee),
+                    seats,
+                    note: entry.note.clone(),
+                    riders: event
+                        .riders_of(entry.attendee)
+                        .map(|rider| name_of(rider.attendee))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                })
+            }
+            CarpoolRole::Rider => None,
+        })
+        .collect()
+}
+
+fn carpool_waiting_rows(
+    event: &event_db::Event,
+) -> Vec<templates::CarpoolRequestRow> {
+    event
+        .carpool
+        .iter()
+        .filter(|entry| {
+            entry.role == CarpoolRole::Rider && entry.driver.is_none()
+        })
+        .map(|entry| templates::CarpoolRequestRow {
+            name: guest_name(event, entry.attendee).unwrap_or_default(),
+            note: entry.note.clone(),
+        })
+        .collect()
+}
+
+fn agenda_rows(event: &event_db::Event) -> Vec<templates::ManageAgendaRow> {
+    let id = event.id;
+    event
+        .agenda
+        .iter()
+        .map(|item| templates::ManageAgendaRow {
+            time: Language::English.date_time(item.at),
+            title: item.title.clone(),
+            details: item.details.clone(),
+            remove_link: format!(
+                "/invite/remove-agenda-item/{id}/{}",
+                item.number
+            ),
+        })
+        .collect()
+}
+
+fn field_rows(event: &event_db::Event) -> Vec<templates::ManageFieldRow> {
+    let id = event.id;
+    event
+        .custom_fields
+        .iter()
+        .map(|field| templates::ManageFieldRow {
+            name: field.name.clone(),
+            kind: field.kind.as_str(),
+            remove_link: format!("/invite/remove-field/{id}/{}", field.number),
+        })
+        .collect()
+}
+
+fn expense_rows(event: &event_db::Event) -> Vec<templates::ManageExpenseRow> {
+    let id = event.id;
+    event
+        .expenses
+        .iter()
+        .map(|expense| templates::ManageExpenseRow {
+            item: expense.item.clone(),
+            amount: Language::English.amount(expense.cents),
+            payer: expense.payer.clone(),
+            remove_link: format!(
+                "/invite/remove-expense/{id}/{}",
+                expense.number
+            ),
+        })
+        .collect()
+}
+
+/// What each payer paid and how far that's off their share
+fn balance_rows(event: &event_db::Event) -> Vec<templates::BalanceRow> {
+    let share = event.expense_share().unwrap_or_default();
+    let mut paid: Vec<(&str, u64)> = Vec::new();
+    for expense in &event.expenses {
+        match paid.iter_mut().find(|(payer, _)| *payer == expense.payer) {
+            Some((_, cents)) => *cents += expense.cents,
+            None => paid.push((&expense.payer, expense.cents)),
+        }
+    }
+    paid.into_iter()
+        .map(|(payer, cents)| {
+            let owed = i128::from(cents) - i128::from(share);
+            let sign = if owed < 0 { "-" } else { "+" };
+            templates::BalanceRow {
+                payer: payer.to_string(),
+                paid: Language::English.amount(cents),
+                balance: format!(
+                    "{sign}{}",
+                    Language::English.amount(owed.unsigned_abs() as u64)
+                ),
+            }
+        })
+        .collect()
+}
+
+fn slot_rows(event: &event_db::Event) -> Vec<templates::ManageSlotRow> {
+    let id = event.id;
+    event
+        .time_slots
+        .iter()
+        .map(|slot| templates::ManageSlotRow {
+            time: Language::English.time_range(slot.start, slot.end()),
+            capacity: slot.capacity,
+            booked: event
+                .attendees
+                .iter()
+                .filter(|at| {
+                    at.has_accepted() && at.time_slot == Some(slot.number)
+                })
+                .map(|at| at.name.clone())
+                .collect(),
+            remove_link: format!("/invite/unslot/{id}/{}", slot.number),
+        })
+        .collect()
+}
+
+fn scheduled_send_rows(
+    event: &event_db::Event,
+) -> Vec<templates::ScheduledSendRow> {
+    let id = event.id;
+    event
+        .scheduled_sends
+        .iter()
+        .map(|send| templates::ScheduledSendRow {
+            time: clock::describe_local_time(send.at),
+            recipients: match send.attendee {
+                Some(at_id) => guest_name(event, at_id).unwrap_or_default(),
+                None => "Everyone not sent their invitation yet".to_string(),
+            },
+            cancel_link: format!("/invite/cancel-send/{id}/{}", send.number),
+        })
+        .collect()
+}
+
+fn announcement_rows(
+    event: &event_db::Event,
+) -> Vec<templates::ManageAnnouncementRow> {
+    let id = event.id;
+    event
+        .announcements
+        .iter()
+        .rev()
+        .map(|announcement| templates::ManageAnnouncementRow {
+            posted: templates::relative_time(Some(announcement.at), ""),
+            text: announcement.text.clone(),
+            remove_link: format!(
+                "/invite/unannounce/{id}/{}",
+                announcement.number
+            ),
+        })
+        .collect()
+}
+
+/// Contacts of the book who aren't on the guest list yet
+fn contact_options(
+    event: &event_db::Event,
+    book: &contacts::ContactBook,
+) -> Vec<templates::ContactOption> {
+    book.contacts
+        .iter()
+        .filter(|c| !event.attendees.iter().any(|at| c.matches(at)))
+        .map(|c| templates::ContactOption {
+            number: c.number,
+            name: c.name.clone(),
+            email: c.email.clone().unwrap_or_default(),
+        })
+        .collect()
+}
+
+async fn render_manage_page(mut event: event_db::Event) -> Response {
+    event.attendees.sort_by_key(|at| at.order);
+    let id = event.id;
+    let now = clock::now();
+    let nudge_due = event
+        .attendees
+        .iter()
+        .filter(|at| at.nudge_due(now))
+        .count();
+    let purge_at = event.purge_at();
+    let contact_book = match event.contact_book {
+        Some(book_id) => contacts::find_book(book_id).await.ok(),
+        None => None,
+    };
+    let contact_rows = contact_book
+        .as_ref()
+        .map(|book| contact_options(&event, book))
+        .unwrap_or_default();
+    let expense_share = event.expense_share();
+    let expenses_total = Language::English.amount(event.expenses_total());
+    let expense_heads = event
+        .attendees
+        .iter()
+        .filter(|at| at.has_accepted())
+        .count();
+    let invitations_sent = event
+        .attendees
+        .iter()
+        .filter(|at| at.invitation_sent.is_some())
+        .count();
+    let organizer_url = event
+        .organizer_key
+        .as_ref()
+        .map(|_| event.manage_link())
+        .unwrap_or_default();
+    let cover_image = cover_image_url(&event);
+    let decline_reasons = event.decline_reasons().join(", ");
+    let rsvp_counts = event.rsvp_counts();
+    let headcount = event.headcount();
+    let when = event.describe_when(Language::English).unwrap_or_default();
+    let forecast = weather::for_event(&event)
+        .map(|forecast| weather::describe(&forecast, Language::English))
+        .unwrap_or_default();
+    // the rows are built before the guest list is moved into the page
+    let decline_summary = decline_rows(&event);
+    let render_errors = render_error_rows(&event);
+    let access_log = access_rows(&event);
+    let bot_flags = bot_flag_rows(&event);
+    let activity = templates::activity_rows(&event);
+    let carpool_rides = carpool_ride_rows(&event);
+    let carpool_waiting = carpool_waiting_rows(&event);
+    let agenda = agenda_rows(&event);
+    let custom_fields = field_rows(&event);
+    let expenses = expense_rows(&event);
+    let balances = balance_rows(&event);
+    let time_slots = slot_rows(&event);
+    let scheduled_sends = scheduled_send_rows(&event);
+    let announcements = announcement_rows(&event);
+    let joined_openly = event.joined_openly();
+    let responses_closed = event.responses_closed(now);
+    let event_name = event.name.unwrap_or("Untitled Event".to_string());
+    let Ok(template) = templates::ManagePage {
+        event_name: &event_name,
+        notify_webhook: event.notify_webhook.as_deref().unwrap_or_default(),
+        organizer_email: event.organizer_email.as_deref().unwrap_or_default(),
+        digest: event.digest.as_str(),
+        thanks_html: event.thanks_html.as_deref().unwrap_or_default(),
+        withdraw_html: event.withdraw_html.as_deref().unwrap_or_default(),
+        decline_reasons: &decline_reasons,
+        decline_summary,
+        rsvp_counts,
+        headcount,
+        revision: event.revision,
+        attendees: event
+            .attendees
+            .into_iter()
+            .map(|at| templates::Attendee {
+                fields: templates::FieldCell::row(&event.custom_fields, &at),
+                ..templates::Attendee::from(at)
+            })
+            .collect(),
+        update_link: &format!("/invite/update/{}", id),
+        conflict_link: &format!("/invite/conflict/{}", id),
+        patch_link: &format!("/invite/api/events/{}", id),
+        add_link: &format!("/invite/add/{}", id),
+        reorder_link: &format!("/invite/reorder/{}", id),
+        nudge_link: &format!("/invite/nudge/{}", id),
+        nudge_due,
+        can_nudge: notify::has_channel(event.notify_webhook.as_deref()),
+        deletes_on: purge_at
+            .map(clock::describe_local_date)
+            .unwrap_or_default(),
+        deletes_in: templates::relative_time(purge_at, ""),
+        lifetime: clock::describe_duration(
+            event_db::retention().event_lifetime,
+        ),
+        extend_link: &format!("/invite/extend/{}", id),
+        pinned: event.pinned,
+        can_pin: event_db::organizer_pinning(),
+        pin_link: &format!("/invite/pin/{}", id),
+        unpin_link: &format!("/invite/unpin/{}", id),
+        rsvp_deadline: event
+            .rsvp_deadline
+            .map(clock::local_input)
+            .unwrap_or_default(),
+        responses_locked: event.responses_locked,
+        responses_closed,
+        lock_link: &format!("/invite/lock/{}", id),
+        unlock_link: &format!("/invite/unlock/{}", id),
+        frozen: event.frozen,
+        freeze_link: &format!("/invite/freeze/{}", id),
+        clone_link: &format!("/invite/clone/{}", id),
+        announcements,
+        announce_link: &format!("/invite/announce/{}", id),
+        open_rsvp_url: event
+            .open_rsvp
+            .as_ref()
+            .map(|link| {
+                format!("{}/invite/join/{}", settings::public_url(), link.id)
+            })
+            .unwrap_or_default(),
+        open_rsvp_capacity: event
+            .open_rsvp
+            .as_ref()
+            .and_then(|link| link.capacity)
+            .map(|capacity| capacity.to_string())
+            .unwrap_or_default(),
+        joined_openly,
+        open_rsvp_link: &format!("/invite/open-rsvp/{}", id),
+        close_rsvp_link: &format!("/invite/close-rsvp/{}", id),
+        check_in_link: &format!("/invite/check-in/{}", id),
+        preview_link: &format!("/invite/preview/{}", id),
+        check_template_link: &format!("/invite/preview/{}", id),
+        undo_check_in_link: &format!("/invite/undo-check-in/{}", id),
+        organizer_url,
+        kiosk_url: event
+            .kiosk
+            .map(|kiosk| {
+                format!("{}/invite/kiosk/{kiosk}", settings::public_url())
+            })
+            .unwrap_or_default(),
+        open_kiosk_link: &format!("/invite/open-kiosk/{}", id),
+        close_kiosk_link: &format!("/invite/close-kiosk/{}", id),
+        contact_book_url: contact_book
+            .map(|book| contacts::book_url(book.id))
+            .unwrap_or_default(),
+        contacts: contact_rows,
+        new_contact_book_link: &format!("/invite/new-contact-book/{}", id),
+        use_contact_book_link: &format!("/invite/use-contact-book/{}", id),
+        unlink_contact_book_link: &format!(
+            "/invite/unlink-contact-book/{}",
+            id
+        ),
+        add_from_contacts_link: &format!("/invite/add-from-contacts/{}", id),
+        save_to_contacts_link: &format!("/invite/save-to-contacts/{}", id),
+        api_key: event.api_key.clone().unwrap_or_default(),
+        new_api_key_link: &format!("/invite/new-api-key/{}", id),
+        revoke_api_key_link: &format!("/invite/revoke-api-key/{}", id),
+        updates_link: &format!("/invite/updates/{}", id),
+        merge_link: &format!("/invite/merge/{}", id),
+        date: event
+            .date
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+        start_time: event
+            .start_time
+            .map(|time| time.format("%H:%M").to_string())
+            .unwrap_or_default(),
+        end_time: event
+            .end_time
+            .map(|time| time.format("%H:%M").to_string())
+            .unwrap_or_default(),
+        timezone: event.timezone.clone().unwrap_or_default(),
+        location: event.location.clone().unwrap_or_default(),
+        when,
+        when_where_link: &format!("/invite/when-where/{}", id),
+        agenda,
+        add_agenda_item_link: &format!("/invite/add-agenda-item/{}", id),
+        custom_fields,
+        add_field_link: &format!("/invite/add-field/{}", id),
+        max_custom_fields: event_db::MAX_CUSTOM_FIELDS,
+        weather_enabled: weather::enabled(),
+        weather_location: event
+            .weather_location
+            .map(|at| format!("{}, {}", at.latitude, at.longitude))
+            .unwrap_or_default(),
+        forecast,
+        forecast_days: weather::FORECAST_DAYS,
+        weather_link: &format!("/invite/weather/{}", id),
+        expenses,
+        expenses_total,
+        expense_heads,
+        expense_share: expense_share
+            .map(|cents| Language::English.amount(cents))
+            .unwrap_or_default(),
+        balances,
+        expenses_shared: event.expenses_shared,
+        add_expense_link: &format!("/invite/add-expense/{}", id),
+        share_expenses_link: &format!("/invite/share-expenses/{}", id),
+        unshare_expenses_link: &format!("/invite/unshare-expenses/{}", id),
+        time_slots,
+        add_slots_link: &format!("/invite/slots/{}", id),
+        max_time_slots: event_db::MAX_TIME_SLOTS,
+        scheduled_sends,
+        schedule_send_link: &format!("/invite/schedule-send/{}", id),
+        max_scheduled_sends: event_db::MAX_SCHEDULED_SENDS,
+        invitations_sent,
+        can_send_invitations: mail::enabled()
+            || notify::has_channel(event.notify_webhook.as_deref()),
+        carpool_open: event.carpool_open,
+        carpool_rides,
+        carpool_waiting,
+        open_carpool_link: &format!("/invite/open-carpool/{}", id),
+        close_carpool_link: &format!("/invite/close-carpool/{}", id),
+        cover_image_url: &cover_image,
+        cover_link: &format!("/invite/cover/{}", id),
+        uncover_link: &format!("/invite/uncover/{}", id),
+        languages: Language::ALL
+            .iter()
+            .map(|lang| (lang.code(), lang.name()))
+            .collect(),
+        bot_flags,
+        render_errors,
+        access_log,
+        activity,
+    }
+    .render_live() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(template).into_response()
+}
+
+async fn update_event(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(form): Json<ManagePageJson>,
+) -> Response {
+    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            let error =
+                ApiError::new(ErrorCode::NotFound, "Event does not exist");
+            return mutation_error(&headers, error, redirect);
+        }
+    };
+
+    match event_db::update_event(ev_id, form).await {
+        Ok(revision) if wants_json(&headers) => {
+            Json(PatchResponse { revision }).into_response()
+        }
+        Ok(_) => redirect.into_response(),
+        // let the page show what changed underneath it
+        Err(e @ UpdateEventError::Conflict(_)) => {
+            ApiError::from(e).into_response()
+        }
+        Err(e) => {
+            if let UpdateEventError::Find(FindEventError::Database(e)) = &e {
+                log::error!("{e}");
+            }
+            mutation_error(&headers, e.into(), redirect)
+        }
+    }
+}
+
+/// Whether the caller can make use of a JSON response, e.g., the manage page's
+/// scripts, rather than being a plain form post
+fn wants_json(headers: &HeaderMap) -> bool {
+    let is_json = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("application/json"))
+    };
+    is_json(header::ACCEPT) || is_json(header::CONTENT_TYPE)
+}
+
+/// Report a failed change to an event. JSON callers get the error, plain form
+/// posts are sent back to the manage page.
+fn mutation_error(
+    headers: &HeaderMap,
+    error: ApiError,
+    redirect: Redirect,
+) -> Response {
+    if wants_json(headers) {
+        error.into_response()
+    } else {
+        redirect.into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct RenameJson {
+    event_name: String,
+}
+
+/// Change only the event's name, leaving attendee data untouched
+async fn rename_event(
+    Path(id): Path<String>,
+    Json(form): Json<RenameJson>,
+) -> Redirect {
+    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
+    let Ok(ev_id) = ids::decode_id(&id) else {
+        log::error!("Event does not exist");
+        return redirect;
+    };
+
+    let patch = EventPatch {
+        event_name: Some(form.event_name),
+        ..Default::default()
+    };
+    match event_db::patch_event(ev_id, patch).await {
+        Ok(_) => {}
+        Err(UpdateEventError::Find(FindEventError::Database(e))) => {
+            log::error!("{e}");
+        }
+        Err(_) => {}
+    }
+
+    redirect
+}
+
+#[derive(Serialize)]
+struct PatchResponse {
+    revision: u64,
+}
+
+/// Apply a partial update, e.g., a single field the manage page autosaved
+async fn patch_event(
+    Path(id): Path<String>,
+    Json(patch): Json<EventPatch>,
+) -> Result<Json<PatchResponse>, ApiError> {
+    let ev_id = ids::decode_id(&id).map_err(|_| {
+        ApiError::new(ErrorCode::NotFound, "Event does not exist")
+    })?;
+
+    let revision = event_db::patch_event(ev_id, patch).await?;
+    Ok(Json(PatchResponse { revision }))
+}
+
+#[derive(Deserialize)]
+struct ConflictForm {
+    /// The rejected update as JSON
+    mine: String,
+    /// The fields as they were when the manage page was loaded, as JSON
+    base: String,
+}
+
+/// Merge a rejected update into the current event, asking the organizer about
+/// fields which were changed on both sides
+async fn show_conflict(
+    Path(id): Path<String>,
+    Form(form): Form<ConflictForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_id(ev_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    };
+    let (Ok(mine), Ok(base)) = (
+        serde_json::from_str::<ManagePageJson>(&form.mine),
+        serde_json::from_str::<ManagePageJson>(&form.base),
+    ) else {
+        return (StatusCode::BAD_REQUEST, "Malformed edits").into_response();
+    };
+
+    let theirs = merge::flatten(&ManagePageJson::from(&event));
+    let (merged, conflicts) =
+        merge::merge(&merge::flatten(&base), &merge::flatten(&mine), &theirs);
+    if conflicts.is_empty() {
+        return apply_merged(&id, ev_id, &merged, event.revision).await;
+    }
+
+    let Ok(merged) = serde_json::to_string(&merged) else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    let event_name = event.name.unwrap_or("Untitled Event".to_string());
+    let Ok(template) = templates::ConflictPage {
+        event_name: &event_name,
+        resolve_link: &format!("/invite/resolve/{id}"),
+        manage_link: &format!("/invite/manage/{id}"),
+        merged: &merged,
+        revision: event.revision,
+        conflicts: conflicts
+            .into_iter()
+            .map(|conflict| templates::ConflictRow {
+                label: merge::label(&conflict.key, &theirs),
+                key: conflict.key,
+                mine: conflict.mine,
+                theirs: conflict.theirs,
+            })
+            .collect(),
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    (StatusCode::CONFLICT, Html(template)).into_response()
+}
+
+/// Save the organizer's picks from the conflict page
+async fn resolve_conflict(
+    Path(id): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let merged = form
+        .get("merged")
+        .and_then(|m| serde_json::from_str::<merge::Fields>(m).ok());
+    let revision = form.get("revision").and_then(|r| r.parse().ok());
+    let (Some(mut merged), Some(revision)) = (merged, revision) else {
+        return (StatusCode::BAD_REQUEST, "Malformed resolution")
+            .into_response();
+    };
+    for (key, value) in form.iter() {
+        if let Some(key) = key.strip_prefix("pick.") {
+            merged.insert(key.to_string(), value.clone());
+        }
+    }
+    apply_merged(&id, ev_id, &merged, revision).await
+}
+
+async fn apply_merged(
+    id: &str,
+    ev_id: ids::Id,
+    merged: &merge::Fields,
+    revision: u64,
+) -> Response {
+    match event_db::update_event(ev_id, merge::unflatten(merged, revision))
+        .await
+    {
+        Ok(_) => {}
+        Err(UpdateEventError::Conflict(_)) => {
+            let Ok(template) = templates::ErrorPage {
+                lang: Language::default().code(),
+                title: "The event changed again",
+                message: "Someone saved changes to this event while you were \
+                          resolving the last ones, so your choices were not \
+                          saved. Go back to the manage page to see the latest \
+                          version.",
+            }
+            .render() else {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to render page",
+                )
+                    .into_response();
+            };
+            return (StatusCode::CONFLICT, Html(template)).into_response();
+        }
+        Err(UpdateEventError::Find(FindEventError::Database(e))) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(UpdateEventError::Find(FindEventError::NotFound(e))) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        Err(UpdateEventError::Frozen) => {
+            return (StatusCode::FORBIDDEN, FROZEN_MESSAGE).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+async fn extend_event(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    match event_db::extend_event(ev_id).await {
+        Ok(_) => {}
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+async fn pin_event(Path(id): Path<String>) -> Response {
+    set_pinned(id, true).await
+}
+
+async fn unpin_event(Path(id): Path<String>) -> Response {
+    set_pinned(id, false).await
+}
+
+/// Pin or unpin on behalf of the organizer, if the operator allows it
+async fn set_pinned(id: String, pinned: bool) -> Response {
+    if !event_db::organizer_pinning() {
+        return (
+            StatusCode::FORBIDDEN,
+            "Only the operator can pin events here",
+        )
+            .into_response();
+    }
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    match event_db::set_pinned(ev_id, pinned).await {
+        Ok(_) => {}
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+async fn lock_responses(Path(id): Path<String>) -> Response {
+    set_responses_locked(id, true).await
+}
+
+async fn unlock_responses(Path(id): Path<String>) -> Response {
+    set_responses_locked(id, false).await
+}
+
+async fn set_responses_locked(id: String, locked: bool) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    match event_db::set_responses_locked(ev_id, locked).await {
+        Ok(_) => {}
+        Err(UpdateEventError::Find(FindEventError::Database(e))) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(UpdateEventError::Find(FindEventError::NotFound(e))) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        // frozen, since no revision is given to conflict with
+        Err(_) => {
+            return (StatusCode::FORBIDDEN, FROZEN_MESSAGE).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+/// Keep the event as a read-only record. Only the operator can undo this.
+async fn freeze_event(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    match event_db::set_frozen(ev_id, true).await {
+        Ok(_) => {}
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+#[derive(Deserialize)]
+struct AnnouncementForm {
+    text: String,
+}
+
+async fn post_announcement(
+    Path(id): Path<String>,
+    Form(form): Form<AnnouncementForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(
+        &id,
+        event_db::post_announcement(ev_id, &form.text).await,
+    )
+}
+
+async fn remove_announcement(
+    Path((id, number)): Path<(String, u32)>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::remove_announcement(ev_id, number).await)
+}
+
+#[derive(Deserialize)]
+struct TimeSlotsForm {
+    /// When the first slot starts, as from a date and time input
+    start: String,
+    minutes: u64,
+    count: usize,
+    capacity: u32,
+}
+
+/// Add back-to-back time slots
+async fn add_time_slots(
+    Path(id): Path<String>,
+    Form(form): Form<TimeSlotsForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let start = match clock::parse_local_input(&form.start) {
+        Ok(start) => start,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    if form.minutes == 0 || form.count == 0 || form.capacity == 0 {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Slots need a length, a count, and room for a guest",
+        )
+            .into_response();
+    }
+    redirect_to_manage(
+        &id,
+        event_db::add_time_slots(
+            ev_id,
+            start,
+            Duration::from_secs(form.minutes * 60),
+            form.count,
+            form.capacity,
+        )
+        .await,
+    )
+}
+
+async fn remove_time_slot(Path((id, number)): Path<(String, u32)>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::remove_time_slot(ev_id, number).await)
+}
+
+#[derive(Deserialize)]
+struct ScheduleSendForm {
+    /// As from a date and time input
+    at: String,
+    /// Empty to send to every guest not sent their invitation yet
+    #[serde(default)]
+    attendee: String,
+}
+
+/// Schedule invitations to go out later, see `sending`
+async fn schedule_send(
+    Path(id): Path<String>,
+    Form(form): Form<ScheduleSendForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let at = match clock::parse_local_input(&form.at) {
+        Ok(at) => at,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let attendee = match form.attendee.as_str() {
+        "" => None,
+        at_id => match ids::decode_id(at_id) {
+            Ok(at_id) => Some(at_id),
+            Err(_) => {
+                return (StatusCode::NOT_FOUND, "Attendee does not exist")
+                    .into_response();
+            }
+        },
+    };
+    redirect_to_manage(&id, event_db::schedule_send(ev_id, at, attendee).await)
+}
+
+async fn cancel_send(Path((id, number)): Path<(String, u32)>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::cancel_send(ev_id, number).await)
+}
+
+#[derive(Deserialize)]
+struct AgendaItemForm {
+    /// As from a date and time input
+    at: String,
+    title: String,
+    #[serde(default)]
+    details: String,
+}
+
+async fn add_agenda_item(
+    Path(id): Path<String>,
+    Form(form): Form<AgendaItemForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let at = match clock::parse_local_input(&form.at) {
+        Ok(at) => at,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    redirect_to_manage(
+        &id,
+        event_db::add_agenda_item(ev_id, at, &form.title, &form.details).await,
+    )
+}
+
+async fn remove_agenda_item(
+    Path((id, number)): Path<(String, u32)>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::remove_agenda_item(ev_id, number).await)
+}
+
+#[derive(Deserialize)]
+struct CustomFieldForm {
+    name: String,
+    /// "text", "number", or "boolean"
+    kind: String,
+}
+
+async fn add_custom_field(
+    Path(id): Path<String>,
+    Form(form): Form<CustomFieldForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let Some(kind) = event_db::FieldKind::parse(&form.kind) else {
+        return (StatusCode::BAD_REQUEST, "Unknown kind of field")
+            .into_response();
+    };
+    redirect_to_manage(
+        &id,
+        event_db::add_custom_field(ev_id, &form.name, kind).await,
+    )
+}
+
+async fn remove_custom_field(
+    Path((id, number)): Path<(String, u32)>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::remove_custom_field(ev_id, number).await)
+}
+
+#[derive(Deserialize)]
+struct WhenWhereForm {
+    /// As from a date input, blank for none
+    #[serde(default)]
+    date: String,
+    /// As from time inputs, blank for none
+    #[serde(default)]
+    start_time: String,
+    #[serde(default)]
+    end_time: String,
+    /// IANA time zone name, blank for the server's
+    #[serde(default)]
+    timezone: String,
+    #[serde(default)]
+    location: String,
+}
+
+async fn set_when_where(
+    Path(id): Path<String>,
+    Form(form): Form<WhenWhereForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let date = match form.date.trim() {
+        "" => None,
+        date => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => Some(date),
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("\"{date}\" is not a date"),
+                )
+                    .into_response();
+            }
+        },
+    };
+    let mut times = [None, None];
+    for (time, input) in
+        times.iter_mut().zip([&form.start_time, &form.end_time])
+    {
+        if input.trim().is_empty() {
+            continue;
+        }
+        match clock::parse_time_of_day(input) {
+            Ok(parsed) => *time = Some(parsed),
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        }
+    }
+    let timezone = form.timezone.trim();
+    if !timezone.is_empty() && timezone.parse::<Tz>().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "\"{timezone}\" is not a time zone, expected e.g., \
+                 \"Europe/Berlin\""
+            ),
+        )
+            .into_response();
+    }
+    let [start_time, end_time] = times;
+    let details = event_db::WhenWhere {
+        date,
+        start_time,
+        end_time,
+        timezone: Some(timezone.to_string()).filter(|tz| !tz.is_empty()),
+        location: Some(form.location),
+    };
+    redirect_to_manage(&id, event_db::set_when_where(ev_id, details).await)
+}
+
+#[derive(Deserialize)]
+struct WeatherForm {
+    /// "latitude, longitude", blank for none
+    #[serde(default)]
+    coordinates: String,
+}
+
+async fn set_weather_location(
+    Path(id): Path<String>,
+    Form(form): Form<WeatherForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let location = if form.coordinates.trim().is_empty() {
+        None
+    } else {
+        let parsed = form
+            .coordinates
+            .split_once(',')
+            .and_then(|(lat, lon)| {
+                Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?))
+            })
+            .filter(|&(lat, lon): &(f64, f64)| {
+                (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)
+            });
+        let Some((latitude, longitude)) = parsed else {
+            return (
+                StatusCode::BAD_REQUEST,
+                "The location has to be a latitude and longitude in degrees, \
+                 e.g., \"52.52, 13.41\"",
+            )
+                .into_response();
+        };
+        Some(event_db::Coordinates {
+            latitude,
+            longitude,
+        })
+    };
+    redirect_to_manage(
+        &id,
+        event_db::set_weather_location(ev_id, location).await,
+    )
+}
+
+#[derive(Deserialize)]
+struct ExpenseForm {
+    item: String,
+    /// e.g., "12.50" or "12,50"
+    amount: String,
+    #[serde(default)]
+    payer: String,
+}
+
+async fn add_expense(
+    Path(id): Path<String>,
+    Form(form): Form<ExpenseForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let Some(cents) = parse_cents(&form.amount) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "The amount has to be a number with at most two decimals, e.g., \
+             \"12.50\"",
+        )
+            .into_response();
+    };
+    redirect_to_manage(
+        &id,
+        event_db::add_expense(ev_id, &form.item, cents, &form.payer).await,
+    )
+}
+
+/// Parse an amount of money such as "12", "12.5", or "12,50" into cents
+fn parse_cents(amount: &str) -> Option<u64> {
+    let amount = amount.trim();
+    let (whole, fraction) =
+        amount.split_once(['.', ',']).unwrap_or((amount, ""));
+    if (whole.is_empty() && fraction.is_empty())
+        || fraction.len() > 2
+        || !whole.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction: u64 = format!("{fraction:0<2}").parse().ok()?;
+    whole.checked_mul(100)?.checked_add(fraction)
+}
+
+async fn remove_expense(Path((id, number)): Path<(String, u32)>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::remove_expense(ev_id, number).await)
+}
+
+async fn share_expenses(Path(id): Path<String>) -> Response {
+    set_expenses_shared(id, true).await
+}
+
+async fn unshare_expenses(Path(id): Path<String>) -> Response {
+    set_expenses_shared(id, false).await
+}
+
+async fn set_expenses_shared(id: String, shared: bool) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::set_expenses_shared(ev_id, shared).await)
+}
+
+#[derive(Deserialize)]
+struct MergeForm {
+    /// ID of the attendee kept
+    keep: String,
+    /// ID of the attendee merged into the kept one
+    duplicate: String,
+}
+
+async fn merge_attendees(
+    Path(id): Path<String>,
+    Form(form): Form<MergeForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (Ok(keep), Ok(duplicate)) =
+        (ids::decode_id(&form.keep), ids::decode_id(&form.duplicate))
+    else {
+        return (StatusCode::NOT_FOUND, "Attendee does not exist")
+            .into_response();
+    };
+    redirect_to_manage(
+        &id,
+        event_db::merge_attendees(ev_id, keep, duplicate).await,
+    )
+}
+
+#[derive(Deserialize)]
+struct OpenRsvpForm {
+    /// Most guests who can join, blank for no limit
+    #[serde(default)]
+    capacity: String,
+}
+
+/// Create the open RSVP link, or change how many guests can join through it
+async fn open_rsvp_link(
+    Path(id): Path<String>,
+    Form(form): Form<OpenRsvpForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let capacity = match form.capacity.trim() {
+        "" => None,
+        capacity => match capacity.parse() {
+            Ok(capacity) => Some(capacity),
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "The guest limit has to be a whole number",
+                )
+                    .into_response();
+            }
+        },
+    };
+    redirect_to_manage(
+        &id,
+        event_db::open_rsvp_link(ev_id, capacity).await.map(|_| ()),
+    )
+}
+
+async fn close_rsvp_link(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::close_rsvp_link(ev_id).await)
+}
+
+async fn check_in(Path((id, at_id)): Path<(String, String)>) -> Response {
+    set_checked_in(id, at_id, true).await
+}
+
+async fn undo_check_in(Path((id, at_id)): Path<(String, String)>) -> Response {
+    set_checked_in(id, at_id, false).await
+}
+
+async fn set_checked_in(
+    id: String,
+    at_id: String,
+    checked_in: bool,
+) -> Response {
+    // find event
+    let (Ok(ev_id), Ok(at_id)) = (ids::decode_id(&id), ids::decode_id(&at_id))
+    else {
+        return (StatusCode::NOT_FOUND, "Event does not exist").into_response();
+    };
+    redirect_to_manage(
+        &id,
+        event_db::set_checked_in(ev_id, at_id, checked_in).await,
+    )
+}
+
+/// Render an invitation template for the organizer. The error says why it
+/// failed, so they can fix it.
+fn render_preview(
+    event: &event_db::Event,
+    attendee: &event_db::Attendee,
+    template: &str,
+) -> templates::Preview {
+    let id = attendee.id.encode();
+    let news = templates::GuestNews::of(event, attendee.language);
+    let ctx = invitation_context(event, attendee, &id, &news);
+    match render_custom(template, &ctx) {
+        Ok(page) => templates::Preview {
+            page: with_news(page, template, &news),
+            error: String::new(),
+        },
+        Err(e) => templates::Preview {
+            page: String::new(),
+            error: describe_render_error(&e),
+        },
+    }
+}
+
+/// Show the attendee's live invitation beside their draft
+async fn preview_invitation(
+    Path((id, at_id)): Path<(String, String)>,
+) -> Response {
+    // find event
+    let (Ok(ev_id), Ok(at_id)) = (ids::decode_id(&id), ids::decode_id(&at_id))
+    else {
+        return (StatusCode::NOT_FOUND, "Event does not exist").into_response();
+    };
+    let event = match event_db::find_event_by_id(ev_id).await {
+        Ok(event) => event,
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+    };
+    let Some(attendee) = event.attendees.iter().find(|at| at.id == at_id)
+    else {
+        return (StatusCode::NOT_FOUND, "Attendee does not exist")
+            .into_response();
+    };
+    let at_id = at_id.encode();
+    let Ok(page) = templates::PreviewPage {
+        event_name: &event.display_name(),
+        attendee_name: &attendee.name,
+        frozen: event.frozen,
+        live: render_preview(&event, attendee, &attendee.custom_html),
+        draft: attendee
+            .draft_html
+            .as_deref()
+            .map(|draft| render_preview(&event, attendee, draft)),
+        draft_html: attendee
+            .draft_html
+            .as_deref()
+            .unwrap_or(&attendee.custom_html),
+        manage_link: &format!("/invite/manage/{id}"),
+        save_link: &format!("/invite/save-draft/{id}/{at_id}"),
+        promote_link: &format!("/invite/promote-draft/{id}/{at_id}"),
+        discard_link: &format!("/invite/discard-draft/{id}/{at_id}"),
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(page).into_response()
+}
+
+#[derive(Deserialize)]
+struct TemplateCheck {
+    html: String,
+}
+
+/// How a template would be stored and what it renders to
+#[derive(Serialize)]
+struct TemplateCheckResult {
+    /// The template without what sanitizing removes
+    sanitized: String,
+    /// Whether sanitizing removed anything
+    changed: bool,
+    /// Rendered for a sample guest, absent if it failed to render
+    page: Option<String>,
+    /// Why it failed to render
+    error: Option<String>,
+    /// Variables it uses which guests don't have, e.g., misspelled ones
+    unknown_variables: Vec<String>,
+}
+
+/// Render a template for a sample guest of the event, so the manage page can
+/// show render errors before guests see them
+async fn check_template(
+    Path(id): Path<String>,
+    Json(check): Json<TemplateCheck>,
+) -> Result<Json<TemplateCheckResult>, ApiError> {
+    // find event
+    let ev_id = ids::decode_id(&id).map_err(|_| {
+        ApiError::new(ErrorCode::NotFound, "Event does not exist")
+    })?;
+    let event = event_db::find_event_by_id(ev_id).await?;
+    let sample = event_db::Attendee {
+        name: "Sample Guest".to_string(),
+        ..event_db::Attendee::new(ids::Id::from(0u64))
+    };
+    let sanitized = sanitize::sanitize(&check.html);
+    let preview = render_preview(&event, &sample, &sanitized);
+    let news = templates::GuestNews::of(&event, sample.language);
+    let ctx = invitation_context(&event, &sample, &sample.id.encode(), &news);
+    Ok(Json(TemplateCheckResult {
+        unknown_variables: template_lint::unknown_variables(&sanitized, &ctx),
+        changed: sanitized != check.html,
+        sanitized,
+        page: preview.error.is_empty().then_some(preview.page),
+        error: Some(preview.error).filter(|e| !e.is_empty()),
+    }))
+}
+
+#[derive(Deserialize)]
+struct DraftForm {
+    html: String,
+}
+
+async fn save_draft(
+    Path((id, at_id)): Path<(String, String)>,
+    Form(form): Form<DraftForm>,
+) -> Response {
+    // find event
+    let (Ok(ev_id), Ok(attendee)) =
+        (ids::decode_id(&id), ids::decode_id(&at_id))
+    else {
+        return (StatusCode::NOT_FOUND, "Event does not exist").into_response();
+    };
+    // back to the preview rather than the manage page
+    match redirect_to_manage(
+        &id,
+        event_db::set_draft(ev_id, attendee, Some(form.html)).await,
+    ) {
+        response if response.status().is_redirection() => {
+            Redirect::to(&format!("/invite/preview/{id}/{at_id}"))
+                .into_response()
+        }
+        response => response,
+    }
+}
+
+async fn promote_draft(Path((id, at_id)): Path<(String, String)>) -> Response {
+    // find event
+    let (Ok(ev_id), Ok(at_id)) = (ids::decode_id(&id), ids::decode_id(&at_id))
+    else {
+        return (StatusCode::NOT_FOUND, "Event does not exist").into_response();
+    };
+    redirect_to_manage(&id, event_db::promote_draft(ev_id, at_id).await)
+}
+
+async fn discard_draft(Path((id, at_id)): Path<(String, String)>) -> Response {
+    // find event
+    let (Ok(ev_id), Ok(at_id)) = (ids::decode_id(&id), ids::decode_id(&at_id))
+    else {
+        return (StatusCode::NOT_FOUND, "Event does not exist").into_response();
+    };
+    redirect_to_manage(&id, event_db::set_draft(ev_id, at_id, None).await)
+}
+
+async fn open_kiosk(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::open_kiosk(ev_id).await.map(|_| ()))
+}
+
+async fn new_api_key(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::create_api_key(ev_id).await.map(|_| ()))
+}
+
+async fn revoke_api_key(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::revoke_api_key(ev_id).await)
+}
+
+/// Start a contact book for the event
+async fn new_contact_book(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let book_id = match contacts::create_book().await {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    redirect_to_manage(
+        &id,
+        event_db::set_contact_book(ev_id, Some(book_id)).await,
+    )
+}
+
+#[derive(Deserialize)]
+struct ContactBookForm {
+    /// The book's link or ID
+    book: String,
+}
+
+/// Link the event to a contact book made for an earlier one
+async fn use_contact_book(
+    Path(id): Path<String>,
+    Form(form): Form<ContactBookForm>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let Some(book_id) = contacts::parse_book_link(&form.book) else {
+        return contacts::ContactsError::NotFound.into_response();
+    };
+    if let Err(e) = contacts::find_book(book_id).await {
+        return e.into_response();
+    }
+    redirect_to_manage(
+        &id,
+        event_db::set_contact_book(ev_id, Some(book_id)).await,
+    )
+}
+
+async fn unlink_contact_book(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::set_contact_book(ev_id, None).await)
+}
+
+/// Invite the checked contacts, sent as one `contact` field per number
+async fn add_from_contacts(
+    Path(id): Path<String>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_id(ev_id).await {
+        Ok(v) => v,
+        Err(e) => return redirect_to_manage(&id, Err(e.into())),
+    };
+    let Some(book_id) = event.contact_book else {
+        return contacts::ContactsError::NotFound.into_response();
+    };
+    let book = match contacts::find_book(book_id).await {
+        Ok(v) => v,
+        Err(e) => return e.into_response(),
+    };
+    let picked: Vec<u32> = fields
+        .iter()
+        .filter(|(name, _)| name == "contact")
+        .filter_map(|(_, number)| number.parse().ok())
+        .collect();
+    let guests = book
+        .contacts
+        .into_iter()
+        .filter(|c| picked.contains(&c.number))
+        .map(|c| AttendeePatch {
+            name: Some(c.name),
+            email: c.email,
+            notes: Some(c.notes),
+            ..Default::default()
+        })
+        .collect();
+    redirect_to_manage(
+        &id,
+        event_db::add_attendees(ev_id, guests).await.map(|_| ()),
+    )
+}
+
+/// Add the event's guests to its contact book
+async fn save_to_contacts(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_id(ev_id).await {
+        Ok(v) => v,
+        Err(e) => return redirect_to_manage(&id, Err(e.into())),
+    };
+    let Some(book_id) = event.contact_book else {
+        return contacts::ContactsError::NotFound.into_response();
+    };
+    match contacts::save_guests(book_id, &event.attendees).await {
+        Ok(added) => log::info!("Saved {added} guests of {ev_id} as contacts"),
+        Err(e) => return e.into_response(),
+    }
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+async fn close_kiosk(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::close_kiosk(ev_id).await)
+}
+
+/// Send an `update` event with what `look` finds now, and again whenever the
+/// database changes and it finds something else. Ends once `look` finds
+/// nothing, e.g., when the event is gone.
+fn live_updates<F, Fut>(
+    look: F,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Option<String>> + Send,
+{
+    let mut changes = event_db::watch_changes();
+    changes.mark_changed();
+    let stream = futures_util::stream::unfold(
+        (changes, look, None),
+        |(mut changes, look, mut last)| async move {
+            loop {
+                changes.changed().await.ok()?;
+                let now = look().await?;
+                if last.as_ref() != Some(&now) {
+                    let event =
+                        sse::Event::default().event("update").data(&now);
+                    last = Some(now);
+                    return Some((Ok(event), (changes, look, last)));
+                }
+            }
+        },
+    );
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Tells the manage page the event's revision whenever it changes, so it can
+/// offer to reload when someone else changed the event
+async fn manage_updates(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    if let Err(e) = event_db::find_event_by_id(ev_id).await {
+        return match e {
+            FindEventError::Database(e) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, e).into_response()
+            }
+            FindEventError::NotFound(e) => {
+                (StatusCode::NOT_FOUND, e).into_response()
+            }
+        };
+    }
+    live_updates(move || async move {
+        let event = event_db::find_event_by_id(ev_id).await.ok()?;
+        Some(event.revision.to_string())
+    })
+    .into_response()
+}
+
+/// The kiosk display, which shows who has arrived without letting anyone
+/// change anything
+async fn view_kiosk(Path(id): Path<String>, headers: HeaderMap) -> Response {
+    // find event
+    let kiosk_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_kiosk(kiosk_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    };
+    let event_name = event.name.as_deref().unwrap_or("Untitled Event");
+    let Ok(template) = templates::KioskPage {
+        t: browser_language(&headers).phrases(event_name),
+        event_name,
+        status: templates::KioskStatus::of(&event),
+        updates_link: &format!("/invite/kiosk/{id}/updates"),
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(template).into_response()
+}
+
+/// Tells the kiosk display about arrivals as they happen
+async fn kiosk_updates(Path(id): Path<String>) -> Response {
+    // find event
+    let kiosk_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    if let Err(e) = event_db::find_event_by_kiosk(kiosk_id).await {
+        return match e {
+            FindEventError::Database(e) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, e).into_response()
+            }
+            FindEventError::NotFound(e) => {
+                (StatusCode::NOT_FOUND, e).into_response()
+            }
+        };
+    }
+    live_updates(move || async move {
+        let event = event_db::find_event_by_kiosk(kiosk_id).await.ok()?;
+        serde_json::to_string(&templates::KioskStatus::of(&event)).ok()
+    })
+    .into_response()
+}
+
+async fn open_carpool(Path(id): Path<String>) -> Response {
+    set_carpool_open(id, true).await
+}
+
+async fn close_carpool(Path(id): Path<String>) -> Response {
+    set_carpool_open(id, false).await
+}
+
+async fn set_carpool_open(id: String, open: bool) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    redirect_to_manage(&id, event_db::set_carpool_open(ev_id, open).await)
+}
+
+/// Replace the event's cover image with the one uploaded in the `image` field
+async fn upload_cover(
+    Path(id): Path<String>,
+    mut multipart: Multipart,
+) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let mut data = Vec::new();
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("image") => {
+                match field.bytes().await {
+                    Ok(bytes) => data = bytes.to_vec(),
+                    Err(e) => {
+                        return (e.status(), e.body_text()).into_response()
+                    }
+                }
+                break;
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => return (e.status(), e.body_text()).into_response(),
+        }
+    }
+    if data.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No image was uploaded")
+            .into_response();
+    }
+
+    let name = match cover::store(data).await {
+        Ok(name) => name,
+        Err(e @ cover::CoverError::NotAnImage) => {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+        Err(e) => {
+            log::error!("{e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                .into_response();
+        }
+    };
+    let result = event_db::set_cover_image(ev_id, Some(name.clone())).await;
+    match &result {
+        Ok(Some(replaced)) => cover::remove(replaced).await,
+        Ok(None) => {}
+        Err(_) => cover::remove(&name).await,
+    }
+    redirect_to_manage(&id, result.map(|_| ()))
+}
+
+async fn remove_cover(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let result = event_db::set_cover_image(ev_id, None).await;
+    if let Ok(Some(replaced)) = &result {
+        cover::remove(replaced).await;
+    }
+    redirect_to_manage(&id, result.map(|_| ()))
+}
+
+/// Back to the manage page after a change, or the reason it failed
+fn redirect_to_manage(
+    id: &str,
+    result: Result<(), UpdateEventError>,
+) -> Response {
+    match result {
+        Ok(_) => {}
+        Err(UpdateEventError::Find(FindEventError::Database(e))) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(UpdateEventError::Find(FindEventError::NotFound(e))) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        // frozen, since no revision is given to conflict with
+        Err(_) => {
+            return (StatusCode::FORBIDDEN, FROZEN_MESSAGE).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+async fn nudge_event(Path(id): Path<String>) -> Response {
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    match event_db::nudge_unopened(ev_id).await {
+        Ok(queued) => log::info!("Queued {queued} nudges for event {ev_id}"),
+        Err(UpdateEventError::Find(FindEventError::Database(e))) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(UpdateEventError::Find(FindEventError::NotFound(e))) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        // frozen, since no revision is given to conflict with
+        Err(_) => {
+            return (StatusCode::FORBIDDEN, FROZEN_MESSAGE).into_response();
+        }
+    }
+
+    Redirect::to(&format!("/invite/manage/{id}")).into_response()
+}
+
+#[derive(Serialize)]
+struct NewAttendeeResponse {
+    attendee_id: String,
+    invite_link: String,
+}
+
+/// Invite another person, optionally with their details filled in
+async fn add_attendee(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    new: Option<Json<NewAttendee>>,
+) -> Response {
+    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
+    // find event
+    let ev_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            let error =
+                ApiError::new(ErrorCode::NotFound, "Event does not exist");
+            return mutation_error(&headers, error, redirect);
+        }
+    };
+
+    let new = new.map(|Json(new)| new).unwrap_or_default();
+    match event_db::add_attendee(ev_id, new).await {
+        Ok(at_id) if wants_json(&headers) => {
+            let response = NewAttendeeResponse {
+                attendee_id: at_id.encode(),
+                invite_link: format!(
+                    "{}/invite/attend/{at_id}",
+                    settings::public_url()
+                ),
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Ok(_) => redirect.into_response(),
+        Err(e) => {
+            if let UpdateEventError::Find(FindEventError::Database(e)) = &e {
+                log::error!("{e}");
+            }
+            mutation_error(&headers, e.into(), redirect)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AttendeeUpdate {
+    /// Proves the request comes from the organizer. Guests know their own
+    /// attendee ID, but not the event's.
+    event_id: String,
+    #[serde(flatten)]
+    patch: AttendeePatch,
+}
+
+/// Change a single attendee's name, invitation, or notes
+async fn update_attendee(
+    Path(id): Path<String>,
+    Json(form): Json<AttendeeUpdate>,
+) -> Result<Json<PatchResponse>, ApiError> {
+    let (Ok(at_id), Ok(ev_id)) =
+        (ids::decode_id(&id), ids::decode_id(&form.event_id))
+    else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            "Attendee does not exist",
+        ));
+    };
+
+    let revision = event_db::update_attendee(ev_id, at_id, form.patch).await?;
+    Ok(Json(PatchResponse { revision }))
+}
+
+#[derive(Deserialize)]
+struct ReorderJson {
+    /// Encoded attendee IDs, first to last
+    attendees: Vec<String>,
+}
+
+/// Arrange the attendees on the manage page
+async fn reorder_attendees(
+    Path(id): Path<String>,
+    Json(form): Json<ReorderJson>,
+) -> Result<Json<PatchResponse>, ApiError> {
+    let ev_id = ids::decode_id(&id).map_err(|_| {
+        ApiError::new(ErrorCode::NotFound, "Event does not exist")
+    })?;
+    let order = form
+        .attendees
+        .iter()
+        .filter_map(|at_id| ids::decode_id(at_id).ok())
+        .collect();
+
+    let revision = event_db::reorder_attendees(ev_id, order).await?;
+    Ok(Json(PatchResponse { revision }))
+}
+
+async fn remove_attendee(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
+    // find attendee
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            let error =
+                ApiError::new(ErrorCode::NotFound, "Attendee does not exist");
+            return mutation_error(&headers, error, redirect);
+        }
+    };
+
+    match event_db::remove_attendee(at_id).await {
+        Ok(_) if wants_json(&headers) => StatusCode::NO_CONTENT.into_response(),
+        Ok(ev_id) => {
+            Redirect::to(&format!("/invite/manage/{ev_id}")).into_response()
+        }
+        Err(e) => {
+            if let UpdateEventError::Find(FindEventError::Database(e)) = &e {
+                log::error!("{e}");
+            }
+            mutation_error(&headers, e.into(), redirect)
+        }
+    }
+}
+
+/// Revoke an attendee's link by giving them a new ID. JSON callers get the
+/// new ID and link as when adding an attendee.
+async fn rotate_attendee(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let redirect = Redirect::to(&format!("/invite/manage/{id}"));
+    // find attendee
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            let error =
+                ApiError::new(ErrorCode::NotFound, "Attendee does not exist");
+            return mutation_error(&headers, error, redirect);
+        }
+    };
+
+    match event_db::rotate_attendee_id(at_id).await {
+        Ok((_, new_id)) if wants_json(&headers) => Json(NewAttendeeResponse {
+            attendee_id: new_id.encode(),
+            invite_link: format!(
+                "{}/invite/attend/{new_id}",
+                settings::public_url()
+            ),
+        })
+        .into_response(),
+        Ok((ev_id, _)) => {
+            Redirect::to(&format!("/invite/manage/{ev_id}")).into_response()
+        }
+        Err(e) => {
+            if let UpdateEventError::Find(FindEventError::Database(e)) = &e {
+                log::error!("{e}");
+            }
+            mutation_error(&headers, e.into(), redirect)
+        }
+    }
+}
+
+/// Substrings of user agents belonging to text-mode browsers, which get the
+/// plain invitation unless they ask otherwise
+const TEXT_MODE_USER_AGENTS: &[&str] = &["lynx", "links", "w3m", "browsh"];
+
+#[derive(Deserialize)]
+struct InvitationQuery {
+    /// "1" for the plain invitation, "0" for the invitation as written.
+    /// Absent to decide by the user agent.
+    plain: Option<String>,
+}
+
+/// Whether to show the plain invitation rather than the organizer's HTML
+fn wants_plain(query: &InvitationQuery, headers: &HeaderMap) -> bool {
+    match query.plain.as_deref() {
+        Some(plain) => plain != "0",
+        None => {
+            let user_agent = headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_lowercase();
+            TEXT_MODE_USER_AGENTS
+                .iter()
+                .any(|agent| user_agent.contains(agent))
+        }
+    }
+}
+
+async fn view_invitation(
+    Path(id): Path<String>,
+    Query(query): Query<InvitationQuery>,
+    headers: HeaderMap,
+) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/attend/{into}")
+                })
+                .await;
+            }
+        };
+    // scanners fetching the page are not the guest opening it
+    if matches!(bot_filter::check_link(&headers), Verdict::Human) {
+        if let Err(FindEventError::Database(e)) =
+            event_db::record_view(at_id).await
+        {
+            log::error!("{e}");
+        }
+    }
+    let decline_reasons = event.decline_reasons();
+    let responses_closed = event.responses_closed(clock::now());
+    let news = templates::GuestNews::of(&event, attendee.language);
+    let url = format!("{}/invite/attend/{id}", settings::public_url());
+    let mut head = structured_data::open_graph(&event, &url);
+    if structured_data::on_invitations() {
+        head += &structured_data::event_script(&event, &url);
+    }
+    let carpool = templates::CarpoolBoard::of(&event, at_id);
+    let time_slot = templates::booked_slot(&event, &attendee);
+    let schedule_link = schedule_link(&event, &id);
+    let event_name = event.name.clone().unwrap_or("Untitled Event".to_string());
+    let t = attendee.language.phrases(&event_name);
+
+    if attendee.anonymized {
+        let Ok(template) = templates::ErrorPage {
+            lang: t.lang,
+            title: t.removed_title,
+            message: t.removed_message,
+        }
+        .render() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render page",
+            )
+                .into_response();
+        };
+        return Html(template).into_response();
+    }
+
+    if wants_plain(&query, &headers) {
+        let Ok(template) = templates::PlainInvitationPage {
+            event_name: &event_name,
+            has_accepted: attendee.has_accepted(),
+            is_maybe: attendee.rsvp == Rsvp::Maybe,
+            plus_ones: attendee.plus_ones,
+            max_plus_ones: event_db::MAX_PLUS_ONES,
+            respond_link: &format!("/invite/respond/{}", id),
+            comment: attendee.comment.as_deref().unwrap_or_default(),
+            max_comment_chars: event_db::MAX_COMMENT_CHARS,
+            maybe_link: &format!("/invite/maybe/{}", id),
+            withdraw_link: &format!("/invite/withdraw/{}", id),
+            full_link: &format!("/invite/attend/{}?plain=0", id),
+            forget_link: &format!("/invite/forget/{}", id),
+            export_link: &format!("/invite/export/{}", id),
+            form_time: rsvp_form::form_time(),
+            decline_reasons,
+            responses_closed,
+            news,
+            t,
+        }
+        .render_live() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render page",
+            )
+                .into_response();
+        };
+        return Html(template).into_response();
+    }
+
+    // if accepted, show withdraw page instead
+    if attendee.has_accepted() {
+        if let Some(withdraw_html) = event.withdraw_html.as_ref() {
+            let ctx = invitation_context(&event, &attendee, &id, &news);
+            match render_custom(withdraw_html, &ctx) {
+                Ok(page) => {
+                    let page = with_news(page, withdraw_html, &news);
+                    let page = structured_data::inject(page, &head);
+                    return Html(page).into_response();
+                }
+                Err(e) => log::warn!(
+                    "Custom withdraw page of {} failed to render: \"{e}\"",
+                    event.id
+                ),
+            }
+        }
+        let Ok(template) = templates::WithdrawPage {
+            event_name: &event_name,
+            respond_link: &format!("/invite/respond/{}", id),
+            comment: attendee.comment.as_deref().unwrap_or_default(),
+            max_comment_chars: event_db::MAX_COMMENT_CHARS,
+            maybe_link: &format!("/invite/maybe/{}", id),
+            withdraw_link: &format!("/invite/withdraw/{}", id),
+            forget_link: &format!("/invite/forget/{}", id),
+            export_link: &format!("/invite/export/{}", id),
+            form_time: rsvp_form::form_time(),
+            decline_reasons,
+            responses_closed,
+            plus_ones: attendee.plus_ones,
+            max_plus_ones: event_db::MAX_PLUS_ONES,
+            news,
+            carpool,
+            time_slot: &time_slot,
+            schedule_link: &schedule_link,
+            t,
+        }
+        .render_live() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render page",
+            )
+                .into_response();
+        };
+        let template = structured_data::inject(template, &head);
+        return Html(template).into_response();
+    }
+
+    // render template
+    let ctx = invitation_context(&event, &attendee, &id, &news);
+    let page = match render_custom(&attendee.custom_html, &ctx) {
+        Ok(page) => with_news(page, &attendee.custom_html, &news),
+        Err(e) => {
+            // the guest still gets to answer, and the organizer learns why
+            // their invitation wasn't shown
+            let message = describe_render_error(&e);
+            log::warn!("Invitation of {at_id} failed to render: \"{message}\"");
+            if let Err(FindEventError::Database(e)) =
+                event_db::record_render_error(at_id, &message).await
+            {
+                log::error!("{e}");
+            }
+            let when = event.describe_when(attendee.language);
+            let Ok(page) = templates::DefaultInvitationPage {
+                event_name: &event_name,
+                attendee_name: &attendee.name,
+                cover_image_url: &cover_image_url(&event),
+                when: &when.unwrap_or_default(),
+                location: event.location.as_deref().unwrap_or_default(),
+                is_maybe: attendee.rsvp == Rsvp::Maybe,
+                plus_ones: attendee.plus_ones,
+                max_plus_ones: event_db::MAX_PLUS_ONES,
+                respond_link: &format!("/invite/respond/{}", id),
+                comment: attendee.comment.as_deref().unwrap_or_default(),
+                max_comment_chars: event_db::MAX_COMMENT_CHARS,
+                maybe_link: &format!("/invite/maybe/{}", id),
+                withdraw_link: &format!("/invite/withdraw/{}", id),
+                forget_link: &format!("/invite/forget/{}", id),
+                export_link: &format!("/invite/export/{}", id),
+                form_time: rsvp_form::form_time(),
+                decline_reasons,
+                responses_closed,
+                news,
+                t,
+            }
+            .render_live() else {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to render page",
+                )
+                    .into_response();
+            };
+            page
+        }
+    };
+    Html(structured_data::inject(page, &head)).into_response()
+}
+
+/// Why an organizer's template failed to render, e.g., "Failed to render
+/// '__tera_one_off': Variable `nme` not found in context"
+fn describe_render_error(e: &tera::Error) -> String {
+    match std::error::Error::source(e) {
+        Some(cause) => format!("{e}: {cause}"),
+        None => e.to_string(),
+    }
+}
+
+/// Render an organizer's template. Stored templates are sanitized already,
+/// but Tera's `safe` filter can put together markup they didn't have, so the
+/// page is sanitized again.
+fn render_custom(
+    template: &str,
+    ctx: &tera::Context,
+) -> Result<String, tera::Error> {
+    tera::Tera::one_off(template, ctx, true)
+        .map(|page| sanitize::sanitize(&page))
+}
+
+/// Put the news banner atop a page rendered from an organizer's template,
+/// unless the template places the announcements and updates itself
+fn with_news(
+    mut page: String,
+    template: &str,
+    news: &templates::GuestNews,
+) -> String {
+    let placed = ["announcements", "updates"]
+        .iter()
+        .any(|name| template.contains(name));
+    if news.is_empty() || placed {
+        return page;
+    }
+    let Ok(banner) = templates::NewsBanner { news }.render() else {
+        return page;
+    };
+    // right inside the body if the page has one
+    let lower = page.to_ascii_lowercase();
+    let at = lower
+        .find("<body")
+        .and_then(|start| Some(start + lower[start..].find('>')? + 1))
+        .unwrap_or(0);
+    page.insert_str(at, &banner);
+    page
+}
+
+/// Send links of an attendee merged into another on to the same page of the
+/// one kept, so old invitations keep working. Other unknown attendees get a
+/// 404 with `e`.
+async fn moved_or_not_found(
+    at_id: ids::Id,
+    e: String,
+    path: impl FnOnce(ids::Id) -> String,
+) -> Response {
+    match event_db::merged_into(at_id).await {
+        Some(into) => Redirect::permanent(&path(into)).into_response(),
+        None => (StatusCode::NOT_FOUND, e).into_response(),
+    }
+}
+
+/// Variables available to organizer-written templates
+fn invitation_context(
+    event: &event_db::Event,
+    attendee: &event_db::Attendee,
+    id: &str,
+    news: &templates::GuestNews,
+) -> tera::Context {
+    let event_name = event.name.as_deref().unwrap_or("Untitled Event");
+    let carpool = templates::CarpoolBoard::of(event, attendee.id);
+    let t = attendee.language.phrases(event_name);
+    let mut ctx = tera::Context::new();
+    ctx.insert("event_name", event_name);
+    ctx.insert("cover_image_url", &cover_image_url(event));
+    ctx.insert("attendee_name", &attendee.name);
+    ctx.insert("language", attendee.language.code());
+    ctx.insert("accept_link", &format!("/invite/accept/{}", id));
+    ctx.insert(
+        "accept_form",
+        &rsvp_form::form_html(&format!("/invite/accept/{id}"), t.accept),
+    );
+    ctx.insert(
+        "accept_plus_ones_form",
+        &rsvp_form::plus_ones_form_html(
+            &format!("/invite/accept/{id}"),
+            t.accept,
+            t.plus_ones_label,
+            attendee.plus_ones,
+        ),
+    );
+    ctx.insert("respond_link", &format!("/invite/respond/{}", id));
+    ctx.insert(
+        "respond_form",
+        &rsvp_form::comment_form_html(
+            &format!("/invite/respond/{id}"),
+            t.accept,
+            t.plus_ones_label,
+            attendee.plus_ones,
+            t.comment_placeholder,
+            attendee.comment.as_deref().unwrap_or_default(),
+        ),
+    );
+    ctx.insert("comment", attendee.comment.as_deref().unwrap_or_default());
+    ctx.insert("maybe_link", &format!("/invite/maybe/{}", id));
+    ctx.insert(
+        "maybe_form",
+        &rsvp_form::form_html(&format!("/invite/maybe/{id}"), t.maybe),
+    );
+    ctx.insert("rsvp", attendee.rsvp.as_str());
+    ctx.insert("plus_ones", &attendee.plus_ones);
+    ctx.insert("withdraw_link", &format!("/invite/withdraw/{}", id));
+    ctx.insert(
+        "withdraw_form",
+        &rsvp_form::form_html(&format!("/invite/withdraw/{id}"), t.withdraw),
+    );
+    ctx.insert(
+        "withdraw_note_form",
+        &rsvp_form::note_form_html(
+            &format!("/invite/withdraw/{id}"),
+            t.withdraw,
+            t.note_placeholder,
+            &event.decline_reasons(),
+        ),
+    );
+    ctx.insert("remove_data_link", &format!("/invite/forget/{}", id));
+    ctx.insert("export_data_link", &format!("/invite/export/{}", id));
+    ctx.insert("certificate_link", &certificate_link(attendee, id));
+    ctx.insert(
+        "when",
+        &event.describe_when(attendee.language).unwrap_or_default(),
+    );
+    ctx.insert("location", &event.location.clone().unwrap_or_default());
+    ctx.insert("fields", &event.field_values(attendee));
+    ctx.insert("calendar_link", &calendar_link(event, attendee, id));
+    ctx.insert("responses_closed", &event.responses_closed(clock::now()));
+    ctx.insert("time_slot", &templates::booked_slot(event, attendee));
+    ctx.insert("schedule_link", &schedule_link(event, id));
+    ctx.insert("forecast", &forecast(event, attendee.language));
+    ctx.insert(
+        "costs",
+        &templates::CostSplit::of(event, attendee.id, attendee.language),
+    );
+    ctx.insert(
+        "time_slots",
+        &templates::SlotRow::all(event, attendee.language),
+    );
+    ctx.insert("announcements", &news.announcements);
+    ctx.insert("updates", &news.updates);
+    ctx.insert(
+        "carpool_board",
+        &templates::CarpoolSection {
+            t: &t,
+            carpool: &carpool,
+        }
+        .render()
+        .unwrap_or_default(),
+    );
+    ctx
+}
+
+/// Where the guest downloads their confirmation, empty unless they accepted
+fn certificate_link(attendee: &event_db::Attendee, id: &str) -> String {
+    if attendee.has_accepted() {
+        format!("/invite/certificate/{id}")
+    } else {
+        String::new()
+    }
+}
+
+/// Where the guest downloads the event for their calendar, empty unless they
+/// accepted and the event has a date
+fn calendar_link(
+    event: &event_db::Event,
+    attendee: &event_db::Attendee,
+    id: &str,
+) -> String {
+    if attendee.has_accepted() && event.date.is_some() {
+        format!("/invite/ical/{id}")
+    } else {
+        String::new()
+    }
+}
+
+/// Where the guest finds the event's agenda, empty if it has none
+fn schedule_link(event: &event_db::Event, id: &str) -> String {
+    if event.agenda.is_empty() {
+        String::new()
+    } else {
+        format!("/invite/schedule/{id}")
+    }
+}
+
+/// The weather forecast for the day of the event, empty without one
+fn forecast(event: &event_db::Event, language: Language) -> String {
+    weather::for_event(event)
+        .map(|forecast| weather::describe(&forecast, language))
+        .unwrap_or_default()
+}
+
+/// Where the event's cover image is served, empty if it has none
+fn cover_image_url(event: &event_db::Event) -> String {
+    event
+        .cover_image
+        .as_deref()
+        .map(cover::url)
+        .unwrap_or_default()
+}
+
+/// The attendee's invitation as plain text, e.g., for the text/plain part of
+/// mail carrying it. `None` if the organizer's template fails to render.
+pub async fn invitation_text(
+    at_id: ids::Id,
+) -> Result<Option<String>, FindEventError> {
+    let (event, attendee) = event_db::find_event_by_attendee(at_id).await?;
+    let id = at_id.encode();
+    let news = templates::GuestNews::of(&event, attendee.language);
+    let ctx = invitation_context(&event, &attendee, &id, &news);
+    match render_custom(&attendee.custom_html, &ctx) {
+        Ok(page) => {
+            let page = with_news(page, &attendee.custom_html, &news);
+            Ok(Some(plain_text::from_html(&page, &settings::public_url())))
+        }
+        Err(e) => {
+            log::warn!(
+                "Invitation of attendee {at_id} failed to render: \"{e}\""
+            );
+            Ok(None)
+        }
+    }
+}
+
+async fn accept_invitation(Path(id): Path<String>) -> Response {
+    confirm_rsvp(&id, Rsvp::Yes).await
+}
+
+async fn accept_invitation_form(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<RsvpForm>,
+) -> Response {
+    let verdict = bot_filter::check_form(&headers, &form);
+    let details = RsvpDetails {
+        plus_ones: form.plus_ones,
+        time_slot: form.slot,
+        ..Default::default()
+    };
+    set_rsvp(&id, Rsvp::Yes, details, verdict).await
+}
+
+/// Accept with a comment for the organizer, or change the comment after
+/// accepting
+async fn respond_to_invitation(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<RsvpForm>,
+) -> Response {
+    let verdict = bot_filter::check_form(&headers, &form);
+    let details = RsvpDetails {
+        plus_ones: form.plus_ones,
+        time_slot: form.slot,
+        comment: Some(form.comment),
+        ..Default::default()
+    };
+    set_rsvp(&id, Rsvp::Yes, details, verdict).await
+}
+
+async fn maybe_invitation(Path(id): Path<String>) -> Response {
+    confirm_rsvp(&id, Rsvp::Maybe).await
+}
+
+async fn maybe_invitation_form(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<RsvpForm>,
+) -> Response {
+    let verdict = bot_filter::check_form(&headers, &form);
+    let details = RsvpDetails {
+        plus_ones: form.plus_ones,
+        ..Default::default()
+    };
+    set_rsvp(&id, Rsvp::Maybe, details, verdict).await
+}
+
+async fn withdraw_invitation(Path(id): Path<String>) -> Response {
+    confirm_rsvp(&id, Rsvp::No).await
+}
+
+async fn withdraw_invitation_form(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<RsvpForm>,
+) -> Response {
+    let verdict = bot_filter::check_form(&headers, &form);
+    let details = RsvpDetails {
+        decline: Decline {
+            reason: Some(form.reason).filter(|r| !r.is_empty()),
+            note: Some(form.note.trim().to_string()).filter(|n| !n.is_empty()),
+        },
+        ..Default::default()
+    };
+    set_rsvp(&id, Rsvp::No, details, verdict).await
+}
+
+/// What a guest may give along with their answer
+#[derive(Default)]
+struct RsvpDetails {
+    /// Absent to keep the number given before
+    plus_ones: Option<u8>,
+    time_slot: Option<u32>,
+    decline: Decline,
+    /// Absent to keep the comment given before, empty to remove it
+    comment: Option<String>,
+}
+
+/// The page a link to the accept, maybe, or withdraw route opens. Following a
+/// link never changes the answer, since scanners and previewers follow them
+/// too.
+async fn confirm_rsvp(id: &str, rsvp: Rsvp) -> Response {
+    // find event
+    let at_id = match ids::decode_id(id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/{}/{into}", rsvp_action(rsvp))
+                })
+                .await;
+            }
+        };
+    let event_name = event.name.unwrap_or("Untitled Event".to_string());
+    let t = attendee.language.phrases(&event_name);
+    let action_link = format!("/invite/{}/{id}", rsvp_action(rsvp));
+    confirm_page(&event_name, t, rsvp, &action_link)
+}
+
+/// A form for the guest to submit the answer with
+fn confirm_page(
+    event_name: &str,
+    t: Phrases,
+    rsvp: Rsvp,
+    action_link: &str,
+) -> Response {
+    let label = match rsvp {
+        Rsvp::Yes => t.accept,
+        Rsvp::Maybe => t.maybe,
+        Rsvp::Pending | Rsvp::No => t.withdraw,
+    };
+    let Ok(template) = templates::ConfirmRsvpPage {
+        event_name,
+        action_link,
+        label,
+        form_time: rsvp_form::form_time(),
+        t,
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(template).into_response()
+}
+
+/// Shared body of the accept, maybe, and withdraw form handlers. Requests which
+/// look automated are recorded for the organizer and answered with a
+/// confirmation form instead of changing anything. Guests accepting in
+/// appointment mode without an open slot picked are asked to pick one.
+async fn set_rsvp(
+    id: &str,
+    rsvp: Rsvp,
+    details: RsvpDetails,
+    verdict: Verdict,
+) -> Response {
+    // find event
+    let at_id = match ids::decode_id(id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+
+    // the pages below are in the guest's language
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/{}/{into}", rsvp_action(rsvp))
+                })
+                .await;
+            }
+        };
+    let event_name = event.name.clone().unwrap_or("Untitled Event".to_string());
+    let t = attendee.language.phrases(&event_name);
+
+    let action_link = format!("/invite/{}/{id}", rsvp_action(rsvp));
+
+    if !matches!(verdict, Verdict::Human) {
+        log::info!(
+            "Ignored automated RSVP for attendee {at_id}: {}",
+            verdict.reason()
+        );
+        if let Err(FindEventError::Database(e)) =
+            event_db::record_bot_flag(at_id, verdict.reason()).await
+        {
+            log::error!("{e}");
+        }
+        return confirm_page(&event_name, t, rsvp, &action_link);
+    }
+
+    if !rate_limit::RSVP.check(at_id) {
+        log::info!("Rate limited RSVP changes of attendee {at_id}");
+        let Ok(template) = templates::ErrorPage {
+            lang: t.lang,
+            title: t.too_many_title,
+            message: t.too_many_message,
+        }
+        .render() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render page",
+            )
+                .into_response();
+        };
+        return (StatusCode::TOO_MANY_REQUESTS, Html(template)).into_response();
+    }
+
+    // whether the picked slot filled up first, if a slot has to be picked
+    let RsvpDetails {
+        plus_ones,
+        time_slot,
+        decline,
+        comment,
+    } = details;
+    let pick_slot =
+        match event_db::set_rsvp(at_id, rsvp, plus_ones, time_slot, decline)
+            .await
+        {
+            Ok(_) => None,
+            Err(RsvpError::Find(FindEventError::Database(e))) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(RsvpError::Find(FindEventError::NotFound(e))) => {
+                return (StatusCode::NOT_FOUND, e).into_response();
+            }
+            // only guests joining through the open RSVP link can find it full
+            Err(RsvpError::Closed | RsvpError::Full) => {
+                let Ok(template) = templates::ErrorPage {
+                    lang: t.lang,
+                    title: t.closed_title,
+                    message: t.closed_error,
+                }
+                .render() else {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to render page",
+                    )
+                        .into_response();
+                };
+                return (StatusCode::FORBIDDEN, Html(template)).into_response();
+            }
+            Err(RsvpError::SlotRequired) => Some(false),
+            Err(RsvpError::SlotFull) => Some(true),
+        };
+    if let Some(slot_was_full) = pick_slot {
+        let Ok(template) = templates::PickSlotPage {
+            event_name: &event_name,
+            action_link: &action_link,
+            form_time: rsvp_form::form_time(),
+            slots: templates::SlotRow::all(&event, attendee.language),
+            slot_was_full,
+            plus_ones,
+            t,
+        }
+        .render() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render page",
+            )
+                .into_response();
+        };
+        let status = if slot_was_full {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::OK
+        };
+        return (status, Html(template)).into_response();
+    }
+
+    if let Some(comment) = comment {
+        if let Err(e) = event_db::set_comment(at_id, &comment).await {
+            log::error!("Comment of {at_id} was not saved: {e:?}");
+        }
+    }
+
+    // redirect
+    if rsvp == Rsvp::Yes {
+        Redirect::to(&format!("/invite/thanks/{id}")).into_response()
+    } else {
+        Redirect::to(&format!("/invite/attend/{id}")).into_response()
+    }
+}
+
+/// The path segment of the endpoint recording the answer
+fn rsvp_action(rsvp: Rsvp) -> &'static str {
+    match rsvp {
+        Rsvp::Yes => "accept",
+        Rsvp::Maybe => "maybe",
+        Rsvp::Pending | Rsvp::No => "withdraw",
+    }
+}
+
+/// The language a guest's browser asks for, for pages shown before they're on
+/// the guest list
+fn browser_language(headers: &HeaderMap) -> Language {
+    headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(Language::from_accept_language)
+        .unwrap_or_default()
+}
+
+/// Show the form of the open RSVP link, with `name` filled in if the guest
+/// entered one before
+fn join_page(
+    id: &str,
+    event: &event_db::Event,
+    language: Language,
+    name: &str,
+    slot_was_full: bool,
+    status: StatusCode,
+) -> Response {
+    let event_name = event.name.as_deref().unwrap_or("Untitled Event");
+    let Ok(template) = templates::JoinPage {
+        t: language.phrases(event_name),
+        event_name,
+        action_link: &format!("/invite/join/{id}"),
+        form_time: rsvp_form::form_time(),
+        name,
+        slots: templates::SlotRow::all(event, language),
+        slot_was_full,
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    (status, Html(template)).into_response()
+}
+
+async fn view_join(Path(id): Path<String>, headers: HeaderMap) -> Response {
+    // find event
+    let link_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_rsvp_link(link_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    };
+    join_page(
+        &id,
+        &event,
+        browser_language(&headers),
+        "",
+        false,
+        StatusCode::OK,
+    )
+}
+
+/// Add whoever filled in the open RSVP link's form to the guest list and send
+/// them to their own page. Requests which look automated get the form again.
+async fn join(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<RsvpForm>,
+) -> Response {
+    // find event
+    let link_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let event = match event_db::find_event_by_rsvp_link(link_id).await {
+        Ok(v) => v,
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    };
+    let language = browser_language(&headers);
+    let t = language.phrases(event.name.as_deref().unwrap_or("Untitled Event"));
+    let error_page = |status, title, message| {
+        let Ok(template) = templates::ErrorPage {
+            lang: t.lang,
+            title,
+            message,
+        }
+        .render() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render page",
+            )
+                .into_response();
+        };
+        (status, Html(template)).into_response()
+    };
+
+    let verdict = bot_filter::check_form(&headers, &form);
+    if !matches!(verdict, Verdict::Human) {
+        log::info!(
+            "Ignored automated RSVP through the link of event {}: {}",
+            event.id,
+            verdict.reason()
+        );
+        return join_page(
+            &id,
+            &event,
+            language,
+            &form.name,
+            false,
+            StatusCode::OK,
+        );
+    }
+    if form.name.trim().is_empty() {
+        return join_page(
+            &id,
+            &event,
+            language,
+            "",
+            false,
+            StatusCode::BAD_REQUEST,
+        );
+    }
+    if !rate_limit::OPEN_RSVP.check(link_id) {
+        log::info!("Rate limited guests joining event {}", event.id);
+        return error_page(
+            StatusCode::TOO_MANY_REQUESTS,
+            t.too_many_title,
+            t.too_many_message,
+        );
+    }
+
+    let slot_was_full = match event_db::join_through_rsvp_link(
+        link_id, &form.name, language, form.slot,
+    )
+    .await
+    {
+        Ok(at_id) => {
+            return Redirect::to(&format!("/invite/thanks/{at_id}"))
+                .into_response();
+        }
+        Err(RsvpError::Find(FindEventError::Database(e))) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(RsvpError::Find(FindEventError::NotFound(e))) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        Err(RsvpError::Closed) => {
+            return error_page(
+                StatusCode::FORBIDDEN,
+                t.closed_title,
+                t.closed_error,
+            );
+        }
+        Err(RsvpError::Full) => {
+            return error_page(
+                StatusCode::CONFLICT,
+                t.join_full_title,
+                t.join_full_message,
+            );
+        }
+        Err(RsvpError::SlotRequired) => false,
+        Err(RsvpError::SlotFull) => true,
+    };
+    // the slots as they are now
+    let event = event_db::find_event_by_rsvp_link(link_id)
+        .await
+        .unwrap_or(event);
+    let status = if slot_was_full {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    join_page(&id, &event, language, &form.name, slot_was_full, status)
+}
+
+#[derive(Deserialize)]
+struct CarpoolForm {
+    /// "driver" or "rider"
+    role: String,
+    #[serde(default)]
+    seats: Option<u8>,
+    #[serde(default)]
+    note: String,
+}
+
+async fn post_carpool(
+    Path(id): Path<String>,
+    Form(form): Form<CarpoolForm>,
+) -> Response {
+    let change = if form.role == "driver" {
+        CarpoolChange::Offer {
+            seats: form.seats.unwrap_or(1),
+            note: form.note,
+        }
+    } else {
+        CarpoolChange::Request { note: form.note }
+    };
+    change_carpool(&id, change).await
+}
+
+async fn join_carpool(Path((id, offer)): Path<(String, u32)>) -> Response {
+    change_carpool(&id, CarpoolChange::Join { offer }).await
+}
+
+async fn leave_carpool(Path(id): Path<String>) -> Response {
+    change_carpool(&id, CarpoolChange::Leave).await
+}
+
+/// Apply a guest's change to the carpool board and show them the board again
+async fn change_carpool(id: &str, change: CarpoolChange) -> Response {
+    // find attendee
+    let at_id = match ids::decode_id(id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let full = match event_db::update_carpool(at_id, change).await {
+        Ok(_) => {
+            return Redirect::to(&format!("/invite/attend/{id}"))
+                .into_response();
+        }
+        Err(CarpoolError::Find(FindEventError::Database(e))) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(CarpoolError::Find(FindEventError::NotFound(e))) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+        Err(CarpoolError::Full) => true,
+        Err(CarpoolError::Unavailable) => false,
+    };
+    // the error page is in the guest's language
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return (StatusCode::NOT_FOUND, e).into_response();
+            }
+        };
+    let t = attendee
+        .language
+        .phrases(event.name.as_deref().unwrap_or("Untitled Event"));
+    let (title, message) = if full {
+        (t.carpool_full_title, t.carpool_full_message)
+    } else {
+        (t.carpool_closed_title, t.carpool_closed_message)
+    };
+    let Ok(template) = templates::ErrorPage {
+        lang: t.lang,
+        title,
+        message,
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    (StatusCode::CONFLICT, Html(template)).into_response()
+}
+
+async fn view_event(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/thanks/{into}")
+                })
+                .await;
+            }
+        };
+
+    // if not accepted, redirect to invitation
+    if !attendee.has_accepted() {
+        let redirect_url = format!("/invite/attend/{id}");
+        return Redirect::to(&redirect_url).into_response();
+    }
+
+    // render response
+    let decline_reasons = event.decline_reasons();
+    let responses_closed = event.responses_closed(clock::now());
+    let news = templates::GuestNews::of(&event, attendee.language);
+    let json_ld = structured_data::event_script(
+        &event,
+        &format!("{}/invite/attend/{id}", settings::public_url()),
+    );
+    let cover_image_url = cover_image_url(&event);
+    let carpool = templates::CarpoolBoard::of(&event, at_id);
+    let time_slot = templates::booked_slot(&event, &attendee);
+    let schedule_link = schedule_link(&event, &id);
+    let forecast = forecast(&event, attendee.language);
+    let costs = templates::CostSplit::of(&event, at_id, attendee.language);
+    let certificate_link = certificate_link(&attendee, &id);
+    let when = event.describe_when(attendee.language).unwrap_or_default();
+    let calendar_link = calendar_link(&event, &attendee, &id);
+    let event_name = event.name.clone().unwrap_or("Untitled Event".to_string());
+    let t = attendee.language.phrases(&event_name);
+    if let Some(thanks_html) = event.thanks_html.as_ref() {
+        let ctx = invitation_context(&event, &attendee, &id, &news);
+        match render_custom(thanks_html, &ctx) {
+            Ok(page) => {
+                let page = with_news(page, thanks_html, &news);
+                let page = structured_data::inject(page, &json_ld);
+                return Html(page).into_response();
+            }
+            // fall back to the default page rather than leave the guest
+            // wondering whether their RSVP went through
+            Err(e) => log::warn!(
+                "Custom thanks page of event {} failed to render: \"{e}\"",
+                event.id
+            ),
+        }
+    }
+    let Ok(template) = templates::ThanksPage {
+        event_name: &event_name,
+        cover_image_url: &cover_image_url,
+        withdraw_link: &format!("/invite/withdraw/{}", id),
+        forget_link: &format!("/invite/forget/{}", id),
+        export_link: &format!("/invite/export/{}", id),
+        form_time: rsvp_form::form_time(),
+        decline_reasons,
+        responses_closed,
+        news,
+        carpool,
+        time_slot: &time_slot,
+        schedule_link: &schedule_link,
+        forecast: &forecast,
+        costs,
+        certificate_link: &certificate_link,
+        when: &when,
+        location: event.location.as_deref().unwrap_or_default(),
+        calendar_link: &calendar_link,
+        t,
+    }
+    .render_live() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(structured_data::inject(template, &json_ld)).into_response()
+}
+
+/// The event's agenda, with a countdown to when it starts, for guests who
+/// accepted
+async fn view_schedule(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/schedule/{into}")
+                })
+                .await;
+            }
+        };
+
+    // if not accepted, redirect to invitation
+    if !attendee.has_accepted() {
+        let redirect_url = format!("/invite/attend/{id}");
+        return Redirect::to(&redirect_url).into_response();
+    }
+
+    let language = attendee.language;
+    let countdown = event
+        .starts_at()
+        .filter(|&start| start > clock::now())
+        .map(|start| language.until(start))
+        .unwrap_or_default();
+    let event_name = event.name.as_deref().unwrap_or("Untitled Event");
+    let Ok(template) = templates::SchedulePage {
+        t: language.phrases(event_name),
+        event_name,
+        countdown,
+        agenda: templates::AgendaRow::all(&event, language),
+        back_link: &format!("/invite/attend/{id}"),
+        news: templates::GuestNews::of(&event, language),
+    }
+    .render_live() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(template).into_response()
+}
+
+async fn export_attendee(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return (StatusCode::NOT_FOUND, e).into_response();
+            }
+        };
+
+    let disposition = format!("attachment; filename=\"invite-{id}.json\"");
+    (
+        [(header::CONTENT_DISPOSITION, disposition)],
+        Json(attendee.export(&event)),
+    )
+        .into_response()
+}
+
+/// The guest's RSVP confirmation as a PDF, e.g., to show at the venue
+/// The event as an iCalendar file, for guests who accepted to add it to their
+/// calendar
+async fn download_calendar(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/ical/{into}")
+                })
+                .await;
+            }
+        };
+
+    if !attendee.has_accepted() {
+        let redirect_url = format!("/invite/attend/{id}");
+        return Redirect::to(&redirect_url).into_response();
+    }
+
+    let url = format!("{}/invite/attend/{id}", settings::public_url());
+    let Some(calendar) = ical::event_calendar(&event, &url) else {
+        return (StatusCode::NOT_FOUND, "The event has no date yet")
+            .into_response();
+    };
+    let disposition = format!("attachment; filename=\"event-{id}.ics\"");
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/calendar; charset=utf-8".to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        calendar,
+    )
+        .into_response()
+}
+
+async fn download_certificate(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return moved_or_not_found(at_id, e, |into| {
+                    format!("/invite/certificate/{into}")
+                })
+                .await;
+            }
+        };
+
+    // only guests who are coming get a confirmation
+    if !attendee.has_accepted() {
+        let redirect_url = format!("/invite/attend/{id}");
+        return Redirect::to(&redirect_url).into_response();
+    }
+
+    let rendered = {
+        let id = id.clone();
+        tokio::task::spawn_blocking(move || {
+            certificate::render(&event, &attendee, &id)
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|pdf| pdf)
+    };
+    match rendered {
+        Ok(pdf) => {
+            let disposition = format!("attachment; filename=\"rsvp-{id}.pdf\"");
+            (
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_string()),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                pdf,
+            )
+                .into_response()
+        }
+        Err(e) => {
+            log::error!("Could not render confirmation PDF: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render document",
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn confirm_forget_attendee(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    let (event, attendee) =
+        match event_db::find_event_by_attendee(at_id).await {
+            Ok(v) => v,
+            Err(FindEventError::Database(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(FindEventError::NotFound(e)) => {
+                return (StatusCode::NOT_FOUND, e).into_response();
+            }
+        };
+
+    let event_name = event.name.unwrap_or("Untitled Event".to_string());
+    let Ok(template) = templates::ForgetPage {
+        event_name: &event_name,
+        forget_link: &format!("/invite/forget/{}", id),
+        t: attendee.language.phrases(&event_name),
+    }
+    .render() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render page")
+            .into_response();
+    };
+    Html(template).into_response()
+}
+
+async fn forget_attendee(Path(id): Path<String>) -> Response {
+    // find event
+    let at_id = match ids::decode_id(&id) {
+        Ok(v) => v,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Event does not exist")
+                .into_response();
+        }
+    };
+    match event_db::forget_attendee(at_id).await {
+        Ok(_) => {}
+        Err(FindEventError::Database(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+        Err(FindEventError::NotFound(e)) => {
+            return (StatusCode::NOT_FOUND, e).into_response();
+        }
+    }
+
+    // redirect
+    Redirect::to(&format!("/invite/attend/{}", id)).into_response()
+}
+
+async fn index_page(State(state): State<AppState>) -> Response {
+    match content::read(&state.content_dir, "pages/index.html").await {
+        Some(page) => Html(page).into_response(),
+        None => {
+            log::error!("index.html is missing from the content directory");
+            (StatusCode::NOT_FOUND, "Page not found").into_response()
+        }
+    }
+}
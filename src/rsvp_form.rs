@@ -0,0 +1,125 @@
+//! The forms guests answer invitations with
+//!
+//! Answering changes state, so it only ever happens through a POST. Links to
+//! the accept, maybe, and withdraw routes open a page with the form instead.
+//! Every form carries the fields `bot_filter` judges submissions by.
+
+use serde::Deserialize;
+
+use crate::{
+    clock::{self, unix_secs},
+    event_db::{MAX_COMMENT_CHARS, MAX_PLUS_ONES},
+};
+
+/// Name of the honeypot input. Something a form-filling bot can't resist.
+pub const HONEYPOT_FIELD: &str = "website";
+
+/// Fields every built-in RSVP form submits
+#[derive(Deserialize, Debug, Default)]
+pub struct RsvpForm {
+    #[serde(default, rename = "website")]
+    pub honeypot: String,
+    /// When the form was rendered, in seconds since the Unix epoch
+    pub t: Option<u64>,
+    /// Optional message for the organizer, e.g., why the guest withdrew
+    #[serde(default)]
+    pub note: String,
+    /// One of the event's decline reasons
+    #[serde(default)]
+    pub reason: String,
+    /// Number of the time slot picked when accepting in appointment mode
+    #[serde(default)]
+    pub slot: Option<u32>,
+    /// How many people the guest brings along, when accepting or answering
+    /// maybe. Absent to keep the number given before.
+    #[serde(default)]
+    pub plus_ones: Option<u8>,
+    /// The guest's name when joining through the open RSVP link
+    #[serde(default)]
+    pub name: String,
+    /// Short message for the organizer when accepting, e.g., dietary notes
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// Value for the form's `t` field
+pub fn form_time() -> u64 {
+    unix_secs(clock::now())
+}
+
+/// A complete RSVP form with a single button, for use in custom invitations
+pub fn form_html(action: &str, label: &str) -> String {
+    form_html_with(action, label, "")
+}
+
+/// Like `form_html`, with a choice of reasons and a box for a note to the
+/// organizer above the button
+pub fn note_form_html(
+    action: &str,
+    label: &str,
+    placeholder: &str,
+    reasons: &[String],
+) -> String {
+    let mut fields = String::new();
+    for reason in reasons {
+        let reason = tera::escape_html(reason);
+        fields += &format!(
+            "<label><input type=\"radio\" name=\"reason\" \
+             value=\"{reason}\"> {reason}</label><br>"
+        );
+    }
+    fields += &format!(
+        "<textarea name=\"note\" maxlength=\"1000\" \
+         placeholder=\"{placeholder}\"></textarea><br>"
+    );
+    form_html_with(action, label, &fields)
+}
+
+/// Like `form_html`, with a field for how many people the guest brings along
+pub fn plus_ones_form_html(
+    action: &str,
+    label: &str,
+    field_label: &str,
+    plus_ones: u8,
+) -> String {
+    let fields = format!(
+        "<label>{field_label} <input type=\"number\" name=\"plus_ones\" \
+         min=\"0\" max=\"{MAX_PLUS_ONES}\" value=\"{plus_ones}\" required>\
+         </label><br>"
+    );
+    form_html_with(action, label, &fields)
+}
+
+/// Like `plus_ones_form_html`, with a box for a comment to the organizer
+pub fn comment_form_html(
+    action: &str,
+    label: &str,
+    field_label: &str,
+    plus_ones: u8,
+    placeholder: &str,
+    comment: &str,
+) -> String {
+    let comment = tera::escape_html(comment);
+    let fields = format!(
+        "<label>{field_label} <input type=\"number\" name=\"plus_ones\" \
+         min=\"0\" max=\"{MAX_PLUS_ONES}\" value=\"{plus_ones}\" required>\
+         </label><br>\
+         <textarea name=\"comment\" maxlength=\"{MAX_COMMENT_CHARS}\" \
+         placeholder=\"{placeholder}\">{comment}</textarea><br>"
+    );
+    form_html_with(action, label, &fields)
+}
+
+fn form_html_with(action: &str, label: &str, fields: &str) -> String {
+    format!(
+        "<form method=\"post\" action=\"{action}\">\
+         <input type=\"text\" name=\"{HONEYPOT_FIELD}\" value=\"\" \
+         tabindex=\"-1\" autocomplete=\"off\" aria-hidden=\"true\" \
+         style=\"position:absolute;left:-10000px\">\
+         <input type=\"hidden\" name=\"t\" value=\"{}\">\
+         {fields}\
+         <button type=\"submit\">{label}</button>\
+         </form>",
+        form_time()
+    )
+}
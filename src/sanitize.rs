@@ -0,0 +1,331 @@
+//! Stripping scripts from organizers' HTML
+//!
+//! Invitations, thanks pages, and withdraw pages are HTML written by
+//! organizers and served from this site, so a script in one would run with
+//! the site's origin in every guest's browser. Before it's stored, the HTML
+//! loses the elements which run or embed code (`script`, `iframe`, `object`,
+//! and the like), event handler attributes such as `onclick`, and links to
+//! `javascript:` and other script addresses. Everything else is kept as
+//! written, including Tera tags inside elements, so styling and layout are
+//! the organizer's to choose.
+
+/// Elements dropped with their content
+const DROPPED_TAGS: &[&str] = &[
+    "script", "iframe", "frame", "frameset", "object", "embed", "applet",
+    "noscript",
+];
+/// Elements dropped alone, as they have no content
+const DROPPED_VOID_TAGS: &[&str] = &["base"];
+/// Attributes holding an address which is followed or loaded
+const URL_ATTRIBUTES: &[&str] = &[
+    "href",
+    "src",
+    "action",
+    "formaction",
+    "xlink:href",
+    "poster",
+    "background",
+    "data",
+];
+
+/// The HTML without scripts, as described above
+pub fn sanitize(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out += &rest[..start];
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            let end = rest[4..].find("-->").map_or(rest.len(), |end| end + 7);
+            out += &rest[..end];
+            rest = &rest[end..];
+            continue;
+        }
+        if !starts_tag(rest) {
+            // not a tag after all, e.g., "a < b"
+            out.push('<');
+            rest = &rest[1..];
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            // a tag which never ends, e.g., with a quote left open, could
+            // hide anything after it
+            return out;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag_name(tag.trim_start_matches('/'));
+        if DROPPED_TAGS.contains(&name.as_str()) {
+            if !closing && !tag.ends_with('/') {
+                // the content is skipped unparsed, as scripts aren't HTML
+                let close = format!("</{name}");
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(at) => {
+                        let after = &rest[at..];
+                        after.find('>').map_or("", |end| &after[end + 1..])
+                    }
+                    None => "",
+                };
+            }
+            continue;
+        }
+        let refresh = name == "meta"
+            && attributes(tag)
+                .any(|(name, _)| name.eq_ignore_ascii_case("http-equiv"));
+        if DROPPED_VOID_TAGS.contains(&name.as_str()) || refresh {
+            continue;
+        }
+        out.push('<');
+        if closing {
+            out += tag;
+        } else {
+            out += &clean_attributes(tag);
+        }
+        out.push('>');
+    }
+    out + rest
+}
+
+/// Whether the "<" at the start of `html` starts a tag rather than text
+fn starts_tag(html: &str) -> bool {
+    html.as_bytes()
+        .get(1)
+        .is_some_and(|&b| b.is_ascii_alphabetic() || b == b'/' || b == b'!')
+}
+
+/// Where the tag at the start of `html` ends, skipping `>` in quoted values
+/// and Tera tags
+fn tag_end(html: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'>' => return Some(i),
+            quote @ (b'"' | b'\'') => {
+                i += html[i + 1..].find(quote as char)? + 1;
+            }
+            b'{' if matches!(bytes.get(i + 1), Some(b'{' | b'%' | b'#')) => {
+                i += tera_tag_len(&html[i..])? - 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Length of the Tera tag at the start of `html`, e.g., "{{ name }}"
+fn tera_tag_len(html: &str) -> Option<usize> {
+    let close = match html.get(..2)? {
+        "{{" => "}}",
+        "{%" => "%}",
+        "{#" => "#}",
+        _ => return None,
+    };
+    html[2..].find(close).map(|end| end + 4)
+}
+
+fn tag_name(tag: &str) -> String {
+    let end = tag
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(tag.len());
+    tag[..end].to_ascii_lowercase()
+}
+
+/// The attributes of a tag as written, as name and value if it has one
+fn attributes(tag: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    Attributes {
+        rest: &tag[tag_name(tag).len()..],
+    }
+    .map(|attribute| (attribute.name, attribute.value))
+}
+
+struct Attribute<'a> {
+    name: &'a str,
+    value: Option<&'a str>,
+    /// The whole attribute as written, including the space before it
+    text: &'a str,
+}
+
+struct Attributes<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Attribute<'a>;
+
+    fn next(&mut self) -> Option<Attribute<'a>> {
+        let start = self.rest;
+        let trimmed = self.rest.trim_start();
+        if trimmed.is_empty() || trimmed == "/" {
+            return None;
+        }
+        // Tera tags between attributes are kept as they are
+        let name_len = tera_tag_len(trimmed).unwrap_or_else(|| {
+            trimmed
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+                .unwrap_or(trimmed.len())
+                .max(1)
+        });
+        let name = &trimmed[..name_len];
+        let mut rest = &trimmed[name_len..];
+        let mut value = None;
+        if let Some(after) = rest.trim_start().strip_prefix('=') {
+            let after = after.trim_start();
+            let len = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    after[1..].find(quote).map_or(after.len(), |end| end + 2)
+                }
+                _ => after.find(char::is_whitespace).unwrap_or(after.len()),
+            };
+            value = Some(after[..len].trim_matches(['"', '\'']));
+            rest = &after[len..];
+        }
+        self.rest = rest;
+        Some(Attribute {
+            name,
+            value,
+            text: &start[..start.len() - rest.len()],
+        })
+    }
+}
+
+/// The tag without event handlers and script addresses
+fn clean_attributes(tag: &str) -> String {
+    let name_len = tag_name(tag).len();
+    let mut out = tag[..name_len].to_string();
+    let mut rest = &tag[name_len..];
+    let attributes = Attributes { rest };
+    for attribute in attributes {
+        rest = &rest[attribute.text.len()..];
+        let name = attribute.name.to_ascii_lowercase();
+        let handler = name.starts_with("on");
+        let script_url = URL_ATTRIBUTES.contains(&name.as_str())
+            && attribute.value.is_some_and(is_script_url);
+        if !handler && !script_url {
+            out += attribute.text;
+        }
+    }
+    out + rest
+}
+
+/// Whether following the address runs code, as `javascript:` addresses do.
+/// Browsers ignore whitespace and control characters in the scheme and
+/// decode character references first, so this does too.
+fn is_script_url(url: &str) -> bool {
+    let decoded = decode_references(url);
+    let scheme: String = decoded
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .take_while(|&c| c != ':')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if !decoded.contains(':') {
+        return false;
+    }
+    match scheme.as_str() {
+        "javascript" | "vbscript" => true,
+        // inline images are fine, inline pages aren't
+        "data" => !decoded
+            .split_once(':')
+            .is_some_and(|(_, data)| data.trim_start().starts_with("image/")),
+        _ => false,
+    }
+}
+
+/// Decode the character references which can hide a scheme, e.g., "&#58;"
+fn decode_references(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out += &rest[..start];
+        rest = &rest[start..];
+        let end = rest.find(';').filter(|&end| end <= 10);
+        let decoded = end.and_then(|end| {
+            let reference = &rest[1..end];
+            let c = match reference.to_ascii_lowercase().as_str() {
+                "colon" => ':',
+                "tab" => '\t',
+                "newline" => '\n',
+                number => {
+                    let code = match number.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_scripts() {
+        assert_eq!(
+            sanitize("<p>Hi</p><script>alert(1)</script><p>there</p>"),
+            "<p>Hi</p><p>there</p>"
+        );
+        assert_eq!(sanitize("<SCRIPT>alert(1)</SCRIPT>after"), "after");
+        assert_eq!(
+            sanitize("<iframe src=\"https://example.org\"></iframe>x"),
+            "x"
+        );
+        assert_eq!(sanitize("<base href=\"https://example.org\">x"), "x");
+        assert_eq!(
+            sanitize("<meta http-equiv=\"refresh\" content=\"0\">x"),
+            "x"
+        );
+    }
+
+    #[test]
+    fn drops_handlers_and_script_addresses() {
+        assert_eq!(
+            sanitize("<p onclick=\"steal()\" class=\"a\">Hi</p>"),
+            "<p class=\"a\">Hi</p>"
+        );
+        assert_eq!(sanitize("<a href=\"javascript:x()\">x</a>"), "<a>x</a>");
+        assert_eq!(
+            sanitize("<a href=\"jav&#x09;ascript&colon;x()\">x</a>"),
+            "<a>x</a>"
+        );
+        assert_eq!(sanitize("<a href=\"data:text/html,hi\">x</a>"), "<a>x</a>");
+        let image = "<img src=\"data:image/png;base64,AAAA\">";
+        assert_eq!(sanitize(image), image);
+    }
+
+    #[test]
+    fn keeps_everything_else() {
+        for html in [
+            "<p class=\"{{ style }}\">{{ name }}</p>",
+            "<div {% if big %}class=\"big\"{% endif %}>x</div>",
+            "<!-- <script>x</script> -->",
+            "a < b and b > c",
+            "<a href=\"https://example.org/?a=1&b=2\">link</a>",
+        ] {
+            assert_eq!(sanitize(html), html);
+        }
+    }
+
+    #[test]
+    fn drops_what_follows_an_unfinished_tag() {
+        assert_eq!(sanitize("ok<p title=\"oops>never closed"), "ok");
+    }
+}
@@ -0,0 +1,103 @@
+//! Scheduled invitations
+//!
+//! Organizers can compose invitations ahead of time and have them go out at
+//! a set time, either to one guest or to every guest whose invitation hasn't
+//! been sent yet. When a send is due, guests with an email address are
+//! emailed their invitation if `--smtp-host` is set. Otherwise the invitation
+//! link is posted as an `invitation` notification to the event's webhooks,
+//! for integrations which text or message guests. Like other notifications,
+//! deliveries that fail are logged but never retried.
+
+use std::time::Duration;
+
+use crate::{
+    clock,
+    event_db::{self, Delivery},
+    mail,
+    notify::{self, Notification, NotificationKind, Priority},
+    routes, settings,
+};
+
+/// Longest wait before looking at the schedule again without a change
+const RECHECK_PERIOD: Duration = Duration::from_mins(10);
+
+/// Deliver scheduled invitations as they come due
+pub async fn send_task() {
+    let mut changes = event_db::watch_changes();
+    loop {
+        changes.mark_unchanged();
+        let deliveries = match event_db::take_due_sends(clock::now()).await {
+            Ok(deliveries) => deliveries,
+            Err(_) => {
+                log::warn!("Could not read scheduled invitations");
+                Vec::new()
+            }
+        };
+        for delivery in deliveries {
+            deliver(delivery).await;
+        }
+        let wait = async {
+            match event_db::next_send_at().await {
+                Ok(Some(at)) => clock::sleep_until(at).await,
+                _ => clock::sleep(RECHECK_PERIOD).await,
+            }
+        };
+        tokio::select! {
+            _ = wait => {}
+            changed = changes.changed() => {
+                // the database thread is gone
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn deliver(delivery: Delivery) {
+    let link = format!(
+        "{}/invite/attend/{}",
+        settings::public_url(),
+        delivery.attendee_id
+    );
+    if let Some(email) = delivery.email.as_deref().filter(|_| mail::enabled()) {
+        let text = match routes::invitation_text(delivery.attendee_id).await {
+            Ok(Some(text)) => text,
+            _ => String::new(),
+        };
+        let body = format!(
+            "{text}\n\nSee the invitation and let the host know if you can \
+             come:\n{link}\n"
+        );
+        let subject = format!("You're invited to \"{}\"", delivery.event_name);
+        mail::send(email, &subject, body.trim_start().to_string()).await;
+        return;
+    }
+    if !notify::has_channel(delivery.event_webhook.as_deref()) {
+        log::warn!(
+            "Nowhere to send the invitation of attendee {} of event {}",
+            delivery.attendee_id,
+            delivery.event_id
+        );
+        return;
+    }
+    let notification = Notification {
+        kind: NotificationKind::Invitation,
+        priority: Priority::Normal,
+        event_id: delivery.event_id,
+        event_name: delivery.event_name.clone(),
+        subject: format!(
+            "Send {} their invitation to \"{}\"",
+            delivery.name, delivery.event_name
+        ),
+        message: format!(
+            "The invitation of {} to \"{}\" was scheduled to go out now. \
+             Send them the link.",
+            delivery.name, delivery.event_name
+        ),
+        link: Some(link),
+        attendee_id: Some(delivery.attendee_id),
+        event_webhook: delivery.event_webhook,
+    };
+    notify::send(&notification).await;
+}
@@ -0,0 +1,416 @@
+//! Settings operators change while the server runs
+//!
+//! Unlike command line options, these are changed from the admin dashboard,
+//! or with `--setting` and `--banner` at startup, and take effect right away.
+//! They're kept in `settings.json` beside the database and read once at
+//! startup, so handlers consult the copy in memory rather than the file.
+//!
+//! Most settings override a command line option. Unset, the option counts,
+//! so instances which never change a setting behave as they're started.
+//!
+//! The banner is a notice shown atop every page the module serves, e.g., for
+//! a maintenance window. It's put right inside the `body` of HTML responses,
+//! so pages don't have to place it themselves, and responses which aren't
+//! whole pages are left alone.
+
+use std::{
+    io::{self, Write},
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    body::Body, extract::Request, http::header, middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::{ApiError, ErrorCode},
+    clock,
+    event_db::{self, DigestFrequency},
+};
+
+const SETTINGS_PATH: &str = "settings.json";
+/// Where the module is reachable unless set otherwise
+const DEFAULT_PUBLIC_URL: &str = "https://blacepos.xyz";
+/// Longest banner text
+pub const MAX_BANNER_CHARS: usize = 500;
+
+/// Held while the settings file is written
+static STORE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+static CURRENT: LazyLock<RwLock<Settings>> = LazyLock::new(Default::default);
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Settings {
+    /// Shown atop every page, absent if there's no notice
+    #[serde(default)]
+    pub banner: Option<String>,
+    /// Where the module is reachable, for links in notifications, exports,
+    /// and the like, e.g., "https://invite.example.org"
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// Overrides `--event-lifetime`
+    #[serde(default)]
+    pub event_lifetime_secs: Option<u64>,
+    /// Overrides `--organizer-pinning`
+    #[serde(default)]
+    pub organizer_pinning: Option<bool>,
+    /// Overrides `--weather-forecasts`
+    #[serde(default)]
+    pub weather_forecasts: Option<bool>,
+    /// Overrides `--json-ld-on-invitations`
+    #[serde(default)]
+    pub json_ld_on_invitations: Option<bool>,
+    /// How often new events send their organizer a digest, off if unset
+    #[serde(default)]
+    pub default_digest: Option<DigestFrequency>,
+    /// Overrides `--nudge-cooldown`
+    #[serde(default)]
+    pub nudge_cooldown_secs: Option<u64>,
+}
+
+/// Names of the settings `set` takes, as on the dashboard and command line,
+/// with what they're for
+pub const KEYS: &[(&str, &str)] = &[
+    (
+        "public_url",
+        "Base of links in notifications and exports, e.g., \
+         https://invite.example.org",
+    ),
+    ("event_lifetime", "How long events are kept, e.g., 90d"),
+    (
+        "organizer_pinning",
+        "Whether organizers can pin events, on or off",
+    ),
+    (
+        "weather_forecasts",
+        "Whether guests see forecasts, on or off",
+    ),
+    (
+        "json_ld_on_invitations",
+        "Whether invitations carry structured data, on or off",
+    ),
+    (
+        "default_digest",
+        "Digest new events start with: off, daily, or weekly",
+    ),
+    (
+        "nudge_cooldown",
+        "Time before a guest can be nudged again, e.g., 3d",
+    ),
+];
+
+/// Parse "key=value" as given to `--setting`, checking the value
+pub fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("\"{s}\" is not key=value"))?;
+    Settings::default().set(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+impl Settings {
+    /// Change one setting given as text, e.g., "event_lifetime" to "30d". An
+    /// empty value unsets it.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let duration = || -> Result<Option<u64>, String> {
+            if value.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(clock::parse_period(value)?.as_secs()))
+        };
+        let toggle = || match value {
+            "" => Ok(None),
+            "on" | "true" => Ok(Some(true)),
+            "off" | "false" => Ok(Some(false)),
+            _ => Err(format!("{key} must be \"on\" or \"off\"")),
+        };
+        match key {
+            "public_url" => {
+                let url = value.trim_end_matches('/');
+                if !url.is_empty()
+                    && !url.starts_with("https://")
+                    && !url.starts_with("http://")
+                {
+                    return Err(
+                        "public_url must start with https:// or http://"
+                            .to_string(),
+                    );
+                }
+                self.public_url =
+                    Some(url.to_string()).filter(|u| !u.is_empty());
+            }
+            "event_lifetime" => self.event_lifetime_secs = duration()?,
+            "organizer_pinning" => self.organizer_pinning = toggle()?,
+            "weather_forecasts" => self.weather_forecasts = toggle()?,
+            "json_ld_on_invitations" => {
+                self.json_ld_on_invitations = toggle()?;
+            }
+            "default_digest" => {
+                self.default_digest = match value {
+                    "" => None,
+                    _ => Some(DigestFrequency::parse(value).ok_or(
+                        "default_digest must be \"off\", \"daily\", or \
+                         \"weekly\"",
+                    )?),
+                };
+            }
+            "nudge_cooldown" => self.nudge_cooldown_secs = duration()?,
+            _ => return Err(format!("There's no setting \"{key}\"")),
+        }
+        Ok(())
+    }
+
+    /// Unset the durations `set` would refuse, e.g., in a file edited by hand,
+    /// as a lifetime of zero would purge every event
+    fn drop_invalid_durations(&mut self) {
+        let durations = [
+            ("event_lifetime", &mut self.event_lifetime_secs),
+            ("nudge_cooldown", &mut self.nudge_cooldown_secs),
+        ];
+        for (key, secs) in durations {
+            let valid = |secs: u64| {
+                secs > 0 && Duration::from_secs(secs) <= clock::MAX_PERIOD
+            };
+            if secs.is_some_and(|secs| !valid(secs)) {
+                log::error!("Ignoring the saved {key}, which is out of range");
+                *secs = None;
+            }
+        }
+    }
+
+    /// A setting as text, as `set` takes it, empty if it's unset
+    pub fn get(&self, key: &str) -> String {
+        let duration = |secs: Option<u64>| {
+            secs.map(|secs| format_duration(Duration::from_secs(secs)))
+        };
+        let toggle = |on: Option<bool>| {
+            on.map(|on| if on { "on" } else { "off" }.to_string())
+        };
+        match key {
+            "public_url" => self.public_url.clone(),
+            "event_lifetime" => duration(self.event_lifetime_secs),
+            "organizer_pinning" => toggle(self.organizer_pinning),
+            "weather_forecasts" => toggle(self.weather_forecasts),
+            "json_ld_on_invitations" => toggle(self.json_ld_on_invitations),
+            "default_digest" => {
+                self.default_digest.map(|d| d.as_str().to_string())
+            }
+            "nudge_cooldown" => duration(self.nudge_cooldown_secs),
+            _ => None,
+        }
+        .unwrap_or_default()
+    }
+}
+
+/// A duration as `clock::parse_duration` reads it, in its largest whole unit,
+/// e.g., "90d"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (unit, unit_secs) = [
+        ("w", 7 * 24 * 60 * 60),
+        ("d", 24 * 60 * 60),
+        ("h", 60 * 60),
+        ("m", 60),
+    ]
+    .into_iter()
+    .find(|(_, unit_secs)| secs > 0 && secs.is_multiple_of(*unit_secs))
+    .unwrap_or(("s", 1));
+    format!("{}{unit}", secs / unit_secs)
+}
+
+/// Read the settings saved before, keeping the defaults if there are none,
+/// then change them as given at startup. Invalid changes are logged and left
+/// out.
+pub fn initialize(values: &[(String, String)], banner: Option<&str>) {
+    let mut settings = match std::fs::read(SETTINGS_PATH) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            log::error!("Could not parse {SETTINGS_PATH}: {e}");
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    };
+    settings.drop_invalid_durations();
+    if !values.is_empty() || banner.is_some() {
+        settings = changed_at_startup(settings, values, banner);
+    }
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = settings;
+}
+
+/// The saved settings with the changes given at startup, unless saving them
+/// fails
+fn changed_at_startup(
+    saved: Settings,
+    values: &[(String, String)],
+    banner: Option<&str>,
+) -> Settings {
+    let mut settings = saved.clone();
+    // either all of the values change or none, as with `set`
+    let mut changed = settings.clone();
+    match values
+        .iter()
+        .try_for_each(|(key, value)| changed.set(key, value))
+    {
+        Ok(()) => settings = changed,
+        Err(e) => log::error!("Could not change the settings: {e}"),
+    }
+    match banner.map(banner_text) {
+        Some(Ok(text)) => settings.banner = text,
+        Some(Err(e)) => log::error!("Could not set the banner: {e}"),
+        None => {}
+    }
+    if let Err(e) = save(&settings) {
+        log::error!("Could not write {SETTINGS_PATH}: {e}");
+        return saved;
+    }
+    settings
+}
+
+/// Replace the settings file. Replaced whole, so a crash mid-write leaves the
+/// previous settings.
+fn save(settings: &Settings) -> io::Result<()> {
+    let data = serde_json::to_vec_pretty(settings)
+        .expect("Settings are serializable");
+    event_db::replace_file(SETTINGS_PATH, |file| file.write_all(&data))
+}
+
+/// The settings in effect
+pub fn current() -> Settings {
+    read(Settings::clone)
+}
+
+/// Look at the settings in effect without copying them
+pub fn read<T>(f: impl FnOnce(&Settings) -> T) -> T {
+    f(&CURRENT.read().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Where the module is reachable, without a trailing slash
+pub fn public_url() -> String {
+    read(|s| s.public_url.clone())
+        .unwrap_or_else(|| DEFAULT_PUBLIC_URL.to_string())
+}
+
+/// Change settings given as text, see `Settings::set`. Either all of them
+/// change or, if one is invalid, none.
+pub async fn set(values: &[(String, String)]) -> Result<(), ApiError> {
+    change(|settings| {
+        for (key, value) in values {
+            settings
+                .set(key, value)
+                .map_err(|e| ApiError::new(ErrorCode::InvalidRequest, e))?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Change the settings and save them, keeping the old ones if saving fails
+async fn change(
+    change: impl FnOnce(&mut Settings) -> Result<(), ApiError>,
+) -> Result<(), ApiError> {
+    let _guard = STORE.lock().await;
+    let mut settings = current();
+    change(&mut settings)?;
+    let written = {
+        let settings = settings.clone();
+        tokio::task::spawn_blocking(move || save(&settings))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)))
+    };
+    written.map_err(|e| {
+        log::error!("Could not write {SETTINGS_PATH}: {e}");
+        ApiError::new(ErrorCode::Internal, "Could not save the settings")
+    })?;
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = settings;
+    Ok(())
+}
+
+/// Show the banner on every page, or remove it if `text` is empty
+pub async fn set_banner(text: &str) -> Result<(), ApiError> {
+    let banner = banner_text(text)
+        .map_err(|e| ApiError::new(ErrorCode::InvalidRequest, e))?;
+    change(|settings| {
+        settings.banner = banner;
+        Ok(())
+    })
+    .await
+}
+
+/// The banner showing `text`, or none if it's empty
+fn banner_text(text: &str) -> Result<Option<String>, String> {
+    let text = text.trim();
+    if text.chars().count() > MAX_BANNER_CHARS {
+        return Err(format!(
+            "The banner can be at most {MAX_BANNER_CHARS} characters"
+        ));
+    }
+    Ok(Some(text.to_string()).filter(|t| !t.is_empty()))
+}
+
+/// Middleware putting the banner into HTML pages, as described above
+pub async fn show_banner(req: Request, next: Next) -> Response {
+    let response = next.run(req).await;
+    let Some(banner) = current().banner else {
+        return response;
+    };
+    let html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if !html {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let Ok(mut page) = String::from_utf8(bytes.to_vec()) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let lower = page.to_ascii_lowercase();
+    let Some(at) = lower
+        .find("<body")
+        .and_then(|start| Some(start + lower[start..].find('>')? + 1))
+    else {
+        return Response::from_parts(parts, Body::from(page));
+    };
+    page.insert_str(
+        at,
+        &format!(
+            "<div class=\"site-banner\" role=\"status\" style=\"padding: 8px \
+             12px; background-color: #232327; color: #fff3c4; text-align: \
+             center;\">{}</div>",
+            tera::escape_html(&banner)
+        ),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_durations() {
+        let mut settings = Settings::default();
+        for key in ["event_lifetime", "nudge_cooldown"] {
+            assert!(settings.set(key, "0s").is_err());
+            assert!(settings.set(key, "99999999999w").is_err());
+            assert!(settings.set(key, "30d").is_ok());
+            assert_eq!(settings.get(key), "30d");
+        }
+
+        let mut saved: Settings = serde_json::from_str(
+            r#"{"event_lifetime_secs": 0, "nudge_cooldown_secs": 3600}"#,
+        )
+        .unwrap();
+        saved.drop_invalid_durations();
+        assert_eq!(saved.event_lifetime_secs, None);
+        assert_eq!(saved.nudge_cooldown_secs, Some(3600));
+    }
+}
@@ -0,0 +1,211 @@
+//! Short links for invitations
+//!
+//! Full invitation links are unwieldy in text messages. With `--shortener`,
+//! every guest's link is registered with an external URL shortener and the
+//! short link is kept with the guest, to be copied from the manage page.
+//! Links are registered in the background once the organizer's view of the
+//! manage page is written down, so the page never waits for the shortener,
+//! and new guests' short links show up on a later view. Failed registrations
+//! are remembered for a while, so an unreachable shortener isn't asked again
+//! on every view.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{LazyLock, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    clock,
+    event_db::{self, Event},
+    ids::Id,
+    settings,
+};
+
+/// How long after a failed registration the shortener is asked again
+const RETRY_AFTER: Duration = Duration::from_mins(15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest short link accepted from a shortener. Anything longer is more
+/// likely an error page than a link.
+const MAX_SHORT_URL_CHARS: usize = 200;
+
+static SHORTENER: OnceLock<Shortener> = OnceLock::new();
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+/// Guests whose link is being registered, so each is only registered once at
+/// a time
+static REGISTERING: LazyLock<Mutex<HashSet<Id>>> =
+    LazyLock::new(Default::default);
+/// When registering a guest's link last failed
+static FAILED: LazyLock<Mutex<HashMap<Id, SystemTime>>> =
+    LazyLock::new(Default::default);
+
+/// The API a shortener speaks
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    /// is.gd, which needs no account
+    IsGd,
+    /// TinyURL's anonymous API
+    Tinyurl,
+    /// A YOURLS instance at `--shortener-url`, with its signature token as
+    /// the key
+    Yourls,
+    /// A Shlink instance at `--shortener-url`, with an API key
+    Shlink,
+    /// Any API which takes the link as the `url` query parameter of a GET
+    /// request to `--shortener-url` and responds with the short link alone.
+    /// The key, if any, is sent as a bearer token.
+    Custom,
+}
+
+#[derive(Clone, Debug)]
+pub struct Shortener {
+    pub service: Service,
+    /// Where the shortener's API is, for services which aren't hosted
+    pub endpoint: Option<String>,
+    pub key: Option<String>,
+}
+
+pub fn initialize(shortener: Shortener) {
+    if SHORTENER.set(shortener).is_err() {
+        log::warn!("URL shortener was already set");
+    }
+}
+
+/// Register the links of the event's guests which have no short link yet, in
+/// the background
+pub fn register_missing(event: &Event) {
+    let Some(shortener) = SHORTENER.get() else {
+        return;
+    };
+    if event.frozen {
+        return;
+    }
+    let now = clock::now();
+    for attendee in event.attendees.iter().filter(|at| at.short_url.is_none()) {
+        let id = attendee.id;
+        let retry_due = FAILED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+            .is_none_or(|&failed| {
+                now.duration_since(failed).unwrap_or_default() >= RETRY_AFTER
+            });
+        if !retry_due {
+            continue;
+        }
+        let mut registering =
+            REGISTERING.lock().unwrap_or_else(|e| e.into_inner());
+        if !registering.insert(id) {
+            continue;
+        }
+        drop(registering);
+        let ev_id = event.id;
+        tokio::spawn(async move {
+            let link = format!(
+                "{}/invite/attend/{}",
+                settings::public_url(),
+                id.encode()
+            );
+            match shorten(shortener, &link).await {
+                Ok(short_url) => {
+                    FAILED
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .remove(&id);
+                    if event_db::set_short_url(ev_id, id, short_url)
+                        .await
+                        .is_err()
+                    {
+                        log::warn!("Could not save the short link of {id}");
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Could not shorten the link of {id}: {e}");
+                    FAILED
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(id, clock::now());
+                }
+            }
+            REGISTERING
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&id);
+        });
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ShlinkResponse {
+    short_url: String,
+}
+
+/// Register a link with the shortener and return the short link
+async fn shorten(shortener: &Shortener, link: &str) -> Result<String, String> {
+    let endpoint = || {
+        shortener
+            .endpoint
+            .as_deref()
+            .map(|url| url.trim_end_matches('/'))
+            .ok_or("The shortener needs --shortener-url")
+    };
+    let key = shortener.key.as_deref().unwrap_or_default();
+    let request = match shortener.service {
+        Service::IsGd => CLIENT
+            .get("https://is.gd/create.php")
+            .query(&[("format", "simple"), ("url", link)]),
+        Service::Tinyurl => CLIENT
+            .get("https://tinyurl.com/api-create.php")
+            .query(&[("url", link)]),
+        Service::Yourls => CLIENT.get(endpoint()?).query(&[
+            ("signature", key),
+            ("action", "shorturl"),
+            ("format", "simple"),
+            ("url", link),
+        ]),
+        Service::Shlink => CLIENT
+            .post(format!("{}/rest/v3/short-urls", endpoint()?))
+            .header("X-Api-Key", key)
+            .json(&serde_json::json!({ "longUrl": link })),
+        Service::Custom => {
+            let request = CLIENT.get(endpoint()?).query(&[("url", link)]);
+            match &shortener.key {
+                Some(key) => request.bearer_auth(key),
+                None => request,
+            }
+        }
+    };
+    let response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let short_url = match shortener.service {
+        Service::Shlink => {
+            response
+                .json::<ShlinkResponse>()
+                .await
+                .map_err(|e| e.to_string())?
+                .short_url
+        }
+        _ => response.text().await.map_err(|e| e.to_string())?,
+    };
+    let short_url = short_url.trim();
+    let is_link =
+        short_url.starts_with("https://") || short_url.starts_with("http://");
+    if !is_link
+        || short_url.chars().count() > MAX_SHORT_URL_CHARS
+        || short_url.contains(char::is_whitespace)
+    {
+        return Err("The shortener did not respond with a link".to_string());
+    }
+    Ok(short_url.to_string())
+}
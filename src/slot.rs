@@ -0,0 +1,77 @@
+//! Registration with the Slot server
+//!
+//! `slot_client` registers once and can't tell when the Slot server goes away,
+//! so a restarted server would stop routing to this module. Registering is
+//! idempotent, so a heartbeat simply registers again on every beat. A server
+//! which restarted between two beats picks the module up on the next one,
+//! however quickly it came back. While the server is unreachable, the
+//! heartbeat backs off.
+
+use std::{net::Ipv4Addr, time::Duration};
+
+use slot_client::protocol::ValidName;
+use tokio::{net::TcpStream, task::JoinHandle};
+
+use crate::metrics;
+
+/// Time between registrations while the server is reachable
+const HEARTBEAT_PERIOD: Duration = Duration::from_secs(30);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_mins(5);
+
+async fn server_reachable(slot_port: u16) -> bool {
+    TcpStream::connect((Ipv4Addr::LOCALHOST, slot_port))
+        .await
+        .is_ok()
+}
+
+/// Keep the module registered with the Slot server on `slot_port`
+pub async fn heartbeat_task(slot_port: u16, name: ValidName, http_port: u16) {
+    let mut registered = false;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        if server_reachable(slot_port).await {
+            if !registered {
+                log::info!("Registering with Slot server on port {slot_port}");
+                metrics::record_slot_registration();
+            }
+            slot_client::client_impl::run_client(
+                slot_port,
+                name.clone(),
+                http_port,
+            );
+            registered = true;
+            backoff = MIN_BACKOFF;
+        } else if registered {
+            log::warn!(
+                "Slot server on port {slot_port} is unreachable. \
+                 Registering again once it is back."
+            );
+            registered = false;
+            metrics::record_slot_lost();
+        }
+
+        let wait = if registered {
+            HEARTBEAT_PERIOD
+        } else {
+            let wait = backoff;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            log::debug!(
+                "Slot server unreachable. Retrying in {} secs.",
+                wait.as_secs()
+            );
+            wait
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Stop registering the module while in-flight requests drain on shutdown.
+/// The Slot server isn't told the module is going away, since `slot_client`
+/// has no message for that, so it routes here until it notices the port is
+/// closed.
+pub fn stop_heartbeat(heartbeat: JoinHandle<()>) {
+    heartbeat.abort();
+    metrics::record_slot_lost();
+    log::info!("Stopped Slot heartbeat for shutdown");
+}
@@ -0,0 +1,103 @@
+//! schema.org structured data for guests' pages
+//!
+//! The thanks page carries the event as `Event` JSON-LD, so calendar
+//! assistants and link unfurlers can pick up its details without scraping the
+//! page. Invitations carry it too if the instance is started with
+//! `--json-ld-on-invitations`. Organizers write invitations themselves, and
+//! not every organizer wants their invitation read by machines.
+//!
+//! Invitations of events with a cover image also get Open Graph tags, so link
+//! previews in chat apps show the cover.
+
+use std::{sync::OnceLock, time::SystemTime};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+
+use crate::{cover, event_db::Event, settings};
+
+static ON_INVITATIONS: OnceLock<bool> = OnceLock::new();
+
+/// Put the structured data on invitations as well as the thanks page
+pub fn set_on_invitations(enabled: bool) {
+    if ON_INVITATIONS.set(enabled).is_err() {
+        log::warn!("Structured data on invitations was already set");
+    }
+}
+
+pub fn on_invitations() -> bool {
+    settings::read(|s| s.json_ld_on_invitations)
+        .unwrap_or_else(|| ON_INVITATIONS.get().copied().unwrap_or_default())
+}
+
+/// A script element holding the event as JSON-LD. `url` is where the event
+/// can be seen, e.g., the guest's invitation.
+pub fn event_script(event: &Event, url: &str) -> String {
+    let mut data = json!({
+        "@context": "https://schema.org",
+        "@type": "Event",
+        "name": event.name.as_deref().unwrap_or("Untitled Event"),
+        "url": url,
+    });
+    if let Some(image) = cover_image_url(event) {
+        data["image"] = image.into();
+    }
+    if let Some(start) = event.starts_at().filter(|_| event.date.is_some()) {
+        data["startDate"] = iso_8601(start).into();
+    }
+    if let Some(end) = event.ends_at() {
+        data["endDate"] = iso_8601(end).into();
+    }
+    if let Some(location) = &event.location {
+        data["location"] = json!({ "@type": "Place", "name": location });
+    }
+    // "</script>" in a value mustn't end the element early
+    let data = data.to_string().replace('<', "\\u003c");
+    format!("<script type=\"application/ld+json\">{data}</script>")
+}
+
+/// Open Graph tags describing the invitation at `url`. Empty for events
+/// without a cover image, whose previews are left to the page.
+pub fn open_graph(event: &Event, url: &str) -> String {
+    let Some(image) = cover_image_url(event) else {
+        return String::new();
+    };
+    let title = event.name.as_deref().unwrap_or("Untitled Event");
+    [
+        ("og:type", "website"),
+        ("og:title", title),
+        ("og:url", url),
+        ("og:image", &image),
+    ]
+    .iter()
+    .map(|(property, content)| {
+        format!(
+            "<meta property=\"{property}\" content=\"{}\">",
+            content
+                .replace('&', "&amp;")
+                .replace('"', "&quot;")
+                .replace('<', "&lt;")
+        )
+    })
+    .collect()
+}
+
+fn iso_8601(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn cover_image_url(event: &Event) -> Option<String> {
+    let name = event.cover_image.as_deref()?;
+    Some(format!("{}{}", settings::public_url(), cover::url(name)))
+}
+
+/// Put a script or tags into a page's head, or at its start if it has none
+pub fn inject(mut page: String, elements: &str) -> String {
+    if elements.is_empty() {
+        return page;
+    }
+    let lower = page.to_ascii_lowercase();
+    let at = lower.find("</head>").unwrap_or(0);
+    page.insert_str(at, elements);
+    page
+}
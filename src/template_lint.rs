@@ -0,0 +1,199 @@
+//! Finding variables a template uses which don't exist
+//!
+//! Tera fails to render `{{ attendee_nmae }}`, but a misspelled variable
+//! elsewhere, e.g., in `{% if responses_closd %}`, counts as unset and
+//! quietly changes what guests see. Checking the template's variables
+//! against those guests get catches both, along with keys of `fields` the
+//! event has no custom field for.
+//!
+//! Variables the template defines itself, with `set`, loops, or macro
+//! arguments, are known from where they're defined. Uses which expect a
+//! variable might be missing, `is defined` tests and the `default` filter,
+//! aren't reported.
+
+use std::collections::HashSet;
+
+use tera::ast::{Expr, ExprVal, Node};
+
+/// Variables used in the template which `context` doesn't have, in the
+/// order they're first used, e.g., "attendee_nmae" or "fields.Tshirt".
+/// Templates which don't parse have none, as rendering them reports why.
+pub fn unknown_variables(
+    template: &str,
+    context: &tera::Context,
+) -> Vec<String> {
+    let Ok(parsed) = tera::Template::new("lint", None, template) else {
+        return Vec::new();
+    };
+    let mut lint = Lint {
+        context: context.clone().into_json(),
+        defined: Vec::new(),
+        unknown: Vec::new(),
+        reported: HashSet::new(),
+    };
+    lint.nodes(&parsed.ast);
+    lint.unknown
+}
+
+struct Lint {
+    context: serde_json::Value,
+    /// Names the template defined, innermost last
+    defined: Vec<String>,
+    unknown: Vec<String>,
+    reported: HashSet<String>,
+}
+
+impl Lint {
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::VariableBlock(_, expr) => self.expr(expr),
+            Node::Set(_, set) => {
+                self.expr(&set.value);
+                self.defined.push(set.key.clone());
+            }
+            Node::FilterSection(_, section, _) => {
+                self.args(section.filter.args.values());
+                self.nodes(&section.body);
+            }
+            Node::Block(_, block, _) => self.nodes(&block.body),
+            Node::Forloop(_, forloop, _) => {
+                self.expr(&forloop.container);
+                let scope = self.defined.len();
+                self.defined.extend(forloop.key.clone());
+                self.defined.push(forloop.value.clone());
+                self.defined.push("loop".to_string());
+                self.nodes(&forloop.body);
+                self.defined.truncate(scope);
+                if let Some(empty_body) = &forloop.empty_body {
+                    self.nodes(empty_body);
+                }
+            }
+            Node::If(conditions, _) => {
+                for (_, condition, body) in &conditions.conditions {
+                    self.expr(condition);
+                    self.nodes(body);
+                }
+                if let Some((_, body)) = &conditions.otherwise {
+                    self.nodes(body);
+                }
+            }
+            Node::MacroDefinition(_, definition, _) => {
+                let scope = self.defined.len();
+                self.defined.extend(definition.args.keys().cloned());
+                self.nodes(&definition.body);
+                self.defined.truncate(scope);
+            }
+            _ => {}
+        }
+    }
+
+    fn args<'a>(&mut self, args: impl IntoIterator<Item = &'a Expr>) {
+        for arg in args {
+            self.expr(arg);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        // `{{ x | default(value="") }}` is written for when `x` is missing
+        if expr.filters.iter().any(|filter| filter.name == "default") {
+            return;
+        }
+        self.value(&expr.val);
+        for filter in &expr.filters {
+            self.args(filter.args.values());
+        }
+    }
+
+    fn value(&mut self, value: &ExprVal) {
+        match value {
+            ExprVal::Ident(path) => self.ident(path),
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs);
+                self.expr(&math.rhs);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs);
+                self.expr(&logic.rhs);
+            }
+            ExprVal::In(within) => {
+                self.expr(&within.lhs);
+                self.expr(&within.rhs);
+            }
+            // `x is defined` asks whether it's missing, so only the
+            // arguments count
+            ExprVal::Test(test) => self.args(&test.args),
+            ExprVal::MacroCall(call) => self.args(call.args.values()),
+            ExprVal::FunctionCall(call) => self.args(call.args.values()),
+            ExprVal::Array(items) => self.args(items),
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    self.value(value);
+                }
+            }
+            ExprVal::String(_)
+            | ExprVal::Int(_)
+            | ExprVal::Float(_)
+            | ExprVal::Bool(_) => {}
+        }
+    }
+
+    /// Check a variable like "fields.Age" or `fields["T-shirt size"]` against
+    /// the context, as far as its path names keys of objects
+    fn ident(&mut self, path: &str) {
+        let (root, mut rest) = split_key(path);
+        if root == "__tera_context" || self.defined.contains(&root) {
+            return;
+        }
+        let mut value = &self.context;
+        let mut shown = root.clone();
+        let mut key = root;
+        loop {
+            let Some(next) = value.as_object().and_then(|o| o.get(&key)) else {
+                if value.is_object() {
+                    self.report(shown);
+                }
+                return;
+            };
+            value = next;
+            let Some((next_key, after)) = next_key(rest) else {
+                return;
+            };
+            shown = format!("{shown}.{next_key}");
+            key = next_key;
+            rest = after;
+        }
+    }
+
+    fn report(&mut self, variable: String) {
+        if self.reported.insert(variable.clone()) {
+            self.unknown.push(variable);
+        }
+    }
+}
+
+/// The first key of a path and what follows it
+fn split_key(path: &str) -> (String, &str) {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    (path[..end].to_string(), &path[end..])
+}
+
+/// The key after the first, if it's written out rather than a variable or a
+/// number, e.g., "Age" in ".Age" or `["Age"]`
+fn next_key(rest: &str) -> Option<(String, &str)> {
+    if let Some(after) = rest.strip_prefix('.') {
+        let (key, rest) = split_key(after);
+        return (!key.chars().all(|c| c.is_ascii_digit()))
+            .then_some((key, rest));
+    }
+    let inner = rest.strip_prefix('[')?;
+    let quote = inner.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let end = inner[1..].find(quote)? + 1;
+    let after = inner[end + 1..].strip_prefix(']')?;
+    Some((inner[1..end].to_string(), after))
+}
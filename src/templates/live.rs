@@ -0,0 +1,84 @@
+//! Live templates
+//!
+//! With `--live-templates <dir>`, the pages implementing `LivePage` are
+//! rendered from Tera templates of the same name in that directory instead of
+//! the compiled Askama templates. The directory is reloaded whenever a file in
+//! it changes, so wording and styling can be tweaked without a rebuild. Pages
+//! without a file in the directory keep using the compiled template.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use askama::Template;
+use serde::Serialize;
+use tera::{Context, Tera};
+
+static TEMPLATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// The loaded directory and the newest modification time in it when loaded
+static LOADED: Mutex<Option<(SystemTime, Tera)>> = Mutex::new(None);
+
+pub fn initialize(dir: PathBuf) {
+    if TEMPLATE_DIR.set(dir).is_err() {
+        log::warn!("Live template directory was already set");
+    }
+}
+
+/// Newest modification time of any file in the directory
+fn newest_change(dir: &Path) -> Option<SystemTime> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .max()
+}
+
+fn render_from_dir(
+    dir: &Path,
+    name: &str,
+    context: &Context,
+) -> Option<Result<String, String>> {
+    if !dir.join(name).is_file() {
+        return None;
+    }
+    let changed = newest_change(dir).unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    if loaded.as_ref().is_none_or(|(at, _)| *at < changed) {
+        let glob = format!("{}/**/*.html", dir.display());
+        match Tera::new(&glob) {
+            Ok(tera) => {
+                log::info!("Loaded live templates from {}", dir.display());
+                *loaded = Some((changed, tera));
+            }
+            Err(e) => return Some(Err(e.to_string())),
+        }
+    }
+    let (_, tera) = loaded.as_ref().expect("Templates were just loaded");
+    Some(tera.render(name, context).map_err(|e| e.to_string()))
+}
+
+/// A built-in page which can be replaced by a live template
+pub trait LivePage: Template + Serialize {
+    /// File name of the live template
+    const NAME: &'static str;
+
+    /// Render the live template if there is one, otherwise the compiled one
+    fn render_live(&self) -> Result<String, String> {
+        if let Some(dir) = TEMPLATE_DIR.get() {
+            let context = Context::from_serialize(self);
+            let rendered = context
+                .map_err(|e| e.to_string())
+                .map(|context| render_from_dir(dir, Self::NAME, &context));
+            match rendered {
+                Ok(None) => {}
+                Ok(Some(Ok(page))) => return Ok(page),
+                Ok(Some(Err(e))) | Err(e) => {
+                    log::error!("Live template {} failed: \"{e}\"", Self::NAME);
+                    return Err(e);
+                }
+            }
+        }
+        self.render().map_err(|e| e.to_string())
+    }
+}
@@ -1,66 +1,1328 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, SystemTime},
+};
 
 use askama::Template;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::{
+    clock,
+    event_db::{DigestFrequency, Rsvp, RsvpCounts},
+    i18n::{Language, Phrases},
+    ids::Id,
+    settings,
+};
+
+pub mod live;
+
+use live::LivePage;
+
+/// Describe a point in time relative to now, e.g., "3 hours ago"
+pub fn relative_time(time: Option<SystemTime>, none: &str) -> String {
+    let Some(time) = time else {
+        return none.to_string();
+    };
+    let (secs, future) = match time.duration_since(clock::now()) {
+        Ok(d) => (d.as_secs(), true),
+        Err(e) => (e.duration().as_secs(), false),
+    };
+    let amount = match secs {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 60 * 60 => format!("{} minutes", s / 60),
+        s if s < 60 * 60 * 24 => format!("{} hours", s / (60 * 60)),
+        s => format!("{} days", s / (60 * 60 * 24)),
+    };
+    if future {
+        format!("in {amount}")
+    } else {
+        format!("{amount} ago")
+    }
+}
+
+/// How long a withdrawal is highlighted on the manage page
+const RECENT_WITHDRAWAL: Duration = Duration::from_days(7);
+
+#[derive(Serialize)]
 pub struct Attendee {
     pub name: String,
     pub custom_html: String,
     pub has_accepted: bool,
+    /// The guest's answer: "pending", "yes", "maybe", or "no"
+    pub rsvp: &'static str,
+    /// How many people the guest brings along
+    pub plus_ones: u8,
+    /// Empty if the guest left no note
+    pub withdraw_note: String,
+    /// Empty if the guest gave no reason
+    pub decline_reason: String,
+    /// What the guest wrote when accepting, empty if nothing
+    pub comment: String,
+    /// Accepted and then withdrew within `RECENT_WITHDRAWAL`
+    pub recently_withdrawn: bool,
+    pub views: u64,
+    /// Empty if unknown
+    pub email: String,
+    pub notes: String,
+    /// e.g., "seen 3 days ago"
+    pub first_viewed: String,
+    /// When the guest was checked in, e.g., "14:05", empty if they weren't
+    pub checked_in: String,
+    /// Whether a scheduled send delivered the invitation
+    pub invitation_sent: bool,
+    /// Whether the organizer is trying out another invitation
+    pub has_draft: bool,
+    /// Values of the event's custom fields. Filled in by the manage page,
+    /// as the attendee alone doesn't know the event's fields.
+    pub fields: Vec<FieldCell>,
+    /// Code of the guest's language
+    pub language: &'static str,
     pub id: String,
     pub invite_link: String,
+    /// Empty if the link wasn't shortened (yet)
+    pub short_link: String,
     pub remove_link: String,
+    /// Gives the guest a new link, revoking the old one
+    pub rotate_link: String,
 }
 
 impl From<crate::event_db::Attendee> for Attendee {
     fn from(value: crate::event_db::Attendee) -> Self {
-        let encoded_id = base62::encode(value.id);
+        let encoded_id = value.id.encode();
+        let has_accepted = value.has_accepted();
         Self {
             name: value.name,
             custom_html: value.custom_html,
-            has_accepted: value.has_accepted,
+            email: value.email.unwrap_or_default(),
+            notes: value.notes,
+            has_accepted,
+            rsvp: value.rsvp.as_str(),
+            plus_ones: value.plus_ones,
+            withdraw_note: value.withdraw_note.unwrap_or_default(),
+            decline_reason: value.decline_reason.unwrap_or_default(),
+            comment: value.comment.unwrap_or_default(),
+            recently_withdrawn: !has_accepted
+                && match value.rsvp_history.as_slice() {
+                    [.., accepted, change] => {
+                        accepted.rsvp == Rsvp::Yes
+                            && change.at + RECENT_WITHDRAWAL > clock::now()
+                    }
+                    _ => false,
+                },
+            views: value.view_count,
+            first_viewed: match value.first_viewed {
+                Some(at) => format!("seen {}", relative_time(Some(at), "")),
+                None => "never opened".to_string(),
+            },
+            checked_in: value
+                .checked_in
+                .map(clock::describe_local_clock)
+                .unwrap_or_default(),
+            invitation_sent: value.invitation_sent.is_some(),
+            has_draft: value.draft_html.is_some(),
+            fields: Vec::new(),
+            language: value.language.code(),
             id: encoded_id.clone(),
             // full link since this will be copied by event organizer
             invite_link: format!(
-                "https://blacepos.xyz/invite/attend/{}",
-                encoded_id
+                "{}/invite/attend/{encoded_id}",
+                settings::public_url()
             ),
+            short_link: value.short_url.unwrap_or_default(),
             remove_link: format!("/invite/remove/{}", encoded_id),
+            rotate_link: format!("/invite/rotate/{}", encoded_id),
+        }
+    }
+}
+
+/// How many days of activity the manage page charts
+const ACTIVITY_DAYS: usize = 14;
+
+#[derive(Serialize)]
+pub struct ActivityRow {
+    pub label: String,
+    pub views: usize,
+    pub accepts: usize,
+    /// Widths of the bars relative to the busiest day
+    pub views_percent: usize,
+    pub accepts_percent: usize,
+}
+
+/// An announcement as guests see it
+#[derive(Serialize)]
+pub struct AnnouncementRow {
+    /// e.g., "3 hours ago"
+    pub posted: String,
+    pub text: String,
+}
+
+/// A change to the event's details as guests see it
+#[derive(Serialize)]
+pub struct UpdateRow {
+    /// e.g., "March 4, 2027"
+    pub date: String,
+    pub description: String,
+}
+
+/// What guests are told about the event since they were invited, newest first
+#[derive(Serialize)]
+pub struct GuestNews {
+    pub announcements: Vec<AnnouncementRow>,
+    pub updates: Vec<UpdateRow>,
+    /// Precedes the date of each update
+    pub updated_on: &'static str,
+}
+
+impl GuestNews {
+    pub fn of(event: &crate::event_db::Event, language: Language) -> Self {
+        Self {
+            announcements: event
+                .announcements
+                .iter()
+                .rev()
+                .map(|announcement| AnnouncementRow {
+                    posted: language.ago(announcement.at),
+                    text: announcement.text.clone(),
+                })
+                .collect(),
+            updates: event
+                .detail_changes
+                .iter()
+                .rev()
+                .map(|change| UpdateRow {
+                    date: language.date(change.at),
+                    description: change.description.clone(),
+                })
+                .collect(),
+            updated_on: language.updated_on(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.announcements.is_empty() && self.updates.is_empty()
+    }
+}
+
+/// Banner with the news, shown atop guests' pages. Renders nothing without
+/// news.
+#[derive(Template)]
+#[template(path = "guest_news.html")]
+pub struct NewsBanner<'a> {
+    pub news: &'a GuestNews,
+}
+
+/// The carpool board as one guest sees it
+#[derive(Serialize, Default)]
+pub struct CarpoolBoard {
+    /// Whether the board is shown at all
+    pub open: bool,
+    /// Seats the guest offers, zero unless driving
+    pub seats: u8,
+    /// Whether the guest asked for a ride
+    pub riding: bool,
+    pub note: String,
+    /// Who rides with the guest, if driving
+    pub riders: Vec<String>,
+    /// Who the guest rides with, empty if nobody yet
+    pub driver: String,
+    /// Other drivers' offers
+    pub offers: Vec<CarpoolOfferRow>,
+    /// Other guests still looking for a ride
+    pub requests: Vec<CarpoolRequestRow>,
+    pub post_link: String,
+    pub leave_link: String,
+}
+
+#[derive(Serialize)]
+pub struct CarpoolOfferRow {
+    pub name: String,
+    pub note: String,
+    pub seats_left: usize,
+    pub join_link: String,
+}
+
+#[derive(Serialize)]
+pub struct CarpoolRequestRow {
+    pub name: String,
+    pub note: String,
+}
+
+/// A contact the organizer can add to the guest list
+#[derive(Serialize)]
+pub struct ContactOption {
+    pub number: u32,
+    pub name: String,
+    pub email: String,
+}
+
+/// The event's expenses as guests who accepted see them
+#[derive(Serialize, Default)]
+pub struct CostSplit {
+    /// Whether the costs are shown at all
+    pub shown: bool,
+    pub expenses: Vec<ExpenseRow>,
+    pub total: String,
+    /// What each guest who accepted owes
+    pub share: String,
+}
+
+impl CostSplit {
+    /// The costs for the attendee. Hidden unless the organizer shares them,
+    /// there are any, and the attendee accepted.
+    pub fn of(
+        event: &crate::event_db::Event,
+        at_id: Id,
+        language: Language,
+    ) -> Self {
+        let accepted = event
+            .attendees
+            .iter()
+            .any(|at| at.id == at_id && at.has_accepted());
+        if !event.expenses_shared || event.expenses.is_empty() || !accepted {
+            return Self::default();
+        }
+        let Some(share) = event.expense_share() else {
+            return Self::default();
+        };
+        Self {
+            shown: true,
+            expenses: event
+                .expenses
+                .iter()
+                .map(|expense| ExpenseRow {
+                    item: expense.item.clone(),
+                    amount: language.amount(expense.cents),
+                    payer: expense.payer.clone(),
+                })
+                .collect(),
+            total: language.amount(event.expenses_total()),
+            share: language.amount(share),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExpenseRow {
+    pub item: String,
+    pub amount: String,
+    pub payer: String,
+}
+
+/// An expense as the organizer sees it
+#[derive(Serialize)]
+pub struct ManageExpenseRow {
+    pub item: String,
+    pub amount: String,
+    pub payer: String,
+    pub remove_link: String,
+}
+
+/// How far ahead or behind someone who paid is once everyone paid their share
+#[derive(Serialize)]
+pub struct BalanceRow {
+    pub payer: String,
+    pub paid: String,
+    /// e.g., "+12.50" if they're owed money, "-3.00" if they owe some
+    pub balance: String,
+}
+
+impl CarpoolBoard {
+    /// The board for the attendee. Closed unless the organizer opened it and
+    /// the attendee accepted.
+    pub fn of(event: &crate::event_db::Event, at_id: Id) -> Self {
+        use crate::event_db::CarpoolRole;
+
+        let accepted = event
+            .attendees
+            .iter()
+            .any(|at| at.id == at_id && at.has_accepted() && !at.anonymized);
+        if !event.carpool_open || event.frozen || !accepted {
+            return Self::default();
+        }
+        let name_of = |id: Id| {
+            event
+                .attendees
+                .iter()
+                .find(|at| at.id == id)
+                .map(|at| at.name.clone())
+                .unwrap_or_default()
+        };
+        let own = event.carpool_entry(at_id);
+        let encoded = at_id.encode();
+        Self {
+            open: true,
+            seats: match own.map(|entry| entry.role) {
+                Some(CarpoolRole::Driver { seats }) => seats,
+                _ => 0,
+            },
+            riding: own.is_some_and(|entry| entry.role == CarpoolRole::Rider),
+            note: own.map(|entry| entry.note.clone()).unwrap_or_default(),
+            riders: event
+                .riders_of(at_id)
+                .map(|entry| name_of(entry.attendee))
+                .collect(),
+            driver: own
+                .and_then(|entry| entry.driver)
+                .map(name_of)
+                .unwrap_or_default(),
+            offers: event
+                .carpool
+                .iter()
+                .filter(|entry| entry.attendee != at_id)
+                .filter(|entry| {
+                    matches!(entry.role, CarpoolRole::Driver { .. })
+                })
+                .map(|entry| CarpoolOfferRow {
+                    name: name_of(entry.attendee),
+                    note: entry.note.clone(),
+                    seats_left: event.seats_left(entry.attendee),
+                    join_link: format!(
+                        "/invite/carpool/{encoded}/join/{}",
+                        entry.number
+                    ),
+                })
+                .collect(),
+            requests: event
+                .carpool
+                .iter()
+                .filter(|entry| entry.attendee != at_id)
+                .filter(|entry| {
+                    entry.role == CarpoolRole::Rider && entry.driver.is_none()
+                })
+                .map(|entry| CarpoolRequestRow {
+                    name: name_of(entry.attendee),
+                    note: entry.note.clone(),
+                })
+                .collect(),
+            post_link: format!("/invite/carpool/{encoded}"),
+            leave_link: format!("/invite/carpool/{encoded}/leave"),
         }
     }
+
+    pub fn joined(&self) -> bool {
+        self.seats > 0 || self.riding
+    }
 }
 
+/// The carpool board on its own, for organizers' templates
 #[derive(Template)]
+#[template(path = "carpool.html")]
+pub struct CarpoolSection<'a> {
+    pub t: &'a Phrases,
+    pub carpool: &'a CarpoolBoard,
+}
+
+/// A driver and who rides with them, as the organizer sees it
+#[derive(Serialize)]
+pub struct ManageCarpoolRow {
+    pub driver: String,
+    pub seats: u8,
+    pub note: String,
+    /// Comma separated
+    pub riders: String,
+}
+
+#[derive(Serialize)]
+pub struct ManageAnnouncementRow {
+    pub posted: String,
+    pub text: String,
+    pub remove_link: String,
+}
+
+/// Invitation views and accepts per day over the last `ACTIVITY_DAYS` days,
+/// most recent first
+pub fn activity_rows(event: &crate::event_db::Event) -> Vec<ActivityRow> {
+    let now = clock::now();
+    let day_of = |time: SystemTime| {
+        let age = now.duration_since(time).unwrap_or_default();
+        (age.as_secs() / (60 * 60 * 24)) as usize
+    };
+
+    let mut views = [0; ACTIVITY_DAYS];
+    let mut accepts = [0; ACTIVITY_DAYS];
+    for attendee in event.attendees.iter() {
+        for &view in attendee.recent_views.iter() {
+            if let Some(count) = views.get_mut(day_of(view)) {
+                *count += 1;
+            }
+        }
+        for change in
+            attendee.rsvp_history.iter().filter(|c| c.rsvp == Rsvp::Yes)
+        {
+            if let Some(count) = accepts.get_mut(day_of(change.at)) {
+                *count += 1;
+            }
+        }
+    }
+
+    let busiest = views.iter().chain(accepts.iter()).copied().max();
+    let percent = |count: usize| {
+        (count * 100).checked_div(busiest.unwrap_or(0)).unwrap_or(0)
+    };
+    (0..ACTIVITY_DAYS)
+        .map(|day| ActivityRow {
+            label: match day {
+                0 => "Today".to_string(),
+                1 => "Yesterday".to_string(),
+                n => format!("{n} days ago"),
+            },
+            views: views[day],
+            accepts: accepts[day],
+            views_percent: percent(views[day]),
+            accepts_percent: percent(accepts[day]),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct DeclineRow {
+    pub reason: String,
+    /// Guests who aren't coming and gave this reason
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct BotFlagRow {
+    pub when: String,
+    pub attendee: String,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct AccessRow {
+    /// e.g., "March 4, 2027, 14:00", the hour it happened in
+    pub when: String,
+    pub what: &'static str,
+    /// The guest, or "You" for the organizer
+    pub who: String,
+    /// How many times it happened within the hour
+    pub count: u32,
+}
+
+#[derive(Serialize)]
+pub struct RenderErrorRow {
+    pub when: String,
+    pub attendee: String,
+    pub message: String,
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "manage_event.html")]
 pub struct ManagePage<'a> {
     pub event_name: &'a str,
+    pub notify_webhook: &'a str,
+    pub organizer_email: &'a str,
+    /// One of "off", "daily", or "weekly"
+    pub digest: &'a str,
+    /// Empty when the default thanks page is used
+    pub thanks_html: &'a str,
+    /// Empty when the default withdraw page is used
+    pub withdraw_html: &'a str,
+    /// Comma separated
+    pub decline_reasons: &'a str,
+    pub decline_summary: Vec<DeclineRow>,
+    pub rsvp_counts: RsvpCounts,
+    /// Guests who accepted and whoever they bring along
+    pub headcount: usize,
+    /// Revision of the event when the page was rendered
+    pub revision: u64,
     pub attendees: Vec<Attendee>,
     pub update_link: &'a str,
+    /// Where rejected saves are sent to be merged
+    pub conflict_link: &'a str,
+    /// Where single fields are autosaved
+    pub patch_link: &'a str,
+    /// Where the attendees are rearranged
+    pub reorder_link: &'a str,
     pub add_link: &'a str,
+    pub nudge_link: &'a str,
+    /// Guests who haven't opened their invitation and can be reminded now
+    pub nudge_due: usize,
+    /// Whether the event has anywhere to deliver reminders
+    pub can_nudge: bool,
+    /// Date the event will be purged on. Empty when it's pinned.
+    pub deletes_on: String,
+    /// Time until then, e.g., "in 89 days"
+    pub deletes_in: String,
+    /// How long extending keeps the event, e.g., "90 days"
+    pub lifetime: String,
+    pub extend_link: &'a str,
+    /// Whether the event is exempt from purging
+    pub pinned: bool,
+    /// Whether the operator lets organizers pin events
+    pub can_pin: bool,
+    pub pin_link: &'a str,
+    pub unpin_link: &'a str,
+    /// As a date and time input expects it. Empty without a deadline.
+    pub rsvp_deadline: String,
+    /// Whether the organizer locked responses
+    pub responses_locked: bool,
+    /// Whether guests can't change their response, locked or past the
+    /// deadline
+    pub responses_closed: bool,
+    pub lock_link: &'a str,
+    pub unlock_link: &'a str,
+    /// Whether the event is a read-only record
+    pub frozen: bool,
+    pub freeze_link: &'a str,
+    pub clone_link: &'a str,
+    /// Newest first
+    pub announcements: Vec<ManageAnnouncementRow>,
+    /// Languages guests can be given, as code and name
+    pub languages: Vec<(&'static str, &'static str)>,
+    pub announce_link: &'a str,
+    /// Whether guests can use the carpool board
+    pub carpool_open: bool,
+    pub carpool_rides: Vec<ManageCarpoolRow>,
+    /// Guests still looking for a ride
+    pub carpool_waiting: Vec<CarpoolRequestRow>,
+    pub open_carpool_link: &'a str,
+    pub close_carpool_link: &'a str,
+    /// Full address of the open RSVP link, empty if there is none
+    pub open_rsvp_url: String,
+    /// Most guests who can join through it, empty for no limit
+    pub open_rsvp_capacity: String,
+    pub joined_openly: usize,
+    pub open_rsvp_link: &'a str,
+    pub close_rsvp_link: &'a str,
+    pub merge_link: &'a str,
+    /// Check-in links continue with the attendee's ID
+    pub check_in_link: &'a str,
+    /// Preview links continue with the attendee's ID
+    pub preview_link: &'a str,
+    /// Where edited templates are checked
+    pub check_template_link: &'a str,
+    pub undo_check_in_link: &'a str,
+    /// Full address of this page with the organizer key, empty for events
+    /// without one
+    pub organizer_url: String,
+    /// Full address of the kiosk display, empty if there is none
+    pub kiosk_url: String,
+    pub open_kiosk_link: &'a str,
+    pub close_kiosk_link: &'a str,
+    /// Full address of the linked contact book, empty if there is none
+    pub contact_book_url: String,
+    /// Contacts of the linked book who aren't on the guest list yet
+    pub contacts: Vec<ContactOption>,
+    pub new_contact_book_link: &'a str,
+    pub use_contact_book_link: &'a str,
+    pub unlink_contact_book_link: &'a str,
+    pub add_from_contacts_link: &'a str,
+    pub save_to_contacts_link: &'a str,
+    /// Empty while the event has none
+    pub api_key: String,
+    pub new_api_key_link: &'a str,
+    pub revoke_api_key_link: &'a str,
+    /// Where the page learns the event changed elsewhere
+    pub updates_link: &'a str,
+    /// As for a date input, empty if unset
+    pub date: String,
+    /// As for time inputs, empty if unset
+    pub start_time: String,
+    pub end_time: String,
+    pub timezone: String,
+    pub location: String,
+    /// The date and times for people, empty without a date
+    pub when: String,
+    pub when_where_link: &'a str,
+    pub agenda: Vec<ManageAgendaRow>,
+    pub add_agenda_item_link: &'a str,
+    /// Extra columns of the guest list, in order
+    pub custom_fields: Vec<ManageFieldRow>,
+    pub add_field_link: &'a str,
+    pub max_custom_fields: usize,
+    /// Whether the instance shows weather forecasts
+    pub weather_enabled: bool,
+    /// e.g., "52.52, 13.41", empty if unset
+    pub weather_location: String,
+    pub forecast: String,
+    pub forecast_days: u64,
+    pub weather_link: &'a str,
+    pub expenses: Vec<ManageExpenseRow>,
+    pub expenses_total: String,
+    /// Guests who accepted, who the expenses are split between
+    pub expense_heads: usize,
+    /// Empty while nobody has accepted
+    pub expense_share: String,
+    pub balances: Vec<BalanceRow>,
+    /// Whether guests who accepted see the expenses
+    pub expenses_shared: bool,
+    pub add_expense_link: &'a str,
+    pub share_expenses_link: &'a str,
+    pub unshare_expenses_link: &'a str,
+    pub time_slots: Vec<ManageSlotRow>,
+    pub add_slots_link: &'a str,
+    pub max_time_slots: usize,
+    pub scheduled_sends: Vec<ScheduledSendRow>,
+    pub schedule_send_link: &'a str,
+    pub max_scheduled_sends: usize,
+    /// Guests whose invitation a scheduled send delivered
+    pub invitations_sent: usize,
+    /// Whether scheduled invitations can reach anyone, by email or webhook
+    pub can_send_invitations: bool,
+    /// Empty without a cover image
+    pub cover_image_url: &'a str,
+    pub cover_link: &'a str,
+    pub uncover_link: &'a str,
+    pub bot_flags: Vec<BotFlagRow>,
+    /// Invitations guests were shown the default one in place of, newest
+    /// first
+    pub render_errors: Vec<RenderErrorRow>,
+    /// Newest first
+    pub access_log: Vec<AccessRow>,
+    pub activity: Vec<ActivityRow>,
+}
+
+impl LivePage for ManagePage<'_> {
+    const NAME: &'static str = "manage_event.html";
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ManagePageJson {
     pub event_name: String,
+    #[serde(default)]
+    pub notify_webhook: Option<String>,
+    #[serde(default)]
+    pub organizer_email: Option<String>,
+    #[serde(default)]
+    pub digest: Option<DigestFrequency>,
+    #[serde(default)]
+    pub thanks_html: Option<String>,
+    #[serde(default)]
+    pub withdraw_html: Option<String>,
+    /// Comma separated
+    #[serde(default)]
+    pub decline_reasons: Option<String>,
+    /// Local date and time, empty for no deadline
+    #[serde(default)]
+    pub rsvp_deadline: Option<String>,
+    // absent when the event has no attendees
+    #[serde(default)]
     pub attendee_data: HashMap<String, ManagePageAttendeeJson>,
+    /// The revision the edits were made against. Absent to overwrite
+    /// unconditionally.
+    #[serde(default)]
+    pub revision: Option<u64>,
 }
 
-#[derive(Deserialize, Debug)]
+impl From<&crate::event_db::Event> for ManagePageJson {
+    fn from(event: &crate::event_db::Event) -> Self {
+        Self {
+            event_name: event.name.clone().unwrap_or_default(),
+            notify_webhook: event.notify_webhook.clone(),
+            organizer_email: event.organizer_email.clone(),
+            digest: Some(event.digest),
+            thanks_html: event.thanks_html.clone(),
+            withdraw_html: event.withdraw_html.clone(),
+            decline_reasons: Some(event.decline_reasons().join(", ")),
+            rsvp_deadline: event.rsvp_deadline.map(crate::clock::local_input),
+            attendee_data: event
+                .attendees
+                .iter()
+                .map(|at| {
+                    let data = ManagePageAttendeeJson {
+                        name: at.name.clone(),
+                        custom_html: at.custom_html.clone(),
+                        email: at.email.clone(),
+                        notes: at.notes.clone(),
+                        order: Some(at.order),
+                        language: Some(at.language),
+                        fields: event
+                            .custom_fields
+                            .iter()
+                            .map(|f| (f.name.clone(), at.field_text(f)))
+                            .collect(),
+                    };
+                    (at.id.encode(), data)
+                })
+                .collect(),
+            revision: Some(event.revision),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ManagePageAttendeeJson {
     pub name: String,
     pub custom_html: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    /// Absent to keep the attendee where it is
+    #[serde(default)]
+    pub order: Option<u32>,
+    #[serde(default)]
+    pub language: Option<Language>,
+    /// Values of custom fields by name, as text
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
 }
 
-#[derive(Template)]
+/// A partial update of an event. Absent fields are left alone.
+#[derive(Deserialize, Debug, Default)]
+pub struct EventPatch {
+    pub event_name: Option<String>,
+    pub notify_webhook: Option<String>,
+    /// Empty to stop emailing the organizer
+    pub organizer_email: Option<String>,
+    pub digest: Option<DigestFrequency>,
+    pub thanks_html: Option<String>,
+    pub withdraw_html: Option<String>,
+    /// Comma separated
+    pub decline_reasons: Option<String>,
+    /// Local date and time, e.g., "2027-03-04T18:00". Empty for no deadline.
+    pub rsvp_deadline: Option<String>,
+    /// By encoded attendee ID
+    #[serde(default)]
+    pub attendees: HashMap<String, AttendeePatch>,
+    /// The revision the edits were made against. Absent to apply
+    /// unconditionally.
+    pub revision: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct AttendeePatch {
+    pub name: Option<String>,
+    pub custom_html: Option<String>,
+    /// Empty to forget the address
+    pub email: Option<String>,
+    pub notes: Option<String>,
+    pub order: Option<u32>,
+    pub language: Option<Language>,
+    /// Values of custom fields by name. Empty to clear a value.
+    pub fields: Option<BTreeMap<String, String>>,
+}
+
+/// What an attendee starts out with. Anything absent gets the usual
+/// placeholder.
+#[derive(Deserialize, Debug, Default)]
+pub struct NewAttendee {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// ID of an attendee of the same event whose invitation is copied
+    pub template: Option<String>,
+}
+
+impl From<ManagePageJson> for EventPatch {
+    fn from(data: ManagePageJson) -> Self {
+        Self {
+            event_name: Some(data.event_name),
+            notify_webhook: data.notify_webhook,
+            organizer_email: data.organizer_email,
+            digest: data.digest,
+            thanks_html: data.thanks_html,
+            withdraw_html: data.withdraw_html,
+            decline_reasons: data.decline_reasons,
+            rsvp_deadline: data.rsvp_deadline,
+            attendees: data
+                .attendee_data
+                .into_iter()
+                .map(|(id, at)| {
+                    let patch = AttendeePatch {
+                        name: Some(at.name),
+                        custom_html: Some(at.custom_html),
+                        email: at.email,
+                        notes: Some(at.notes),
+                        order: at.order,
+                        language: at.language,
+                        fields: Some(at.fields),
+                    };
+                    (id, patch)
+                })
+                .collect(),
+            revision: data.revision,
+        }
+    }
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "thanks.html")]
 pub struct ThanksPage<'a> {
+    pub t: Phrases,
     pub event_name: &'a str,
+    /// Shown atop the page. Empty without a cover image.
+    pub cover_image_url: &'a str,
     pub withdraw_link: &'a str,
+    pub forget_link: &'a str,
+    pub export_link: &'a str,
+    pub form_time: u64,
+    pub decline_reasons: Vec<String>,
+    /// Whether guests can no longer change their response
+    pub responses_closed: bool,
+    pub news: GuestNews,
+    pub carpool: CarpoolBoard,
+    /// When the guest's time slot is, empty outside appointment mode
+    pub time_slot: &'a str,
+    /// Empty if the event has no agenda
+    pub schedule_link: &'a str,
+    /// The weather forecast for the day of the event, empty without one
+    pub forecast: &'a str,
+    pub costs: CostSplit,
+    pub certificate_link: &'a str,
+    /// When the event takes place, empty without a date
+    pub when: &'a str,
+    /// Where the event takes place, empty if unset
+    pub location: &'a str,
+    /// Empty without a date
+    pub calendar_link: &'a str,
 }
 
-#[derive(Template)]
+impl LivePage for ThanksPage<'_> {
+    const NAME: &'static str = "thanks.html";
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "withdraw_invitation.html")]
 pub struct WithdrawPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    /// Where the guest changes how many people they bring along and their
+    /// comment
+    pub respond_link: &'a str,
+    /// Empty if the guest left no comment
+    pub comment: &'a str,
+    pub max_comment_chars: usize,
+    pub maybe_link: &'a str,
+    pub withdraw_link: &'a str,
+    pub forget_link: &'a str,
+    pub export_link: &'a str,
+    pub form_time: u64,
+    pub decline_reasons: Vec<String>,
+    /// Whether guests can no longer change their response
+    pub responses_closed: bool,
+    /// How many people the guest said they bring along
+    pub plus_ones: u8,
+    pub max_plus_ones: u8,
+    pub news: GuestNews,
+    pub carpool: CarpoolBoard,
+    /// When the guest's time slot is, empty outside appointment mode
+    pub time_slot: &'a str,
+    /// Empty if the event has no agenda
+    pub schedule_link: &'a str,
+}
+
+impl LivePage for WithdrawPage<'_> {
+    const NAME: &'static str = "withdraw_invitation.html";
+}
+
+/// Shown in place of an invitation which fails to render, so the guest can
+/// still answer
+#[derive(Template, Serialize)]
+#[template(path = "default_invitation.html")]
+pub struct DefaultInvitationPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    pub attendee_name: &'a str,
+    /// Empty without a cover image
+    pub cover_image_url: &'a str,
+    /// Empty without a date
+    pub when: &'a str,
+    pub location: &'a str,
+    pub is_maybe: bool,
+    pub plus_ones: u8,
+    pub max_plus_ones: u8,
+    /// Accepts with a comment
+    pub respond_link: &'a str,
+    pub comment: &'a str,
+    pub max_comment_chars: usize,
+    pub maybe_link: &'a str,
+    pub withdraw_link: &'a str,
+    pub forget_link: &'a str,
+    pub export_link: &'a str,
+    pub form_time: u64,
+    pub decline_reasons: Vec<String>,
+    pub responses_closed: bool,
+    pub news: GuestNews,
+}
+
+impl LivePage for DefaultInvitationPage<'_> {
+    const NAME: &'static str = "default_invitation.html";
+}
+
+/// The invitation's essentials without the organizer's HTML, for screen
+/// readers, text-mode browsers, and clients which can't cope with the
+/// invitation as written
+#[derive(Template, Serialize)]
+#[template(path = "plain_invitation.html")]
+pub struct PlainInvitationPage<'a> {
+    pub t: Phrases,
     pub event_name: &'a str,
+    pub has_accepted: bool,
+    /// Whether the guest answered maybe
+    pub is_maybe: bool,
+    /// How many people the guest said they bring along
+    pub plus_ones: u8,
+    pub max_plus_ones: u8,
+    /// Accepts with a comment
+    pub respond_link: &'a str,
+    pub comment: &'a str,
+    pub max_comment_chars: usize,
+    pub maybe_link: &'a str,
     pub withdraw_link: &'a str,
+    /// The invitation as the organizer wrote it
+    pub full_link: &'a str,
+    pub forget_link: &'a str,
+    pub export_link: &'a str,
+    pub form_time: u64,
+    pub decline_reasons: Vec<String>,
+    pub responses_closed: bool,
+    pub news: GuestNews,
+}
+
+impl LivePage for PlainInvitationPage<'_> {
+    const NAME: &'static str = "plain_invitation.html";
+}
+
+#[derive(Template)]
+#[template(path = "forget.html")]
+pub struct ForgetPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    pub forget_link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorPage<'a> {
+    /// Code of the page's language
+    pub lang: &'a str,
+    pub title: &'a str,
+    pub message: &'a str,
+}
+
+pub struct WeekRow {
+    pub label: String,
+    pub count: usize,
+    /// Width of the bar relative to the busiest week
+    pub percent: usize,
+}
+
+#[derive(Template)]
+#[template(path = "admin_dashboard.html")]
+pub struct AdminDashboard {
+    pub events: usize,
+    pub attendees: usize,
+    pub accepted: usize,
+    pub rsvp_rate: String,
+    pub db_size: String,
+    pub notification_failures: u64,
+    pub last_purge: String,
+    pub next_purge: String,
+    pub purges_succeeded: u64,
+    pub purges_failed: u64,
+    pub events_purged: u64,
+    pub slot_registered: bool,
+    pub slot_registrations: u64,
+    pub weeks: Vec<WeekRow>,
+    /// Empty if no banner is shown
+    pub banner: String,
+    pub banner_link: &'static str,
+    pub max_banner_chars: usize,
+    pub settings: Vec<SettingRow>,
+    pub settings_link: &'static str,
+}
+
+/// A setting on the admin dashboard, empty if it's unset
+pub struct SettingRow {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub value: String,
+}
+
+#[derive(Template)]
+#[template(path = "confirm_rsvp.html")]
+pub struct ConfirmRsvpPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    pub action_link: &'a str,
+    pub label: &'a str,
+    pub form_time: u64,
+}
+
+/// The event's agenda as accepted guests see it
+#[derive(Template, Serialize)]
+#[template(path = "schedule.html")]
+pub struct SchedulePage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    /// How long until the event starts, e.g., "in 3 days", empty once it has
+    pub countdown: String,
+    pub agenda: Vec<AgendaRow>,
+    pub back_link: &'a str,
+    pub news: GuestNews,
+}
+
+impl LivePage for SchedulePage<'_> {
+    const NAME: &'static str = "schedule.html";
+}
+
+/// An item on the agenda
+#[derive(Serialize)]
+pub struct AgendaRow {
+    /// e.g., "March 4, 2027, 14:00"
+    pub time: String,
+    pub title: String,
+    pub details: String,
+}
+
+impl AgendaRow {
+    pub fn all(
+        event: &crate::event_db::Event,
+        language: Language,
+    ) -> Vec<Self> {
+        event
+            .agenda
+            .iter()
+            .map(|item| Self {
+                time: language.date_time(item.at),
+                title: item.title.clone(),
+                details: item.details.clone(),
+            })
+            .collect()
+    }
+}
+
+/// An item on the agenda as the organizer sees it
+#[derive(Serialize)]
+pub struct ManageAgendaRow {
+    pub time: String,
+    pub title: String,
+    pub details: String,
+    pub remove_link: String,
+}
+
+/// A custom field as the organizer sees it
+#[derive(Serialize)]
+pub struct ManageFieldRow {
+    pub name: String,
+    /// "text", "number", or "boolean"
+    pub kind: &'static str,
+    pub remove_link: String,
+}
+
+/// An attendee's value of a custom field, in a column of the guest list
+#[derive(Serialize)]
+pub struct FieldCell {
+    pub name: String,
+    /// "text", "number", or "boolean"
+    pub kind: &'static str,
+    /// Empty if unset
+    pub value: String,
+    /// For boolean fields
+    pub checked: bool,
+}
+
+impl FieldCell {
+    /// The attendee's value of every custom field, in the fields' order
+    pub fn row(
+        fields: &[crate::event_db::CustomField],
+        attendee: &crate::event_db::Attendee,
+    ) -> Vec<Self> {
+        fields
+            .iter()
+            .map(|field| {
+                let value = attendee.field_text(field);
+                Self {
+                    name: field.name.clone(),
+                    kind: field.kind.as_str(),
+                    checked: value == "true",
+                    value,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where guests pick a time slot when accepting in appointment mode
+#[derive(Template)]
+#[template(path = "pick_slot.html")]
+pub struct PickSlotPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    pub action_link: &'a str,
+    pub form_time: u64,
+    pub slots: Vec<SlotRow>,
+    /// Whether the slot the guest picked filled up first
+    pub slot_was_full: bool,
+    /// How many people the guest said they bring along, to keep it
+    pub plus_ones: Option<u8>,
+}
+
+/// A large display of who has arrived, e.g., for a tablet at the entrance
+#[derive(Template)]
+#[template(path = "kiosk.html")]
+pub struct KioskPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    pub status: KioskStatus,
+    /// Where the page is told about arrivals as they happen
+    pub updates_link: &'a str,
+}
+
+/// What the kiosk display shows, sent again whenever it changes
+#[derive(Serialize)]
+pub struct KioskStatus {
+    /// Guests checked in
+    pub arrived: usize,
+    /// Guests who accepted or were checked in anyway
+    pub expected: usize,
+    /// Latest first
+    pub recent: Vec<ArrivalRow>,
+}
+
+/// How many arrivals the kiosk display lists
+const KIOSK_RECENT: usize = 8;
+
+impl KioskStatus {
+    pub fn of(event: &crate::event_db::Event) -> Self {
+        let mut arrivals: Vec<_> = event
+            .attendees
+            .iter()
+            .filter_map(|at| Some((at.checked_in?, at.name.clone())))
+            .collect();
+        arrivals.sort_by_key(|&(at, _)| std::cmp::Reverse(at));
+        Self {
+            arrived: arrivals.len(),
+            expected: event
+                .attendees
+                .iter()
+                .filter(|at| at.has_accepted() || at.checked_in.is_some())
+                .count(),
+            recent: arrivals
+                .into_iter()
+                .take(KIOSK_RECENT)
+                .map(|(at, name)| ArrivalRow {
+                    name,
+                    time: clock::describe_local_clock(at),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ArrivalRow {
+    pub name: String,
+    /// e.g., "14:05"
+    pub time: String,
+}
+
+/// Where anyone with the open RSVP link adds themselves to the guest list
+#[derive(Template)]
+#[template(path = "join.html")]
+pub struct JoinPage<'a> {
+    pub t: Phrases,
+    pub event_name: &'a str,
+    pub action_link: &'a str,
+    pub form_time: u64,
+    /// The name entered before, when the page is shown again
+    pub name: &'a str,
+    /// Empty outside appointment mode
+    pub slots: Vec<SlotRow>,
+    /// Whether the slot the guest picked filled up first
+    pub slot_was_full: bool,
+}
+
+/// A time slot as guests see it
+#[derive(Serialize)]
+pub struct SlotRow {
+    pub number: u32,
+    /// e.g., "March 4, 2027, 14:00–14:15"
+    pub time: String,
+    pub places_left: usize,
+}
+
+impl SlotRow {
+    /// Every slot of the event, earliest first
+    pub fn all(
+        event: &crate::event_db::Event,
+        language: Language,
+    ) -> Vec<Self> {
+        event
+            .time_slots
+            .iter()
+            .map(|slot| Self {
+                number: slot.number,
+                time: language.time_range(slot.start, slot.end()),
+                places_left: event.places_left(slot.number),
+            })
+            .collect()
+    }
+}
+
+/// When the attendee's booked slot is, empty if they have none
+pub fn booked_slot(
+    event: &crate::event_db::Event,
+    attendee: &crate::event_db::Attendee,
+) -> String {
+    attendee
+        .time_slot
+        .and_then(|number| event.time_slot(number))
+        .map(|slot| attendee.language.time_range(slot.start, slot.end()))
+        .unwrap_or_default()
+}
+
+/// A time slot and who booked it, as the organizer sees it
+#[derive(Serialize)]
+pub struct ManageSlotRow {
+    pub time: String,
+    pub capacity: u32,
+    /// Names of the guests who booked it
+    pub booked: Vec<String>,
+    pub remove_link: String,
+}
+
+/// An invitation waiting to be sent, as the organizer sees it
+#[derive(Serialize)]
+pub struct ScheduledSendRow {
+    pub time: String,
+    /// The guest's name, or who a send to everyone will reach
+    pub recipients: String,
+    pub cancel_link: String,
+}
+
+pub struct ConflictRow {
+    pub key: String,
+    pub label: String,
+    pub mine: String,
+    pub theirs: String,
+}
+
+/// An attendee's live invitation beside their draft
+#[derive(Template)]
+#[template(path = "preview.html")]
+pub struct PreviewPage<'a> {
+    pub event_name: &'a str,
+    pub attendee_name: &'a str,
+    pub frozen: bool,
+    pub live: Preview,
+    /// None without a draft
+    pub draft: Option<Preview>,
+    /// What the draft form starts with, the live template without a draft
+    pub draft_html: &'a str,
+    pub manage_link: &'a str,
+    pub save_link: &'a str,
+    pub promote_link: &'a str,
+    pub discard_link: &'a str,
+}
+
+/// A rendered invitation, or why it failed to render
+pub struct Preview {
+    pub page: String,
+    /// Empty if it rendered
+    pub error: String,
+}
+
+#[derive(Template)]
+#[template(path = "conflict.html")]
+pub struct ConflictPage<'a> {
+    pub event_name: &'a str,
+    pub resolve_link: &'a str,
+    pub manage_link: &'a str,
+    /// Fields without conflicts, as JSON
+    pub merged: &'a str,
+    /// Revision the merge was made against
+    pub revision: u64,
+    pub conflicts: Vec<ConflictRow>,
 }
@@ -0,0 +1,243 @@
+//! Weather forecasts for outdoor events
+//!
+//! With `--weather-forecasts`, events whose organizer set a forecast location
+//! show guests the forecast for the day they start, once that's at most
+//! `FORECAST_DAYS` away. Forecasts come from Open-Meteo and are cached per
+//! place and day. Pages never wait for it: they show what's cached, and a
+//! missing or stale forecast is fetched in the background for the next view.
+//! Failed fetches are remembered for a while too, so an unreachable API isn't
+//! asked again on every view.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{LazyLock, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock, event_db::Event, i18n::Language, settings};
+
+const API_URL: &str = "https://api.open-meteo.com/v1/forecast";
+/// How far ahead of the event the forecast is shown
+pub const FORECAST_DAYS: u64 = 5;
+/// How long a forecast is shown before it's fetched again
+const FRESH_FOR: Duration = Duration::from_hours(3);
+/// How long after a failed fetch the API is asked again
+const RETRY_AFTER: Duration = Duration::from_mins(15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+static CACHE: LazyLock<Mutex<HashMap<Key, Cached>>> =
+    LazyLock::new(Default::default);
+/// Forecasts being fetched, so each is only asked for once at a time
+static FETCHING: LazyLock<Mutex<HashSet<Key>>> =
+    LazyLock::new(Default::default);
+
+/// Fetch forecasts for events with a forecast location. Off by default, since
+/// it tells Open-Meteo where and when events take place.
+pub fn set_enabled(enabled: bool) {
+    if ENABLED.set(enabled).is_err() {
+        log::warn!("Weather forecasts were already set");
+    }
+}
+
+pub fn enabled() -> bool {
+    settings::read(|s| s.weather_forecasts)
+        .unwrap_or_else(|| ENABLED.get().copied().unwrap_or_default())
+}
+
+/// The forecast for one day at one place
+#[derive(Serialize, Clone, Debug)]
+pub struct Forecast {
+    /// WMO weather interpretation code
+    pub code: u8,
+    /// Highest temperature in °C
+    pub high: i32,
+    /// Lowest temperature in °C
+    pub low: i32,
+    /// Chance of precipitation in percent
+    pub precipitation: u8,
+}
+
+/// A place, to about a kilometer, and a day
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Key {
+    latitude: i32,
+    longitude: i32,
+    day: NaiveDate,
+}
+
+impl Key {
+    fn latitude(&self) -> f64 {
+        f64::from(self.latitude) / 100.0
+    }
+
+    fn longitude(&self) -> f64 {
+        f64::from(self.longitude) / 100.0
+    }
+}
+
+struct Cached {
+    fetched: SystemTime,
+    /// None if the fetch failed
+    forecast: Option<Forecast>,
+}
+
+/// The cached forecast for the day the event starts, if it's soon enough.
+/// Never waits: if there's none or it's stale, one is fetched in the
+/// background for later views.
+pub fn for_event(event: &Event) -> Option<Forecast> {
+    if !enabled() {
+        return None;
+    }
+    let location = event.weather_location.as_ref()?;
+    let start = event.starts_at()?;
+    let now = clock::now();
+    let ahead = start.duration_since(now).ok()?;
+    if ahead > Duration::from_days(FORECAST_DAYS) {
+        return None;
+    }
+    let key = Key {
+        latitude: (location.latitude * 100.0).round() as i32,
+        longitude: (location.longitude * 100.0).round() as i32,
+        day: DateTime::<Local>::from(start).date_naive(),
+    };
+
+    let cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cached = cache.get(&key);
+    let stale = cached.is_none_or(|cached| {
+        let age = now.duration_since(cached.fetched).unwrap_or_default();
+        match cached.forecast {
+            Some(_) => age >= FRESH_FOR,
+            None => age >= RETRY_AFTER,
+        }
+    });
+    let forecast = cached.and_then(|cached| cached.forecast.clone());
+    drop(cache);
+    if stale {
+        refresh(key);
+    }
+    forecast
+}
+
+/// Fetch the forecast in the background, unless that's already happening
+fn refresh(key: Key) {
+    let mut fetching = FETCHING.lock().unwrap_or_else(|e| e.into_inner());
+    if !fetching.insert(key) {
+        return;
+    }
+    drop(fetching);
+    tokio::spawn(async move {
+        let forecast = match fetch(key).await {
+            Ok(forecast) => Some(forecast),
+            Err(e) => {
+                log::warn!("Could not fetch weather forecast: {e}");
+                None
+            }
+        };
+        let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        let now = clock::now();
+        // forget days which have passed so the cache doesn't grow forever
+        let today = DateTime::<Local>::from(now).date_naive();
+        cache.retain(|key, _| key.day >= today);
+        let forecast = match (forecast, cache.remove(&key)) {
+            (Some(forecast), _) => Some(forecast),
+            // an older forecast beats none while the API is down
+            (None, Some(old)) => old.forecast,
+            (None, None) => None,
+        };
+        cache.insert(
+            key,
+            Cached {
+                fetched: now,
+                forecast,
+            },
+        );
+        drop(cache);
+        FETCHING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&key);
+    });
+}
+
+#[derive(Deserialize)]
+struct Response {
+    daily: Daily,
+}
+
+#[derive(Deserialize)]
+struct Daily {
+    weather_code: Vec<Option<u8>>,
+    temperature_2m_max: Vec<Option<f64>>,
+    temperature_2m_min: Vec<Option<f64>>,
+    precipitation_probability_max: Vec<Option<u8>>,
+}
+
+async fn fetch(key: Key) -> Result<Forecast, String> {
+    let day = key.day.format("%Y-%m-%d").to_string();
+    let response = CLIENT
+        .get(API_URL)
+        .query(&[
+            ("latitude", key.latitude().to_string()),
+            ("longitude", key.longitude().to_string()),
+            (
+                "daily",
+                "weather_code,temperature_2m_max,temperature_2m_min,\
+                 precipitation_probability_max"
+                    .to_string(),
+            ),
+            ("timezone", "auto".to_string()),
+            ("start_date", day.clone()),
+            ("end_date", day),
+        ])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let daily = response
+        .json::<Response>()
+        .await
+        .map_err(|e| e.to_string())?
+        .daily;
+    let first = |values: &[Option<f64>]| values.first().copied().flatten();
+    Ok(Forecast {
+        code: daily
+            .weather_code
+            .first()
+            .copied()
+            .flatten()
+            .ok_or("The forecast has no weather")?,
+        high: first(&daily.temperature_2m_max)
+            .ok_or("The forecast has no temperatures")?
+            .round() as i32,
+        low: first(&daily.temperature_2m_min)
+            .ok_or("The forecast has no temperatures")?
+            .round() as i32,
+        precipitation: daily
+            .precipitation_probability_max
+            .first()
+            .copied()
+            .flatten()
+            .unwrap_or_default(),
+    })
+}
+
+/// The forecast in a sentence for guests, e.g., "Partly cloudy, 4–12 °C, 20%
+/// chance of rain"
+pub fn describe(forecast: &Forecast, language: Language) -> String {
+    let conditions = language.weather(forecast.code);
+    let rain = language.rain_chance(forecast.precipitation);
+    format!(
+        "{conditions}, {}–{} °C, {rain}",
+        forecast.low, forecast.high
+    )
+}